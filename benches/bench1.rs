@@ -1,9 +1,15 @@
 use std::time::Instant;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use indexed_file::{mem_file::MemFile, string::IndexedString, File, Indexable, ReadByLine};
+use indexed_file::{
+    mem_file::MemFile, string::IndexedString, Builder, File, Indexable, IndexableFile, ReadByLine,
+};
 use rand::{distributions::Uniform, Rng};
-use std::fs;
+use std::{
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    ops::ControlFlow,
+};
 
 fn in_memory_random_lines_bench(c: &mut Criterion) {
     c.bench_function("read random lines in memory", |b| {
@@ -52,6 +58,56 @@ fn random_lines_bench(c: &mut Criterion) {
         });
     });
 
+    c.bench_function("read random lines pooled", |b| {
+        b.iter_custom(|iters| {
+            let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+
+            let lines: Vec<_> = rand::thread_rng()
+                .sample_iter(Uniform::new(0, file.total_lines() - 1))
+                .take(file.total_lines())
+                .collect();
+
+            let start = Instant::now();
+
+            for _i in 0..iters {
+                for line in &lines {
+                    black_box(file.read_line_pooled(black_box(*line)).unwrap());
+                }
+            }
+
+            start.elapsed()
+        });
+    });
+
+    c.bench_function("read nearby lines", |b| {
+        b.iter_custom(|iters| {
+            let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+            let total = file.total_lines();
+
+            // Walk through the file in small steps back and forth, which are served from the
+            // buffer of the reader
+            let mut line = 0usize;
+            let lines: Vec<_> = rand::thread_rng()
+                .sample_iter(Uniform::new_inclusive(-3isize, 4))
+                .take(total)
+                .map(|step| {
+                    line = (line as isize + step).clamp(0, total as isize - 1) as usize;
+                    line
+                })
+                .collect();
+
+            let start = Instant::now();
+
+            for _i in 0..iters {
+                for line in &lines {
+                    file.read_line(black_box(*line)).unwrap();
+                }
+            }
+
+            start.elapsed()
+        });
+    });
+
     c.bench_function("read random lines in memory file new", |b| {
         b.iter_custom(|iters| {
             let content = std::fs::read_to_string("./testfiles/LICENSE").unwrap();
@@ -92,6 +148,78 @@ fn sequencial_bench(c: &mut Criterion) {
             start.elapsed()
         });
     });
+
+    c.bench_function("read sequential read_line", |b| {
+        b.iter_custom(|iters| {
+            let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+
+            let start = Instant::now();
+
+            for _i in 0..iters {
+                for line in 0..file.total_lines() {
+                    black_box(file.read_line(black_box(line)).unwrap());
+                }
+            }
+
+            start.elapsed()
+        });
+    });
+
+    c.bench_function("read sequential read_line assume_utf8", |b| {
+        b.iter_custom(|iters| {
+            let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+            // The file doesn't change while benchmarking
+            unsafe { file.assume_utf8() }.unwrap();
+
+            let start = Instant::now();
+
+            for _i in 0..iters {
+                for line in 0..file.total_lines() {
+                    black_box(file.read_line(black_box(line)).unwrap());
+                }
+            }
+
+            start.elapsed()
+        });
+    });
+
+    c.bench_function("read sequential for_each_line", |b| {
+        b.iter_custom(|iters| {
+            let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+
+            let start = Instant::now();
+
+            for _i in 0..iters {
+                file.for_each_line(|_, line| {
+                    black_box(line);
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+            }
+
+            start.elapsed()
+        });
+    });
+
+    c.bench_function("read sequential read_until", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+
+            let mut buff = Vec::new();
+            for _i in 0..iters {
+                let mut reader = BufReader::new(fs::File::open("./testfiles/LICENSE").unwrap());
+                loop {
+                    buff.clear();
+                    if reader.read_until(b'\n', &mut buff).unwrap() == 0 {
+                        break;
+                    }
+                    black_box(&buff);
+                }
+            }
+
+            start.elapsed()
+        });
+    });
 }
 
 fn sequencial_in_memory_bench(c: &mut Criterion) {
@@ -135,11 +263,83 @@ fn sequencial_in_memory_bench(c: &mut Criterion) {
     });
 }
 
+fn long_lines_bench(c: &mut Criterion) {
+    // JSON lines like records of 1 KiB and 100 KiB
+    for record_len in [1024, 100 * 1024] {
+        let path = std::env::temp_dir().join(format!(
+            "indexed_file_bench_long_{}_{}",
+            record_len,
+            std::process::id()
+        ));
+        let record = format!("{{\"data\":\"{}\"}}\n", "x".repeat(record_len));
+        fs::write(&path, record.repeat(6 * 1024 * 1024 / record_len)).unwrap();
+
+        for capacity in [8 * 1024, 256 * 1024] {
+            let name = format!(
+                "read sequential {} KiB lines with {} KiB buffer",
+                record_len / 1024,
+                capacity / 1024
+            );
+            c.bench_function(&name, |b| {
+                b.iter_custom(|iters| {
+                    let mut file = Builder::new()
+                        .buffer_capacity(capacity)
+                        .index_from(&path)
+                        .unwrap();
+
+                    let start = Instant::now();
+
+                    let mut buff = Vec::new();
+                    for _i in 0..iters {
+                        for line in 0..file.total_lines() {
+                            file.read_line_raw(black_box(line), &mut buff).unwrap();
+                        }
+                    }
+
+                    start.elapsed()
+                });
+            });
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+fn write_bench(c: &mut Criterion) {
+    let src = std::env::temp_dir().join(format!("indexed_file_bench_write_{}", std::process::id()));
+    let dst = src.with_extension("indexed");
+    let line = format!("{}\n", "x".repeat(255));
+    fs::write(&src, line.repeat(256 * 1024)).unwrap();
+    let mut file = File::open_raw(&src).unwrap();
+
+    let mut group = c.benchmark_group("write 64 MiB");
+    group.sample_size(10);
+    group.bench_function("write_to with BufWriter", |b| {
+        b.iter(|| {
+            let mut out = BufWriter::new(fs::File::create(&dst).unwrap());
+            file.write_to(&mut out).unwrap();
+            out.flush().unwrap();
+        });
+    });
+    group.bench_function("write_to_file", |b| {
+        b.iter(|| {
+            let mut out = fs::File::create(&dst).unwrap();
+            file.write_to_file(&mut out, false).unwrap();
+        });
+    });
+    group.finish();
+
+    fs::remove_file(&src).unwrap();
+    fs::remove_file(&dst).unwrap();
+}
+
 criterion_group!(
     benches,
     in_memory_random_lines_bench,
     random_lines_bench,
     sequencial_bench,
     sequencial_in_memory_bench,
+    long_lines_bench,
+    write_bench,
 );
 criterion_main!(benches);
@@ -1,10 +1,15 @@
 //! Convert a normal to an indexed file
 
-use indexed_file::IndexableFile;
+use std::{io::BufReader, sync::Arc};
+
+use indexed_file::{index::Index, IndexableFile};
 
 fn main() {
-    // Open and index file
-    let mut file = indexed_file::File::open_raw("<some unindexed file>").unwrap();
+    // Index the file and print statistics about its lines
+    let mut reader = BufReader::new(std::fs::File::open("<some unindexed file>").unwrap());
+    let (index, report) = Index::build_with_report(&mut reader).unwrap();
+    println!("{}", report);
+    let mut file = indexed_file::File::from_buf_reader(reader, Arc::new(index));
 
     // Create a new file which will contain the index data and the original content
     let mut output = std::fs::File::create("output").unwrap();
@@ -1,9 +1,24 @@
 use std::{
-    io::{BufReader, Cursor, Write},
+    borrow::Cow,
+    convert::TryFrom,
+    io::{self, BufReader, Cursor, Read, Write},
+    mem,
+    ops::{ControlFlow, Range},
     sync::Arc,
 };
 
-use crate::{bufreader::IndexedReader, index::Index, Indexable, IndexableFile, ReadByLine, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    bloom::BloomFilter,
+    bufreader::IndexedReader,
+    error::Error,
+    hash_bytes,
+    index::{first_unordered, Header, Index},
+    lookup::LookupTable,
+    window::Region,
+    Indexable, IndexableFile, ReadByLine, Result,
+};
 
 // little shortcut
 pub trait Anyable: AsRef<[u8]> + Clone + Send + Sync {}
@@ -13,14 +28,33 @@ impl<T: AsRef<[u8]> + Clone + Send + Sync> Anyable for T {}
 #[derive(Debug)]
 pub struct CloneableIndexedReader<T: Anyable> {
     // requried to allow duplicating the IndexedReader
-    data: ArcAny<T>,
+    pub(crate) data: ArcAny<T>,
     pub(crate) reader: IndexedReader<Cursor<ArcAny<T>>>,
+    /// Absolute position of the line based reads. Lines are sliced out of `data`, so the
+    /// position of `reader` is not moved by them.
+    pos: u64,
+    /// Amount of mutations of this reader and the reader it was cloned from
+    version: u64,
 }
 
 /// A wrapper around Arc<T> to allow using an arc as reader for Cursor<Arc<T>>
 #[derive(Debug, Clone)]
 pub struct ArcAny<T: Anyable>(Arc<T>);
 
+impl<T: Anyable> ArcAny<T> {
+    /// Returns the shared data
+    #[inline]
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+
+    /// Returns a reference to the shared data
+    #[inline]
+    pub fn as_arc(&self) -> &Arc<T> {
+        &self.0
+    }
+}
+
 impl<T: Anyable> AsRef<[u8]> for ArcAny<T> {
     #[inline]
     fn as_ref(&self) -> &[u8] {
@@ -35,6 +69,21 @@ impl<T: Anyable> From<T> for ArcAny<T> {
     }
 }
 
+impl<T: Anyable> From<Arc<T>> for ArcAny<T> {
+    /// Shares the data of `arc` without copying it
+    #[inline]
+    fn from(arc: Arc<T>) -> Self {
+        Self(arc)
+    }
+}
+
+impl From<Cow<'_, str>> for ArcAny<String> {
+    #[inline]
+    fn from(s: Cow<'_, str>) -> Self {
+        Self(Arc::new(s.into_owned()))
+    }
+}
+
 impl From<&str> for ArcAny<String> {
     #[inline]
     fn from(s: &str) -> Self {
@@ -50,7 +99,8 @@ impl<T: Anyable> From<&T> for ArcAny<T> {
 }
 
 impl<T: Anyable> CloneableIndexedReader<T> {
-    /// Read data with containing an index into ram.
+    /// Read data with containing an index into ram. The index may be stored in front of the data
+    /// or behind it, see `IndexedWriter`.
     ///
     /// Returns an error if the index is malformed, missing or an io error occurs
     #[inline]
@@ -58,8 +108,12 @@ impl<T: Anyable> CloneableIndexedReader<T> {
         let arc = s.into();
         let mut reader = Cursor::new(arc.clone());
 
-        let index = Index::parse_index(&mut reader)?;
-        Ok(Self::from_reader(arc, reader, Arc::new(index)))
+        let (index, section) = Index::parse_any(&mut reader)?;
+        let mut reader = Self::from_reader(arc, reader, Arc::new(index));
+        if let Some(section) = section {
+            reader.reader.set_data_section(section);
+        }
+        Ok(reader)
     }
 
     /// Create a new `IndexedReader` from unindexed data and builds an index.
@@ -88,6 +142,65 @@ impl<T: Anyable> CloneableIndexedReader<T> {
         Self::from_reader(arc, reader, index)
     }
 
+    /// Creates a new reader over `data` using the index of this reader, without parsing or
+    /// building an index. `data` has to have the same layout as the data of this reader, for
+    /// example a transformed copy in which every line keeps its length. The line checksums, bloom
+    /// filter and lookup table of the index and the hash of the data describe the content of the
+    /// old data, so they are dropped.
+    ///
+    /// Returns `Error::LengthMismatch` if `data` doesn't have the same length
+    pub fn with_data<U: Into<ArcAny<T>>>(&self, data: U) -> Result<CloneableIndexedReader<T>> {
+        let data = data.into();
+        let expected = self.data.as_ref().len() as u64;
+        let actual = data.as_ref().len() as u64;
+        if actual != expected {
+            return Err(Error::LengthMismatch { expected, actual });
+        }
+
+        let mut reader = self.reader.duplicate(Cursor::new(data.clone()));
+        reader.content_hash = None;
+        if self.get_index().has_line_tables() {
+            let mut index = Index::clone(self.get_index());
+            index.clear_line_tables();
+            reader.index = Arc::new(index);
+        }
+
+        Ok(Self {
+            reader,
+            data,
+            pos: 0,
+            version: 0,
+        })
+    }
+
+    /// Returns a shared reference to the index, which can be used to create readers over other
+    /// data using `new_custom`
+    #[inline]
+    pub fn index_arc(&self) -> Arc<Index> {
+        Arc::clone(&self.reader.index)
+    }
+
+    /// Returns the text of all lines, which is the data without an embedded header and index and
+    /// without a skipped BOM. Readers are compared by this content.
+    #[inline]
+    pub fn content(&self) -> &[u8] {
+        let section = self.data_section();
+        let start = section.start + self.get_index().bom_len() as usize;
+        self.data
+            .as_ref()
+            .get(start..section.end)
+            .unwrap_or_default()
+    }
+
+    /// Returns the range of the data section within `data`, which excludes an embedded header
+    /// and index regardless of whether they are stored in front of the data or behind it
+    #[inline]
+    fn data_section(&self) -> Range<usize> {
+        let start = self.get_index_byte_len();
+        let len = self.data.as_ref().len().saturating_sub(start);
+        start..start + len.min(self.data_len() as usize)
+    }
+
     #[inline]
     fn from_reader(
         data: ArcAny<T>,
@@ -95,7 +208,217 @@ impl<T: Anyable> CloneableIndexedReader<T> {
         index: Arc<Index>,
     ) -> CloneableIndexedReader<T> {
         let reader = IndexedReader::new(reader, index);
-        Self { data, reader }
+        Self {
+            data,
+            reader,
+            pos: 0,
+            version: 0,
+        }
+    }
+
+    /// Sets whether binary searches check that the lines are sorted first, see
+    /// `IndexedReader::set_check_sorted`. Clones keep the setting.
+    #[inline]
+    pub fn set_check_sorted(&mut self, check: bool) {
+        self.reader.set_check_sorted(check);
+    }
+
+    /// Builds a bloom filter of all lines, see `IndexedReader::build_bloom`
+    #[inline]
+    pub fn build_bloom(&mut self, fp_rate: f64) -> Result<()> {
+        self.build_bloom_by_key(fp_rate, |line| line)
+    }
+
+    /// Builds a bloom filter of the keys of all lines, see `IndexedReader::build_bloom_by_key`
+    pub fn build_bloom_by_key<K>(&mut self, fp_rate: f64, mut key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let mut bloom = BloomFilter::new(self.total_lines(), fp_rate)?;
+        let mut keyed = false;
+        self.for_each_line(|_, line| {
+            let line_key = key(line);
+            keyed |= line_key != line;
+            bloom.insert(line_key);
+            ControlFlow::Continue(())
+        })?;
+        bloom.set_keyed(keyed);
+
+        let mut index = Index::clone(self.get_index());
+        index.set_bloom(bloom);
+        // The data keeps its position behind an embedded index
+        index.set_len_bytes(self.get_index().len_bytes());
+        self.reader.index = Arc::new(index);
+        Ok(())
+    }
+
+    /// Builds a lookup table of the keys of all lines, see `IndexedReader::build_lookup_table`
+    pub fn build_lookup_table<K>(&mut self, key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let table = LookupTable::build(self, key)?;
+        let mut index = Index::clone(self.get_index());
+        index.set_lookup_table(table);
+        // The data keeps its position behind an embedded index
+        index.set_len_bytes(self.get_index().len_bytes());
+        self.reader.index = Arc::new(index);
+        Ok(())
+    }
+
+    /// Returns the amount of mutations like `push_line` or `reindex` applied to the reader. A
+    /// clone keeps the version and the data of the reader at the time it was cloned, so a clone
+    /// whose version differs from the original's holds an outdated snapshot.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Replaces the data with the unindexed `data` and builds a new index for it.
+    ///
+    /// Mutations never modify the shared data or index but replace them, so existing clones keep
+    /// reading their snapshot, see `version`.
+    pub fn reindex<U: Into<ArcAny<T>>>(&mut self, data: U) -> Result<()> {
+        let data = data.into();
+        let mut reader = BufReader::new(Cursor::new(data.clone()));
+        let index = Index::build_with_delimiter(&mut reader, self.get_index().delimiter())?;
+        self.replace_snapshot(data, Arc::new(index));
+        Ok(())
+    }
+
+    /// Appends `line` terminated by the delimiter to the data, which is modified by `append`.
+    /// The data is only copied if it's shared, for example with clones. `append` gets passed the
+    /// data, the range of the data section which has to be kept without the embedded header and
+    /// index and whether the last line needs to be terminated first.
+    fn append_line<F>(&mut self, line: &[u8], append: F) -> Result<()>
+    where
+        T: Default,
+        F: FnOnce(&mut T, Range<usize>, bool),
+    {
+        let delimiter = self.get_index().delimiter();
+        if line.contains(&delimiter) {
+            return Err(Error::ContainsNewline {
+                entry: self.total_lines(),
+            });
+        }
+
+        let section = self.data_section();
+        let data = &self.data.as_ref()[section.clone()];
+        let terminate =
+            data.len() as u64 > self.get_index().bom_len() && !data.ends_with(&[delimiter]);
+        let start = u32::try_from(data.len() + usize::from(terminate)).map_err(|_| {
+            Error::OffsetOverflow {
+                line: self.total_lines(),
+            }
+        })?;
+
+        // Offsets are relative to the data section, so they stay valid without the header
+        let mut index = Index::clone(self.get_index());
+        index.try_add(start)?;
+
+        // The reader shares the data as well, so it gets released before the data is modified
+        // in place. `replace_snapshot` creates a new reader afterwards.
+        drop(mem::replace(
+            self.reader.reader.get_mut(),
+            Cursor::new(T::default().into()),
+        ));
+        append(Arc::make_mut(&mut self.data.0), section, terminate);
+        self.replace_snapshot(self.data.clone(), Arc::new(index.zero_len()));
+        Ok(())
+    }
+
+    /// Replaces the data and the index without modifying the shared ones and increments the
+    /// version
+    fn replace_snapshot(&mut self, data: ArcAny<T>, index: Arc<Index>) {
+        self.reader.replace_source(Cursor::new(data.clone()), index);
+        self.data = data;
+        self.pos = 0;
+        self.version += 1;
+    }
+
+    /// Returns the bytes of `span` within the data section. Lines are read directly from the
+    /// data instead of going through the `Read`/`Seek` implementation of `reader`, so the cursor
+    /// is only used to write the data.
+    #[inline]
+    fn data_span(&self, span: Range<u64>, line: usize) -> Result<&[u8]> {
+        let offset = self.get_index_byte_len();
+        self.data
+            .as_ref()
+            .get(offset + span.start as usize..offset + span.end as usize)
+            .ok_or(Error::InvalidIndex { line })
+    }
+}
+
+impl CloneableIndexedReader<Vec<u8>> {
+    /// Appends `line` followed by the delimiter. The data is modified in place unless it's shared
+    /// with clones, which keep reading their snapshot, see `version`. Then the data gets copied.
+    /// An embedded index is removed from the data, since the index is held in memory.
+    ///
+    /// Returns `Error::ContainsNewline` if `line` contains the delimiter
+    pub fn push_line(&mut self, line: &[u8]) -> Result<()> {
+        let delimiter = self.get_index().delimiter();
+        self.append_line(line, |data, section, terminate| {
+            data.truncate(section.end);
+            data.drain(..section.start);
+            if terminate {
+                data.push(delimiter);
+            }
+            data.extend_from_slice(line);
+            data.push(delimiter);
+        })
+    }
+
+    /// Reads indexed data from a stream which can't seek, like a network connection, into ram.
+    /// Use `File::from_stream_to_file` for streams too large to fit into memory.
+    ///
+    /// Returns an error if the index is malformed, missing or an io error occurs
+    pub fn from_stream<R: Read>(mut r: R) -> Result<CloneableIndexedReader<Vec<u8>>> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        Self::new(data)
+    }
+
+    /// Reads unindexed data from a stream which can't seek into ram and builds an index.
+    pub fn from_stream_raw<R: Read>(mut r: R) -> Result<CloneableIndexedReader<Vec<u8>>> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        Self::new_raw(data)
+    }
+}
+
+impl CloneableIndexedReader<String> {
+    /// Appends `line` followed by the delimiter, see `CloneableIndexedReader<Vec<u8>>::push_line`.
+    ///
+    /// Returns `Error::UTF8Error` if the delimiter is not an ASCII character
+    pub fn push_line(&mut self, line: &str) -> Result<()> {
+        let delimiter = self.get_index().delimiter();
+        if !delimiter.is_ascii() {
+            return Err(Error::UTF8Error);
+        }
+        self.append_line(line.as_bytes(), |data, section, terminate| {
+            data.truncate(section.end);
+            data.drain(..section.start);
+            if terminate {
+                data.push(char::from(delimiter));
+            }
+            data.push_str(line);
+            data.push(char::from(delimiter));
+        })
+    }
+}
+
+impl<T: Anyable> CloneableIndexedReader<Region<T>> {
+    /// Reads the indexed data stored within `range` of `data`, like `new` does for the whole
+    /// data. The data is shared instead of being copied, so multiple indexed blobs packed into a
+    /// single buffer can be read independently.
+    ///
+    /// Returns an error if `range` is out of bounds, the index is malformed, missing or an io
+    /// error occurs
+    pub fn new_at(data: Arc<T>, range: Range<usize>) -> Result<Self> {
+        let region = Region::new(data, range).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the range exceeds the data")
+        })?;
+        Self::new(region)
     }
 }
 
@@ -109,30 +432,607 @@ impl<T: Anyable> Indexable for CloneableIndexedReader<T> {
 impl<T: Anyable> IndexableFile for CloneableIndexedReader<T> {
     #[inline]
     fn read_current_line(&mut self, buf: &mut Vec<u8>, line: usize) -> Result<usize> {
-        self.reader.read_current_line(buf, line)
+        let span = self.line_span(line)?;
+        let data = self.data_span(span.clone(), line)?;
+
+        buf.clear();
+        buf.extend_from_slice(data);
+        self.pos = self.data_start() + span.end;
+        Ok(buf.len())
     }
 
     #[inline]
     fn seek_line(&mut self, line: usize) -> Result<()> {
-        self.reader.seek_line(line)
+        let offset = self.get_offset(line)?;
+        self.reader.last_line = Some(line);
+        self.pos = self.data_start() + offset as u64;
+        Ok(())
+    }
+
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        self.reader.current_line()
     }
 
     #[inline]
+    fn checks_sorted(&self) -> bool {
+        self.reader.checks_sorted()
+    }
+
+    #[inline]
+    fn data_len(&self) -> u64 {
+        self.reader.data_len()
+    }
+
+    #[inline]
+    fn absolute_position(&mut self) -> Result<u64> {
+        Ok(self.pos)
+    }
+
+    #[inline]
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        let span = self.line_span(line)?;
+        let end = span.end.min(span.start.saturating_add(max as u64));
+
+        buf.clear();
+        buf.extend_from_slice(self.data_span(span.start..end, line)?);
+
+        self.reader.last_line = Some(line);
+        self.pos = self.data_start() + end;
+        Ok(buf.len())
+    }
+
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        buf.clear();
+
+        if lines.start >= lines.end {
+            return Ok(0);
+        }
+
+        let start = self.line_span(lines.start)?.start;
+        let end = self.line_span(lines.end - 1)?.end;
+        buf.extend_from_slice(self.data_span(start..end, lines.start)?);
+
+        self.reader.last_line = Some(lines.end - 1);
+        self.pos = self.data_start() + end;
+        Ok(buf.len())
+    }
+
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let span = self.line_span(line)?;
+        let start = span.end.min(span.start.saturating_add(offset));
+        let end = span.end.min(start + buf.len() as u64);
+        let data = self.data_span(start..end, line)?;
+        let len = data.len();
+        buf[..len].copy_from_slice(data);
+
+        self.reader.last_line = Some(line);
+        self.pos = self.data_start() + end;
+        Ok(len)
+    }
+
+    /// Writes the header, the index and the data in one pass. The data is in memory already, so
+    /// it is hashed directly instead of being copied through a temporary file.
     fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
-        self.reader.write_to(writer)
+        let data = &self.data.as_ref()[self.data_section()];
+        let index = self.get_index();
+        let mut header = index.get_header();
+        header.set_content_hash(hash_bytes(data));
+        let header = header.encode();
+
+        writer.write_all(&header)?;
+        let index_len = index.encode_into(writer)?;
+        writer.write_all(data)?;
+
+        self.reader.last_line = None;
+        Ok(header.len() + index_len + data.len())
     }
 }
 
 impl<T: Anyable> Clone for CloneableIndexedReader<T> {
-    /// Does not clone the entire text but the IndexedString and the Arc reference to the index
+    /// Does not clone the entire text but the IndexedString and the Arc reference to the index.
+    /// The shared index and data are never mutated, mutations replace them instead, while the
+    /// read position is owned by each clone. So clones can be read from different threads
+    /// concurrently and keep their snapshot while the original gets mutated.
     #[inline]
     fn clone(&self) -> Self {
         let new_arc = self.data.clone();
         Self {
             reader: self.reader.duplicate(Cursor::new(new_arc.clone())),
             data: new_arc,
+            pos: 0,
+            version: self.version,
         }
     }
 }
 
 impl<T: Anyable> ReadByLine for CloneableIndexedReader<T> {}
+
+impl<T: Anyable, U: Anyable> PartialEq<CloneableIndexedReader<U>> for CloneableIndexedReader<T> {
+    /// Compares the `content` of both readers. Whether the index is embedded into the data or
+    /// was built is irrelevant, and so is the delimiter.
+    #[inline]
+    fn eq(&self, other: &CloneableIndexedReader<U>) -> bool {
+        self.content() == other.content()
+    }
+}
+
+impl<T: Anyable> PartialEq<[u8]> for CloneableIndexedReader<T> {
+    /// Compares the `content` of the reader with `other`, so `other` doesn't contain an index
+    #[inline]
+    fn eq(&self, other: &[u8]) -> bool {
+        self.content() == other
+    }
+}
+
+impl PartialEq<str> for CloneableIndexedReader<String> {
+    /// Compares the `content` of the string with `other`, so `other` doesn't contain an index
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.content() == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for CloneableIndexedReader<String> {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+/// The serialized layout of `CloneableIndexedReader`, a struct with the fields
+///
+/// | field            | content                                                        |
+/// |------------------|----------------------------------------------------------------|
+/// | `data`           | the data as serialized by `T`, including an embedded index     |
+/// | `offsets`        | sequence of the line offsets as `u32`, relative to the data    |
+/// | `delimiter`      | the byte terminating the lines as `u8`                         |
+/// | `embedded_index` | `bool` telling whether `data` contains its header and index    |
+/// | `index`          | optional header and encoded index as sequence of `u8`          |
+///
+/// The length of an embedded index is never serialized. It's taken from the header within the
+/// data when deserializing. `index` is only written for indexes which aren't embedded and hold
+/// more than their offsets and delimiter, like a skipped BOM, a collation, line checksums, a
+/// bloom filter or a lookup table, so readers without them keep the layout of older versions.
+#[derive(Serialize)]
+#[serde(rename = "CloneableIndexedReader")]
+struct SerializedReader<'a, T> {
+    data: &'a T,
+    offsets: &'a [u32],
+    delimiter: u8,
+    embedded_index: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<Vec<u8>>,
+}
+
+/// Owned counterpart of `SerializedReader`
+#[derive(Deserialize)]
+#[serde(rename = "CloneableIndexedReader")]
+struct DeserializedReader<T> {
+    data: T,
+    offsets: Vec<u32>,
+    delimiter: u8,
+    embedded_index: bool,
+    #[serde(default)]
+    index: Option<Vec<u8>>,
+}
+
+impl<T: Anyable + Serialize> Serialize for CloneableIndexedReader<T> {
+    /// Serializes the data and the index, see `SerializedReader` for the layout
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let index = self.get_index();
+        let embedded_index = self.data_section() != (0..self.data.as_ref().len());
+        let has_metadata =
+            index.has_bom() || index.collation().is_some() || index.has_line_tables();
+        let encoded = (!embedded_index && has_metadata).then(|| {
+            let mut encoded = index.get_header().encode();
+            encoded.extend_from_slice(&index.encode());
+            encoded
+        });
+
+        SerializedReader {
+            data: &**self.data.as_arc(),
+            offsets: index.offsets(),
+            delimiter: index.delimiter(),
+            embedded_index,
+            index: encoded,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Anyable + Deserialize<'de>> Deserialize<'de> for CloneableIndexedReader<T> {
+    /// Deserializes a reader serialized by `Serialize`. Embedded indexes are parsed from the
+    /// data and serialized indexes are decoded, both have to match the serialized offsets.
+    /// Offsets which are not strictly increasing are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let wire = DeserializedReader::<T>::deserialize(deserializer)?;
+
+        if let Some(line) = first_unordered(&wire.offsets) {
+            return Err(de::Error::custom(Error::InvalidIndex { line }));
+        }
+
+        if wire.embedded_index {
+            let reader = Self::new(wire.data).map_err(de::Error::custom)?;
+            let index = reader.get_index();
+            if index.offsets() != &wire.offsets[..] || index.delimiter() != wire.delimiter {
+                return Err(de::Error::custom(Error::MalformedIndex));
+            }
+            return Ok(reader);
+        }
+
+        let index = match wire.index {
+            Some(encoded) => {
+                let mut encoded = Cursor::new(encoded);
+                let header = Header::decode(&mut encoded).map_err(de::Error::custom)?;
+                let index = Index::decode(&mut encoded, &header).map_err(de::Error::custom)?;
+                if index.offsets() != &wire.offsets[..] || index.delimiter() != wire.delimiter {
+                    return Err(de::Error::custom(Error::MalformedIndex));
+                }
+                index.zero_len()
+            }
+            None => {
+                let mut index = Index::new(wire.offsets).zero_len();
+                index.set_delimiter(wire.delimiter);
+                index
+            }
+        };
+        Ok(Self::new_custom(wire.data, Arc::new(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{temp::TempFile, verify::HashStatus, IndexedString, IndexedWriter};
+    use std::thread;
+
+    #[test]
+    fn test_arc_any() {
+        let data = Arc::new(String::from("a\nb\n"));
+        let arc = ArcAny::from(Arc::clone(&data));
+        assert!(Arc::ptr_eq(arc.as_arc(), &data));
+        assert_eq!(Arc::strong_count(&data), 2);
+
+        // Readers share the data instead of copying it
+        let reader = IndexedString::new_raw(arc.clone()).unwrap();
+        assert!(Arc::ptr_eq(reader.data.as_arc(), &data));
+        drop(reader);
+
+        assert!(Arc::ptr_eq(&arc.into_inner(), &data));
+        assert_eq!(Arc::strong_count(&data), 1);
+
+        let from_cow = ArcAny::from(Cow::Borrowed("a\nb\n"));
+        assert_eq!(from_cow.as_ref(), data.as_bytes());
+    }
+
+    #[test]
+    fn test_serde() {
+        // The layout must stay stable, so the fixture has to keep deserializing
+        let fixture = include_str!("../testfiles/serde_indexed_string.json").trim_end();
+        let reader = IndexedString::new_raw("first\nsecond\n\nlast").unwrap();
+        assert_eq!(serde_json::to_string(&reader).unwrap(), fixture);
+
+        let mut decoded: IndexedString = serde_json::from_str(fixture).unwrap();
+        assert_eq!(decoded.get_index(), reader.get_index());
+        assert_eq!(decoded.get_index_byte_len(), 0);
+        assert_eq!(decoded.read_line(3).unwrap(), "last");
+
+        // Readers with an embedded index rebuild the length of the index from the data
+        let mut indexed = Vec::new();
+        reader.clone().write_to(&mut indexed).unwrap();
+        let reader = CloneableIndexedReader::<Vec<u8>>::new(indexed).unwrap();
+        let json = serde_json::to_string(&reader).unwrap();
+        let mut decoded: CloneableIndexedReader<Vec<u8>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.get_index_byte_len(), reader.get_index_byte_len());
+        assert_eq!(decoded.read_line(1).unwrap(), "second\n");
+
+        let unordered = r#"{"data":"a\nb","offsets":[2,0],"delimiter":10,"embedded_index":false}"#;
+        assert!(serde_json::from_str::<IndexedString>(unordered).is_err());
+        let mismatch = json.replace(r#""offsets":[0,"#, r#""offsets":[1,"#);
+        assert!(serde_json::from_str::<CloneableIndexedReader<Vec<u8>>>(&mismatch).is_err());
+    }
+
+    #[test]
+    fn test_serde_index_metadata() {
+        let data = "\u{feff}a\nb\nc\n";
+        let mut index = crate::Builder::new()
+            .skip_bom(true)
+            .build_index(&mut BufReader::new(Cursor::new(data)))
+            .unwrap();
+        index.set_collation(Some(crate::Collation::CaselessAscii));
+        index.set_line_checksums(
+            ["a\n", "b\n", "c\n"]
+                .iter()
+                .map(|i| crate::index::line_checksum(i.as_bytes()))
+                .collect(),
+        );
+        let mut reader = IndexedString::new_custom(data, Arc::new(index.zero_len()));
+        reader.build_lookup_table(|line| line).unwrap();
+
+        let json = serde_json::to_string(&reader).unwrap();
+        let mut decoded: IndexedString = serde_json::from_str(&json).unwrap();
+        assert!(decoded == reader);
+        assert_eq!(decoded.get_index(), reader.get_index());
+        assert!(decoded.get_index().has_bom());
+        assert_eq!(
+            decoded.get_index().collation(),
+            Some(crate::Collation::CaselessAscii)
+        );
+        assert_eq!(
+            decoded.get_index().line_checksums(),
+            reader.get_index().line_checksums()
+        );
+        assert!(decoded.get_index().lookup_table().is_some());
+        assert_eq!(decoded.content(), b"a\nb\nc\n");
+        assert_eq!(decoded.read_line(0).unwrap(), "a\n");
+
+        // The serialized index has to match the offsets
+        let mismatch = json.replace(r#""offsets":[3,"#, r#""offsets":[4,"#);
+        assert!(serde_json::from_str::<IndexedString>(&mismatch).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_clones() {
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {}", i)).collect();
+        let reader = IndexedString::new_raw(lines.join("\n")).unwrap();
+        let lines = Arc::new(lines);
+
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let mut reader = reader.clone();
+                let lines = Arc::clone(&lines);
+                thread::spawn(move || {
+                    // Mix sequential and random reads so every thread moves its position
+                    // differently
+                    for i in 0..5000 {
+                        let line = (i * (t * 2 + 1) + t) % lines.len();
+                        let read = reader.read_line(line).unwrap();
+                        assert_eq!(read.trim_end_matches('\n'), lines[line]);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // The original reader must be unaffected by its clones
+        let mut reader = reader;
+        assert_eq!(reader.current_line(), None);
+        assert_eq!(reader.read_line(0).unwrap(), "line 0\n");
+    }
+
+    #[test]
+    fn test_snapshot_clones() {
+        let mut reader = IndexedString::new_raw("line 0").unwrap();
+        let snapshot = reader.clone();
+        reader.push_line("line 1").unwrap();
+        assert_eq!(reader.version(), 1);
+        assert_eq!(reader.content(), b"line 0\nline 1\n");
+        assert_eq!(snapshot.version(), 0);
+        assert_eq!(snapshot.content(), b"line 0");
+        assert!(matches!(
+            reader.push_line("a\nb"),
+            Err(Error::ContainsNewline { entry: 2 })
+        ));
+
+        // Readers with an embedded index drop it when being mutated
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let mut bytes = CloneableIndexedReader::<Vec<u8>>::new(indexed).unwrap();
+        bytes.push_line(b"line 2").unwrap();
+        assert_eq!(bytes.get_index_byte_len(), 0);
+        assert_eq!(bytes.content(), b"line 0\nline 1\nline 2\n");
+        assert_eq!(bytes.read_line(2).unwrap(), "line 2\n");
+
+        // Data which isn't shared is modified in place
+        let data = Arc::as_ptr(reader.data.as_arc());
+        for i in 2..100 {
+            reader.push_line(&format!("line {}", i)).unwrap();
+        }
+        assert_eq!(Arc::as_ptr(reader.data.as_arc()), data);
+        assert_eq!(reader.read_line(99).unwrap(), "line 99\n");
+
+        reader.reindex("a\nb\nc\n").unwrap();
+        assert_eq!(reader.version(), 100);
+        assert_eq!(reader.total_lines(), 3);
+        assert_eq!(snapshot.total_lines(), 1);
+
+        // Clones taken at any time keep reading consistent snapshots while the original is
+        // mutated on another thread
+        let mut reader = IndexedString::new_raw("").unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let writer = thread::spawn(move || {
+            for i in 0..200 {
+                reader.push_line(&format!("line {}", i)).unwrap();
+                if i % 25 == 0 {
+                    sender.send(reader.clone()).unwrap();
+                }
+            }
+        });
+
+        let handles: Vec<_> = receiver
+            .into_iter()
+            .map(|mut clone| {
+                thread::spawn(move || {
+                    let lines = clone.version() as usize;
+                    for _ in 0..20 {
+                        assert_eq!(clone.total_lines(), lines);
+                        for line in (0..lines).rev() {
+                            let read = clone.read_line(line).unwrap();
+                            assert_eq!(read, format!("line {}\n", line));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    /// A stream which can only be read, like a network connection
+    struct Stream(Cursor<Vec<u8>>);
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            // Return short reads to make sure the whole stream gets read
+            let len = buf.len().min(7);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_from_stream() {
+        let data = std::fs::read("./testfiles/LICENSE").unwrap();
+        let mut raw = CloneableIndexedReader::<Vec<u8>>::new_raw(data.clone()).unwrap();
+        let mut indexed = Vec::new();
+        raw.write_to(&mut indexed).unwrap();
+
+        let mut streamed =
+            CloneableIndexedReader::from_stream(Stream(Cursor::new(indexed.clone()))).unwrap();
+        let mut streamed_raw =
+            CloneableIndexedReader::from_stream_raw(Stream(Cursor::new(data.clone()))).unwrap();
+        assert_eq!(streamed.get_index(), raw.get_index());
+        assert_eq!(streamed_raw.get_index(), raw.get_index());
+
+        let temp = TempFile::new("stream");
+        let path = temp.path();
+        let mut file =
+            crate::File::from_stream_to_file(Stream(Cursor::new(indexed)), path).unwrap();
+        assert_eq!(file.get_index(), raw.get_index());
+
+        for line in 0..raw.total_lines() {
+            let expected = raw.read_line(line).unwrap();
+            assert_eq!(streamed.read_line(line).unwrap(), expected);
+            assert_eq!(streamed_raw.read_line(line).unwrap(), expected);
+            assert_eq!(file.read_line(line).unwrap(), expected);
+        }
+
+        // Non indexed data can't be opened as indexed stream
+        assert!(CloneableIndexedReader::from_stream(Stream(Cursor::new(data.clone()))).is_err());
+        assert!(crate::File::from_stream_to_file(Stream(Cursor::new(data)), path).is_err());
+    }
+
+    #[test]
+    fn test_write_to_in_memory() {
+        let data = "\u{FEFF}a\nb\nc";
+        let index = crate::Builder::new()
+            .skip_bom(true)
+            .build_index(&mut BufReader::new(Cursor::new(data)))
+            .unwrap();
+        let mut with_bom = IndexedString::new_custom(data, Arc::new(index.zero_len()));
+        let mut embedded = Vec::new();
+        IndexedString::new_raw("a\nb\n")
+            .unwrap()
+            .write_to(&mut embedded)
+            .unwrap();
+        let mut embedded = CloneableIndexedReader::<Vec<u8>>::new(embedded).unwrap();
+
+        // The output matches the one written by copying the data through the reader
+        let mut written = Vec::new();
+        let len = with_bom.clone().write_to(&mut written).unwrap();
+        assert_eq!(len, written.len());
+        let mut expected = Vec::new();
+        with_bom.reader.write_to(&mut expected).unwrap();
+        assert_eq!(written, expected);
+
+        let mut written = Vec::new();
+        embedded.clone().write_to(&mut written).unwrap();
+        let mut expected = Vec::new();
+        embedded.reader.write_to(&mut expected).unwrap();
+        assert_eq!(written, expected);
+
+        let mut reopened = CloneableIndexedReader::<Vec<u8>>::new(written).unwrap();
+        assert_eq!(reopened, embedded);
+        assert_eq!(
+            reopened.reader.verify().unwrap().content_hash,
+            HashStatus::Valid
+        );
+    }
+
+    #[test]
+    fn test_with_data() {
+        let reader = IndexedString::new_raw("ab\ncd\nef").unwrap();
+        let mut upper = reader.with_data("AB\nCD\nEF").unwrap();
+        assert!(Arc::ptr_eq(&upper.index_arc(), &reader.index_arc()));
+        assert_eq!(upper.read_line(1).unwrap(), "CD\n");
+        assert_eq!(upper.read_line(2).unwrap(), "EF");
+
+        assert!(matches!(
+            reader.with_data("ab\ncd\nefg"),
+            Err(Error::LengthMismatch {
+                expected: 8,
+                actual: 9
+            })
+        ));
+
+        // Data containing an index keeps it in front of the lines
+        let mut indexed = Vec::new();
+        reader.clone().write_to(&mut indexed).unwrap();
+        let reader = CloneableIndexedReader::<Vec<u8>>::new(indexed.clone()).unwrap();
+        let len = indexed.len();
+        indexed[len - 2..].copy_from_slice(b"EF");
+        let mut changed = reader.with_data(indexed).unwrap();
+        assert_eq!(changed.read_line(0).unwrap(), "ab\n");
+        assert_eq!(changed.read_line(2).unwrap(), "EF");
+        assert_eq!(changed.get_index_byte_len(), reader.get_index_byte_len());
+
+        // Tables and hashes of the old content are dropped
+        let mut reader = IndexedWriter::new(Vec::new());
+        reader.write_all(b"ab\ncd\nef\n").unwrap();
+        let mut reader = CloneableIndexedReader::new(reader.finish().unwrap()).unwrap();
+        reader.build_bloom(0.01).unwrap();
+        reader.build_lookup_table(|line| line).unwrap();
+        let mut data = reader.data.as_ref().to_vec();
+        data[..8].copy_from_slice(b"AB\nCD\nEF");
+        let mut upper = reader.with_data(data).unwrap();
+        assert!(upper.get_index().bloom().is_none());
+        assert!(upper.get_index().lookup_table().is_none());
+        assert_eq!(upper.get_index().offsets(), reader.get_index().offsets());
+        assert_eq!(upper.find_exact(b"CD").unwrap(), Some(1));
+        assert_eq!(
+            upper.reader.verify().unwrap().content_hash,
+            HashStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_eq_content() {
+        let text = "first\nsecond\n";
+        let reader = IndexedString::new_raw(text).unwrap();
+        assert_eq!(reader, text);
+        assert_eq!(reader, *text);
+        assert_eq!(reader, *text.as_bytes());
+        assert_ne!(reader, "first\nsecond");
+        assert_ne!(reader, IndexedString::new_raw("first\n").unwrap());
+
+        // Embedded indexes are not part of the content
+        let mut indexed = Vec::new();
+        reader.clone().write_to(&mut indexed).unwrap();
+        let embedded = CloneableIndexedReader::<Vec<u8>>::new(indexed.clone()).unwrap();
+        assert_eq!(embedded.content(), text.as_bytes());
+        assert_eq!(embedded, reader);
+        assert_eq!(reader, embedded);
+        assert_ne!(embedded, *indexed);
+
+        // Neither is a skipped BOM
+        let data = format!("\u{FEFF}{}", text);
+        let mut cursor = BufReader::new(Cursor::new(data.clone()));
+        let index = crate::Builder::new()
+            .skip_bom(true)
+            .build_index(&mut cursor)
+            .unwrap();
+        let with_bom = IndexedString::new_custom(data, Arc::new(index.zero_len()));
+        assert_eq!(with_bom, text);
+        assert_eq!(with_bom, reader);
+
+        let empty = IndexedString::new_raw("").unwrap();
+        assert_eq!(empty, "");
+        assert_eq!(empty.content(), b"");
+    }
+}
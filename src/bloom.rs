@@ -0,0 +1,330 @@
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{error::Error, hash_bytes, Result};
+
+/// Smallest amount of 64 bit words of a filter
+const MIN_WORDS: usize = 1;
+
+/// Largest amount of hash functions of a filter
+const MAX_HASHES: u32 = 32;
+
+/// A bloom filter over the keys of the lines, which tells for sure that a key is not contained
+/// in a file without searching it. See `IndexedReader::build_bloom` and
+/// `ReadByLine::binary_search_with_bloom`.
+///
+/// The filter is stored behind the offsets and line checksums of the index and is encoded as
+/// follows, using little endian for all numbers:
+///
+/// | bytes | content                          |
+/// |-------|----------------------------------|
+/// | 4     | amount of hash functions         |
+/// | 1     | 1 for a filter of keys, else 0   |
+/// | 8     | amount of 64 bit words           |
+/// | 8 * n | the bits of the filter as words  |
+///
+/// The bit positions of a key are derived from its 64 bit FNV-1a hash using double hashing.
+/// Filters built by `IndexedReader::build_bloom_by_key` are marked as filters of keys, since
+/// they can't tell whether a whole line is missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    words: Vec<u64>,
+    hashes: u32,
+    /// Whether the filter holds keys other than the whole lines
+    keyed: bool,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `items` keys with a false positive rate of `fp_rate`.
+    ///
+    /// Returns `Error::InvalidFalsePositiveRate` unless `0 < fp_rate < 1`
+    pub fn new(items: usize, fp_rate: f64) -> Result<Self> {
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(Error::InvalidFalsePositiveRate);
+        }
+
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(items.max(1) as f64) * fp_rate.ln() / (ln2 * ln2)).ceil();
+        let words = ((bits / 64.0).ceil() as usize).max(MIN_WORDS);
+        let hashes = ((words * 64) as f64 / items.max(1) as f64 * ln2).round() as u32;
+
+        Ok(Self {
+            words: vec![0; words],
+            hashes: hashes.clamp(1, MAX_HASHES),
+            keyed: false,
+        })
+    }
+
+    /// Returns `true` if the filter holds keys other than the whole lines, see
+    /// `IndexedReader::build_bloom_by_key`
+    #[inline]
+    pub fn is_keyed(&self) -> bool {
+        self.keyed
+    }
+
+    /// Sets whether the filter holds keys other than the whole lines
+    #[inline]
+    pub(crate) fn set_keyed(&mut self, keyed: bool) {
+        self.keyed = keyed;
+    }
+
+    /// Adds `key` to the filter
+    #[inline]
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.bits(key) {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `false` if `key` was never inserted. `true` means that `key` was probably
+    /// inserted.
+    #[inline]
+    pub fn maybe_contains(&self, key: &[u8]) -> bool {
+        self.bits(key)
+            .all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the positions of the bits of `key`
+    #[inline]
+    fn bits(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let len = (self.words.len() * 64) as u64;
+        let first = hash_bytes(key);
+        // An odd step visits different bits for every hash function
+        let step = mix(first) | 1;
+        (0..u64::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+
+    /// Returns the length of the encoded filter in bytes
+    #[inline]
+    pub(crate) fn encoded_len(&self) -> usize {
+        13 + self.words.len() * 8
+    }
+
+    /// Writes the encoded filter into `w`
+    pub(crate) fn encode_into<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&self.hashes.to_le_bytes())?;
+        w.write_all(&[u8::from(self.keyed)])?;
+        w.write_all(&(self.words.len() as u64).to_le_bytes())?;
+        for word in &self.words {
+            w.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a filter written by `encode_into`
+    pub(crate) fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut hashes = [0u8; 4];
+        reader.read_exact(&mut hashes)?;
+        let hashes = u32::from_le_bytes(hashes);
+
+        let mut keyed = [0u8; 1];
+        reader.read_exact(&mut keyed)?;
+        let keyed = match keyed[0] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedIndex),
+        };
+
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        let len = usize::try_from(u64::from_le_bytes(len)).map_err(|_| Error::MalformedIndex)?;
+        if hashes == 0 || hashes > MAX_HASHES || len < MIN_WORDS {
+            return Err(Error::MalformedIndex);
+        }
+
+        // The length isn't trusted for allocating, truncated data fails while reading
+        let mut words = Vec::with_capacity(len.min(1 << 16));
+        let mut word = [0u8; 8];
+        for _ in 0..len {
+            reader.read_exact(&mut word)?;
+            words.push(u64::from_le_bytes(word));
+        }
+
+        Ok(Self {
+            words,
+            hashes,
+            keyed,
+        })
+    }
+}
+
+impl Serialize for BloomFilter {
+    /// Serializes the filter as its encoded bytes, see `BloomFilter`
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut encoded = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut encoded)
+            .map_err(serde::ser::Error::custom)?;
+        encoded.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BloomFilter {
+    /// Deserializes the encoded bytes of a filter, rejecting malformed filters like `decode`
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let encoded = Vec::<u8>::deserialize(deserializer)?;
+        let mut reader = encoded.as_slice();
+        let filter = Self::decode(&mut reader).map_err(de::Error::custom)?;
+        if !reader.is_empty() {
+            return Err(de::Error::custom(Error::MalformedIndex));
+        }
+        Ok(filter)
+    }
+}
+
+/// The finalizer of SplitMix64, deriving a second hash from the first one
+#[inline]
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::CloneableIndexedReader, temp::TempFile, File, Indexable, IndexableFile, ReadByLine,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_filter() {
+        assert!(matches!(
+            BloomFilter::new(10, 0.0),
+            Err(Error::InvalidFalsePositiveRate)
+        ));
+        assert!(BloomFilter::new(10, 1.0).is_err());
+        assert!(BloomFilter::new(10, f64::NAN).is_err());
+
+        let mut filter = BloomFilter::new(1000, 0.01).unwrap();
+        for i in 0..1000 {
+            filter.insert(format!("key {}", i).as_bytes());
+        }
+        assert!((0..1000).all(|i| filter.maybe_contains(format!("key {}", i).as_bytes())));
+        let false_positives = (0..10_000)
+            .filter(|i| filter.maybe_contains(format!("missing {}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+
+        let mut encoded = Vec::new();
+        filter.encode_into(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), filter.encoded_len());
+        assert_eq!(&encoded[..4], &filter.hashes.to_le_bytes());
+        assert_eq!(encoded[4], 0);
+        let decoded = BloomFilter::decode(&mut Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoded, filter);
+        assert!(BloomFilter::decode(&mut Cursor::new(&encoded[..encoded.len() - 1])).is_err());
+        encoded[4] = 2;
+        assert!(matches!(
+            BloomFilter::decode(&mut Cursor::new(&encoded)),
+            Err(Error::MalformedIndex)
+        ));
+        encoded[4] = 1;
+        assert!(BloomFilter::decode(&mut Cursor::new(&encoded))
+            .unwrap()
+            .is_keyed());
+        encoded[..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            BloomFilter::decode(&mut Cursor::new(&encoded)),
+            Err(Error::MalformedIndex)
+        ));
+
+        // Empty filters contain nothing
+        let empty = BloomFilter::new(0, 0.5).unwrap();
+        assert!(!empty.maybe_contains(b""));
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut filter = BloomFilter::new(10, 0.01).unwrap();
+        filter.insert(b"key");
+        filter.set_keyed(true);
+        let json = serde_json::to_string(&filter).unwrap();
+        let decoded: BloomFilter = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, filter);
+        let trailing = json.replace(']', ",0]");
+        assert!(serde_json::from_str::<BloomFilter>(&trailing).is_err());
+
+        // Filters without words would divide by zero when looking up keys
+        let mut empty = Vec::new();
+        empty.extend(1u32.to_le_bytes());
+        empty.push(0);
+        empty.extend(0u64.to_le_bytes());
+        let json = serde_json::to_string(&empty).unwrap();
+        assert!(serde_json::from_str::<BloomFilter>(&json).is_err());
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+        assert!(file.maybe_contains(b"anything"));
+        file.build_bloom(0.01).unwrap();
+        let bloom = file.get_index().bloom().unwrap().clone();
+
+        let temp = TempFile::new("bloom");
+        file.write_to(&mut std::fs::File::create(temp.path()).unwrap())
+            .unwrap();
+        let mut reopened = File::open(temp.path()).unwrap();
+        assert_eq!(reopened.get_index().bloom(), Some(&bloom));
+        assert_eq!(reopened.get_index(), file.get_index());
+
+        let mut line = Vec::new();
+        for i in 0..reopened.total_lines() {
+            let read = reopened.read_line_raw(i, &mut line).unwrap();
+            let key = line[..read]
+                .strip_suffix(b"\n")
+                .unwrap_or(&line[..read])
+                .to_vec();
+            assert!(reopened.maybe_contains(&key));
+        }
+        assert!(!reopened.maybe_contains(b"not a line of the license"));
+        assert!(matches!(
+            reopened.binary_search_with_bloom(b"not a line of the license"),
+            Err(Error::NotFound)
+        ));
+
+        // Rebuilding the filter keeps the data behind the embedded index readable
+        let first = reopened.read_line(0).unwrap();
+        let data_start = reopened.data_start();
+        reopened.build_bloom(0.01).unwrap();
+        assert_eq!(reopened.data_start(), data_start);
+        assert_eq!(reopened.read_line(0).unwrap(), first);
+
+        // Appending lines invalidates the filter since it doesn't know them
+        let mut index = reopened.get_index().clone();
+        index.add(u32::MAX);
+        assert_eq!(index.bloom(), None);
+    }
+
+    #[test]
+    fn test_key_bloom() {
+        let mut file = crate::IndexedString::new_raw("a=1\nb=2\nd=4\n").unwrap();
+        file.build_bloom_by_key(0.01, |line| line.split(|i| *i == b'=').next().unwrap())
+            .unwrap();
+        assert!(file.maybe_contains(b"b"));
+        assert!(!file.maybe_contains(b"c"));
+        assert!(!file.maybe_contains(b"b=2"));
+        assert!(file.get_index().bloom().unwrap().is_keyed());
+
+        // Lines missing from a filter of keys are still searched
+        assert_eq!(file.binary_search_with_bloom(b"b=2").unwrap(), 1);
+
+        // The filter is stored in the embedded index as well
+        let mut written = Vec::new();
+        file.write_to(&mut written).unwrap();
+        let mut parsed = CloneableIndexedReader::new(written).unwrap();
+        assert_eq!(parsed.read_line(2).unwrap(), "d=4\n");
+        assert!(!parsed.maybe_contains(b"c"));
+        assert!(parsed.get_index().bloom().unwrap().is_keyed());
+        assert_eq!(parsed.binary_search_with_bloom(b"d=4").unwrap(), 2);
+
+        // Keys equal to the whole lines build a filter of lines
+        file.build_bloom_by_key(0.01, |line| line).unwrap();
+        assert!(!file.get_index().bloom().unwrap().is_keyed());
+    }
+}
@@ -1,19 +1,71 @@
-use crate::{index::Index, Indexable, IndexableFile};
+use crate::{
+    bloom::BloomFilter,
+    error::Error,
+    hash_reader,
+    index::{line_checksum, DataSection, Index},
+    lookup::LookupTable,
+    observer::ReadObserver,
+    verify::{self, VerifyReport},
+    HashingWriter, Indexable, IndexableFile,
+};
 use crate::{ReadByLine, Result};
 
 use std::{
     io::{self, prelude::*, BufReader, Read, SeekFrom, Write},
+    ops::{ControlFlow, Range},
     sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
+/// Capacity of the buffer used by `IndexedReader::new`
+pub(crate) const DEFAULT_BUF_CAPACITY: usize = 8 * 1024;
+
+/// Amount of consecutive sequential reads after which the automatic readahead starts
+const AUTO_READAHEAD_AFTER: usize = 32;
+
+/// Amount of bytes read ahead by the automatic readahead
+const AUTO_READAHEAD_LEN: usize = 256 * 1024;
+
+/// Amount of bytes read at once while a read deadline is set. The deadline is checked between
+/// these chunks.
+const DEADLINE_CHUNK_LEN: usize = 64 * 1024;
+
 /// A wrapper around `BufReader<R>` which implements `ReadByLine` and holds an index of the
 /// lines.
 #[derive(Debug)]
 pub struct IndexedReader<R: Read + Unpin + Seek + Send> {
     pub reader: BufReader<R>,
     pub(crate) index: Arc<Index>,
+    /// The line the reader was last positioned at
     pub(crate) last_line: Option<usize>,
-    pub(crate) curr_pos: u64,
+    /// Absolute position of `reader` or `None` if unknown
+    pub(crate) curr_pos: Option<u64>,
+    /// Length of the data section in bytes
+    pub(crate) data_len: u64,
+    /// Hash of the data section if its header is not in front of it, see `set_data_section`
+    pub(crate) content_hash: Option<u64>,
+    /// Lines longer than this are not read
+    max_line_len: Option<usize>,
+    /// Whether binary searches check that the lines are sorted first
+    check_sorted: bool,
+    /// How often and after which delay reading an incomplete line is retried
+    incomplete_retry: (usize, Duration),
+    /// Maximum duration of reading a single line
+    read_deadline: Option<Duration>,
+    /// Amount of bytes to read ahead or `None` to read ahead automatically
+    readahead: Option<usize>,
+    /// Amount of consecutive reads which didn't require a seek
+    sequential_reads: usize,
+    /// Absolute position of the first byte of `ahead`
+    ahead_start: u64,
+    /// Buffer holding the lines which have been read ahead in its first `ahead_len` bytes. While
+    /// lines have been read ahead, `reader` is positioned at their end and `curr_pos` is the
+    /// position within them.
+    ahead: Vec<u8>,
+    ahead_len: usize,
+    /// Receives events about reads and seeks
+    observer: Option<Box<dyn ReadObserver>>,
 }
 
 impl<R: Read + Unpin + Seek + Send> IndexedReader<R> {
@@ -22,37 +74,617 @@ impl<R: Read + Unpin + Seek + Send> IndexedReader<R> {
     /// useless.
     #[inline]
     pub fn new(reader: R, index: Arc<Index>) -> IndexedReader<R> {
+        Self::with_capacity(DEFAULT_BUF_CAPACITY, reader, index)
+    }
+
+    /// Creates a new `IndexedBufReader` like `new` whose buffer holds `capacity` bytes.
+    ///
+    /// Lines which are larger than the buffer are read directly into the line buffer, so long
+    /// lines don't require a larger capacity. Reading JSON lines with records of 100 KiB is about
+    /// 40% slower with a 256 KiB buffer than with the default 8 KiB, since every line gets copied
+    /// through the buffer. A larger capacity pays off for sequential scans of many short lines,
+    /// which are about 25% faster for 1 KiB lines with a 256 KiB buffer. Random reads fill the
+    /// whole buffer on every seek, so they should use a small capacity.
+    pub fn with_capacity(capacity: usize, reader: R, index: Arc<Index>) -> IndexedReader<R> {
+        let mut reader = BufReader::with_capacity(capacity, reader);
+
+        // The data section is everything behind the index
+        let data_len = reader
+            .seek(SeekFrom::End(0))
+            .map(|end| end.saturating_sub(index.len_bytes() as u64))
+            .unwrap_or(0);
+        let curr_pos = reader.seek(SeekFrom::Start(0)).ok();
+
         Self {
             index,
-            reader: BufReader::new(reader),
+            reader,
             last_line: None,
-            curr_pos: 0,
+            curr_pos,
+            data_len,
+            content_hash: None,
+            max_line_len: None,
+            check_sorted: false,
+            incomplete_retry: (0, Duration::ZERO),
+            read_deadline: None,
+            readahead: None,
+            sequential_reads: 0,
+            ahead_start: 0,
+            ahead: Vec::new(),
+            ahead_len: 0,
+            observer: None,
         }
     }
 
     /// Creates a new `IndexedBufReader` with the current index. `reader` should contain the same
-    /// data used in `&self` or the index might be invalid for the given reader
+    /// data used in `&self` or the index might be invalid for the given reader. The new reader
+    /// uses the same buffer capacity, data length and settings but has no observer.
     #[inline]
     pub fn duplicate(&self, reader: R) -> Self {
-        Self::new(reader, Arc::clone(&self.index))
+        let mut new = Self::with_capacity(self.reader.capacity(), reader, Arc::clone(&self.index));
+        new.data_len = self.data_len;
+        new.content_hash = self.content_hash;
+        new.max_line_len = self.max_line_len;
+        new.check_sorted = self.check_sorted;
+        new.incomplete_retry = self.incomplete_retry;
+        new.read_deadline = self.read_deadline;
+        new.readahead = self.readahead;
+        new
+    }
+
+    /// Replaces the underlying reader and the index, keeping all settings and the observer
+    pub(crate) fn replace_source(&mut self, reader: R, index: Arc<Index>) {
+        let mut new = Self::with_capacity(self.reader.capacity(), reader, index);
+        new.max_line_len = self.max_line_len;
+        new.check_sorted = self.check_sorted;
+        new.incomplete_retry = self.incomplete_retry;
+        new.read_deadline = self.read_deadline;
+        new.readahead = self.readahead;
+        new.observer = self.observer.take();
+        *self = new;
+    }
+
+    /// Limits the data section to `len` bytes behind the index, so data following it is never
+    /// read. This allows reading indexed data embedded in a larger container, followed by other
+    /// sections. By default the data section extends to the end of the reader.
+    #[inline]
+    pub fn set_data_len(&mut self, len: u64) {
+        self.data_len = len;
+        self.ahead_len = 0;
+    }
+
+    /// Limits the data section to the data in front of a header and index stored behind it and
+    /// keeps the hash of their header, so `verify` can check the data
+    #[inline]
+    pub(crate) fn set_data_section(&mut self, section: DataSection) {
+        self.set_data_len(section.len);
+        self.content_hash = section.header.content_hash();
     }
 
-    /// Read the `IndexedBufReader` into a newly allocated string
+    /// Reads the whole data section into `buf`, replacing its content. The header, the index and
+    /// a skipped BOM are not included. Returns the amount of bytes read, which is 0 for empty
+    /// data.
     #[inline]
     pub fn read_all(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
-        let start = self.get_index().get(0)?;
-        self.reader.seek(SeekFrom::Start(start as u64))?;
+        self.forget_position();
+        let bom_len = self.index.bom_len();
+        self.reader
+            .seek(SeekFrom::Start(self.data_start() + bom_len))?;
 
         if !buf.is_empty() {
             buf.clear();
         }
-        Ok(self.reader.read_to_end(buf)?)
+        let len = self.data_len.saturating_sub(bom_len);
+        Ok(self.reader.by_ref().take(len).read_to_end(buf)?)
+    }
+
+    /// Verifies the data and the index, see `File::verify`
+    pub fn verify(&mut self) -> Result<VerifyReport> {
+        self.forget_position();
+        verify::verify_data(
+            &mut self.reader,
+            &self.index,
+            self.data_len,
+            self.content_hash,
+        )
+    }
+
+    /// Returns `Error::UTF8Error` unless the data section is valid UTF-8 and every line starts at
+    /// a character boundary, so every single line is valid UTF-8 too. Only the `data_len` bytes
+    /// covered by the index are validated, so the lines have to start in ascending order within
+    /// them.
+    pub(crate) fn validate_utf8(&mut self) -> Result<()> {
+        let offsets = self.index.offsets();
+        if offsets.windows(2).any(|i| i[0] > i[1])
+            || offsets.last().is_some_and(|i| *i as u64 > self.data_len)
+        {
+            return Err(Error::UTF8Error);
+        }
+
+        self.forget_position();
+        self.reader.seek(SeekFrom::Start(self.data_start()))?;
+        let mut data = self.reader.by_ref().take(self.data_len);
+
+        let mut offsets = self.index.offsets().iter().map(|i| *i as u64).peekable();
+        let mut chunk = vec![0u8; 64 * 1024];
+        // Validated bytes are removed, so this only holds a character split between two chunks
+        let mut pending = Vec::new();
+        let mut pos = 0u64;
+
+        loop {
+            let read = match data.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            // Continuation bytes can't start a character
+            while let Some(offset) = offsets.next_if(|i| *i < pos + read as u64) {
+                if chunk[(offset - pos) as usize] & 0xC0 == 0x80 {
+                    return Err(Error::UTF8Error);
+                }
+            }
+            pos += read as u64;
+
+            pending.extend_from_slice(&chunk[..read]);
+            match std::str::from_utf8(&pending) {
+                Ok(_) => pending.clear(),
+                Err(err) if err.error_len().is_none() => {
+                    pending.drain(..err.valid_up_to());
+                }
+                Err(_) => return Err(Error::UTF8Error),
+            }
+        }
+
+        if !pending.is_empty() {
+            return Err(Error::UTF8Error);
+        }
+        Ok(())
+    }
+
+    /// Reads the line before the current line into `buf` and makes it the current line, so
+    /// repeated calls walk backwards through the data. Returns the index of the read line or
+    /// `None` if the reader is at the first line or its position is unknown.
+    ///
+    /// The reader seeks relative to its position, so the buffer is kept if the previous line is
+    /// still within it. This can be mixed with forward reads.
+    pub fn read_prev_line(&mut self, buf: &mut Vec<u8>) -> Result<Option<usize>> {
+        let line = match self.last_line.and_then(|line| line.checked_sub(1)) {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+
+        self.seek_line(line)?;
+        let read = self.read_current_line(buf, line)?;
+        buf.truncate(read);
+        Ok(Some(line))
+    }
+
+    /// Writes the data like `write_to` but stores a checksum of every line along with the index,
+    /// so corrupted lines can be detected by `File::read_line_verified` without hashing the whole
+    /// data. The checksums double the size of the index.
+    pub fn write_to_with_line_checksums<W: Write + Unpin + Send>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<usize> {
+        let mut checksums = Vec::with_capacity(self.total_lines());
+        let mut buf = Vec::new();
+        for line in 0..self.total_lines() {
+            buf.clear();
+            let read = self.read_line_raw(line, &mut buf)?;
+            checksums.push(line_checksum(&buf[..read]));
+        }
+
+        let mut index = Index::clone(&self.index);
+        index.set_line_checksums(checksums);
+        self.write_with_index(&index, writer)
+    }
+
+    /// Builds a bloom filter of all lines without their terminator with a false positive rate of
+    /// `fp_rate`. The filter gets stored by `write_to` and allows
+    /// `ReadByLine::binary_search_with_bloom` to skip searching for missing lines.
+    ///
+    /// Returns `Error::InvalidFalsePositiveRate` unless `0 < fp_rate < 1`
+    #[inline]
+    pub fn build_bloom(&mut self, fp_rate: f64) -> Result<()> {
+        self.build_bloom_by_key(fp_rate, |line| line)
+    }
+
+    /// Builds a bloom filter like `build_bloom` but inserts the key returned by `key` for every
+    /// line instead of the whole line. Callers have to check `Indexable::maybe_contains` with
+    /// the same kind of key themselves. The filter is marked as filter of keys unless every key
+    /// is equal to its line, so `ReadByLine::binary_search_with_bloom` doesn't use it.
+    pub fn build_bloom_by_key<K>(&mut self, fp_rate: f64, mut key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let mut bloom = BloomFilter::new(self.total_lines(), fp_rate)?;
+        let mut keyed = false;
+        self.for_each_line(|_, line| {
+            let line_key = key(line);
+            keyed |= line_key != line;
+            bloom.insert(line_key);
+            ControlFlow::Continue(())
+        })?;
+        bloom.set_keyed(keyed);
+
+        let mut index = Index::clone(&self.index);
+        index.set_bloom(bloom);
+        // The data keeps its position behind an embedded index
+        index.set_len_bytes(self.index.len_bytes());
+        self.index = Arc::new(index);
+        Ok(())
+    }
+
+    /// Builds a `LookupTable` of the keys returned by `key` for all lines, which gets stored by
+    /// `write_to` and allows `ReadByLine::find_exact_by_key` to find lines of unsorted data
+    /// without reading all lines. Use `|line| line` as key for `ReadByLine::find_exact`.
+    pub fn build_lookup_table<K>(&mut self, key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let table = LookupTable::build(self, key)?;
+        let mut index = Index::clone(&self.index);
+        index.set_lookup_table(table);
+        // The data keeps its position behind an embedded index
+        index.set_len_bytes(self.index.len_bytes());
+        self.index = Arc::new(index);
+        Ok(())
+    }
+
+    /// Writes the header and encoding of `index` followed by the data into `writer`. `index` has
+    /// to describe the same lines as the index of the reader.
+    ///
+    /// The header in front of the data holds the hash of the data and `writer` can't seek back to
+    /// it, so the data is read twice: once to hash it and once to copy it behind the header.
+    /// Seekable writers use `write_hashed_with`, which reads the data only once, and readers of
+    /// data in memory like `CloneableIndexedReader` hash it directly.
+    fn write_with_index<W: Write>(&mut self, index: &Index, writer: &mut W) -> Result<usize> {
+        self.forget_position();
+        self.reader.seek(SeekFrom::Start(self.data_start()))?;
+        let content_hash = hash_reader(&mut self.reader.by_ref().take(self.data_len))?;
+
+        let mut header = index.get_header();
+        header.set_content_hash(content_hash);
+        let header = header.encode();
+        writer.write_all(&header)?;
+        let mut bytes_written = header.len() + index.encode_into(writer)?;
+
+        self.reader.seek(SeekFrom::Start(self.data_start()))?;
+        bytes_written += io::copy(&mut self.reader.by_ref().take(self.data_len), writer)? as usize;
+
+        // Reset file back to start position
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.curr_pos = Some(0);
+
+        Ok(bytes_written)
+    }
+
+    /// Writes the header and `index` into the seekable `writer`, followed by the data which is
+    /// copied using `copy`. `copy` gets called with a reader over the data section and returns the
+    /// amount of bytes copied. The data is hashed while it is copied, so it is only read once, see
+    /// `Index::write_hashed`.
+    pub(crate) fn write_hashed_with<W, C>(
+        &mut self,
+        index: &Index,
+        writer: &mut W,
+        copy: C,
+    ) -> Result<usize>
+    where
+        W: Write + Seek,
+        C: FnOnce(&mut io::Take<&mut BufReader<R>>, &mut HashingWriter<&mut W>) -> io::Result<u64>,
+    {
+        self.forget_position();
+
+        self.reader
+            .seek(SeekFrom::Start(self.get_index().len_bytes() as u64))?;
+        let data = &mut self.reader;
+        let data_len = self.data_len;
+        let written = index.write_hashed(writer, |writer| {
+            Ok(copy(&mut data.by_ref().take(data_len), writer)?)
+        })?;
+
+        // Reset file back to start position
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.curr_pos = Some(0);
+
+        Ok(written)
+    }
+
+    /// Sets the maximum length of a line including its terminator. Reading a longer line returns
+    /// `Error::LineTooLong` instead of allocating a buffer for it, so a corrupted index can't
+    /// cause huge allocations. This applies to all reads of lines, including `read_span`,
+    /// `read_line_prefix` and `read_line_at`. `None` allows lines of any length, which is the
+    /// default.
+    #[inline]
+    pub fn set_max_line_len(&mut self, max: Option<usize>) {
+        self.max_line_len = max;
+    }
+
+    /// Sets whether binary searches like `ReadByLine::binary_search` and
+    /// `ReadByLine::partition_point` check that the lines are ordered as the search expects
+    /// before searching. Searches of unsorted lines then return `Error::Unsorted` instead of a
+    /// wrong result. This scans all lines on every search, so it's meant for tests and debugging.
+    /// Disabled by default.
+    #[inline]
+    pub fn set_check_sorted(&mut self, check: bool) {
+        self.check_sorted = check;
+    }
+
+    /// Sets an observer which gets notified about every read and seek, replacing the previous
+    /// one. Without an observer, no events are created.
+    #[inline]
+    pub fn set_observer(&mut self, observer: Box<dyn ReadObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Removes the observer and returns it
+    #[inline]
+    pub fn take_observer(&mut self) -> Option<Box<dyn ReadObserver>> {
+        self.observer.take()
+    }
+
+    /// Sets how often reading a line is retried if the data ends within the line, waiting `delay`
+    /// before every retry. Once all retries failed, `Error::IncompleteLine` is returned. This
+    /// allows reading lines which are indexed but still being written by another process.
+    /// Defaults to no retries.
+    #[inline]
+    pub fn set_incomplete_retry(&mut self, retries: usize, delay: Duration) {
+        self.incomplete_retry = (retries, delay);
+    }
+
+    /// Limits the time reading a single line may take to `deadline`, returning `Error::TimedOut`
+    /// once it passed. This bounds lookups on slow media like network filesystems.
+    ///
+    /// The deadline is best-effort: the data is read in chunks of 64 KiB and the deadline is
+    /// checked between them, so a single read blocking in the operating system can't be
+    /// interrupted. Waiting for incomplete lines, see `set_incomplete_retry`, counts towards
+    /// the deadline. After a timeout the reader seeks again, so it stays usable. Defaults to no
+    /// deadline.
+    #[inline]
+    pub fn set_read_deadline(&mut self, deadline: Option<Duration>) {
+        self.read_deadline = deadline;
+    }
+
+    /// Returns the point in time reads started now have to finish by
+    #[inline]
+    fn deadline(&self) -> Option<Instant> {
+        self.read_deadline.map(|i| Instant::now() + i)
+    }
+
+    /// Sets the amount of bytes read ahead during sequential reads. Reading a line then reads
+    /// all following lines which fit into `bytes` at once, and subsequent lines are served from
+    /// the read data. This speeds up sequential scans of files with short lines. Passing 0
+    /// disables the readahead.
+    ///
+    /// By default, the reader starts reading 256 KiB ahead after 32 consecutive sequential
+    /// reads and stops at the next seek. Use `set_auto_readahead` to restore this behavior.
+    #[inline]
+    pub fn set_readahead(&mut self, bytes: usize) {
+        self.readahead = Some(bytes);
+    }
+
+    /// Enables the automatic readahead, see `set_readahead`
+    #[inline]
+    pub fn set_auto_readahead(&mut self) {
+        self.readahead = None;
+    }
+
+    /// Returns the amount of bytes to read ahead with the next read
+    #[inline]
+    fn readahead_len(&self) -> usize {
+        match self.readahead {
+            Some(bytes) => bytes,
+            None if self.sequential_reads >= AUTO_READAHEAD_AFTER => AUTO_READAHEAD_LEN,
+            None => 0,
+        }
+    }
+
+    /// Absolute end of the data read ahead
+    #[inline]
+    fn ahead_end(&self) -> u64 {
+        self.ahead_start + self.ahead_len as u64
+    }
+
+    /// Forgets the position of the reader before seeking it to an absolute position
+    #[inline]
+    fn forget_position(&mut self) {
+        self.last_line = None;
+        self.curr_pos = None;
+        self.sequential_reads = 0;
+        self.ahead_len = 0;
+    }
+
+    /// Drops the data read ahead and positions `reader` at `curr_pos`, so it can be read
+    /// directly again
+    fn sync_reader(&mut self) -> io::Result<()> {
+        if self.ahead_len == 0 {
+            return Ok(());
+        }
+
+        let end = self.ahead_end();
+        self.ahead_len = 0;
+
+        let pos = match self.curr_pos {
+            Some(pos) if pos != end => pos,
+            _ => return Ok(()),
+        };
+        self.curr_pos = None;
+        self.reader.seek_relative(pos as i64 - end as i64)?;
+        self.curr_pos = Some(pos);
+        Ok(())
+    }
+
+    /// Reads `line` from the data read ahead, reading ahead first if the line isn't available.
+    /// Returns `None` if the line has to be read directly since readahead is disabled or the
+    /// reader isn't positioned at the line.
+    fn read_ahead(&mut self, line: usize, out_buf: &mut Vec<u8>) -> Result<Option<usize>> {
+        let data_start = self.data_start();
+        let span = self.line_span(line)?;
+        let (start, end) = (data_start + span.start, data_start + span.end);
+
+        if self.curr_pos != Some(start) || end < start {
+            return Ok(None);
+        }
+
+        let available = self.ahead_len != 0 && start >= self.ahead_start && end <= self.ahead_end();
+        if !available {
+            // Reading ahead only pays off if multiple lines fit into the data read ahead
+            let bytes = self.readahead_len();
+            if bytes == 0 || span.end.saturating_sub(span.start) as usize > bytes / 2 {
+                return Ok(None);
+            }
+            self.sync_reader()?;
+
+            // Read all lines ending within `bytes`, but at least `line`
+            let limit = span.start + bytes as u64;
+            let offsets = self.index.offsets();
+            let following = offsets.partition_point(|offset| *offset as u64 <= limit);
+            let lines_end = match offsets.get(following) {
+                None if self.data_len <= limit => self.data_len,
+                // Only an unsorted index has no line in front of `limit`
+                _ => following
+                    .checked_sub(1)
+                    .map(|last| offsets[last] as u64)
+                    .ok_or(Error::InvalidIndex { line })?,
+            };
+            let len = (lines_end.max(span.end) - span.start) as usize;
+
+            // The buffer only grows, so it doesn't get initialized again for every read
+            if self.ahead.len() < len {
+                self.ahead.resize(len, 0);
+            }
+            // Unknown while reading, see `read_complete`
+            self.curr_pos = None;
+            let deadline = self.deadline();
+            match read_before(&mut self.reader, &mut self.ahead[..len], deadline, line) {
+                Ok(read) if read == len => (),
+                Ok(read) => {
+                    // The data ends early, so `line` gets read directly which handles incomplete
+                    // lines
+                    self.reader.seek_relative(-(read as i64))?;
+                    self.curr_pos = Some(start);
+                    return Ok(None);
+                }
+                Err(err) => {
+                    self.forget_position();
+                    return Err(err);
+                }
+            }
+            self.ahead_start = start;
+            self.ahead_len = len;
+        }
+
+        let from = (start - self.ahead_start) as usize;
+        out_buf.clear();
+        out_buf.extend_from_slice(&self.ahead[from..from + (end - start) as usize]);
+        self.curr_pos = Some(end);
+
+        let read = out_buf.len();
+        self.observe(|o| o.on_read(line, read));
+        Ok(Some(read))
+    }
+
+    /// Returns `Error::LineTooLong` if `line` is longer than the maximum set by
+    /// `set_max_line_len`
+    fn check_line_len(&self, line: usize) -> Result<()> {
+        if let Some(max) = self.max_line_len {
+            let span = self.line_span(line)?;
+            let len = span.end.saturating_sub(span.start);
+            if len > max as u64 {
+                return Err(Error::LineTooLong {
+                    line,
+                    len: len as usize,
+                });
+            }
+        }
+        Ok(())
     }
 
     #[inline]
     fn get_pos(&mut self, pos: usize) -> Result<u32> {
         self.index.get(pos)
     }
+
+    /// Passes the observer to `f` if there is one
+    #[inline]
+    fn observe<F: FnOnce(&mut dyn ReadObserver)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_deref_mut() {
+            f(observer);
+        }
+    }
+
+    /// Fills `buf` with the next bytes of `line`, retrying as configured by
+    /// `set_incomplete_retry` if the data ends early. Advances `curr_pos` on success and leaves
+    /// it unknown otherwise.
+    fn read_complete(&mut self, line: usize, buf: &mut [u8]) -> Result<()> {
+        // The position is unknown while reading, so it isn't stale if the reader panics
+        let start = self.curr_pos.take();
+        let deadline = self.deadline();
+        let (mut retries, delay) = self.incomplete_retry;
+        let mut have = 0;
+        loop {
+            have += read_before(&mut self.reader, &mut buf[have..], deadline, line)?;
+            if have == buf.len() {
+                self.curr_pos = start.map(|pos| pos + have as u64);
+                return Ok(());
+            }
+            if retries == 0 {
+                return Err(Error::IncompleteLine {
+                    line,
+                    have,
+                    want: buf.len(),
+                });
+            }
+            if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                return Err(Error::TimedOut { line });
+            }
+            retries -= 1;
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Reads into `buf` like `read_available`. With a `deadline`, `buf` is read in chunks and
+/// `Error::TimedOut` is returned if the deadline passed before reading the next chunk.
+fn read_before<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    deadline: Option<Instant>,
+    line: usize,
+) -> Result<usize> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Ok(read_available(reader, buf)?),
+    };
+
+    let mut read = 0;
+    for chunk in buf.chunks_mut(DEADLINE_CHUNK_LEN) {
+        if Instant::now() >= deadline {
+            return Err(Error::TimedOut { line });
+        }
+        let len = read_available(reader, chunk)?;
+        read += len;
+        if len < chunk.len() {
+            break;
+        }
+    }
+    Ok(read)
+}
+
+/// Reads into `buf` until it is full or `reader` reaches its end. Returns the amount of bytes
+/// read.
+fn read_available<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
 }
 
 impl<R: Read + Unpin + Seek + Send> Indexable for IndexedReader<R> {
@@ -64,73 +696,596 @@ impl<R: Read + Unpin + Seek + Send> Indexable for IndexedReader<R> {
 
 impl<R: Read + Unpin + Seek + Send> IndexableFile for IndexedReader<R> {
     fn read_current_line(&mut self, out_buf: &mut Vec<u8>, line: usize) -> Result<usize> {
-        let curr_line = self.get_pos(line)?;
-        let next_line = self.get_pos(line + 1);
-
-        // Get space between current start index and next lines start index. The result is the
-        // amount of bytes we have to read.
-        let need_read = next_line
-            .map(|next_line| (next_line - curr_line) as usize)
-            .ok();
-
-        // If there is a next line to read up to
-        if let Some(need_read) = need_read {
-            if out_buf.len() < need_read {
-                out_buf.resize(need_read, 0);
-            }
-            self.reader.read_exact(&mut out_buf[0..need_read])?;
+        self.check_line_len(line)?;
 
-            return Ok(need_read);
+        if let Some(read) = self.read_ahead(line, out_buf)? {
+            return Ok(read);
         }
+        self.sync_reader()?;
 
-        if !out_buf.is_empty() {
-            out_buf.clear();
+        // The last line ends at the end of the data, which might be before its start
+        let span = self.line_span(line)?;
+        if span.end < span.start {
+            return Err(Error::IncompleteLine {
+                line,
+                have: 0,
+                want: 0,
+            });
         }
+        let need_read = (span.end - span.start) as usize;
 
-        Ok(self.reader.read_to_end(out_buf)?)
+        if out_buf.len() < need_read {
+            out_buf.resize(need_read, 0);
+        }
+        self.read_complete(line, &mut out_buf[..need_read])?;
+        self.observe(|o| o.on_read(line, need_read));
+        Ok(need_read)
     }
 
     fn seek_line(&mut self, line: usize) -> Result<()> {
-        let last_line = self.last_line;
-        self.last_line = Some(line);
+        // Validate the line before touching the state so a failed seek doesn't break sequencial
+        // reads afterwards
+        let seek_pos = self.get_pos(line)? as u64 + self.get_index_byte_len() as u64;
+
+        if self.curr_pos == Some(seek_pos) {
+            self.sequential_reads = self.sequential_reads.saturating_add(1);
+            self.observe(|o| o.on_sequential_hit(line));
+        } else {
+            self.sequential_reads = 0;
+        }
+
+        // Lines read ahead are read from memory, so the reader stays where it is
+        if self.ahead_len != 0 && (self.ahead_start..self.ahead_end()).contains(&seek_pos) {
+            self.curr_pos = Some(seek_pos);
+            self.last_line = Some(line);
+            return Ok(());
+        }
+
+        // Leaving the data read ahead, the reader is positioned at its end
+        if self.ahead_len != 0 {
+            self.curr_pos = Some(self.ahead_end());
+            self.ahead_len = 0;
+        }
 
         // We don't need to seek if we're sequencially reading the file
-        if let Some(last_line) = last_line {
-            if line == last_line + 1 {
-                return Ok(());
-            }
+        if self.curr_pos != Some(seek_pos) {
+            let res = match self.curr_pos {
+                // Seeking relative keeps the buffer if the target is within it
+                Some(pos) => self.reader.seek_relative(seek_pos as i64 - pos as i64),
+                None => self.reader.seek(SeekFrom::Start(seek_pos)).map(|_| ()),
+            };
+
+            // If the seek fails, the position of the reader is unknown
+            let skipped = self.curr_pos.map(|pos| seek_pos as i64 - pos as i64);
+            self.curr_pos = None;
+            res?;
+            self.curr_pos = Some(seek_pos);
+            self.observe(|o| o.on_seek(line, skipped));
         }
 
-        let seek_pos = self.get_pos(line)? as u64 + self.get_index_byte_len() as u64;
-        self.reader.seek(SeekFrom::Start(seek_pos))?;
+        self.last_line = Some(line);
         Ok(())
     }
 
-    fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
-        let header = self.get_index().get_header().encode();
-        let encoded_index = self.get_index().encode();
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        self.last_line
+    }
 
-        // Write the header
-        writer.write_all(&header)?;
+    #[inline]
+    fn checks_sorted(&self) -> bool {
+        self.check_sorted
+    }
 
-        // Write the index
-        writer.write_all(&encoded_index)?;
+    #[inline]
+    fn data_len(&self) -> u64 {
+        self.data_len
+    }
 
-        let mut bytes_written = encoded_index.len() + header.len();
+    fn absolute_position(&mut self) -> Result<u64> {
+        if let Some(pos) = self.curr_pos {
+            return Ok(pos);
+        }
 
-        // We want to get all bytes. Since the seek position might change over time (eg. by using
-        // read_line) we have to seek to the beginning of the data
-        self.reader
-            .seek(SeekFrom::Start(self.get_index().len_bytes() as u64))?;
+        let pos = self.reader.stream_position()?;
+        self.curr_pos = Some(pos);
+        Ok(pos)
+    }
 
-        bytes_written += io::copy(&mut self.reader, writer)? as usize;
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        self.check_line_len(line)?;
+        let span = self.line_span(line)?;
+        let len = (span.end.saturating_sub(span.start) as usize).min(max);
 
-        // Reset file back to start position
-        self.reader.seek(SeekFrom::Start(0))?;
-        self.curr_pos = 0;
+        self.sync_reader()?;
+        self.seek_line(line)?;
 
-        Ok(bytes_written)
+        buf.clear();
+        buf.resize(len, 0);
+        // The reader is now within the line. Since the position is still known, the next seek
+        // is relative and keeps the buffer
+        self.read_complete(line, buf)?;
+        self.observe(|o| o.on_read(line, len));
+
+        Ok(len)
+    }
+
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        buf.clear();
+
+        if lines.start >= lines.end {
+            return Ok(0);
+        }
+        if self.max_line_len.is_some() {
+            for line in lines.clone() {
+                self.check_line_len(line)?;
+            }
+        }
+
+        // The last line ends at the end of the data, which might be before its start
+        let start = self.line_span(lines.start)?.start;
+        let end = self.line_span(lines.end - 1)?.end;
+        let len = end.checked_sub(start).ok_or(Error::IncompleteLine {
+            line: lines.start,
+            have: 0,
+            want: 0,
+        })? as usize;
+
+        self.sync_reader()?;
+        self.seek_line(lines.start)?;
+
+        buf.resize(len, 0);
+        if let Err(err) = self.read_complete(lines.start, buf) {
+            buf.clear();
+            return Err(err);
+        }
+
+        // The reader is now at the beginning of `lines.end` so sequencial reads can continue
+        self.last_line = Some(lines.end - 1);
+        self.observe(|o| o.on_read(lines.start, len));
+
+        Ok(len)
+    }
+
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.check_line_len(line)?;
+        let span = self.line_span(line)?;
+        let len = span.end.saturating_sub(span.start).saturating_sub(offset);
+        let len = len.min(buf.len() as u64) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        self.sync_reader()?;
+        let pos = self.data_start() + span.start + offset;
+        if self.curr_pos != Some(pos) {
+            let res = match self.curr_pos {
+                Some(curr) => self.reader.seek_relative(pos as i64 - curr as i64),
+                None => self.reader.seek(SeekFrom::Start(pos)).map(|_| ()),
+            };
+            let skipped = self.curr_pos.map(|curr| pos as i64 - curr as i64);
+            self.curr_pos = None;
+            res?;
+            self.observe(|o| o.on_seek(line, skipped));
+        }
+
+        self.curr_pos = Some(pos);
+        self.read_complete(line, &mut buf[..len])?;
+
+        // The reader might be within the line, which is fine since the position is still known
+        self.last_line = Some(line);
+        self.observe(|o| o.on_read(line, len));
+        Ok(len)
+    }
+
+    #[inline]
+    fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
+        let index = Arc::clone(&self.index);
+        self.write_with_index(&index, writer)
     }
 }
 
 impl<R: Read + Unpin + Seek + Send> ReadByLine for IndexedReader<R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Cursor,
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
+
+    /// Counts the calls to the underlying reader
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.inner.seek(pos)
+        }
+    }
+
+    /// Panics after the next read if armed
+    struct PanickingReader {
+        inner: Cursor<Vec<u8>>,
+        armed: Arc<AtomicBool>,
+    }
+
+    impl Read for PanickingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            if self.armed.swap(false, Ordering::Relaxed) {
+                panic!("read failed");
+            }
+            Ok(read)
+        }
+    }
+
+    impl Seek for PanickingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    /// Sleeps before every read, like slow media
+    struct SlowReader(Cursor<Vec<u8>>);
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(20));
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for SlowReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_read_deadline() {
+        let mut data = vec![b'x'; 4 * DEADLINE_CHUNK_LEN];
+        data.extend_from_slice(b"\nshort\n");
+        let index = Index::build(&mut BufReader::new(Cursor::new(data.clone()))).unwrap();
+        let mut reader =
+            IndexedReader::new(SlowReader(Cursor::new(data)), Arc::new(index.zero_len()));
+
+        reader.set_read_deadline(Some(Duration::from_millis(30)));
+        assert!(matches!(
+            reader.read_line_raw(0, &mut Vec::new()),
+            Err(Error::TimedOut { line: 0 })
+        ));
+        assert_eq!(reader.current_line(), Some(0));
+        assert_eq!(reader.read_line(1).unwrap(), "short\n");
+
+        // The reader stays usable after a timeout
+        reader.set_read_deadline(None);
+        let mut buf = Vec::new();
+        assert_eq!(
+            reader.read_line_raw(0, &mut buf).unwrap(),
+            4 * DEADLINE_CHUNK_LEN + 1
+        );
+        assert_eq!(reader.read_line(1).unwrap(), "short\n");
+    }
+
+    #[test]
+    fn test_embedded_data() {
+        let data = "a\nb";
+        let index = Index::build(&mut BufReader::new(Cursor::new(data))).unwrap();
+        let container = format!("{}\ntrailing section", data);
+        let mut reader = IndexedReader::new(Cursor::new(container), Arc::new(index));
+        assert_eq!(reader.read_line(1).unwrap(), "b\ntrailing section");
+
+        reader.set_data_len(data.len() as u64);
+        assert_eq!(reader.data_len(), 3);
+        assert_eq!(reader.read_line(1).unwrap(), "b");
+        let mut all = Vec::new();
+        reader.read_all(&mut all).unwrap();
+        assert_eq!(all, data.as_bytes());
+
+        let mut written = Vec::new();
+        reader.write_to(&mut written).unwrap();
+        let mut parsed = crate::any::CloneableIndexedReader::new(written).unwrap();
+        assert_eq!(parsed.content(), data.as_bytes());
+        assert_eq!(parsed.read_line(1).unwrap(), "b");
+    }
+
+    /// Counts the bytes read from the inner reader
+    struct ByteCountingReader {
+        inner: Cursor<Vec<u8>>,
+        read: Arc<AtomicUsize>,
+    }
+
+    impl Read for ByteCountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.read.fetch_add(read, Ordering::Relaxed);
+            Ok(read)
+        }
+    }
+
+    impl Seek for ByteCountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_write_to_reads_twice() {
+        let data = b"line\n".repeat(100_000);
+        let index = Index::build(&mut BufReader::new(Cursor::new(data.clone()))).unwrap();
+        let read = Arc::new(AtomicUsize::new(0));
+        let inner = ByteCountingReader {
+            inner: Cursor::new(data.clone()),
+            read: Arc::clone(&read),
+        };
+        let mut reader = IndexedReader::new(inner, Arc::new(index));
+
+        let mut written = Vec::new();
+        reader.write_to(&mut written).unwrap();
+        // Once to hash the data and once to copy it
+        assert_eq!(read.load(Ordering::Relaxed), 2 * data.len());
+
+        let mut parsed = crate::OpenBuilder::new()
+            .open_reader(Cursor::new(written))
+            .unwrap();
+        let mut all = Vec::new();
+        parsed.read_all(&mut all).unwrap();
+        assert_eq!(all, data);
+        assert_eq!(
+            parsed.verify().unwrap().content_hash,
+            crate::verify::HashStatus::Valid
+        );
+    }
+
+    #[test]
+    fn test_unwind_keeps_position() {
+        let data = b"a\nb\nc\nd\n".to_vec();
+        let index = Index::build(&mut BufReader::new(Cursor::new(data.clone()))).unwrap();
+        let armed = Arc::new(AtomicBool::new(false));
+        let inner = PanickingReader {
+            inner: Cursor::new(data),
+            armed: Arc::clone(&armed),
+        };
+        // Lines are larger than the buffer, so they are read directly from `inner`
+        let mut reader = IndexedReader::with_capacity(1, inner, Arc::new(index.zero_len()));
+
+        let search = panic::catch_unwind(AssertUnwindSafe(|| {
+            reader.binary_search_by(|_| panic!("comparator failed"))
+        }));
+        assert!(search.is_err());
+        for (line, expected) in ["a\n", "b\n", "c\n", "d\n"].iter().enumerate() {
+            assert_eq!(reader.read_line(line).unwrap(), *expected);
+        }
+
+        // The reader moved before panicking, so the next read has to seek
+        reader.read_line(0).unwrap();
+        armed.store(true, Ordering::Relaxed);
+        let read = panic::catch_unwind(AssertUnwindSafe(|| reader.read_line(1)));
+        assert!(read.is_err());
+        assert_eq!(reader.read_line(1).unwrap(), "b\n");
+        assert_eq!(reader.read_line(2).unwrap(), "c\n");
+
+        armed.store(true, Ordering::Relaxed);
+        let mut buf = Vec::new();
+        let read = panic::catch_unwind(AssertUnwindSafe(|| reader.read_span(0..2, &mut buf)));
+        assert!(read.is_err());
+        assert_eq!(reader.read_line(2).unwrap(), "c\n");
+    }
+
+    #[test]
+    fn test_nearby_seeks_keep_buffer() {
+        let data: String = (0..100).map(|i| format!("{}\n", i)).collect();
+        let index = Index::build(&mut BufReader::new(Cursor::new(data.as_bytes()))).unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = CountingReader {
+            inner: Cursor::new(data.clone().into_bytes()),
+            calls: Arc::clone(&calls),
+        };
+        let mut reader = IndexedReader::new(inner, Arc::new(index));
+
+        // Fills the buffer which holds all of the data from line 10 on
+        assert_eq!(reader.read_line(10).unwrap(), "10\n");
+        let after_fill = calls.load(Ordering::Relaxed);
+
+        for line in [12, 11, 30, 15, 98, 50, 10] {
+            assert_eq!(reader.read_line(line).unwrap(), format!("{}\n", line));
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), after_fill);
+    }
+
+    #[test]
+    fn test_read_prev_line() {
+        let data: String = (0..100).map(|i| format!("{}\n", i)).collect();
+        let index = Index::build(&mut BufReader::new(Cursor::new(data.as_bytes()))).unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = CountingReader {
+            inner: Cursor::new(data.clone().into_bytes()),
+            calls: Arc::clone(&calls),
+        };
+        let mut reader = IndexedReader::new(inner, Arc::new(index));
+
+        let mut buf = Vec::new();
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), None);
+
+        // Fills the buffer which holds all of the data from line 10 on
+        assert_eq!(reader.read_line(10).unwrap(), "10\n");
+        let after_fill = calls.load(Ordering::Relaxed);
+
+        assert_eq!(reader.read_line(40).unwrap(), "40\n");
+
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), Some(39));
+        assert_eq!(buf, b"39\n");
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), Some(38));
+        assert_eq!(buf, b"38\n");
+
+        // Forward reads continue from the line read last
+        assert_eq!(reader.read_line(39).unwrap(), "39\n");
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), Some(38));
+        assert_eq!(buf, b"38\n");
+        assert_eq!(calls.load(Ordering::Relaxed), after_fill);
+
+        reader.seek_line(1).unwrap();
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), Some(0));
+        assert_eq!(buf, b"0\n");
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let data: String = (0..20)
+            .map(|i| format!("{}\n", "x".repeat(i * 10)))
+            .collect();
+        let index =
+            Arc::new(Index::build(&mut BufReader::new(Cursor::new(data.as_bytes()))).unwrap());
+
+        let mut small = IndexedReader::with_capacity(16, Cursor::new(data.clone()), index);
+        assert_eq!(small.reader.capacity(), 16);
+        for (line, expected) in data.split_inclusive('\n').enumerate() {
+            assert_eq!(small.read_line(line).unwrap(), expected);
+        }
+
+        small.set_max_line_len(Some(100));
+        small.set_readahead(1024);
+        let dup = small.duplicate(Cursor::new(data));
+        assert_eq!(dup.reader.capacity(), 16);
+        assert_eq!(dup.max_line_len, Some(100));
+        assert_eq!(dup.readahead, Some(1024));
+    }
+
+    #[test]
+    fn test_readahead() {
+        let data: String = (0..20_000).map(|i| format!("{:05}\n", i)).collect();
+        let index =
+            Arc::new(Index::build(&mut BufReader::new(Cursor::new(data.as_bytes()))).unwrap());
+
+        let scan = |readahead: Option<usize>| {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let inner = CountingReader {
+                inner: Cursor::new(data.clone().into_bytes()),
+                calls: Arc::clone(&calls),
+            };
+            let mut reader = IndexedReader::new(inner, Arc::clone(&index));
+            if let Some(bytes) = readahead {
+                reader.set_readahead(bytes);
+            }
+
+            let mut buf = Vec::new();
+            for line in 0..reader.total_lines() {
+                let read = reader.read_line_raw(line, &mut buf).unwrap();
+                assert_eq!(&buf[..read], format!("{:05}\n", line).as_bytes());
+            }
+            calls.load(Ordering::Relaxed)
+        };
+
+        let direct = scan(Some(0));
+        assert!(scan(None) * 3 < direct);
+        assert!(scan(Some(1 << 20)) * 3 < direct);
+        // Lines larger than the readahead are still read completely
+        assert!(scan(Some(1)) > 0);
+    }
+
+    #[test]
+    fn test_readahead_unsorted() {
+        // No line starts in front of the data read ahead for line 3
+        let data = vec![b'a'; 200];
+        let index = Arc::new(Index::new(vec![50, 100, 150, 0, 1]).zero_len());
+        let mut reader = IndexedReader::new(Cursor::new(data), index);
+        reader.set_readahead(10);
+
+        let mut buf = Vec::new();
+        assert!(matches!(
+            reader.read_line_raw(3, &mut buf),
+            Err(Error::InvalidIndex { line: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_readahead_mixed() {
+        let data: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+        let index =
+            Arc::new(Index::build(&mut BufReader::new(Cursor::new(data.as_bytes()))).unwrap());
+        let mut reader = IndexedReader::new(Cursor::new(data.clone()), index);
+        reader.set_readahead(64);
+
+        let mut buf = Vec::new();
+        for line in 0..10 {
+            assert_eq!(reader.read_line(line).unwrap(), format!("{}\n", line));
+        }
+
+        // Other reads continue at the position within the data read ahead
+        reader.seek_line(11).unwrap();
+        assert_eq!(reader.absolute_position().unwrap(), 23);
+        reader.read_span(11..13, &mut buf).unwrap();
+        assert_eq!(buf, b"11\n12\n");
+        assert_eq!(reader.read_line(13).unwrap(), "13\n");
+        reader.read_line_prefix(14, 1, &mut buf).unwrap();
+        assert_eq!(buf, b"1");
+        assert_eq!(reader.read_line(15).unwrap(), "15\n");
+
+        assert_eq!(reader.read_line(998).unwrap(), "998\n");
+        assert_eq!(reader.read_line(999).unwrap(), "999\n");
+        assert_eq!(reader.read_line(5).unwrap(), "5\n");
+        assert_eq!(reader.read_prev_line(&mut buf).unwrap(), Some(4));
+        assert_eq!(buf, b"4\n");
+
+        let mut all = Vec::new();
+        reader.read_all(&mut all).unwrap();
+        assert_eq!(all, data.as_bytes());
+        assert_eq!(reader.read_line(16).unwrap(), "16\n");
+    }
+
+    #[test]
+    fn test_read_all() {
+        let data = "ab\ncd\nef";
+        let mut buf = b"previous".to_vec();
+
+        let mut raw = crate::Builder::new()
+            .index_reader(Cursor::new(data))
+            .unwrap();
+        assert_eq!(raw.read_all(&mut buf).unwrap(), data.len());
+        assert_eq!(buf, data.as_bytes());
+
+        // The header and the index are skipped
+        let mut indexed = Vec::new();
+        raw.write_to(&mut indexed).unwrap();
+        let mut stored = crate::OpenBuilder::new()
+            .open_reader(Cursor::new(indexed))
+            .unwrap();
+        assert_eq!(stored.read_all(&mut buf).unwrap(), data.len());
+        assert_eq!(buf, data.as_bytes());
+
+        // The data starts at 0 even if the first line doesn't
+        let index = Arc::new(Index::new(vec![3, 6]).zero_len());
+        let mut custom = IndexedReader::new(Cursor::new(data), index);
+        assert_eq!(custom.read_line(0).unwrap(), "cd\n");
+        assert_eq!(custom.read_all(&mut buf).unwrap(), data.len());
+        assert_eq!(buf, data.as_bytes());
+
+        let mut empty = crate::Builder::new().index_reader(Cursor::new("")).unwrap();
+        assert_eq!(empty.read_all(&mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+
+        let mut indexed = Vec::new();
+        empty.write_to(&mut indexed).unwrap();
+        let mut stored = crate::OpenBuilder::new()
+            .open_reader(Cursor::new(indexed))
+            .unwrap();
+        assert_eq!(stored.read_all(&mut buf).unwrap(), 0);
+        assert!(buf.is_empty());
+    }
+}
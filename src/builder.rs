@@ -0,0 +1,629 @@
+use std::{
+    io::{BufReader, Read, Seek},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    bufreader::{IndexedReader, DEFAULT_BUF_CAPACITY},
+    collate::Collation,
+    error::Error,
+    file::{self, File, LockMode},
+    index::{BuildProgress, Index, IndexBuildHandle, BACKGROUND_CHUNK_SIZE, DEFAULT_DELIMITER},
+    sniff::{ContentKind, Sniffer, DEFAULT_SNIFF_LEN},
+    window::Window,
+    Indexable, Result,
+};
+
+/// Builds indexes for non indexed data. All format options of the index are set on the builder,
+/// so new options don't require new constructors.
+///
+/// ```no_run
+/// use indexed_file::{Builder, IndexableFile};
+///
+/// let mut file = Builder::new().delimiter(b'\0').index_from("data")?;
+/// file.write_to(&mut std::fs::File::create("data.indexed")?)?;
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Builder {
+    delimiter: u8,
+    /// Whether a leading UTF-8 BOM gets skipped
+    skip_bom: bool,
+    /// Capacity of the read buffer of opened files
+    buffer_capacity: usize,
+    /// Maximum length of a line
+    max_line_len: Option<usize>,
+    /// Rejects data which `Sniffer` classifies as binary
+    text_only: Option<Sniffer>,
+    /// Lock taken on opened files and whether to wait for it
+    lock: Option<(LockMode, bool)>,
+}
+
+impl Default for Builder {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            delimiter: DEFAULT_DELIMITER,
+            skip_bom: false,
+            buffer_capacity: DEFAULT_BUF_CAPACITY,
+            max_line_len: None,
+            text_only: None,
+            lock: None,
+        }
+    }
+}
+
+impl Builder {
+    /// Creates a new `Builder` with the default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the byte terminating the lines. Defaults to `\n`. A custom delimiter gets stored in
+    /// the header, so it is detected when opening the indexed file again.
+    #[inline]
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Detects a UTF-8 BOM at the beginning of the data and skips it, so the first line starts
+    /// after it. Data without a BOM is indexed as usual. Defaults to `false`. The skipped BOM
+    /// gets stored in the header, so it is skipped when opening the indexed file again.
+    #[inline]
+    pub fn skip_bom(mut self, skip: bool) -> Self {
+        self.skip_bom = skip;
+        self
+    }
+
+    /// Sets the capacity of the read buffer in bytes. Defaults to 8 KiB. See
+    /// `IndexedReader::with_capacity` for guidance.
+    #[inline]
+    pub fn buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum length of a line in bytes, including its terminator. Building an index
+    /// for data with a longer line returns `Error::LineTooLong`, and opened files refuse to read
+    /// longer lines. Unlimited by default; for files from untrusted sources a cap like
+    /// `16 * 1024 * 1024` keeps a single line from using up all memory.
+    #[inline]
+    pub fn max_line_len(mut self, max: usize) -> Self {
+        self.max_line_len = Some(max);
+        self
+    }
+
+    /// Samples the first 8 KiB of the data using `sniffer` before building the index and returns
+    /// `Error::NotText` if it looks like binary data. This avoids indexing large binary files
+    /// by accident. Disabled by default.
+    #[inline]
+    pub fn text_only(mut self, sniffer: Sniffer) -> Self {
+        self.text_only = Some(sniffer);
+        self
+    }
+
+    /// Takes an advisory lock on files opened by `index_from` before building the index, waiting
+    /// until conflicting locks are released. The lock is released when the file gets dropped.
+    /// Defaults to no lock.
+    #[inline]
+    pub fn lock(mut self, mode: LockMode) -> Self {
+        self.lock = Some((mode, true));
+        self
+    }
+
+    /// Takes an advisory lock like `lock`, but returns `Error::WouldBlock` instead of waiting if
+    /// another handle holds a conflicting lock.
+    #[inline]
+    pub fn try_lock(mut self, mode: LockMode) -> Self {
+        self.lock = Some((mode, false));
+        self
+    }
+
+    /// Builds the index for the data within `reader`
+    #[inline]
+    pub fn build_index<R: Read + Unpin + Seek>(&self, reader: &mut BufReader<R>) -> Result<Index> {
+        self.build_index_tracked(reader, None)
+    }
+
+    /// Builds the index for the data within `reader` on a new thread. The returned handle can be
+    /// used to track the progress, cancel the build or wait for the index.
+    pub fn build_background<R: Read + Seek + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> IndexBuildHandle<Index> {
+        let builder = *self;
+        IndexBuildHandle::spawn(move |progress| {
+            let mut reader = BufReader::with_capacity(BACKGROUND_CHUNK_SIZE, reader);
+            builder.build_index_tracked(&mut reader, Some(progress))
+        })
+    }
+
+    /// Builds the index like `build_index`, reporting the progress to `progress` if there is one
+    fn build_index_tracked<R: Read + Seek>(
+        &self,
+        reader: &mut BufReader<R>,
+        progress: Option<&BuildProgress>,
+    ) -> Result<Index> {
+        if let Some(sniffer) = self.text_only {
+            if sniffer.sniff(reader, DEFAULT_SNIFF_LEN)? == ContentKind::Binary {
+                return Err(Error::NotText);
+            }
+        }
+
+        let max_line_len = self.max_line_len.map(|max| max as u64);
+        Index::build_tracked(
+            reader,
+            self.delimiter,
+            self.skip_bom,
+            max_line_len,
+            progress,
+        )
+    }
+
+    /// Opens the non indexed file at `path` and builds its index
+    #[inline]
+    pub fn index_from<P: AsRef<Path>>(&self, path: P) -> Result<File> {
+        self.index_from_tracked(path.as_ref(), None)
+    }
+
+    /// Opens the non indexed file at `path` and builds its index on a new thread. The returned
+    /// handle allows tracking the progress and resolves to the opened `File`.
+    pub fn index_from_background<P: AsRef<Path>>(&self, path: P) -> IndexBuildHandle<File> {
+        let builder = *self;
+        let path = path.as_ref().to_path_buf();
+        IndexBuildHandle::spawn(move |progress| builder.index_from_tracked(&path, Some(progress)))
+    }
+
+    /// Opens the file at `path` like `index_from`, reporting the progress to `progress` if there
+    /// is one
+    fn index_from_tracked(&self, path: &Path, progress: Option<&BuildProgress>) -> Result<File> {
+        let (mut reader, path) = file::open_buffered(path)?;
+        if let Some((mode, blocking)) = self.lock {
+            file::lock_file(reader.get_ref(), mode, blocking).map_err(|e| e.with_path(&path))?;
+        }
+        let index = self
+            .build_index_tracked(&mut reader, progress)
+            .map_err(|e| e.with_path(&path))?;
+        let mut file =
+            File::from_buf_reader_with_capacity(self.buffer_capacity, reader, Arc::new(index));
+        file.set_max_line_len(self.max_line_len);
+        Ok(file.with_path(path))
+    }
+
+    /// Builds the index for the non indexed data within `reader`
+    pub fn index_reader<R: Read + Unpin + Seek + Send>(
+        &self,
+        reader: R,
+    ) -> Result<IndexedReader<R>> {
+        let mut reader = BufReader::new(reader);
+        let index = self.build_index(&mut reader)?;
+        let mut reader = IndexedReader::with_capacity(
+            self.buffer_capacity,
+            reader.into_inner(),
+            Arc::new(index),
+        );
+        reader.set_max_line_len(self.max_line_len);
+        Ok(reader)
+    }
+}
+
+/// Opens indexed files. Options stored in the header of the file, like the delimiter, are
+/// detected automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenBuilder {
+    /// Capacity of the read buffer of opened files
+    buffer_capacity: usize,
+    /// Maximum length of a line which can be read
+    max_line_len: Option<usize>,
+    /// Lock taken on opened files and whether to wait for it
+    lock: Option<(LockMode, bool)>,
+    /// Retries and their delay when reading incomplete lines
+    incomplete_retry: (usize, Duration),
+    /// Collation the opened files have to be sorted by
+    collation: Option<Collation>,
+}
+
+impl Default for OpenBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            buffer_capacity: DEFAULT_BUF_CAPACITY,
+            max_line_len: None,
+            lock: None,
+            incomplete_retry: (0, Duration::ZERO),
+            collation: None,
+        }
+    }
+}
+
+impl OpenBuilder {
+    /// Creates a new `OpenBuilder` with the default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the capacity of the read buffer in bytes. Defaults to 8 KiB. See
+    /// `IndexedReader::with_capacity` for guidance.
+    #[inline]
+    pub fn buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum length of a line in bytes, including its terminator. Reading a longer
+    /// line returns `Error::LineTooLong`, so a corrupted index can't cause huge allocations.
+    /// Unlimited by default.
+    #[inline]
+    pub fn max_line_len(mut self, max: usize) -> Self {
+        self.max_line_len = Some(max);
+        self
+    }
+
+    /// Takes an advisory lock on files opened by `open` before parsing the index, waiting until
+    /// conflicting locks are released. Readers usually take a `LockMode::Shared` lock. The lock is
+    /// released when the file gets dropped. Defaults to no lock.
+    #[inline]
+    pub fn lock(mut self, mode: LockMode) -> Self {
+        self.lock = Some((mode, true));
+        self
+    }
+
+    /// Takes an advisory lock like `lock`, but returns `Error::WouldBlock` instead of waiting if
+    /// another handle holds a conflicting lock.
+    #[inline]
+    pub fn try_lock(mut self, mode: LockMode) -> Self {
+        self.lock = Some((mode, false));
+        self
+    }
+
+    /// Retries reading lines which end behind the end of the data up to `retries` times, waiting
+    /// `delay` before each retry. This is useful for files which get indexed before their data is
+    /// written completely. See `IndexedReader::set_incomplete_retry`. Defaults to no retries.
+    #[inline]
+    pub fn retry_incomplete(mut self, retries: usize, delay: Duration) -> Self {
+        self.incomplete_retry = (retries, delay);
+        self
+    }
+
+    /// Requires opened files to be sorted using `collation`. Opening a file whose header records
+    /// a different collation returns `Error::CollationMismatch`, while files without a recorded
+    /// collation are accepted. Defaults to accepting every collation.
+    #[inline]
+    pub fn expect_collation(mut self, collation: Collation) -> Self {
+        self.collation = Some(collation);
+        self
+    }
+
+    /// Returns an error if `index` doesn't match the expected collation
+    #[inline]
+    fn check_collation(&self, index: &Index) -> Result<()> {
+        match self.collation {
+            Some(collation) => collation.check(index),
+            None => Ok(()),
+        }
+    }
+
+    /// Opens the indexed file at `path`. The index may be stored in front of the data or behind
+    /// it, see `IndexedWriter`.
+    ///
+    /// Returns an error if the index is malformed, missing or an io error occurs
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<File> {
+        let (reader, path) = file::open_buffered(path.as_ref())?;
+        if let Some((mode, blocking)) = self.lock {
+            file::lock_file(reader.get_ref(), mode, blocking).map_err(|e| e.with_path(&path))?;
+        }
+        let mut file = File::from_indexed_window(self.buffer_capacity, Window::whole(reader))
+            .map_err(|e| e.with_path(&path))?;
+        self.check_collation(file.get_index())?;
+        file.set_max_line_len(self.max_line_len);
+        let (retries, delay) = self.incomplete_retry;
+        file.set_incomplete_retry(retries, delay);
+        Ok(file.with_path(path))
+    }
+
+    /// Opens the indexed data within `reader`, whose index may be stored in front of the data or
+    /// behind it
+    pub fn open_reader<R: Read + Unpin + Seek + Send>(
+        &self,
+        reader: R,
+    ) -> Result<IndexedReader<R>> {
+        let mut reader = BufReader::new(reader);
+        let (index, section) = Index::parse_any(&mut reader)?;
+        self.check_collation(&index)?;
+        let mut reader = IndexedReader::with_capacity(
+            self.buffer_capacity,
+            reader.into_inner(),
+            Arc::new(index),
+        );
+        if let Some(section) = section {
+            reader.set_data_section(section);
+        }
+        reader.set_max_line_len(self.max_line_len);
+        let (retries, delay) = self.incomplete_retry;
+        reader.set_incomplete_retry(retries, delay);
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{temp::TempFile, Indexable, IndexableFile, ReadByLine};
+    use std::{fs, io::Cursor, ops::ControlFlow};
+
+    fn collect_lines<R: ReadByLine>(reader: &mut R) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        reader
+            .for_each_line(|_, line| {
+                lines.push(line.to_vec());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        lines
+    }
+
+    #[test]
+    fn test_delimiter() {
+        let data = b"first\0second\nline\0third".to_vec();
+        let mut reader = Builder::new()
+            .delimiter(b'\0')
+            .index_reader(Cursor::new(data))
+            .unwrap();
+
+        assert_eq!(reader.total_lines(), 3);
+        assert_eq!(reader.get_index().delimiter(), b'\0');
+        assert_eq!(
+            collect_lines(&mut reader),
+            vec![
+                b"first".to_vec(),
+                b"second\nline".to_vec(),
+                b"third".to_vec()
+            ]
+        );
+
+        // The delimiter has to survive writing and opening the indexed data
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let mut reopened = OpenBuilder::new()
+            .open_reader(Cursor::new(indexed))
+            .unwrap();
+        assert_eq!(reopened.get_index(), reader.get_index());
+        assert_eq!(reopened.get_index().delimiter(), b'\0');
+        assert_eq!(collect_lines(&mut reopened), collect_lines(&mut reader));
+    }
+
+    #[test]
+    fn test_file_wrappers() {
+        let temp = TempFile::new("builder");
+        let path = temp.path();
+
+        let mut raw = Builder::new().index_from("./testfiles/simple").unwrap();
+        raw.write_to(&mut fs::File::create(path).unwrap()).unwrap();
+
+        let mut opened = OpenBuilder::new().open(path).unwrap();
+        let mut file = File::open(path).unwrap();
+        assert_eq!(opened.get_index().delimiter(), DEFAULT_DELIMITER);
+        assert_eq!(opened.get_index(), file.get_index());
+        assert_eq!(collect_lines(&mut opened), collect_lines(&mut file));
+        assert_eq!(collect_lines(&mut opened), collect_lines(&mut raw));
+    }
+
+    #[test]
+    fn test_buffer_capacity() {
+        let temp = TempFile::new("builder_capacity");
+        let path = temp.path();
+
+        let mut raw = Builder::new()
+            .buffer_capacity(64)
+            .index_from("./testfiles/LICENSE")
+            .unwrap();
+        raw.write_to(&mut fs::File::create(path).unwrap()).unwrap();
+
+        let mut opened = OpenBuilder::new()
+            .buffer_capacity(1 << 18)
+            .open(path)
+            .unwrap();
+        assert_eq!(collect_lines(&mut opened), collect_lines(&mut raw));
+
+        let reader = OpenBuilder::new()
+            .buffer_capacity(32)
+            .open_reader(fs::File::open(path).unwrap())
+            .unwrap();
+        assert_eq!(reader.reader.capacity(), 32);
+    }
+
+    #[test]
+    fn test_skip_bom() {
+        let bom_temp = TempFile::new("bom");
+        let bom_path = bom_temp.path();
+        let indexed_temp = TempFile::new("bom_indexed");
+        let indexed_path = indexed_temp.path();
+
+        for name in ["simple", "LICENSE"] {
+            let path = format!("./testfiles/{}", name);
+            let original = fs::read(&path).unwrap();
+            let mut with_bom = b"\xEF\xBB\xBF".to_vec();
+            with_bom.extend_from_slice(&original);
+            fs::write(bom_path, &with_bom).unwrap();
+
+            let mut plain = File::open_raw(&path).unwrap();
+            let expected = collect_lines(&mut plain);
+
+            let mut skipped = Builder::new().skip_bom(true).index_from(bom_path).unwrap();
+            assert!(skipped.get_index().has_bom());
+            assert_eq!(collect_lines(&mut skipped), expected);
+            let mut data = Vec::new();
+            skipped.read_all(&mut data).unwrap();
+            assert_eq!(data, original);
+
+            // The BOM is part of the first line unless it gets skipped
+            let mut kept = File::open_raw(bom_path).unwrap();
+            assert!(!kept.get_index().has_bom());
+            assert!(kept.read_line(0).unwrap().starts_with('\u{feff}'));
+
+            // Opening the written file skips the BOM too
+            skipped
+                .write_to(&mut fs::File::create(indexed_path).unwrap())
+                .unwrap();
+            let mut reopened = File::open(indexed_path).unwrap();
+            assert!(reopened.get_index().has_bom());
+            assert_eq!(collect_lines(&mut reopened), expected);
+            reopened.read_all(&mut data).unwrap();
+            assert_eq!(data, original);
+        }
+
+        // Data without a BOM is not changed
+        let plain = Builder::new()
+            .skip_bom(true)
+            .index_reader(Cursor::new(b"\xEF\xBBa\nb".to_vec()))
+            .unwrap();
+        assert!(!plain.get_index().has_bom());
+        assert_eq!(plain.get_offset(0).unwrap(), 0);
+
+        let only_bom = Builder::new()
+            .skip_bom(true)
+            .index_reader(Cursor::new(b"\xEF\xBB\xBF".to_vec()))
+            .unwrap();
+        assert_eq!(only_bom.total_lines(), 0);
+    }
+
+    #[test]
+    fn test_max_line_len() {
+        let data = b"short\nthis line is too long\nok\n".to_vec();
+        let err = Builder::new()
+            .max_line_len(8)
+            .index_reader(Cursor::new(data.clone()))
+            .unwrap_err();
+        assert!(matches!(err, Error::LineTooLong { line: 1, len } if len > 8));
+
+        // An unterminated line spanning multiple chunks is rejected as well
+        let mut long = b"a\n".to_vec();
+        long.resize(4096, b'x');
+        let err = Builder::new()
+            .max_line_len(100)
+            .build_index(&mut BufReader::with_capacity(16, Cursor::new(long)))
+            .unwrap_err();
+        assert!(matches!(err, Error::LineTooLong { line: 1, .. }));
+
+        // Lines up to the cap are accepted, including their terminator
+        let mut reader = Builder::new()
+            .max_line_len(22)
+            .index_reader(Cursor::new(data.clone()))
+            .unwrap();
+        assert_eq!(reader.total_lines(), 3);
+        assert_eq!(reader.read_line(1).unwrap(), "this line is too long\n");
+
+        // Readers opened with a cap refuse to read longer lines
+        let mut indexed = Vec::new();
+        Builder::new()
+            .index_reader(Cursor::new(data))
+            .unwrap()
+            .write_to(&mut indexed)
+            .unwrap();
+        let mut reader = OpenBuilder::new()
+            .max_line_len(8)
+            .open_reader(Cursor::new(indexed))
+            .unwrap();
+        assert_eq!(reader.read_line(0).unwrap(), "short\n");
+        assert!(matches!(
+            reader.read_line(1),
+            Err(Error::LineTooLong { line: 1, len: 22 })
+        ));
+        assert_eq!(reader.read_line(2).unwrap(), "ok\n");
+
+        // The cap applies to the other reads of lines as well
+        let mut buf = Vec::new();
+        assert!(matches!(
+            reader.read_span(0..2, &mut buf),
+            Err(Error::LineTooLong { line: 1, len: 22 })
+        ));
+        assert!(matches!(
+            reader.read_line_prefix(1, 4, &mut buf),
+            Err(Error::LineTooLong { line: 1, len: 22 })
+        ));
+        assert!(matches!(
+            reader.read_line_at(1, 0, &mut [0; 4]),
+            Err(Error::LineTooLong { line: 1, len: 22 })
+        ));
+        assert_eq!(reader.read_span(2..3, &mut buf).unwrap(), 3);
+        assert_eq!(buf, b"ok\n");
+    }
+
+    #[test]
+    fn test_background() {
+        let temp = crate::temp::TempFile::new("builder_background");
+        fs::write(temp.path(), b"\xEF\xBB\xBFa\0bb\0ccc").unwrap();
+
+        // Building in the background uses the same options as building in the foreground
+        let builder = Builder::new().delimiter(b'\0').skip_bom(true);
+        let mut expected = builder.index_from(temp.path()).unwrap();
+        let index = builder
+            .build_background(fs::File::open(temp.path()).unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(&index, expected.get_index());
+        assert!(index.has_bom());
+        assert_eq!(index.delimiter(), b'\0');
+
+        let mut file = builder.index_from_background(temp.path()).join().unwrap();
+        assert_eq!(file.get_index(), expected.get_index());
+        assert_eq!(collect_lines(&mut file), collect_lines(&mut expected));
+
+        let err = builder
+            .max_line_len(2)
+            .index_from_background(temp.path())
+            .join()
+            .unwrap_err();
+        assert!(matches!(err, Error::LineTooLong { line: 1, len: 3 }));
+        let err = builder
+            .max_line_len(2)
+            .build_background(fs::File::open(temp.path()).unwrap())
+            .join()
+            .unwrap_err();
+        assert!(matches!(err, Error::LineTooLong { line: 1, len: 3 }));
+    }
+
+    #[test]
+    fn test_text_only() {
+        let temp = TempFile::new("binary");
+        let path = temp.path();
+        let mut binary = b"\x1F\x8B\x08\x00".to_vec();
+        binary.extend_from_slice(&[0; 64]);
+        fs::write(path, &binary).unwrap();
+
+        assert!(matches!(
+            File::open_raw_text_only(path),
+            Err(Error::NotText)
+        ));
+        assert!(File::open_raw(path).is_ok());
+        assert!(File::open_raw_text_only("./testfiles/LICENSE").is_ok());
+
+        // Only the start of the data is sampled
+        let mut data = b"text\n".repeat(DEFAULT_SNIFF_LEN / 5 + 1);
+        data.extend_from_slice(&binary);
+        let reader = Builder::new()
+            .text_only(Sniffer::new())
+            .index_reader(Cursor::new(data))
+            .unwrap();
+        assert_eq!(reader.total_lines(), DEFAULT_SNIFF_LEN / 5 + 2);
+
+        let nul_delimited = b"first\0second\0".to_vec();
+        let builder = Builder::new().delimiter(b'\0');
+        assert!(matches!(
+            builder
+                .text_only(Sniffer::new())
+                .index_reader(Cursor::new(nul_delimited.clone())),
+            Err(Error::NotText)
+        ));
+        let reader = builder
+            .text_only(Sniffer::new().max_nul_percent(20))
+            .index_reader(Cursor::new(nul_delimited))
+            .unwrap();
+        assert_eq!(reader.total_lines(), 2);
+    }
+}
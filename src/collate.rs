@@ -0,0 +1,221 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, index::Index, Result};
+
+/// A named comparator for sorting and searching lines. Binary searches only find lines if the
+/// data was sorted using the same comparator, so producers can record the collation in the header
+/// of the file and consumers can check it, see `OpenBuilder::expect_collation` and
+/// `ReadByLine::binary_search_collated`.
+///
+/// # Example
+///
+/// ```
+/// use indexed_file::{collate::Collation, IndexedString, MemFile, ReadByLine};
+/// use std::convert::TryFrom;
+///
+/// let mut file = MemFile::new();
+/// file.extend(["banana", "Apple", "cherry"]);
+/// file.sort_unstable_by(Collation::CaselessAscii.comparator());
+///
+/// let mut lines = IndexedString::try_from(file).unwrap();
+/// let found = lines.binary_search_collated(b"APPLE", Collation::CaselessAscii);
+/// assert_eq!(found.unwrap(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collation {
+    /// Compares the bytes of the lines, see `bytewise`
+    Bytewise,
+    /// Compares the lines ignoring the case of ASCII letters, see `caseless_ascii`
+    CaselessAscii,
+    /// Compares the lines ignoring the case of all letters, see `caseless_unicode`
+    CaselessUnicode,
+}
+
+impl Collation {
+    /// Compares `a` with `b` according to the collation
+    #[inline]
+    pub fn compare(self, a: &[u8], b: &[u8]) -> Ordering {
+        (self.comparator())(a, b)
+    }
+
+    /// Returns the comparator of the collation, which can be passed to sorting functions like
+    /// `MemFile::sort_unstable_by`
+    #[inline]
+    pub fn comparator(self) -> fn(&[u8], &[u8]) -> Ordering {
+        match self {
+            Self::Bytewise => bytewise,
+            Self::CaselessAscii => caseless_ascii,
+            Self::CaselessUnicode => caseless_unicode,
+        }
+    }
+
+    /// Returns `Error::CollationMismatch` if `index` records a different collation. Data without
+    /// a recorded collation is assumed to be sorted correctly.
+    pub fn check(self, index: &Index) -> Result<()> {
+        match index.collation() {
+            Some(found) if found != self => Err(Error::CollationMismatch {
+                expected: self,
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the id of the collation stored in the header
+    #[inline]
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Self::Bytewise => 0,
+            Self::CaselessAscii => 1,
+            Self::CaselessUnicode => 2,
+        }
+    }
+
+    /// Returns the collation of an id stored in the header or `None` if it's unknown
+    #[inline]
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Bytewise),
+            1 => Some(Self::CaselessAscii),
+            2 => Some(Self::CaselessUnicode),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Bytewise => "bytewise",
+            Self::CaselessAscii => "caseless_ascii",
+            Self::CaselessUnicode => "caseless_unicode",
+        })
+    }
+}
+
+/// Compares the bytes of `a` and `b`
+#[inline]
+pub fn bytewise(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+/// Compares `a` and `b` with all ASCII letters converted to lowercase. Other bytes are compared
+/// unchanged.
+#[inline]
+pub fn caseless_ascii(a: &[u8], b: &[u8]) -> Ordering {
+    let fold = |i: &u8| i.to_ascii_lowercase();
+    a.iter().map(fold).cmp(b.iter().map(fold))
+}
+
+/// Compares `a` and `b` by the characters of their lowercase mapping, see `char::to_lowercase`.
+/// Bytes which aren't valid UTF-8 sort after all characters, in their byte order.
+pub fn caseless_unicode(a: &[u8], b: &[u8]) -> Ordering {
+    fold_unicode(a).cmp(fold_unicode(b))
+}
+
+/// Returns the lowercase characters of `data` as `u32`. Invalid bytes are mapped above
+/// `char::MAX`.
+fn fold_unicode(data: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    data.utf8_chunks().flat_map(|chunk| {
+        let valid = chunk
+            .valid()
+            .chars()
+            .flat_map(char::to_lowercase)
+            .map(u32::from);
+        let invalid = chunk
+            .invalid()
+            .iter()
+            .map(|i| u32::from(char::MAX) + 1 + u32::from(*i));
+        valid.chain(invalid)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sort::{sort_file, SortOptions},
+        temp::TempFile,
+        File, Indexable, OpenBuilder, ReadByLine,
+    };
+    use std::fs;
+
+    #[test]
+    fn test_comparators() {
+        assert_eq!(bytewise(b"B", b"a"), Ordering::Less);
+        assert_eq!(caseless_ascii(b"B", b"a"), Ordering::Greater);
+        assert_eq!(caseless_ascii(b"ABC", b"abc"), Ordering::Equal);
+        assert_eq!(
+            caseless_ascii("Ä".as_bytes(), "ä".as_bytes()),
+            Ordering::Less
+        );
+        assert_eq!(
+            caseless_unicode("ÄPFEL".as_bytes(), "äpfel".as_bytes()),
+            Ordering::Equal
+        );
+        assert_eq!(caseless_unicode(b"z", b"\xff"), Ordering::Less);
+        assert_eq!(caseless_unicode(b"a\xfe", b"A\xff"), Ordering::Less);
+
+        for collation in [
+            Collation::Bytewise,
+            Collation::CaselessAscii,
+            Collation::CaselessUnicode,
+        ] {
+            assert_eq!(Collation::from_id(collation.id()), Some(collation));
+        }
+        assert_eq!(Collation::from_id(3), None);
+    }
+
+    #[test]
+    fn test_sorted_collation() {
+        let input = TempFile::new("collate_input");
+        let output = TempFile::new("collate_output");
+        fs::write(input.path(), "Émile\nbob\nAlice\nédouard\nCarl\n").unwrap();
+
+        let opts = SortOptions::new().collation(Collation::CaselessUnicode);
+        sort_file(input.path(), output.path(), opts).unwrap();
+
+        let mut file = File::open(output.path()).unwrap();
+        assert_eq!(
+            file.get_index().collation(),
+            Some(Collation::CaselessUnicode)
+        );
+        let found = file
+            .binary_search_collated("ÉDOUARD".as_bytes(), Collation::CaselessUnicode)
+            .unwrap();
+        assert_eq!(file.read_line(found).unwrap(), "édouard\n");
+        assert!(matches!(
+            file.binary_search_collated(b"bob", Collation::Bytewise),
+            Err(Error::CollationMismatch {
+                expected: Collation::Bytewise,
+                found: Collation::CaselessUnicode,
+            })
+        ));
+
+        assert!(OpenBuilder::new()
+            .expect_collation(Collation::CaselessUnicode)
+            .open(output.path())
+            .is_ok());
+        assert!(matches!(
+            OpenBuilder::new()
+                .expect_collation(Collation::CaselessAscii)
+                .open(output.path()),
+            Err(Error::CollationMismatch { .. })
+        ));
+
+        // Files without a recorded collation are accepted by every collation
+        sort_file(input.path(), output.path(), SortOptions::new()).unwrap();
+        let mut file = OpenBuilder::new()
+            .expect_collation(Collation::CaselessAscii)
+            .open(output.path())
+            .unwrap();
+        assert_eq!(file.get_index().collation(), None);
+        assert_eq!(
+            file.binary_search_collated(b"carl", Collation::Bytewise)
+                .ok(),
+            None
+        );
+    }
+}
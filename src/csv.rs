@@ -0,0 +1,227 @@
+use ::csv::{ReaderBuilder, StringRecord};
+
+use crate::{error::Error, ReadByLine, Result};
+
+/// Reads the lines of indexed data as CSV records. The index provides the boundaries of every
+/// record, so records can be accessed randomly without parsing the data before them.
+///
+/// Every line has to hold exactly one record. Quoted fields spanning multiple lines can't be read
+/// and return `Error::MultilineRecord` for the line opening them. The lines continuing such a
+/// field can't be detected and are read as records of their own.
+///
+/// ```
+/// use indexed_file::{csv::CsvIndexed, IndexedString};
+///
+/// let data = IndexedString::new_raw("name,age\nalice,31\n\"bob, jr.\",4")?;
+/// let mut csv = CsvIndexed::new(data);
+/// assert_eq!(csv.records(), 2);
+/// assert_eq!(csv.field(1, 0)?.as_deref(), Some("bob, jr."));
+/// assert_eq!(&csv.headers()?.unwrap()[1], "age");
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct CsvIndexed<R: ReadByLine> {
+    reader: R,
+    /// Whether the first line holds the header
+    has_headers: bool,
+    delimiter: u8,
+    quote: u8,
+    buf: Vec<u8>,
+}
+
+impl<R: ReadByLine> CsvIndexed<R> {
+    /// Creates a new `CsvIndexed` reading comma separated records from `reader`. The first line is
+    /// treated as header.
+    #[inline]
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            has_headers: true,
+            delimiter: b',',
+            quote: b'"',
+            buf: Vec::new(),
+        }
+    }
+
+    /// Sets whether the first line holds the header. The header is not counted as record, so
+    /// record 0 is the second line if enabled. Defaults to `true`.
+    #[inline]
+    pub fn has_headers(mut self, yes: bool) -> Self {
+        self.has_headers = yes;
+        self
+    }
+
+    /// Sets the byte separating the fields. Defaults to `,`.
+    #[inline]
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the byte quoting fields. Defaults to `"`.
+    #[inline]
+    pub fn quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Returns the amount of records, not counting the header
+    #[inline]
+    pub fn records(&self) -> usize {
+        let lines = self.reader.total_lines();
+        if self.has_headers {
+            lines.saturating_sub(1)
+        } else {
+            lines
+        }
+    }
+
+    /// Reads the header or returns `None` if headers are disabled or the data is empty
+    pub fn headers(&mut self) -> Result<Option<StringRecord>> {
+        if !self.has_headers || self.reader.total_lines() == 0 {
+            return Ok(None);
+        }
+        self.read_record(0).map(Some)
+    }
+
+    /// Reads the record `record`
+    #[inline]
+    pub fn record(&mut self, record: usize) -> Result<StringRecord> {
+        let line = self.line_of(record)?;
+        self.read_record(line)
+    }
+
+    /// Reads the field `col` of the record `record`. Returns `None` if the record has less fields.
+    #[inline]
+    pub fn field(&mut self, record: usize, col: usize) -> Result<Option<String>> {
+        Ok(self.record(record)?.get(col).map(|i| i.to_string()))
+    }
+
+    /// Returns the underlying reader
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns the underlying reader
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the line holding `record`
+    fn line_of(&self, record: usize) -> Result<usize> {
+        let records = self.records();
+        if record >= records {
+            return Err(Error::OutOfBounds {
+                line: record,
+                lines: records,
+            });
+        }
+        Ok(record + self.has_headers as usize)
+    }
+
+    fn read_record(&mut self, line: usize) -> Result<StringRecord> {
+        let read = self.reader.read_line_raw(line, &mut self.buf)?;
+        let data = &self.buf[..read];
+
+        if opens_multiline_field(data, self.delimiter, self.quote) {
+            return Err(Error::MultilineRecord { line });
+        }
+
+        let mut record = StringRecord::new();
+        ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .from_reader(data)
+            .read_record(&mut record)
+            .map_err(|source| Error::Csv { line, source })?;
+        Ok(record)
+    }
+}
+
+/// Returns `true` if a quoted field of `line` isn't closed within the line, so it spans multiple
+/// lines. Only quotes starting a field open a quoted field, quotes within unquoted fields are
+/// part of their content. Escaped quotes within quoted fields are doubled.
+fn opens_multiline_field(line: &[u8], delimiter: u8, quote: u8) -> bool {
+    let mut quoted = false;
+    let mut field_start = true;
+    let mut bytes = line.iter().peekable();
+    while let Some(byte) = bytes.next() {
+        if quoted {
+            if *byte == quote && bytes.next_if_eq(&&quote).is_none() {
+                quoted = false;
+            }
+        } else if field_start && *byte == quote {
+            quoted = true;
+        }
+        field_start = !quoted && *byte == delimiter;
+    }
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IndexedString;
+
+    #[test]
+    fn test_records() {
+        let data = "id;name\r\n1;\"a;b\"\r\n2;\"say \"\"hi\"\"\"\r\n\r\n3;c;extra";
+        let mut csv = CsvIndexed::new(IndexedString::new_raw(data).unwrap()).delimiter(b';');
+
+        assert_eq!(csv.records(), 4);
+        assert_eq!(csv.headers().unwrap().unwrap(), vec!["id", "name"]);
+        assert_eq!(csv.record(0).unwrap(), vec!["1", "a;b"]);
+        assert_eq!(csv.record(1).unwrap(), vec!["2", "say \"hi\""]);
+        assert_eq!(csv.record(2).unwrap().len(), 0);
+        assert_eq!(csv.record(3).unwrap(), vec!["3", "c", "extra"]);
+
+        // Random access in any order
+        assert_eq!(csv.field(3, 2).unwrap().as_deref(), Some("extra"));
+        assert_eq!(csv.field(0, 1).unwrap().as_deref(), Some("a;b"));
+        assert_eq!(csv.field(0, 2).unwrap(), None);
+        assert!(matches!(
+            csv.record(4),
+            Err(Error::OutOfBounds { line: 4, lines: 4 })
+        ));
+
+        // Without headers the first line is a record
+        let mut csv = csv.has_headers(false);
+        assert!(csv.headers().unwrap().is_none());
+        assert_eq!(csv.records(), 5);
+        assert_eq!(csv.record(0).unwrap(), vec!["id", "name"]);
+    }
+
+    #[test]
+    fn test_multiline_record() {
+        let data = "a,b\n1,\"first\nsecond\"\n2,x";
+        let mut csv = CsvIndexed::new(IndexedString::new_raw(data).unwrap());
+
+        assert!(matches!(
+            csv.record(0),
+            Err(Error::MultilineRecord { line: 1 })
+        ));
+        // The continuation of a field can't be told apart from an unquoted field with a quote
+        assert_eq!(csv.record(1).unwrap(), vec!["second\""]);
+        assert_eq!(csv.record(2).unwrap(), vec!["2", "x"]);
+
+        // Quotes within unquoted fields and escaped quotes don't open a field
+        let data = "a,b,c\n5\" screen,\"say \"\"hi\"\"\",x\n\"a,b\",\"\"\"\"\n1,\"\"\"open\n";
+        let mut csv = CsvIndexed::new(IndexedString::new_raw(data).unwrap());
+        assert_eq!(
+            csv.record(0).unwrap(),
+            vec!["5\" screen", "say \"hi\"", "x"]
+        );
+        assert_eq!(csv.record(1).unwrap(), vec!["a,b", "\""]);
+        assert!(matches!(
+            csv.record(2),
+            Err(Error::MultilineRecord { line: 3 })
+        ));
+
+        let mut empty = CsvIndexed::new(IndexedString::new_raw("").unwrap());
+        assert!(empty.headers().unwrap().is_none());
+        assert_eq!(empty.records(), 0);
+    }
+}
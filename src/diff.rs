@@ -0,0 +1,550 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{hash_bytes, ReadByLine, Result};
+
+/// Amount of lines compared at once by `identical`
+const IDENTICAL_BATCH: usize = 1024;
+
+/// A run of lines within the diff of two inputs `a` and `b`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffOp {
+    /// The lines `a` of the first input are equal to the lines `b` of the second input
+    Equal { a: Range<usize>, b: Range<usize> },
+    /// The lines `a` of the first input are missing in the second input
+    Delete { a: Range<usize> },
+    /// The lines `b` of the second input are missing in the first input
+    Insert { b: Range<usize> },
+}
+
+/// Returns `true` if `a` and `b` contain the same lines. The indexes are compared first, so files
+/// with a different amount of lines or different line lengths are detected without reading any
+/// data. Reading stops at the first difference.
+pub fn identical<A: ReadByLine, B: ReadByLine>(a: &mut A, b: &mut B) -> Result<bool> {
+    if a.data_len() != b.data_len() || a.get_index().offsets() != b.get_index().offsets() {
+        return Ok(false);
+    }
+
+    let lines = a.total_lines();
+    let (mut buf_a, mut buf_b) = (Vec::new(), Vec::new());
+
+    for start in (0..lines).step_by(IDENTICAL_BATCH) {
+        let end = (start + IDENTICAL_BATCH).min(lines);
+        a.read_span(start..end, &mut buf_a)?;
+        b.read_span(start..end, &mut buf_b)?;
+        if buf_a != buf_b {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Computes the line-level diff of `a` and `b` as runs of equal, deleted and inserted lines.
+/// Lines are compared including their terminators.
+///
+/// Equal lines at the beginning and the end are skipped first, using the line lengths of the
+/// indexes to detect differences without reading the lines. The remaining lines are compared
+/// using Myers' algorithm, which takes `O((N + M) * D)` time for `D` differing lines and
+/// `O(N + M)` memory.
+/// Lines are grouped by their hashes first, and lines having the same hash are compared by their
+/// bytes, so colliding hashes never make different lines equal.
+#[inline]
+pub fn diff_lines<A: ReadByLine, B: ReadByLine>(a: &mut A, b: &mut B) -> Result<Vec<DiffOp>> {
+    diff_lines_with(a, b, hash_bytes)
+}
+
+/// Computes the diff like `diff_lines`, grouping the lines using `hash`
+fn diff_lines_with<A: ReadByLine, B: ReadByLine>(
+    a: &mut A,
+    b: &mut B,
+    hash: fn(&[u8]) -> u64,
+) -> Result<Vec<DiffOp>> {
+    let (lines_a, lines_b) = (a.total_lines(), b.total_lines());
+    let (mut buf_a, mut buf_b) = (Vec::new(), Vec::new());
+
+    let mut prefix = 0;
+    while prefix < lines_a.min(lines_b)
+        && lines_equal(a, b, prefix, prefix, &mut buf_a, &mut buf_b)?
+    {
+        prefix += 1;
+    }
+
+    let mut suffix = 0;
+    while suffix < lines_a.min(lines_b) - prefix
+        && lines_equal(
+            a,
+            b,
+            lines_a - suffix - 1,
+            lines_b - suffix - 1,
+            &mut buf_a,
+            &mut buf_b,
+        )?
+    {
+        suffix += 1;
+    }
+
+    let (ids_a, ids_b) = line_ids(
+        (a, prefix..lines_a - suffix),
+        (b, prefix..lines_b - suffix),
+        hash,
+        (&mut buf_a, &mut buf_b),
+    )?;
+
+    let mut ops = Vec::new();
+    push_op(&mut ops, Edit::Equal, 0, 0, prefix);
+    let (mut pos_a, mut pos_b) = (prefix, prefix);
+    for edit in myers(&ids_a, &ids_b) {
+        push_op(&mut ops, edit, pos_a, pos_b, 1);
+        match edit {
+            Edit::Equal => {
+                pos_a += 1;
+                pos_b += 1;
+            }
+            Edit::Delete => pos_a += 1,
+            Edit::Insert => pos_b += 1,
+        }
+    }
+    push_op(&mut ops, Edit::Equal, pos_a, pos_b, suffix);
+
+    Ok(ops)
+}
+
+/// Returns `true` if line `line_a` of `a` equals line `line_b` of `b`
+fn lines_equal<A: ReadByLine, B: ReadByLine>(
+    a: &mut A,
+    b: &mut B,
+    line_a: usize,
+    line_b: usize,
+    buf_a: &mut Vec<u8>,
+    buf_b: &mut Vec<u8>,
+) -> Result<bool> {
+    let (span_a, span_b) = (a.line_span(line_a)?, b.line_span(line_b)?);
+    if span_a.end - span_a.start != span_b.end - span_b.start {
+        return Ok(false);
+    }
+
+    let read_a = a.read_line_raw(line_a, buf_a)?;
+    let read_b = b.read_line_raw(line_b, buf_b)?;
+    Ok(buf_a[..read_a] == buf_b[..read_b])
+}
+
+/// The input a line belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    A,
+    B,
+}
+
+/// Returns an id for each of the lines `lines_a` of `a` and `lines_b` of `b`. Two lines get the
+/// same id if and only if they are equal. Lines are grouped by `hash`, and each line is compared
+/// by its bytes with the first line of every id having the same hash.
+fn line_ids<A: ReadByLine, B: ReadByLine>(
+    (a, lines_a): (&mut A, Range<usize>),
+    (b, lines_b): (&mut B, Range<usize>),
+    hash: fn(&[u8]) -> u64,
+    (buf, other): (&mut Vec<u8>, &mut Vec<u8>),
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let mut read = |side: Side, line: usize, buf: &mut Vec<u8>| match side {
+        Side::A => a.read_line_raw(line, buf),
+        Side::B => b.read_line_raw(line, buf),
+    };
+
+    // The first line of every id
+    let mut firsts: Vec<(Side, usize)> = Vec::new();
+    let mut by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut ids_a = Vec::with_capacity(lines_a.len());
+    let mut ids_b = Vec::with_capacity(lines_b.len());
+
+    let lines = lines_a
+        .map(|line| (Side::A, line))
+        .chain(lines_b.map(|line| (Side::B, line)));
+    for (side, line) in lines {
+        let len = read(side, line, buf)?;
+        let candidates = by_hash.entry(hash(&buf[..len])).or_default();
+
+        let mut id = None;
+        for &candidate in candidates.iter() {
+            let (first_side, first_line) = firsts[candidate];
+            let first_len = read(first_side, first_line, other)?;
+            if buf[..len] == other[..first_len] {
+                id = Some(candidate);
+                break;
+            }
+        }
+
+        let id = id.unwrap_or_else(|| {
+            firsts.push((side, line));
+            candidates.push(firsts.len() - 1);
+            firsts.len() - 1
+        });
+        match side {
+            Side::A => ids_a.push(id),
+            Side::B => ids_b.push(id),
+        }
+    }
+
+    Ok((ids_a, ids_b))
+}
+
+/// A single step of an edit script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Appends `len` lines of `edit` at the positions `a` and `b` to `ops`, extending the last run if
+/// it has the same kind
+fn push_op(ops: &mut Vec<DiffOp>, edit: Edit, a: usize, b: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+
+    match (ops.last_mut(), edit) {
+        (Some(DiffOp::Equal { a: ra, b: rb }), Edit::Equal) => {
+            ra.end += len;
+            rb.end += len;
+        }
+        (Some(DiffOp::Delete { a: ra }), Edit::Delete) => ra.end += len,
+        (Some(DiffOp::Insert { b: rb }), Edit::Insert) => rb.end += len,
+        (_, Edit::Equal) => ops.push(DiffOp::Equal {
+            a: a..a + len,
+            b: b..b + len,
+        }),
+        (_, Edit::Delete) => ops.push(DiffOp::Delete { a: a..a + len }),
+        (_, Edit::Insert) => ops.push(DiffOp::Insert { b: b..b + len }),
+    }
+}
+
+/// Computes the shortest edit script turning `a` into `b` using the linear space variant of
+/// Myers' algorithm, which splits the inputs at the middle of a shortest edit script and
+/// recurses into both halves. Only two vectors of diagonals are kept, so memory stays
+/// `O(N + M)` regardless of the amount of differences.
+fn myers(a: &[usize], b: &[usize]) -> Vec<Edit> {
+    let max = (a.len() + b.len()).div_ceil(2) + 1;
+    let mut forward = Diagonals::new(max);
+    let mut backward = Diagonals::new(max);
+
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    conquer(a, b, &mut forward, &mut backward, &mut edits);
+
+    // Put the deletions in front of the insertions between two equal lines
+    for run in edits.split_mut(|edit| *edit == Edit::Equal) {
+        let deleted = run.iter().filter(|edit| **edit == Edit::Delete).count();
+        let (deletes, inserts) = run.split_at_mut(deleted);
+        deletes.fill(Edit::Delete);
+        inserts.fill(Edit::Insert);
+    }
+    edits
+}
+
+/// The furthest reaching x position on each diagonal `k = x - y`, indexed by `k`
+struct Diagonals {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max: usize) -> Self {
+        Self {
+            offset: max as isize,
+            v: vec![0; 2 * max + 1],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for Diagonals {
+    type Output = usize;
+
+    #[inline]
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for Diagonals {
+    #[inline]
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+/// Appends the edit script turning `a` into `b` to `edits`
+fn conquer(
+    a: &[usize],
+    b: &[usize],
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix(a, b);
+    edits.extend((0..prefix).map(|_| Edit::Equal));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+
+    let suffix = common_suffix(a, b);
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.is_empty() {
+        edits.extend(b.iter().map(|_| Edit::Insert));
+    } else if b.is_empty() {
+        edits.extend(a.iter().map(|_| Edit::Delete));
+    } else {
+        let (x, y) = middle_snake(a, b, forward, backward);
+        conquer(&a[..x], &b[..y], forward, backward, edits);
+        conquer(&a[x..], &b[y..], forward, backward, edits);
+    }
+
+    edits.extend((0..suffix).map(|_| Edit::Equal));
+}
+
+/// Returns a point on a shortest edit script of `a` and `b`, which is found by searching from
+/// both ends until the paths overlap. `a` and `b` must not be empty and must differ in their
+/// first and last elements, so the point is never at one of the ends.
+fn middle_snake(
+    a: &[usize],
+    b: &[usize],
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+) -> (usize, usize) {
+    let (n, m) = (a.len(), b.len());
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    forward[1] = 0;
+    backward[1] = 0;
+
+    let max = ((n + m).div_ceil(2) + 1) as isize;
+    for d in 0..max {
+        for k in (-d..=d).rev().step_by(2) {
+            let start = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+            let y = (start as isize - k) as usize;
+            let mut x = start;
+            if x < n && y < m {
+                x += common_prefix(&a[x..], &b[y..]);
+            }
+            forward[k] = x;
+
+            if odd && (k - delta).abs() < d && forward[k] + backward[delta - k] >= n {
+                return (start, y);
+            }
+        }
+
+        // Positions of the backward search are counted from the ends of `a` and `b`
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let len = common_suffix(&a[..n - x], &b[..m - y]);
+                x += len;
+                y += len;
+            }
+            backward[k] = x;
+
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[delta - k] >= n {
+                return (n - x, m - y);
+            }
+        }
+    }
+
+    unreachable!("the searches overlap after at most (N + M) / 2 steps")
+}
+
+/// Returns the amount of equal elements at the beginning of `a` and `b`
+fn common_prefix(a: &[usize], b: &[usize]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns the amount of equal elements at the end of `a` and `b`
+fn common_suffix(a: &[usize], b: &[usize]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IndexedString;
+    use std::fs;
+
+    fn diff(a: &str, b: &str) -> Vec<DiffOp> {
+        let mut a = IndexedString::new_raw(a).unwrap();
+        let mut b = IndexedString::new_raw(b).unwrap();
+        diff_lines(&mut a, &mut b).unwrap()
+    }
+
+    /// Rebuilds `b` using the lines of `a` and the inserted lines of `b`
+    fn apply(a: &str, b: &str, ops: &[DiffOp]) -> String {
+        let lines_a: Vec<_> = a.split_inclusive('\n').collect();
+        let lines_b: Vec<_> = b.split_inclusive('\n').collect();
+
+        let mut out = String::new();
+        for op in ops {
+            match op {
+                DiffOp::Equal { a, b } => {
+                    assert_eq!(&lines_a[a.clone()], &lines_b[b.clone()]);
+                    out.extend(lines_a[a.clone()].iter().copied());
+                }
+                DiffOp::Delete { .. } => {}
+                DiffOp::Insert { b } => out.extend(lines_b[b.clone()].iter().copied()),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_diff_simple() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nx\nc\n"),
+            vec![
+                DiffOp::Equal { a: 0..1, b: 0..1 },
+                DiffOp::Delete { a: 1..2 },
+                DiffOp::Insert { b: 1..2 },
+                DiffOp::Equal { a: 2..3, b: 2..3 },
+            ]
+        );
+        assert_eq!(diff("a\n", "a\n"), vec![DiffOp::Equal { a: 0..1, b: 0..1 }]);
+        assert_eq!(diff("", "a\nb"), vec![DiffOp::Insert { b: 0..2 }]);
+        assert_eq!(diff("a\nb", ""), vec![DiffOp::Delete { a: 0..2 }]);
+        assert!(diff("", "").is_empty());
+
+        // The last line differs by its terminator
+        assert_eq!(
+            diff("a\nb", "a\nb\n"),
+            vec![
+                DiffOp::Equal { a: 0..1, b: 0..1 },
+                DiffOp::Delete { a: 1..2 },
+                DiffOp::Insert { b: 1..2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_mutated() {
+        let original = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let lines: Vec<_> = original.split_inclusive('\n').collect();
+
+        let mut deleted = lines.clone();
+        deleted.drain(10..20);
+        let mut inserted = lines.clone();
+        inserted.insert(5, "new line\n");
+        inserted.insert(300, "another new line\n");
+        let mut changed = lines.clone();
+        changed[0] = "changed\n";
+        changed[lines.len() - 1] = "changed too\n";
+        changed.swap(100, 200);
+
+        for mutated in [deleted, inserted, changed, lines.clone()] {
+            let mutated: String = mutated.concat();
+            let ops = diff(&original, &mutated);
+            assert_eq!(apply(&original, &mutated, &ops), mutated);
+
+            let mut a = IndexedString::new_raw(&original).unwrap();
+            let mut b = IndexedString::new_raw(&mutated).unwrap();
+            assert_eq!(identical(&mut a, &mut b).unwrap(), original == mutated);
+        }
+    }
+
+    #[test]
+    fn test_diff_colliding_hashes() {
+        // Every line has the same hash, so lines can only be told apart by their bytes
+        let (a, b) = ("p\na\nb\nc\np\n", "p\nb\nx\nc\np\n");
+        let ops = diff_lines_with(
+            &mut IndexedString::new_raw(a).unwrap(),
+            &mut IndexedString::new_raw(b).unwrap(),
+            |_| 0,
+        )
+        .unwrap();
+        assert_eq!(ops, diff(a, b));
+        assert_eq!(
+            ops,
+            vec![
+                DiffOp::Equal { a: 0..1, b: 0..1 },
+                DiffOp::Delete { a: 1..2 },
+                DiffOp::Equal { a: 2..3, b: 1..2 },
+                DiffOp::Insert { b: 2..3 },
+                DiffOp::Equal { a: 3..5, b: 3..5 },
+            ]
+        );
+    }
+
+    /// Returns the amount of deleted and inserted lines of `ops`
+    fn changed(ops: &[DiffOp]) -> usize {
+        ops.iter()
+            .map(|op| match op {
+                DiffOp::Equal { .. } => 0,
+                DiffOp::Delete { a } => a.len(),
+                DiffOp::Insert { b } => b.len(),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_diff_shortest() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        for seed in 0..200u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let a: Vec<usize> = (0..rng.gen_range(0..12))
+                .map(|_| rng.gen_range(0..4))
+                .collect();
+            let b: Vec<usize> = (0..rng.gen_range(0..12))
+                .map(|_| rng.gen_range(0..4))
+                .collect();
+
+            // The shortest edit script keeps the longest common subsequence
+            let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    lcs[i][j] = if a[i] == b[j] {
+                        lcs[i + 1][j + 1] + 1
+                    } else {
+                        lcs[i + 1][j].max(lcs[i][j + 1])
+                    };
+                }
+            }
+
+            let text =
+                |lines: &[usize]| -> String { lines.iter().map(|i| format!("{}\n", i)).collect() };
+            let (a_text, b_text) = (text(&a), text(&b));
+            let ops = diff(&a_text, &b_text);
+            let case = format!("seed {}", seed);
+            assert_eq!(apply(&a_text, &b_text, &ops), b_text, "{}", case);
+            assert_eq!(changed(&ops), a.len() + b.len() - 2 * lcs[0][0], "{}", case);
+        }
+    }
+
+    #[test]
+    fn test_diff_large() {
+        // 100k lines with 1000 changed lines spread over the whole input
+        let a: String = (0..100_000).map(|i| format!("line {}\n", i)).collect();
+        let b: String = (0..100_000)
+            .map(|i| match i % 100 {
+                0 => format!("changed {}\n", i),
+                _ => format!("line {}\n", i),
+            })
+            .collect();
+
+        let ops = diff(&a, &b);
+        assert_eq!(changed(&ops), 2000);
+        assert_eq!(apply(&a, &b, &ops), b);
+    }
+
+    #[test]
+    fn test_identical_same_lengths() {
+        let mut a = IndexedString::new_raw("abc\ndef\n").unwrap();
+        let mut b = IndexedString::new_raw("abc\ndeg\n").unwrap();
+        assert!(!identical(&mut a, &mut b).unwrap());
+        assert!(identical(&mut a.clone(), &mut a).unwrap());
+    }
+}
@@ -0,0 +1,591 @@
+use std::{
+    convert::TryFrom,
+    fs,
+    io::{self, BufWriter, Seek, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::Error, index::Index, temp::TempFile, trim_terminator, Fnv1a, IndexableFile, ReadByLine,
+    Result,
+};
+
+/// Statistics about a filtered copy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    /// Amount of lines written to the output
+    pub kept: usize,
+    /// Amount of lines which were filtered out
+    pub dropped: usize,
+    /// Amount of bytes written to the output, including header and index
+    pub bytes_written: usize,
+}
+
+/// Writes the lines of `reader` as new indexed data into `writer`, replacing the lines given in
+/// `edits` by their new content. Replacements are given without terminators and keep the
+/// terminator of the line they replace. If a line is edited multiple times, the last edit wins.
+/// Returns the amount of bytes written, including header and index.
+///
+/// The lines are streamed from `reader`, so only a single line is kept in memory. Since the index
+/// has to be written before the data, the new data is buffered in a temporary file.
+pub fn rewrite_with<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    edits: &[(usize, Vec<u8>)],
+) -> Result<usize>
+where
+    R: ReadByLine,
+    W: Write,
+{
+    let lines = reader.total_lines();
+    let delimiter = reader.get_index().delimiter();
+
+    let mut sorted: Vec<_> = edits.iter().enumerate().collect();
+    sorted.sort_by_key(|(_, (line, _))| *line);
+    for (entry, (line, content)) in &sorted {
+        if *line >= lines {
+            return Err(Error::OutOfBounds { line: *line, lines });
+        }
+        if content.contains(&delimiter) {
+            return Err(Error::ContainsNewline { entry: *entry });
+        }
+    }
+
+    let mut out = DataWriter::new(delimiter)?;
+    let mut edits = sorted.into_iter().map(|(_, edit)| edit).peekable();
+    let mut buf = Vec::new();
+
+    for line in 0..lines {
+        let read = reader.read_line_raw(line, &mut buf)?;
+        let original = &buf[..read];
+
+        // Only apply the last edit of the line
+        let mut replacement = None;
+        while let Some((_, content)) = edits.next_if(|(l, _)| *l == line) {
+            replacement = Some(content);
+        }
+
+        match replacement {
+            Some(content) => {
+                let terminated = original.last() == Some(&delimiter);
+                out.push_line(content, terminated)?;
+            }
+            None => out.push_raw(original)?,
+        }
+    }
+
+    out.finish(writer)
+}
+
+/// Writes all lines of `reader` for which `keep` returns `true` as new indexed data into
+/// `writer`. `keep` gets called with the line-index and the content of the line without its
+/// terminator. Kept lines are written including their original terminators, so their bytes stay
+/// unchanged. If all lines are filtered out, an empty indexed file is written.
+///
+/// Like `rewrite_with`, the kept lines are buffered in a temporary file.
+pub fn filter_to<R, W, F>(reader: &mut R, writer: &mut W, mut keep: F) -> Result<FilterStats>
+where
+    R: IndexableFile,
+    W: Write,
+    F: FnMut(usize, &[u8]) -> bool,
+{
+    let delimiter = reader.get_index().delimiter();
+    let mut out = DataWriter::new(delimiter)?;
+    let mut stats = FilterStats::default();
+    let mut buf = Vec::new();
+
+    for line in 0..reader.total_lines() {
+        reader.seek_line(line)?;
+        let read = reader.read_current_line(&mut buf, line)?;
+        let content = &buf[..read];
+
+        if keep(line, trim_terminator(content, delimiter)) {
+            out.push_raw(content)?;
+            stats.kept += 1;
+        } else {
+            stats.dropped += 1;
+        }
+    }
+
+    stats.bytes_written = out.finish(writer)?;
+    Ok(stats)
+}
+
+/// Amount of lines read at once when copying spans of lines
+const COPY_BATCH: usize = 1024;
+
+/// Splits `reader` into shards of `lines_per_shard` lines, each written as standalone indexed
+/// file into `out_dir`. The file names are built from `name_pattern` by replacing `{}` with the
+/// number of the shard, starting at 0. Returns the paths of all shards in order. The last shard
+/// may contain fewer lines and no shards are written for empty input.
+///
+/// The shards are built from the index of `reader`, so the data is never scanned for newlines.
+///
+/// # Panics
+///
+/// Panics if `lines_per_shard` is 0.
+pub fn split_into<R: ReadByLine>(
+    reader: &mut R,
+    lines_per_shard: usize,
+    out_dir: &Path,
+    name_pattern: &str,
+) -> Result<Vec<PathBuf>> {
+    assert!(lines_per_shard > 0, "lines_per_shard must not be 0");
+
+    let lines = reader.total_lines();
+    let mut shards = Vec::with_capacity(lines.div_ceil(lines_per_shard));
+
+    for (nr, start) in (0..lines).step_by(lines_per_shard).enumerate() {
+        let path = out_dir.join(name_pattern.replace("{}", &nr.to_string()));
+        let end = (start + lines_per_shard).min(lines);
+
+        let file = fs::File::create(&path).map_err(|e| Error::from(e).with_path(&path))?;
+        let mut writer = BufWriter::new(file);
+        write_span(reader, start..end, &mut writer)
+            .and_then(|_| Ok(writer.flush()?))
+            .map_err(|e| e.with_path(&path))?;
+
+        shards.push(path);
+    }
+
+    Ok(shards)
+}
+
+/// Writes `lines` of `reader` as indexed data into `writer`. The index is taken from `reader`,
+/// and the data is hashed while copying it.
+fn write_span<R: ReadByLine, W: Write + Seek>(
+    reader: &mut R,
+    lines: Range<usize>,
+    writer: &mut W,
+) -> Result<usize> {
+    let base = reader.get_offset(lines.start)?;
+    let mut index = Index::new(
+        reader.get_index().offsets()[lines.clone()]
+            .iter()
+            .map(|i| i - base),
+    );
+    index.set_delimiter(reader.get_index().delimiter());
+
+    index.write_hashed(writer, |writer| {
+        let mut copied = 0;
+        for_each_batch(reader, lines, |data| {
+            writer.write_all(data)?;
+            copied += data.len() as u64;
+            Ok(())
+        })?;
+        Ok(copied)
+    })
+}
+
+/// Writes the concatenation of `a` and `b` as indexed data into `writer` without scanning the
+/// data for newlines. Only the data from the first line onward is copied, so data in front of
+/// the first line, like a skipped BOM, is dropped. The offsets of `a` are rebased to the start of
+/// its first line, followed by the offsets of `b` rebased the same way and shifted by the amount
+/// of bytes copied from `a`. If the last line of `a` has no terminator, it stays a separate line
+/// in the index. The output uses the delimiter of `a`. Returns the amount of bytes written,
+/// including header and index. The data is hashed while copying it and the hash gets written
+/// into the header afterwards, so `writer` has to be seekable.
+///
+/// Returns `Error::OffsetOverflow` if an offset of `b` doesn't fit into the index after shifting
+/// it and `Error::InvalidIndex` if the offsets of `a` or `b` aren't sorted.
+pub fn concat_indexed<A, B, W>(a: &mut A, b: &mut B, writer: &mut W) -> Result<usize>
+where
+    A: ReadByLine,
+    B: ReadByLine,
+    W: Write + Seek,
+{
+    let offsets_a = a.get_index().offsets();
+    let lines_a = offsets_a.len();
+    let shift = match offsets_a.first() {
+        Some(base) => a
+            .data_len()
+            .checked_sub(*base as u64)
+            .ok_or(Error::InvalidIndex { line: 0 })?,
+        None => 0,
+    };
+
+    let offsets = rebased(offsets_a, 0, 0)
+        .chain(rebased(b.get_index().offsets(), shift, lines_a))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut index = Index::new(offsets);
+    index.set_delimiter(a.get_index().delimiter());
+
+    let (lines_a, lines_b) = (0..a.total_lines(), 0..b.total_lines());
+    index.write_hashed(writer, |writer| {
+        let mut copied = 0;
+        let mut copy = |data: &[u8]| {
+            writer.write_all(data)?;
+            copied += data.len() as u64;
+            Ok(())
+        };
+        for_each_batch(a, lines_a, &mut copy)?;
+        for_each_batch(b, lines_b, &mut copy)?;
+        Ok(copied)
+    })
+}
+
+/// Returns `offsets` rebased to the first offset and shifted by `shift`. `first_line` is the line
+/// of the first offset in the output, used for errors.
+fn rebased(
+    offsets: &[u32],
+    shift: u64,
+    first_line: usize,
+) -> impl Iterator<Item = Result<u32>> + '_ {
+    let base = offsets.first().copied().unwrap_or(0);
+    offsets.iter().enumerate().map(move |(i, offset)| {
+        let line = first_line + i;
+        let rebased = offset
+            .checked_sub(base)
+            .ok_or(Error::InvalidIndex { line })?;
+        u32::try_from(rebased as u64 + shift).map_err(|_| Error::OffsetOverflow { line })
+    })
+}
+
+/// Writes the header holding `content_hash`, followed by `index`. Returns the amount of bytes
+/// written.
+fn write_head<W: Write>(index: &Index, content_hash: u64, writer: &mut W) -> Result<usize> {
+    let mut header = index.get_header();
+    header.set_content_hash(content_hash);
+    let header = header.encode();
+
+    writer.write_all(&header)?;
+    Ok(header.len() + index.encode_into(writer)?)
+}
+
+/// Calls `f` with the data of `lines`, read in batches of `COPY_BATCH` lines
+fn for_each_batch<R, F>(reader: &mut R, lines: Range<usize>, mut f: F) -> Result<()>
+where
+    R: ReadByLine,
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut buf = Vec::new();
+    for start in lines.clone().step_by(COPY_BATCH) {
+        let read = reader.read_span(start..(start + COPY_BATCH).min(lines.end), &mut buf)?;
+        f(&buf[..read])?;
+    }
+    Ok(())
+}
+
+/// Collects lines into a temporary file while building their index and hash, so they can be
+/// written as indexed data once all lines are known
+pub(crate) struct DataWriter {
+    temp: TempFile,
+    data: BufWriter<fs::File>,
+    delimiter: u8,
+    offsets: Vec<u32>,
+    offset: u64,
+    hasher: Fnv1a,
+}
+
+impl DataWriter {
+    /// Creates a new `DataWriter` for lines terminated by `delimiter`
+    pub(crate) fn new(delimiter: u8) -> Result<Self> {
+        let (temp, data) = TempFile::create("data")?;
+        let data = BufWriter::new(data);
+        Ok(Self {
+            temp,
+            data,
+            delimiter,
+            offsets: Vec::new(),
+            offset: 0,
+            hasher: Fnv1a::new(),
+        })
+    }
+
+    /// Appends `line` which is given without terminator. The delimiter is appended if
+    /// `terminated` is `true`.
+    pub(crate) fn push_line(&mut self, line: &[u8], terminated: bool) -> Result<()> {
+        self.push_offset()?;
+        self.write(line)?;
+        if terminated {
+            self.write(&[self.delimiter])?;
+        }
+        Ok(())
+    }
+
+    /// Appends `line` including its terminator, if it has one
+    pub(crate) fn push_raw(&mut self, line: &[u8]) -> Result<()> {
+        self.push_offset()?;
+        self.write(line)
+    }
+
+    /// Writes the header, the index and all pushed lines into `writer`. Returns the amount of
+    /// bytes written.
+    pub(crate) fn finish<W: Write>(mut self, writer: &mut W) -> Result<usize> {
+        self.data.flush()?;
+        drop(self.data);
+
+        let mut index = Index::new(self.offsets);
+        index.set_delimiter(self.delimiter);
+        let written = write_head(&index, self.hasher.finish(), writer)?;
+        let copied = io::copy(&mut fs::File::open(self.temp.path())?, writer)?;
+
+        Ok(written + copied as usize)
+    }
+
+    fn push_offset(&mut self) -> Result<()> {
+        let pos = u32::try_from(self.offset).map_err(|_| Error::OffsetOverflow {
+            line: self.offsets.len(),
+        })?;
+        self.offsets.push(pos);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.data.write_all(data)?;
+        self.hasher.write(data);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::HashStatus;
+    use crate::{
+        any::CloneableIndexedReader, temp::TempDir, File, Indexable, IndexableFile, IndexedString,
+    };
+    use std::{io::Cursor, sync::Arc};
+
+    fn rewrite(input: &str, edits: &[(usize, Vec<u8>)]) -> Result<String> {
+        let mut reader = IndexedString::new_raw(input).unwrap();
+        let mut out = Vec::new();
+        let written = rewrite_with(&mut reader, &mut out, edits)?;
+        assert_eq!(written, out.len());
+
+        let mut rewritten = CloneableIndexedReader::new(out).unwrap();
+        let mut data = Vec::new();
+        rewritten
+            .read_span(0..rewritten.total_lines(), &mut data)
+            .unwrap();
+        Ok(String::from_utf8(data).unwrap())
+    }
+
+    #[test]
+    fn test_rewrite_with() {
+        let edits = [(2, b"C".to_vec()), (0, b"first".to_vec())];
+        assert_eq!(rewrite("a\nb\nc\nd", &edits).unwrap(), "first\nb\nC\nd");
+        assert_eq!(rewrite("a\nb\nc", &edits).unwrap(), "first\nb\nC");
+        assert_eq!(rewrite("a\nb\nc\n", &[]).unwrap(), "a\nb\nc\n");
+
+        // The last edit of a line wins
+        let edits = [(1, b"x".to_vec()), (1, b"y".to_vec())];
+        assert_eq!(rewrite("a\nb\n", &edits).unwrap(), "a\ny\n");
+
+        assert!(matches!(
+            rewrite("a\nb", &[(2, Vec::new())]),
+            Err(Error::OutOfBounds { line: 2, lines: 2 })
+        ));
+        assert!(matches!(
+            rewrite("a\nb", &[(0, Vec::new()), (1, b"x\ny".to_vec())]),
+            Err(Error::ContainsNewline { entry: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_filter_to() {
+        let mut reader = IndexedString::new_raw("a\nbb\nc\ndd").unwrap();
+
+        let mut out = Vec::new();
+        let stats = reader
+            .filter_to(&mut out, |line, content| line == 0 || content.len() == 2)
+            .unwrap();
+        assert_eq!(stats.kept, 3);
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.bytes_written, out.len());
+
+        let mut filtered = CloneableIndexedReader::new(out).unwrap();
+        let lines: Vec<_> = (0..3).map(|i| filtered.read_line(i).unwrap()).collect();
+        assert_eq!(lines, ["a\n", "bb\n", "dd"]);
+
+        // Filtering out everything results in a valid empty file
+        let mut out = Vec::new();
+        let stats = reader.filter_to(&mut out, |_, _| false).unwrap();
+        assert_eq!(stats.kept, 0);
+        assert_eq!(stats.dropped, 4);
+        let empty = CloneableIndexedReader::new(out).unwrap();
+        assert_eq!(empty.total_lines(), 0);
+    }
+
+    #[test]
+    fn test_split_into() {
+        let original = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let mut source = File::open_raw("./testfiles/LICENSE").unwrap();
+        let lines = source.total_lines();
+
+        let temp = TempDir::create("split").unwrap();
+        let dir = temp.path();
+
+        for per_shard in [1000, 100, lines, 1] {
+            let shards = split_into(&mut source, per_shard, dir, "shard_{}.idx").unwrap();
+            assert_eq!(shards.len(), lines.div_ceil(per_shard));
+            assert_eq!(shards[0], dir.join("shard_0.idx"));
+
+            let mut joined = String::new();
+            for (nr, path) in shards.iter().enumerate() {
+                let mut shard = File::open_verified(path).unwrap();
+                assert!(shard.total_lines() > 0);
+                if nr + 1 < shards.len() {
+                    assert_eq!(shard.total_lines(), per_shard);
+                }
+                for line in 0..shard.total_lines() {
+                    joined.push_str(&shard.read_line(line).unwrap());
+                }
+            }
+            assert_eq!(joined, original);
+
+            for path in shards {
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert!(split_into(&mut empty, 10, dir, "empty_{}")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_concat_indexed() {
+        let license = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let cases = [
+            ("a\nb\n", "c\nd"),
+            ("a\nb", "c\n"),
+            ("", "c\n"),
+            ("a\n", ""),
+            (&license, &license),
+        ];
+
+        for (a, b) in cases {
+            let mut reader_a = IndexedString::new_raw(a).unwrap();
+            let mut reader_b = IndexedString::new_raw(b).unwrap();
+
+            let mut out = Cursor::new(Vec::new());
+            let written = concat_indexed(&mut reader_a, &mut reader_b, &mut out).unwrap();
+            assert_eq!(written, out.get_ref().len());
+            assert_eq!(out.position(), written as u64);
+            let mut concat = CloneableIndexedReader::new(out.into_inner()).unwrap();
+            assert_eq!(
+                concat.reader.verify().unwrap().content_hash,
+                HashStatus::Valid
+            );
+
+            // Compare with indexing the concatenated data, except for a missing terminator
+            // between both inputs which keeps the lines separate
+            let expected: Vec<_> = a
+                .split_inclusive('\n')
+                .chain(b.split_inclusive('\n'))
+                .collect();
+            assert_eq!(concat.total_lines(), expected.len());
+            for (i, line) in expected.iter().enumerate() {
+                assert_eq!(&concat.read_line(i).unwrap(), line);
+            }
+
+            if a.is_empty() || a.ends_with('\n') {
+                let naive = IndexedString::new_raw(format!("{}{}", a, b)).unwrap();
+                assert_eq!(concat.get_index().offsets(), naive.get_index().offsets());
+            }
+        }
+
+        // Offsets of `b` which don't fit into the index after shifting them
+        let mut reader_a = IndexedString::new_raw("a\n").unwrap();
+        let index = Arc::new(Index::new(vec![0, u32::MAX - 1]).zero_len());
+        let mut reader_b = IndexedString::new_custom("b\n", index);
+        assert!(matches!(
+            concat_indexed(&mut reader_a, &mut reader_b, &mut Cursor::new(Vec::new())),
+            Err(Error::OffsetOverflow { line: 2 })
+        ));
+    }
+
+    /// Concatenates `a` and `b` and returns the lines of the output
+    fn concat_lines<A: ReadByLine, B: ReadByLine>(a: &mut A, b: &mut B) -> Vec<String> {
+        let mut out = Cursor::new(Vec::new());
+        concat_indexed(a, b, &mut out).unwrap();
+        let mut concat = CloneableIndexedReader::new(out.into_inner()).unwrap();
+        assert_eq!(
+            concat.reader.verify().unwrap().content_hash,
+            HashStatus::Valid
+        );
+        (0..concat.total_lines())
+            .map(|i| concat.read_line(i).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_concat_indexed_bom() {
+        let bom = || {
+            crate::Builder::new()
+                .skip_bom(true)
+                .index_reader(Cursor::new("\u{feff}a\nb\n".as_bytes().to_vec()))
+                .unwrap()
+        };
+        let mut plain = IndexedString::new_raw("c\nd\n").unwrap();
+
+        let mut a = bom();
+        assert_eq!(a.get_index().offsets(), &[3, 5]);
+        assert_eq!(
+            concat_lines(&mut a, &mut plain),
+            ["a\n", "b\n", "c\n", "d\n"]
+        );
+        assert_eq!(
+            concat_lines(&mut plain, &mut bom()),
+            ["c\n", "d\n", "a\n", "b\n"]
+        );
+        assert_eq!(
+            concat_lines(&mut bom(), &mut bom()),
+            ["a\n", "b\n", "a\n", "b\n"]
+        );
+    }
+
+    #[test]
+    fn test_concat_indexed_custom() {
+        let custom = || {
+            let index = Arc::new(Index::new(vec![2, 4]).zero_len());
+            IndexedString::new_custom("--a\nb\n", index)
+        };
+        let mut plain = IndexedString::new_raw("c\nd").unwrap();
+
+        assert_eq!(
+            concat_lines(&mut custom(), &mut plain),
+            ["a\n", "b\n", "c\n", "d"]
+        );
+        assert_eq!(
+            concat_lines(&mut plain, &mut custom()),
+            ["c\n", "d", "a\n", "b\n"]
+        );
+
+        // Unsorted offsets can't be rebased
+        let index = Arc::new(Index::new(vec![2, 0]).zero_len());
+        let mut unsorted = IndexedString::new_custom("a\nb\n", index);
+        assert!(matches!(
+            concat_indexed(&mut unsorted, &mut plain, &mut Cursor::new(Vec::new())),
+            Err(Error::InvalidIndex { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_file() {
+        let original = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let mut source = File::open_raw("./testfiles/LICENSE").unwrap();
+
+        let temp = TempFile::new("rewrite_test");
+        let edits = [(100, b"edited".to_vec()), (3, Vec::new())];
+        source
+            .rewrite_with(&mut fs::File::create(temp.path()).unwrap(), &edits)
+            .unwrap();
+
+        let mut rewritten = File::open_verified(temp.path()).unwrap();
+        assert_eq!(rewritten.total_lines(), source.total_lines());
+        for (i, line) in original.split_inclusive('\n').enumerate() {
+            let expected = match i {
+                100 => "edited\n",
+                3 => "\n",
+                _ => line,
+            };
+            assert_eq!(rewritten.read_line(i).unwrap(), expected);
+        }
+    }
+}
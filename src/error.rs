@@ -1,16 +1,146 @@
-use std::{fmt::Display, string::FromUtf8Error};
+use crate::{collate::Collation, index::FORMAT_VERSION};
 
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+};
+
+/// The errors of this crate. Some variants only exist if their feature is enabled, so the enum
+/// is `non_exhaustive` and enabling a feature doesn't break matches of other crates.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Io(std::io::Error),
+    /// An io error which occurred while accessing the file at `path`
+    FileIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
     /// Index is not built properly
     MalformedIndex,
+    /// `line` has an offset that is out of order or outside the data. Offsets have to be greater
+    /// than the offset of the previous line and point into the data.
+    InvalidIndex {
+        line: usize,
+    },
     /// Index is missing
     MissingIndex,
     /// On reqest for a non existing index entry
-    OutOfBounds,
+    OutOfBounds {
+        line: usize,
+        lines: usize,
+    },
     UTF8Error,
     NotFound,
+    /// Seeking to a line before the first line
+    InvalidSeek,
+    /// An entry can't be used as line since it contains a newline
+    ContainsNewline {
+        entry: usize,
+    },
+    /// The operation got cancelled
+    Cancelled,
+    /// The file was written using a newer version of the format
+    UnsupportedFormat {
+        version: u16,
+    },
+    /// The file requires features which are not supported by this version
+    UnsupportedFlags {
+        flags: u16,
+    },
+    /// The data doesn't match the hash stored in the header
+    ContentMismatch,
+    /// `line` doesn't match the checksum stored in the index
+    LineCorrupted {
+        line: usize,
+    },
+    /// `line` is `len` bytes long, which exceeds the configured maximum line length
+    LineTooLong {
+        line: usize,
+        len: usize,
+    },
+    /// The data looks like binary data instead of text
+    NotText,
+    /// The file is locked by another handle
+    WouldBlock,
+    /// The data is `actual` bytes long but the index describes `expected` bytes
+    LengthMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// Lines can't be grouped into chunks of zero lines
+    ZeroChunkSize,
+    /// The permutation doesn't contain every position of the `len` entries exactly once
+    InvalidPermutation {
+        len: usize,
+    },
+    /// Only `have` of the `want` bytes of `line` could be read, since the data ends early. This
+    /// happens if the data got truncated or hasn't been written completely yet. The length of the
+    /// last line is taken from the length of the data, so `want` is 0 if the data already ended
+    /// before the last line when it was opened.
+    IncompleteLine {
+        line: usize,
+        have: usize,
+        want: usize,
+    },
+    /// The false positive rate of a bloom filter has to be within `(0, 1)`
+    InvalidFalsePositiveRate,
+    /// Reading `line` took longer than the read deadline
+    TimedOut {
+        line: usize,
+    },
+    /// The lines are sorted using the collation `found`, but `expected` was requested
+    CollationMismatch {
+        expected: Collation,
+        found: Collation,
+    },
+    /// The lines at the positions `failed` of a multi-get are out of bounds, see
+    /// `ReadByLine::read_many`
+    MultiGet {
+        failed: Vec<usize>,
+    },
+    /// `line` would start beyond the largest offset an index can hold, which is `u32::MAX`
+    OffsetOverflow {
+        line: usize,
+    },
+    /// `line` is ordered before its previous line, so the lines can't be binary searched, see
+    /// `IndexedReader::set_check_sorted`
+    Unsorted {
+        line: usize,
+    },
+    /// `line` couldn't be deserialized
+    #[cfg(feature = "json")]
+    Deserialize {
+        line: usize,
+        source: serde_json::Error,
+    },
+    /// `line` couldn't be parsed as CSV record
+    #[cfg(feature = "csv")]
+    Csv {
+        line: usize,
+        source: csv::Error,
+    },
+    /// `line` holds a quoted field which continues on the next line, so the line is not a
+    /// complete CSV record
+    #[cfg(feature = "csv")]
+    MultilineRecord {
+        line: usize,
+    },
+}
+
+impl Error {
+    /// Attaches `path` to io errors. Other errors are returned unchanged.
+    #[inline]
+    pub(crate) fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+        match self {
+            Self::Io(source) => Self::FileIo {
+                path: path.as_ref().to_path_buf(),
+                source,
+            },
+            err => err,
+        }
+    }
 }
 
 impl From<FromUtf8Error> for Error {
@@ -27,11 +157,281 @@ impl From<std::io::Error> for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl From<Error> for std::io::Error {
+    /// Unwraps io errors and wraps all other errors, so they can be returned from `Read`
+    /// implementations
+    #[inline]
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(source) => source,
+            Error::FileIo { ref source, .. } => std::io::Error::new(source.kind(), e),
+            e => std::io::Error::other(e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(source) | Self::FileIo { source, .. } => Some(source),
+            #[cfg(feature = "json")]
+            Self::Deserialize { source, .. } => Some(source),
+            #[cfg(feature = "csv")]
+            Self::Csv { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl Display for Error {
-    #[inline]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Self::Io(err) => write!(f, "io error: {}", err),
+            Self::FileIo { path, source } => {
+                write!(f, "io error on {}: {}", path.display(), source)
+            }
+            Self::MalformedIndex => write!(f, "the index is malformed"),
+            Self::InvalidIndex { line } => write!(
+                f,
+                "line {} has an offset that is out of order or outside the data",
+                line
+            ),
+            Self::MissingIndex => write!(f, "the file does not contain a valid index"),
+            Self::OutOfBounds { line, lines } => write!(
+                f,
+                "line {} is out of bounds (file has {} lines)",
+                line, lines
+            ),
+            Self::UTF8Error => write!(f, "the data is not valid UTF-8"),
+            Self::NotFound => write!(f, "no matching line found"),
+            Self::InvalidSeek => write!(f, "invalid seek to a negative line"),
+            Self::ContainsNewline { entry } => write!(f, "entry {} contains a newline", entry),
+            Self::Cancelled => write!(f, "the operation was cancelled"),
+            Self::UnsupportedFormat { version } => write!(
+                f,
+                "unsupported format version {} (newest supported version is {})",
+                version, FORMAT_VERSION
+            ),
+            Self::UnsupportedFlags { flags } => write!(
+                f,
+                "the file requires unsupported features (flags {:#06x})",
+                flags
+            ),
+            Self::ContentMismatch => write!(f, "the data does not match the stored hash"),
+            Self::LineCorrupted { line } => {
+                write!(f, "line {} does not match its stored checksum", line)
+            }
+            Self::LineTooLong { line, len } => write!(
+                f,
+                "line {} is {} bytes long, which exceeds the maximum line length",
+                line, len
+            ),
+            Self::NotText => write!(f, "the data is not text"),
+            Self::WouldBlock => write!(f, "the file is locked by another handle"),
+            Self::LengthMismatch { expected, actual } => write!(
+                f,
+                "the data is {} bytes long, but the index describes {} bytes",
+                actual, expected
+            ),
+            Self::ZeroChunkSize => write!(f, "the chunk size must not be zero"),
+            Self::InvalidPermutation { len } => write!(
+                f,
+                "the permutation doesn't contain every position of the {} entries exactly once",
+                len
+            ),
+            Self::IncompleteLine { line, have, want } => write!(
+                f,
+                "line {} is incomplete, only {} of {} bytes could be read",
+                line, have, want
+            ),
+            Self::InvalidFalsePositiveRate => {
+                write!(f, "the false positive rate has to be between 0 and 1")
+            }
+            Self::TimedOut { line } => write!(f, "reading line {} timed out", line),
+            Self::CollationMismatch { expected, found } => write!(
+                f,
+                "the lines are sorted using the collation {} but {} was requested",
+                found, expected
+            ),
+            Self::MultiGet { failed } => {
+                write!(
+                    f,
+                    "the lines at the positions {:?} are out of bounds",
+                    failed
+                )
+            }
+            Self::OffsetOverflow { line } => write!(
+                f,
+                "line {} starts beyond the largest offset an index can hold",
+                line
+            ),
+            Self::Unsorted { line } => write!(
+                f,
+                "line {} is ordered before its previous line, so the lines are not sorted",
+                line
+            ),
+            #[cfg(feature = "json")]
+            Self::Deserialize { line, source } => {
+                write!(f, "failed to deserialize line {}: {}", line, source)
+            }
+            #[cfg(feature = "csv")]
+            Self::Csv { line, source } => {
+                write!(f, "invalid CSV record on line {}: {}", line, source)
+            }
+            #[cfg(feature = "csv")]
+            Self::MultilineRecord { line } => write!(
+                f,
+                "line {} has a quoted field spanning multiple lines, which is not supported",
+                line
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error as _, io};
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "not found");
+
+        let cases = [
+            (Error::Io(not_found()), "io error: not found"),
+            (
+                Error::Io(not_found()).with_path("/tmp/file"),
+                "io error on /tmp/file: not found",
+            ),
+            (Error::MalformedIndex, "the index is malformed"),
+            (
+                Error::InvalidIndex { line: 2 },
+                "line 2 has an offset that is out of order or outside the data",
+            ),
+            (
+                Error::MissingIndex,
+                "the file does not contain a valid index",
+            ),
+            (
+                Error::OutOfBounds {
+                    line: 4123,
+                    lines: 4000,
+                },
+                "line 4123 is out of bounds (file has 4000 lines)",
+            ),
+            (Error::UTF8Error, "the data is not valid UTF-8"),
+            (Error::NotFound, "no matching line found"),
+            (Error::InvalidSeek, "invalid seek to a negative line"),
+            (
+                Error::ContainsNewline { entry: 3 },
+                "entry 3 contains a newline",
+            ),
+            (Error::Cancelled, "the operation was cancelled"),
+            (
+                Error::UnsupportedFormat { version: 7 },
+                "unsupported format version 7 (newest supported version is 1)",
+            ),
+            (
+                Error::UnsupportedFlags { flags: 4 },
+                "the file requires unsupported features (flags 0x0004)",
+            ),
+            (
+                Error::ContentMismatch,
+                "the data does not match the stored hash",
+            ),
+            (
+                Error::LineCorrupted { line: 12 },
+                "line 12 does not match its stored checksum",
+            ),
+            (
+                Error::LineTooLong { line: 3, len: 4096 },
+                "line 3 is 4096 bytes long, which exceeds the maximum line length",
+            ),
+            (Error::NotText, "the data is not text"),
+            (Error::WouldBlock, "the file is locked by another handle"),
+            (
+                Error::LengthMismatch {
+                    expected: 10,
+                    actual: 12,
+                },
+                "the data is 12 bytes long, but the index describes 10 bytes",
+            ),
+            (Error::ZeroChunkSize, "the chunk size must not be zero"),
+            (
+                Error::InvalidPermutation { len: 4 },
+                "the permutation doesn't contain every position of the 4 entries exactly once",
+            ),
+            (
+                Error::IncompleteLine {
+                    line: 5,
+                    have: 3,
+                    want: 10,
+                },
+                "line 5 is incomplete, only 3 of 10 bytes could be read",
+            ),
+            (
+                Error::InvalidFalsePositiveRate,
+                "the false positive rate has to be between 0 and 1",
+            ),
+            (Error::TimedOut { line: 4 }, "reading line 4 timed out"),
+            (
+                Error::CollationMismatch {
+                    expected: Collation::Bytewise,
+                    found: Collation::CaselessAscii,
+                },
+                "the lines are sorted using the collation caseless_ascii but bytewise was requested",
+            ),
+            (
+                Error::MultiGet { failed: vec![1, 3] },
+                "the lines at the positions [1, 3] are out of bounds",
+            ),
+            (
+                Error::OffsetOverflow { line: 7 },
+                "line 7 starts beyond the largest offset an index can hold",
+            ),
+            (
+                Error::Unsorted { line: 3 },
+                "line 3 is ordered before its previous line, so the lines are not sorted",
+            ),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let err = Error::Io(io::Error::other("inner"));
+        assert_eq!(err.source().unwrap().to_string(), "inner");
+
+        let err = err.with_path("/tmp/file");
+        assert_eq!(err.source().unwrap().to_string(), "inner");
+
+        assert!(Error::NotFound.source().is_none());
+
+        #[cfg(feature = "json")]
+        {
+            let source = serde_json::from_str::<u32>("x").unwrap_err();
+            let message = source.to_string();
+            let err = Error::Deserialize { line: 4, source };
+            assert_eq!(err.source().unwrap().to_string(), message);
+            assert_eq!(
+                err.to_string(),
+                format!("failed to deserialize line 4: {}", message)
+            );
+        }
+    }
+
+    #[test]
+    fn test_into_io() {
+        let err: io::Error = Error::Io(io::Error::new(io::ErrorKind::NotFound, "inner")).into();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "inner");
+
+        let err: io::Error = Error::UTF8Error.into();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(err.to_string(), "the data is not valid UTF-8");
     }
 }
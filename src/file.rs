@@ -1,58 +1,706 @@
 use std::{
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     fs,
-    io::{BufReader, Write},
-    path::Path,
-    sync::Arc,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use fs4::{FileExt, TryLockError};
+
 use crate::{
-    any::CloneableIndexedReader, bufreader, index::Index, string::IndexedString, Indexable,
-    IndexableFile, ReadByLine, Result,
+    any::CloneableIndexedReader,
+    bufreader,
+    builder::{Builder, OpenBuilder},
+    edit,
+    error::Error,
+    index::{self, Header, Index, IndexBuildHandle, SourceInfo},
+    iter::LineReceiver,
+    observer::ReadObserver,
+    sniff::Sniffer,
+    string::IndexedString,
+    verify::VerifyReport,
+    window::Window,
+    Indexable, IndexableFile, ReadByLine, Result,
 };
 
 /// A wrapper around `std::fs::File` which implements `ReadByLine` and holds an index of the
 /// lines.
 #[derive(Debug)]
-pub struct File(bufreader::IndexedReader<BufReader<fs::File>>);
+pub struct File {
+    inner: bufreader::IndexedReader<Window<BufReader<fs::File>>>,
+    /// Canonicalized path of the file. `None` if the file was not opened by path
+    path: Option<PathBuf>,
+    /// Whether the data was validated to be UTF-8 using `assume_utf8`
+    utf8_validated: bool,
+}
 
 impl File {
-    /// Open a new indexed file.
+    /// Open a new indexed file. The index may be stored in front of the data or behind it, see
+    /// `IndexedWriter`.
     ///
     /// Returns an error if the index is malformed, missing or an io error occurs
     #[inline]
     pub fn open<P: AsRef<Path>>(path: P) -> Result<File> {
-        let mut inner_file = BufReader::new(fs::File::open(path)?);
-        let index = Index::parse_index(&mut inner_file)?;
-        Ok(Self::from_buf_reader(inner_file, Arc::new(index)))
+        OpenBuilder::new().open(path)
+    }
+
+    /// Open a new indexed file and verifies it using `verify`. Files without a stored hash are
+    /// accepted if their index is valid.
+    ///
+    /// Returns `Error::ContentMismatch` if the data doesn't match the stored hash and
+    /// `Error::InvalidIndex` if the index is invalid
+    pub fn open_verified<P: AsRef<Path>>(path: P) -> Result<File> {
+        let mut file = Self::open(path)?;
+        file.verify()?.check()?;
+        Ok(file)
     }
 
-    /// Open a non indexed file and generates the index.
+    /// Open a non indexed file and generates the index. Use `Builder` to customize the index.
     #[inline]
     pub fn open_raw<P: AsRef<Path>>(path: P) -> Result<File> {
-        let mut inner_file = BufReader::new(fs::File::open(path)?);
-        let index = Index::build(&mut inner_file)?;
-        Ok(Self::from_buf_reader(inner_file, Arc::new(index)))
+        Builder::new().index_from(path)
+    }
+
+    /// Open a non indexed file like `open_raw`, but returns `Error::NotText` without indexing it
+    /// if its first 8 KiB look like binary data. Use `Builder::text_only` to adjust the
+    /// thresholds.
+    #[inline]
+    pub fn open_raw_text_only<P: AsRef<Path>>(path: P) -> Result<File> {
+        Builder::new().text_only(Sniffer::new()).index_from(path)
+    }
+
+    /// Open a non indexed file and generates the index on a new thread. The returned handle
+    /// allows tracking the progress and resolves to the opened `File`. Use
+    /// `Builder::index_from_background` to customize the index.
+    #[inline]
+    pub fn open_raw_background<P: AsRef<Path>>(path: P) -> IndexBuildHandle<File> {
+        Builder::new().index_from_background(path)
+    }
+
+    /// Open a non indexed file using the index stored in the sidecar file at `index_path`. If the
+    /// file has grown since the sidecar was written, only the new data gets indexed. If the file
+    /// got truncated or its indexed data was modified, the index is rebuilt entirely. In both
+    /// cases the sidecar gets rewritten. A missing or unreadable sidecar gets created.
+    ///
+    /// Modifications are detected by comparing the length and a hash of the last 4 KiB of the
+    /// indexed data, so the data doesn't have to be read entirely. Changes before that region
+    /// which keep the length of the data can't be detected and leave the index stale, so use
+    /// `open_raw` for files which might get modified in place.
+    pub fn open_with_sidecar_refresh<P, I>(path: P, index_path: I) -> Result<File>
+    where
+        P: AsRef<Path>,
+        I: AsRef<Path>,
+    {
+        let (mut inner_file, path) = open_buffered(path.as_ref())?;
+        let index_path = index_path.as_ref();
+
+        let refresh = |reader: &mut BufReader<fs::File>| -> Result<Index> {
+            let data_len = reader.seek(SeekFrom::End(0))?;
+
+            let sidecar = fs::File::open(index_path)
+                .map(BufReader::new)
+                .map_err(Error::from)
+                .and_then(|mut sidecar| {
+                    let header = Header::decode(&mut sidecar)?;
+                    let index = Index::decode(&mut sidecar, &header)?;
+                    Ok((header, index))
+                });
+
+            let mut index = match sidecar {
+                Ok((header, index)) => match header.source() {
+                    // Only use the sidecar if the indexed data is unchanged
+                    Some(source)
+                        if source.data_len <= data_len
+                            && SourceInfo::read(reader, source.data_len)? == source =>
+                    {
+                        let mut index = index.zero_len();
+                        if source.data_len == data_len {
+                            return Ok(index);
+                        }
+                        // A sidecar which doesn't fit the data gets rebuilt
+                        match index.extend_from_file(&path, source.data_len) {
+                            Ok(_) => index,
+                            Err(_) => Index::build(reader)?,
+                        }
+                    }
+                    _ => Index::build(reader)?,
+                },
+                Err(_) => Index::build(reader)?,
+            };
+
+            let mut header = index.get_header();
+            header.set_source(SourceInfo::read(reader, data_len)?);
+            index.write_to_path(index_path, &header)?;
+
+            // The index describes the raw data
+            index = index.zero_len();
+            reader.seek(SeekFrom::Start(0))?;
+            Ok(index)
+        };
+
+        let index = refresh(&mut inner_file).map_err(|e| e.with_path(&path))?;
+        Ok(Self::from_buf_reader(inner_file, Arc::new(index)).with_path(path))
     }
 
     /// Open a non indexed file and uses a custom index `index`.
     /// Expects the index to be properly built.
     #[inline]
     pub fn open_custom<P: AsRef<Path>>(path: P, index: Arc<Index>) -> Result<File> {
-        let inner_file = BufReader::new(fs::File::open(path)?);
-        Ok(Self::from_buf_reader(inner_file, index))
+        let (inner_file, path) = open_buffered(path.as_ref())?;
+        Ok(Self::from_buf_reader(inner_file, index).with_path(path))
     }
 
-    /// Creates a new `File` using an existing `_std::io::BufReader` and index
+    /// Opens the indexed data stored in the `len` bytes at `offset` within the file at `path`,
+    /// for example one of multiple indexed files packed into a single file. The header, the
+    /// index and the data are read relative to `offset` and nothing behind the region is read.
+    /// `write_to` writes the region as standalone file. The index may be stored in front of or
+    /// behind the data of the region, see `IndexedWriter`.
+    ///
+    /// Returns an error if the index is malformed, missing or an io error occurs
+    pub fn open_at<P: AsRef<Path>>(path: P, offset: u64, len: u64) -> Result<File> {
+        let (inner_file, path) = open_buffered(path.as_ref())?;
+        let open = || -> Result<File> {
+            let window = Window::new(inner_file, offset, len)?;
+            Self::from_indexed_window(bufreader::DEFAULT_BUF_CAPACITY, window)
+        };
+        let file = open().map_err(|e| e.with_path(&path))?;
+        Ok(file.with_path(path))
+    }
+
+    /// Creates a new `File` using an existing `_std::io::BufReader` and index. Since the path of
+    /// the file is unknown, `path()` will return `None`.
     #[inline(always)]
     pub fn from_buf_reader(reader: BufReader<fs::File>, index: Arc<Index>) -> File {
-        Self(bufreader::IndexedReader::new(reader, index))
+        Self::from_buf_reader_with_capacity(bufreader::DEFAULT_BUF_CAPACITY, reader, index)
+    }
+
+    /// Creates a new `File` like `from_buf_reader` whose buffer holds `capacity` bytes, see
+    /// `IndexedReader::with_capacity`.
+    #[inline]
+    pub(crate) fn from_buf_reader_with_capacity(
+        capacity: usize,
+        reader: BufReader<fs::File>,
+        index: Arc<Index>,
+    ) -> File {
+        Self::from_window(capacity, Window::whole(reader), index)
+    }
+
+    /// Creates a new `File` reading the data within `window`
+    #[inline]
+    fn from_window(
+        capacity: usize,
+        window: Window<BufReader<fs::File>>,
+        index: Arc<Index>,
+    ) -> File {
+        Self {
+            inner: bufreader::IndexedReader::with_capacity(capacity, window, index),
+            path: None,
+            utf8_validated: false,
+        }
+    }
+
+    /// Parses the index stored within `window` and creates a new `File` of its data. Indexes
+    /// stored behind the data are detected by their trailer, in which case the window gets
+    /// restricted to the data in front of the header.
+    pub(crate) fn from_indexed_window(
+        capacity: usize,
+        mut window: Window<BufReader<fs::File>>,
+    ) -> Result<File> {
+        let (index, section) = Index::parse_any(&mut window)?;
+        if let Some(section) = &section {
+            let (start, _) = window.bounds();
+            window = Window::new(window.into_inner(), start, section.len)?;
+        }
+        let mut file = Self::from_window(capacity, window, Arc::new(index));
+        if let Some(section) = section {
+            file.inner.set_data_section(section);
+        }
+        Ok(file)
+    }
+
+    /// Creates a new `File` using an existing `BufReader` of non indexed data and builds the index.
+    /// Since the path of the file is unknown, `path()` will return `None`.
+    #[inline]
+    pub fn from_buf_reader_raw(mut reader: BufReader<fs::File>) -> Result<File> {
+        let index = Builder::new().build_index(&mut reader)?;
+        Ok(Self::from_buf_reader(reader, Arc::new(index)))
+    }
+
+    /// Creates a new `File` from an already opened indexed `std::fs::File` and parses its index.
+    /// The index is read from the beginning of the file, regardless of the current position of
+    /// `file`. Since the path of the file is unknown, `path()` will return `None`.
+    ///
+    /// Returns an error if the index is malformed, missing or an io error occurs
+    pub fn from_std(file: fs::File) -> Result<File> {
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(0))?;
+        Self::from_indexed_window(bufreader::DEFAULT_BUF_CAPACITY, Window::whole(reader))
+    }
+
+    /// Creates a new `File` from an already opened non indexed `std::fs::File` and builds the
+    /// index. Since the path of the file is unknown, `path()` will return `None`.
+    #[inline]
+    pub fn from_std_raw(file: fs::File) -> Result<File> {
+        Self::from_buf_reader_raw(BufReader::new(file))
+    }
+
+    /// Writes indexed data from a stream which can't seek, like a network connection, into a new
+    /// file at `temp_path` and opens it. Unlike `CloneableIndexedReader::from_stream`, the data
+    /// doesn't have to fit into memory. The file at `temp_path` is not removed by this crate.
+    ///
+    /// Returns an error if the index is malformed, missing or an io error occurs
+    pub fn from_stream_to_file<R: Read, P: AsRef<Path>>(mut r: R, temp_path: P) -> Result<File> {
+        let temp_path = temp_path.as_ref();
+        let mut writer = io::BufWriter::new(
+            fs::File::create(temp_path).map_err(|e| Error::from(e).with_path(temp_path))?,
+        );
+        io::copy(&mut r, &mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| Error::from(e).with_path(temp_path))?;
+        drop(writer);
+        Self::open(temp_path)
+    }
+
+    /// Opens the file again using the same index, without parsing or building the index again.
+    /// Both handles have their own position and can be used independently. The duplicate keeps
+    /// the settings of the reader, like `set_max_line_len`, but not its observer.
+    ///
+    /// Returns an error if the file wasn't opened by path or can't be opened anymore
+    pub fn duplicate(&self) -> Result<File> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Unsupported, "the file wasn't opened by path")
+        })?;
+        let (inner_file, path) = open_buffered(path)?;
+        let (start, end) = self.inner.reader.get_ref().bounds();
+        let window = Window::with_bounds(inner_file, start, end)?;
+        let file = Self {
+            inner: self.inner.duplicate(window),
+            path: None,
+            utf8_validated: false,
+        };
+        Ok(file.with_path(path))
+    }
+
+    /// Opens the indexed file at `path` once per partition of its lines, see
+    /// `Indexable::partition_lines`. The index is parsed once and shared by all handles, so each
+    /// partition can be read on its own thread.
+    pub fn open_partitions<P: AsRef<Path>>(
+        path: P,
+        parts: usize,
+    ) -> Result<Vec<(Range<usize>, File)>> {
+        let file = Self::open(path)?;
+        let ranges = file.partition_lines(parts);
+
+        let mut partitions = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            partitions.push((range, file.duplicate()?));
+        }
+        Ok(partitions)
+    }
+
+    /// Reads all lines sequentially on a new thread and sends them to the returned receiver, so
+    /// processing the lines doesn't wait for the IO. At most `buffer` lines are buffered before
+    /// the thread waits for the receiver to catch up.
+    ///
+    /// The thread stops once the receiver is dropped. Its handle returns the first error, which
+    /// also ends the receiver.
+    pub fn stream_lines(mut self, buffer: usize) -> (LineReceiver, JoinHandle<Result<()>>) {
+        let (sender, receiver) = mpsc::sync_channel(buffer);
+        let handle = thread::spawn(move || {
+            let mut buf = Vec::new();
+            for line in 0..self.total_lines() {
+                let read = self.read_line_raw(line, &mut buf)?;
+                // The receiver got dropped, so nobody waits for the remaining lines
+                if sender.send((line, buf[..read].to_vec())).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        (LineReceiver::new(receiver), handle)
+    }
+
+    /// Takes an advisory lock on the file, waiting until conflicting locks of other handles are
+    /// released. The lock is released when the file gets dropped or by calling `unlock`. Handles
+    /// created using `duplicate` don't share the lock.
+    #[inline]
+    pub fn lock(&self, mode: LockMode) -> Result<()> {
+        let res = lock_file(self.std_file(), mode, true);
+        self.path_context(res)
+    }
+
+    /// Takes an advisory lock on the file like `lock`, but returns `Error::WouldBlock` instead of
+    /// waiting if another handle holds a conflicting lock.
+    #[inline]
+    pub fn try_lock(&self, mode: LockMode) -> Result<()> {
+        let res = lock_file(self.std_file(), mode, false);
+        self.path_context(res)
+    }
+
+    /// Releases the lock taken on the file
+    #[inline]
+    pub fn unlock(&self) -> Result<()> {
+        let res = FileExt::unlock(self.std_file()).map_err(Error::from);
+        self.path_context(res)
+    }
+
+    /// Returns the underlying `std::fs::File`
+    #[inline]
+    fn std_file(&self) -> &fs::File {
+        self.inner.reader.get_ref().get_ref().get_ref()
+    }
+
+    /// Writes the file like `write_to` into a new file at `path`, which is locked exclusively
+    /// while writing. Waits until readers holding a lock on the file at `path` release it. The
+    /// file gets truncated only after the lock has been taken.
+    pub fn write_to_path<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let path = path.as_ref();
+        let write = |file: &mut Self| -> Result<usize> {
+            let mut out = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            lock_file(&out, LockMode::Exclusive, true)?;
+            out.set_len(0)?;
+            file.write_to_file(&mut out, false)
+        };
+        write(self).map_err(|e| e.with_path(path))
+    }
+
+    /// Writes the file like `write_to` into `file` and calls `File::sync_all` afterwards if
+    /// `sync` is set, so the data is stored durably before the file gets renamed, for example.
+    /// Returns the amount of bytes written.
+    #[inline]
+    pub fn write_to_file(&mut self, file: &mut fs::File, sync: bool) -> Result<usize> {
+        self.write_to_file_with_progress(file, sync, |_, _| {})
+    }
+
+    /// Writes the file like `write_to_file`, calling `progress` with the amount of data bytes
+    /// copied so far and the length of the data after every chunk of 8 MiB.
+    pub fn write_to_file_with_progress<F>(
+        &mut self,
+        file: &mut fs::File,
+        sync: bool,
+        mut progress: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(u64, u64),
+    {
+        let total = self.data_len();
+        let index = self.index_arc();
+        let res = self
+            .inner
+            .write_hashed_with(&index, file, |reader, file| {
+                let mut copied = 0;
+                loop {
+                    let chunk = io::copy(&mut reader.by_ref().take(WRITE_CHUNK_LEN), file)?;
+                    if chunk == 0 {
+                        return Ok(copied);
+                    }
+                    copied += chunk;
+                    progress(copied, total);
+                }
+            })
+            .and_then(|written| {
+                if sync {
+                    file.sync_all()?;
+                }
+                Ok(written)
+            });
+        self.path_context(res)
+    }
+
+    /// Counts the lines for which `f` returns `true` like `ReadByLine::count_matching`, reading
+    /// the partitions of the lines on multiple threads. Every thread opens its own handle of the
+    /// file, see `duplicate`.
+    #[cfg(feature = "rayon")]
+    pub fn par_count_matching<F>(&self, f: F) -> Result<usize>
+    where
+        F: Fn(&[u8]) -> bool + Sync,
+    {
+        let mut readers = Vec::new();
+        for lines in self.partition_lines(rayon::current_num_threads()) {
+            readers.push((lines, self.duplicate()?));
+        }
+        crate::par_count(readers, f)
+    }
+
+    /// Returns a shared reference to the index of the file, which can be used to open other
+    /// handles of the same data using `open_custom`
+    #[inline]
+    pub fn index_arc(&self) -> Arc<Index> {
+        Arc::clone(&self.inner.index)
     }
 
-    /// Read the whole file into a String
+    /// Returns the canonicalized path of the file or `None` if the file wasn't opened by path.
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Reads the whole data of the file into `buf`, without the header and the index
     #[inline(always)]
     pub fn read_all(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
-        self.0.read_all(buf)
+        let res = self.inner.read_all(buf);
+        self.path_context(res)
+    }
+
+    /// Verifies the file by hashing its data and comparing it with the hash stored in the header,
+    /// and by checking that the offsets of the index are strictly increasing and within the data.
+    /// Files written by older versions have no hash, so the hash is reported as unknown.
+    #[inline]
+    pub fn verify(&mut self) -> Result<VerifyReport> {
+        let res = self.inner.verify();
+        self.path_context(res)
+    }
+
+    /// Validates once that the whole data is UTF-8 and that every line starts at a character
+    /// boundary. Afterwards `read_line` doesn't validate the lines it reads anymore, which speeds
+    /// up reading many lines.
+    ///
+    /// Returns `Error::UTF8Error` if the validation fails, in which case lines are still validated
+    /// on every read.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified while this `File` is in use. Reading lines of a modified file
+    /// can result in strings which are not valid UTF-8, which is undefined behavior.
+    pub unsafe fn assume_utf8(&mut self) -> Result<()> {
+        let res = self.inner.validate_utf8();
+        self.path_context(res)?;
+        self.utf8_validated = true;
+        Ok(())
+    }
+
+    /// Sets the maximum length of a line which can be read, see
+    /// `IndexedReader::set_max_line_len`
+    #[inline]
+    pub fn set_max_line_len(&mut self, max: Option<usize>) {
+        self.inner.set_max_line_len(max);
+    }
+
+    /// Sets whether binary searches check that the lines are sorted first, see
+    /// `IndexedReader::set_check_sorted`
+    #[inline]
+    pub fn set_check_sorted(&mut self, check: bool) {
+        self.inner.set_check_sorted(check);
+    }
+
+    /// Sets an observer which gets notified about every read and seek, see
+    /// `IndexedReader::set_observer`
+    #[inline]
+    pub fn set_observer(&mut self, observer: Box<dyn ReadObserver>) {
+        self.inner.set_observer(observer);
+    }
+
+    /// Sets how often reading an incomplete line is retried, see
+    /// `IndexedReader::set_incomplete_retry`
+    #[inline]
+    pub fn set_incomplete_retry(&mut self, retries: usize, delay: Duration) {
+        self.inner.set_incomplete_retry(retries, delay);
+    }
+
+    /// Limits the time reading a single line may take, see `IndexedReader::set_read_deadline`
+    #[inline]
+    pub fn set_read_deadline(&mut self, deadline: Option<Duration>) {
+        self.inner.set_read_deadline(deadline);
+    }
+
+    /// Limits the data section to `len` bytes behind the index, see
+    /// `IndexedReader::set_data_len`. The new end may split a character, so lines are validated
+    /// again until `assume_utf8` gets called.
+    #[inline]
+    pub fn set_data_len(&mut self, len: u64) {
+        self.inner.set_data_len(len);
+        self.utf8_validated = false;
+    }
+
+    /// Sets the amount of bytes read ahead during sequential reads, see
+    /// `IndexedReader::set_readahead`
+    #[inline]
+    pub fn set_readahead(&mut self, bytes: usize) {
+        self.inner.set_readahead(bytes);
+    }
+
+    /// Reads the line before the current line into `buf`, see `IndexedReader::read_prev_line`.
+    #[inline]
+    pub fn read_prev_line(&mut self, buf: &mut Vec<u8>) -> Result<Option<usize>> {
+        let res = self.inner.read_prev_line(buf);
+        self.path_context(res)
+    }
+
+    /// Writes the file into `writer` like `write_to`, storing a checksum of every line along with
+    /// the index. See `read_line_verified`.
+    #[inline]
+    pub fn write_to_with_line_checksums<W: Write + Unpin + Send>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<usize> {
+        let res = self.inner.write_to_with_line_checksums(writer);
+        self.path_context(res)
+    }
+
+    /// Builds a bloom filter of all lines, see `IndexedReader::build_bloom`
+    #[inline]
+    pub fn build_bloom(&mut self, fp_rate: f64) -> Result<()> {
+        let res = self.inner.build_bloom(fp_rate);
+        self.path_context(res)
+    }
+
+    /// Builds a bloom filter of the keys of all lines, see `IndexedReader::build_bloom_by_key`
+    #[inline]
+    pub fn build_bloom_by_key<K>(&mut self, fp_rate: f64, key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let res = self.inner.build_bloom_by_key(fp_rate, key);
+        self.path_context(res)
+    }
+
+    /// Builds a lookup table of the keys of all lines, see `IndexedReader::build_lookup_table`
+    #[inline]
+    pub fn build_lookup_table<K>(&mut self, key: K) -> Result<()>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let res = self.inner.build_lookup_table(key);
+        self.path_context(res)
+    }
+
+    /// Reads `line` like `read_line` and compares it with the checksum stored in the index.
+    /// Returns `Error::LineCorrupted` if the line doesn't match its checksum.
+    ///
+    /// Files written without `write_to_with_line_checksums` have no checksums, so their lines are
+    /// returned unverified. `Index::line_checksums` tells whether lines can be verified.
+    pub fn read_line_verified(&mut self, line: usize) -> Result<String> {
+        let mut buf = Vec::new();
+        let read = self.read_line_raw(line, &mut buf)?;
+        buf.truncate(read);
+
+        if let Some(checksums) = self.inner.index.line_checksums() {
+            if checksums.get(line) != Some(&index::line_checksum(&buf)) {
+                return Err(Error::LineCorrupted { line });
+            }
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Writes the file with the lines given in `edits` replaced into `writer`. See
+    /// `edit::rewrite_with`.
+    #[inline]
+    pub fn rewrite_with<W: Write>(
+        &mut self,
+        writer: &mut W,
+        edits: &[(usize, Vec<u8>)],
+    ) -> Result<usize> {
+        let res = edit::rewrite_with(self, writer, edits);
+        self.path_context(res)
+    }
+
+    #[inline]
+    pub(crate) fn with_path(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Opens the data of `reader`, detecting whether it's indexed, see `TryFrom<&Path>`
+    fn open_detected(mut reader: BufReader<fs::File>) -> Result<File> {
+        if index::has_magic(&mut reader)? || Index::parse_trailing(&mut reader)?.is_some() {
+            let window = Window::whole(reader);
+            Self::from_indexed_window(bufreader::DEFAULT_BUF_CAPACITY, window)
+        } else {
+            Self::from_buf_reader_raw(reader)
+        }
+    }
+
+    /// Adds the files path to io errors within `res`
+    #[inline]
+    fn path_context<T>(&self, res: Result<T>) -> Result<T> {
+        match &self.path {
+            Some(path) => res.map_err(|e| e.with_path(path)),
+            None => res,
+        }
+    }
+}
+
+/// Amount of bytes copied by `File::write_to_file_with_progress` between progress updates
+const WRITE_CHUNK_LEN: u64 = 8 * 1024 * 1024;
+
+/// Mode of an advisory lock on a file. Locks are only respected by other handles taking locks
+/// too, so they don't prevent access by programs which don't lock the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    /// Multiple handles can hold a shared lock at the same time, which is used by readers
+    Shared,
+    /// Only a single handle can hold an exclusive lock, which is used by writers
+    Exclusive,
+}
+
+/// Takes a lock on `file` which is released once `file` gets closed. If `blocking` is `false`,
+/// `Error::WouldBlock` is returned instead of waiting for a conflicting lock to be released.
+pub(crate) fn lock_file(file: &fs::File, mode: LockMode, blocking: bool) -> Result<()> {
+    // The methods are called through `FileExt` since `fs::File` has inherent methods of the same
+    // names on newer toolchains
+    let res = match (mode, blocking) {
+        (LockMode::Shared, true) => return Ok(FileExt::lock_shared(file)?),
+        (LockMode::Exclusive, true) => return Ok(FileExt::lock(file)?),
+        (LockMode::Shared, false) => FileExt::try_lock_shared(file),
+        (LockMode::Exclusive, false) => FileExt::try_lock(file),
+    };
+
+    match res {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(Error::WouldBlock),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+/// Opens the file at `path` and returns it together with its canonicalized path
+pub(crate) fn open_buffered(path: &Path) -> Result<(BufReader<fs::File>, PathBuf)> {
+    let file = fs::File::open(path).map_err(|e| Error::from(e).with_path(path))?;
+    let path = fs::canonicalize(path).map_err(|e| Error::from(e).with_path(path))?;
+    Ok((BufReader::new(file), path))
+}
+
+impl TryFrom<&Path> for File {
+    type Error = Error;
+
+    /// Opens the file at `path`, detecting whether it's indexed. Files starting with the magic
+    /// bytes of a versioned header or ending with a valid trailer are opened like `File::open`,
+    /// all other files are indexed like `File::open_raw`. Files in the legacy format have no
+    /// magic bytes and can't be told apart from non indexed data, so they are treated as non
+    /// indexed files and have to be opened using `File::open` instead.
+    fn try_from(path: &Path) -> Result<File> {
+        let (reader, path) = open_buffered(path)?;
+        let res = Self::open_detected(reader).map_err(|e| e.with_path(&path));
+        Ok(res?.with_path(path))
+    }
+}
+
+impl TryFrom<PathBuf> for File {
+    type Error = Error;
+
+    /// Opens the file at `path`, detecting whether it's indexed. See `TryFrom<&Path>`.
+    #[inline]
+    fn try_from(path: PathBuf) -> Result<File> {
+        Self::try_from(path.as_path())
+    }
+}
+
+impl TryFrom<fs::File> for File {
+    type Error = Error;
+
+    /// Opens an already opened file, detecting whether it's indexed like `TryFrom<&Path>`. The
+    /// data is read from the beginning of the file, regardless of its current position.
+    #[inline]
+    fn try_from(file: fs::File) -> Result<File> {
+        Self::open_detected(BufReader::new(file))
     }
 }
 
@@ -63,7 +711,7 @@ impl TryInto<IndexedString> for File {
     /// into the memory
     #[inline]
     fn try_into(self) -> Result<IndexedString> {
-        let mut reader = self.0;
+        let mut reader = self.inner;
         let mut buf = Vec::new();
         reader.read_all(&mut buf)?;
 
@@ -83,32 +731,707 @@ impl TryInto<CloneableIndexedReader<Vec<u8>>> for File {
     fn try_into(mut self) -> Result<CloneableIndexedReader<Vec<u8>>> {
         let mut data: Vec<u8> = Vec::new();
         self.read_all(&mut data)?;
-        Ok(CloneableIndexedReader::new_custom(data, self.0.index))
+        Ok(CloneableIndexedReader::new_custom(data, self.inner.index))
     }
 }
 
 impl Indexable for File {
     #[inline]
     fn get_index(&self) -> &Index {
-        &self.0.index
+        &self.inner.index
     }
 }
 
 impl IndexableFile for File {
     #[inline]
     fn read_current_line(&mut self, buf: &mut Vec<u8>, line: usize) -> Result<usize> {
-        self.0.read_current_line(buf, line)
+        let res = self.inner.read_current_line(buf, line);
+        self.path_context(res)
     }
 
     #[inline]
     fn seek_line(&mut self, line: usize) -> Result<()> {
-        self.0.seek_line(line)
+        let res = self.inner.seek_line(line);
+        self.path_context(res)
+    }
+
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        self.inner.current_line()
+    }
+
+    #[inline]
+    fn checks_sorted(&self) -> bool {
+        self.inner.checks_sorted()
+    }
+
+    #[inline]
+    fn data_len(&self) -> u64 {
+        self.inner.data_len()
+    }
+
+    #[inline]
+    fn absolute_position(&mut self) -> Result<u64> {
+        let res = self.inner.absolute_position();
+        self.path_context(res)
+    }
+
+    #[inline]
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        let res = self.inner.read_line_prefix(line, max, buf);
+        self.path_context(res)
+    }
+
+    #[inline]
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        let res = self.inner.read_span(lines, buf);
+        self.path_context(res)
+    }
+
+    #[inline]
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let res = self.inner.read_line_at(line, offset, buf);
+        self.path_context(res)
     }
 
     #[inline]
     fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
-        self.0.write_to(writer)
+        let res = self.inner.write_to(writer);
+        self.path_context(res)
     }
 }
 
-impl ReadByLine for File {}
+impl ReadByLine for File {
+    fn read_line(&mut self, line: usize) -> Result<String> {
+        let span = self.line_span(line)?;
+        let mut buf = Vec::with_capacity(span.end.saturating_sub(span.start) as usize);
+        let read = self.read_line_raw(line, &mut buf)?;
+        buf.truncate(read);
+
+        if !self.utf8_validated {
+            return Ok(String::from_utf8(buf)?);
+        }
+
+        // SAFETY: `assume_utf8` validated the data and made sure that every line starts at a
+        // character boundary. Its caller guarantees that the data wasn't modified since.
+        Ok(unsafe { String::from_utf8_unchecked(buf) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{temp, verify::HashStatus};
+
+    #[test]
+    fn test_path() {
+        let file = File::open_raw("./testfiles/simple").unwrap();
+        let expected = fs::canonicalize("./testfiles/simple").unwrap();
+        assert_eq!(file.path(), Some(expected.as_path()));
+        assert!(file.path().unwrap().is_absolute());
+
+        let reader = BufReader::new(fs::File::open("./testfiles/simple").unwrap());
+        let file = File::from_buf_reader(reader, file.inner.index.clone());
+        assert_eq!(file.path(), None);
+    }
+
+    #[test]
+    fn test_sidecar_refresh() {
+        let temp = temp::TempFile::new("sidecar");
+        let index_temp = temp::TempFile::new("sidecar_idx");
+        let (path, index_path) = (temp.path(), index_temp.path());
+
+        let check = |content: &str| {
+            let mut file = File::open_with_sidecar_refresh(path, index_path).unwrap();
+            let lines: Vec<_> = content.lines().collect();
+            assert_eq!(file.total_lines(), lines.len());
+            for (pos, line) in lines.iter().enumerate() {
+                let mut read = file.read_line(pos).unwrap();
+                if read.ends_with('\n') {
+                    read.pop();
+                }
+                assert_eq!(&read, line);
+            }
+        };
+
+        // Creates the sidecar
+        fs::write(path, "a\nb\nc").unwrap();
+        check("a\nb\nc");
+        let header = Header::decode(&mut fs::File::open(index_path).unwrap()).unwrap();
+        assert_eq!(header.source().unwrap().data_len, 5);
+
+        // Unchanged
+        check("a\nb\nc");
+
+        // Appended data
+        fs::write(path, "a\nb\ncd\ne\n").unwrap();
+        check("a\nb\ncd\ne\n");
+        assert_eq!(Index::from_path(index_path).unwrap().len(), 4);
+
+        // Modified in place
+        fs::write(path, "a\nbbbb\ncd\ne\nf").unwrap();
+        check("a\nbbbb\ncd\ne\nf");
+
+        // Truncated
+        fs::write(path, "x\n").unwrap();
+        check("x\n");
+        assert_eq!(Index::from_path(index_path).unwrap().len(), 1);
+
+        // Broken sidecar
+        fs::write(index_path, "garbage").unwrap();
+        check("x\n");
+
+        // Only the last 4 KiB of the indexed data are compared, so modifications before them keep
+        // using the stale index
+        let tail = "y".repeat(5000);
+        fs::write(path, format!("x\n{}\n", tail)).unwrap();
+        check(&format!("x\n{}\n", tail));
+        fs::write(path, format!("\nx{}\n", tail)).unwrap();
+        let mut file = File::open_with_sidecar_refresh(path, index_path).unwrap();
+        assert_eq!(file.total_lines(), 2);
+        assert_eq!(file.read_line(0).unwrap(), "\nx");
+
+        // Modifications within them are detected
+        fs::write(path, format!("\nx{}z\n", &tail[1..])).unwrap();
+        check(&format!("\nx{}z\n", &tail[1..]));
+
+        // A sidecar whose lines don't fit the indexed data gets rebuilt when the data grows
+        fs::write(path, "a\nb\n").unwrap();
+        let mut header = Index::new([0, 9]).get_header();
+        let source = SourceInfo::read(&mut fs::File::open(path).unwrap(), 4).unwrap();
+        header.set_source(source);
+        Index::new([0, 9])
+            .write_to_path(index_path, &header)
+            .unwrap();
+        fs::write(path, "a\nb\nc\n").unwrap();
+        check("a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_from_std() {
+        let raw_temp = temp::TempFile::new("std_raw");
+        let indexed_temp = temp::TempFile::new("std");
+        let (raw_path, indexed_path) = (raw_temp.path(), indexed_temp.path());
+        fs::write(raw_path, "a\nbb\nccc\n").unwrap();
+
+        let mut raw = File::from_std_raw(fs::File::open(raw_path).unwrap()).unwrap();
+        assert_eq!(raw.path(), None);
+        assert_eq!(raw.total_lines(), 3);
+
+        // The handle is positioned behind the written data when it gets passed to `from_std`
+        let mut handle = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(indexed_path)
+            .unwrap();
+        raw.write_to(&mut handle).unwrap();
+
+        let mut indexed = File::from_std(handle).unwrap();
+        assert_eq!(indexed.path(), None);
+        assert_eq!(indexed.get_index(), raw.get_index());
+        for line in 0..raw.total_lines() {
+            assert_eq!(
+                indexed.read_line(line).unwrap(),
+                raw.read_line(line).unwrap()
+            );
+        }
+
+        // Raw data has no index
+        assert!(File::from_std(fs::File::open(raw_path).unwrap()).is_err());
+    }
+
+    /// Writes the indexed LICENSE file into a new temporary file. `testfiles/pre_indexed` can't
+    /// be used since it gets rewritten by other tests.
+    fn indexed_license(name: &str) -> temp::TempFile {
+        let temp = temp::TempFile::new(name);
+        File::open_raw("./testfiles/LICENSE")
+            .unwrap()
+            .write_to(&mut fs::File::create(temp.path()).unwrap())
+            .unwrap();
+        temp
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let temp = indexed_license("duplicate");
+        let path = temp.path();
+        let mut file = File::open(path).unwrap();
+        let mut dup = file.duplicate().unwrap();
+
+        // The index is shared instead of being parsed again
+        assert!(Arc::ptr_eq(&file.index_arc(), &dup.index_arc()));
+        assert_eq!(dup.path(), file.path());
+
+        // Both handles read independently
+        let last = file.total_lines() - 1;
+        assert_eq!(dup.read_line(last).unwrap(), file.read_line(last).unwrap());
+        assert_eq!(dup.read_line(0).unwrap(), file.read_line(0).unwrap());
+        assert_eq!(file.read_line(1).unwrap(), dup.read_line(1).unwrap());
+
+        let handle = File::from_std_raw(fs::File::open("./testfiles/simple").unwrap()).unwrap();
+        assert!(handle.duplicate().is_err());
+
+        // The hash of an index stored behind the data is kept
+        let mut writer = crate::writer::IndexedWriter::new(Vec::new());
+        writer.write_line(b"a").unwrap();
+        writer.write_line(b"b").unwrap();
+        fs::write(path, writer.finish().unwrap()).unwrap();
+        let file = File::open(path).unwrap();
+        let mut dup = file.duplicate().unwrap();
+        assert_eq!(dup.read_line(1).unwrap(), "b\n");
+        assert_eq!(
+            dup.verify().unwrap().content_hash,
+            crate::verify::HashStatus::Valid
+        );
+    }
+
+    #[test]
+    fn test_open_partitions() {
+        let temp = indexed_license("partitions");
+        let path = temp.path();
+        let partitions = File::open_partitions(path, 4).unwrap();
+        assert_eq!(partitions.len(), 4);
+        assert!(partitions
+            .windows(2)
+            .all(|i| Arc::ptr_eq(&i[0].1.index_arc(), &i[1].1.index_arc())));
+
+        let handles: Vec<_> = partitions
+            .into_iter()
+            .map(|(range, mut file)| {
+                std::thread::spawn(move || {
+                    range
+                        .map(|line| file.read_line(line).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let lines: String = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        assert_eq!(lines, fs::read_to_string("./testfiles/LICENSE").unwrap());
+    }
+
+    #[test]
+    fn test_verify() {
+        let temp = indexed_license("verify");
+        let path = temp.path();
+        let report = File::open(path).unwrap().verify().unwrap();
+        assert_eq!(report.content_hash, HashStatus::Valid);
+        assert_eq!(report.invalid_line, None);
+        assert!(File::open_verified(path).is_ok());
+
+        // Modify the last byte of the data
+        let mut data = fs::read(path).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        fs::write(path, &data).unwrap();
+        let report = File::open(path).unwrap().verify().unwrap();
+        assert_eq!(report.content_hash, HashStatus::Mismatch);
+        assert!(!report.is_valid());
+        assert!(matches!(
+            File::open_verified(path),
+            Err(Error::ContentMismatch)
+        ));
+
+        // Files written by older versions have no hash
+        let index = File::open_raw("./testfiles/simple").unwrap().index_arc();
+        let mut legacy = (index.len() as u64).to_le_bytes().to_vec();
+        legacy.extend(index.encode());
+        legacy.extend(fs::read("./testfiles/simple").unwrap());
+        fs::write(path, &legacy).unwrap();
+        let report = File::open(path).unwrap().verify().unwrap();
+        assert_eq!(report.content_hash, HashStatus::Unknown);
+        assert!(report.is_valid());
+
+        // Offsets beyond the data
+        let mut file = File::open_custom(
+            "./testfiles/simple",
+            Arc::new(Index::new(vec![0, 1 << 20]).zero_len()),
+        )
+        .unwrap();
+        assert_eq!(file.verify().unwrap().invalid_line, Some(1));
+    }
+
+    #[test]
+    fn test_assume_utf8() {
+        let original = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+        unsafe { file.assume_utf8() }.unwrap();
+        for (i, line) in original.split_inclusive('\n').enumerate() {
+            assert_eq!(file.read_line(i).unwrap(), line);
+        }
+
+        let temp = crate::temp::TempFile::new("assume_utf8");
+        fs::write(temp.path(), b"valid\ninvalid \xff\n").unwrap();
+        let mut file = File::open_raw(temp.path()).unwrap();
+        assert!(matches!(
+            unsafe { file.assume_utf8() },
+            Err(Error::UTF8Error)
+        ));
+        assert!(matches!(file.read_line(1), Err(Error::UTF8Error)));
+
+        // A line starting within a character
+        fs::write(temp.path(), "aä\nb").unwrap();
+        let index = Arc::new(Index::new(vec![0, 2]).zero_len());
+        let mut file = File::open_custom(temp.path(), index).unwrap();
+        assert!(matches!(
+            unsafe { file.assume_utf8() },
+            Err(Error::UTF8Error)
+        ));
+
+        // Lines starting behind the data or out of order
+        for offsets in [vec![0, 9], vec![4, 0]] {
+            let index = Arc::new(Index::new(offsets).zero_len());
+            let mut file = File::open_custom(temp.path(), index).unwrap();
+            assert!(matches!(
+                unsafe { file.assume_utf8() },
+                Err(Error::UTF8Error)
+            ));
+        }
+
+        // Moving the end of the data into a character requires validating again
+        fs::write(temp.path(), "a\nä").unwrap();
+        let mut file = File::open_raw(temp.path()).unwrap();
+        unsafe { file.assume_utf8() }.unwrap();
+        file.set_data_len(3);
+        assert!(matches!(file.read_line(1), Err(Error::UTF8Error)));
+
+        // Only the shortened data is validated, which ends within the character
+        assert!(matches!(
+            unsafe { file.assume_utf8() },
+            Err(Error::UTF8Error)
+        ));
+        assert!(matches!(file.read_line(1), Err(Error::UTF8Error)));
+        file.set_data_len(2);
+        unsafe { file.assume_utf8() }.unwrap();
+        assert_eq!(file.read_line(0).unwrap(), "a\n");
+        assert_eq!(file.read_line(1).unwrap(), "");
+
+        // The header and index stored behind the data are not validated
+        let mut writer = crate::writer::IndexedWriter::new(Vec::new());
+        writer.write_line("ä".as_bytes()).unwrap();
+        writer.write_line(b"b").unwrap();
+        let data = writer.finish().unwrap();
+        let mut container = b"prefix".to_vec();
+        container.extend_from_slice(&data);
+        container.extend_from_slice(b"\xff");
+        fs::write(temp.path(), &container).unwrap();
+        let mut file = File::open_at(temp.path(), 6, data.len() as u64).unwrap();
+        unsafe { file.assume_utf8() }.unwrap();
+        assert_eq!(file.read_line(0).unwrap(), "ä\n");
+        assert_eq!(file.read_line(1).unwrap(), "b\n");
+    }
+
+    #[test]
+    fn test_try_from() {
+        let temp = indexed_license("try_from");
+        let path = temp.path();
+        let original = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let lines = original.lines().count();
+
+        let indexed = File::try_from(path).unwrap();
+        assert_eq!(indexed.total_lines(), lines);
+        assert!(indexed.get_index_byte_len() > 0);
+        assert!(indexed.path().is_some());
+
+        let raw: File = Path::new("./testfiles/LICENSE").try_into().unwrap();
+        assert_eq!(raw.total_lines(), lines);
+        assert_eq!(raw.get_index_byte_len(), 0);
+
+        let mut from_std = File::try_from(fs::File::open(path).unwrap()).unwrap();
+        assert_eq!(
+            from_std.read_line(0).unwrap(),
+            original.lines().next().unwrap().to_owned() + "\n"
+        );
+        assert!(from_std.path().is_none());
+
+        // Data shorter than the magic bytes
+        let short = crate::temp::TempFile::new("try_from_short");
+        fs::write(short.path(), "a\nb").unwrap();
+        assert_eq!(
+            File::try_from(short.path().to_path_buf())
+                .unwrap()
+                .total_lines(),
+            2
+        );
+
+        assert!(matches!(
+            File::try_from(Path::new("./testfiles/does_not_exist")),
+            Err(Error::FileIo { .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_line_verified() {
+        let temp = crate::temp::TempFile::new("line_checksums");
+        let mut raw = File::open_raw("./testfiles/LICENSE").unwrap();
+        raw.write_to_with_line_checksums(&mut fs::File::create(temp.path()).unwrap())
+            .unwrap();
+
+        let mut file = File::open(temp.path()).unwrap();
+        assert_eq!(
+            file.get_index().line_checksums().unwrap().len(),
+            raw.total_lines()
+        );
+        for line in 0..file.total_lines() {
+            assert_eq!(
+                file.read_line_verified(line).unwrap(),
+                raw.read_line(line).unwrap()
+            );
+        }
+
+        // Corrupt a single byte of the third line
+        let offset = file.get_index_byte_len() + file.get_offset(2).unwrap() as usize;
+        let mut data = fs::read(temp.path()).unwrap();
+        data[offset] ^= 0x20;
+        fs::write(temp.path(), data).unwrap();
+
+        let mut file = File::open(temp.path()).unwrap();
+        assert!(file.read_line_verified(1).is_ok());
+        assert!(matches!(
+            file.read_line_verified(2),
+            Err(Error::LineCorrupted { line: 2 })
+        ));
+
+        // Files without checksums can still be read
+        assert!(raw.get_index().line_checksums().is_none());
+        assert!(raw.read_line_verified(2).is_ok());
+    }
+
+    #[test]
+    fn test_write_to_file() {
+        let src = temp::TempFile::new("write_to_file_src");
+        let dst = temp::TempFile::new("write_to_file_dst");
+
+        // More than one chunk of data
+        let line = format!("{}\n", "x".repeat(1023));
+        let len = WRITE_CHUNK_LEN + 1000 * 1024;
+        fs::write(src.path(), line.repeat(len as usize / 1024)).unwrap();
+        let mut raw = File::open_raw(src.path()).unwrap();
+
+        let mut expected = Vec::new();
+        let expected_len = raw.write_to(&mut expected).unwrap();
+
+        let mut out = fs::File::create(dst.path()).unwrap();
+        let mut updates = Vec::new();
+        let written = raw
+            .write_to_file_with_progress(&mut out, true, |copied, total| {
+                updates.push((copied, total))
+            })
+            .unwrap();
+        assert_eq!(written, expected_len);
+        assert_eq!(updates, [(WRITE_CHUNK_LEN, len), (len, len)]);
+        assert_eq!(fs::read(dst.path()).unwrap(), expected);
+
+        // Reads after writing continue to work
+        assert_eq!(raw.read_line(3).unwrap(), line);
+        let mut out = fs::File::create(dst.path()).unwrap();
+        assert_eq!(raw.write_to_file(&mut out, false).unwrap(), expected_len);
+        let mut reopened = File::open(dst.path()).unwrap();
+        assert_eq!(reopened.read_line(1000).unwrap(), line);
+    }
+
+    #[test]
+    fn test_incomplete_line() {
+        use crate::OpenBuilder;
+        use std::thread;
+
+        let lines: Vec<_> = (0..20).map(|i| format!("line {:02}\n", i)).collect();
+        let temp = temp::TempFile::new("incomplete_line");
+        let mut data = Vec::new();
+        IndexedString::new_raw(lines.concat())
+            .unwrap()
+            .write_to(&mut data)
+            .unwrap();
+        fs::write(temp.path(), &data).unwrap();
+
+        // A small buffer, so the truncated data isn't buffered while opening the file
+        let opener = OpenBuilder::new().buffer_capacity(16);
+        let mut file = opener.open(temp.path()).unwrap();
+        let cut = data.len() - 10;
+        fs::OpenOptions::new()
+            .write(true)
+            .open(temp.path())
+            .unwrap()
+            .set_len(cut as u64)
+            .unwrap();
+
+        assert_eq!(file.read_line(10).unwrap(), lines[10]);
+        assert!(matches!(
+            file.read_line(18),
+            Err(Error::IncompleteLine {
+                line: 18,
+                have: 6,
+                want: 8
+            })
+        ));
+        assert!(matches!(
+            file.read_line(19),
+            Err(Error::IncompleteLine {
+                line: 19,
+                have: 0,
+                want: 8
+            })
+        ));
+        let mut prefix = Vec::new();
+        assert!(matches!(
+            file.read_line_prefix(18, 7, &mut prefix),
+            Err(Error::IncompleteLine { line: 18, .. })
+        ));
+        assert!(matches!(
+            file.read_span(16..19, &mut prefix),
+            Err(Error::IncompleteLine {
+                line: 16,
+                have: 22,
+                want: 24
+            })
+        ));
+        assert!(prefix.is_empty());
+        assert_eq!(file.read_line(17).unwrap(), lines[17]);
+
+        // Sequential reads going through the readahead
+        file.set_readahead(1024);
+        assert_eq!(file.read_line(16).unwrap(), lines[16]);
+        assert_eq!(file.read_line(17).unwrap(), lines[17]);
+        assert!(matches!(
+            file.read_line(18),
+            Err(Error::IncompleteLine { line: 18, .. })
+        ));
+
+        // The length of the last line is unknown if the data ends before it
+        let mut file = opener.open(temp.path()).unwrap();
+        assert!(matches!(
+            file.read_line(19),
+            Err(Error::IncompleteLine {
+                line: 19,
+                have: 0,
+                want: 0
+            })
+        ));
+        assert!(matches!(
+            file.read_span(19..20, &mut prefix),
+            Err(Error::IncompleteLine {
+                line: 19,
+                have: 0,
+                want: 0
+            })
+        ));
+
+        // Retries wait for the missing data to get written
+        let mut file = opener
+            .retry_incomplete(1000, Duration::from_millis(5))
+            .open(temp.path())
+            .unwrap();
+        let rest = data[cut..].to_vec();
+        let path = temp.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut out = fs::OpenOptions::new().append(true).open(path).unwrap();
+            out.write_all(&rest).unwrap();
+        });
+        assert_eq!(file.read_line(18).unwrap(), lines[18]);
+        writer.join().unwrap();
+        assert_eq!(file.read_line(17).unwrap(), lines[17]);
+    }
+
+    #[test]
+    fn test_lock() {
+        use crate::OpenBuilder;
+        use std::{sync::mpsc, thread, time::Duration};
+
+        let temp = temp::TempFile::new("lock");
+        let path = temp.path().to_path_buf();
+        File::open_raw("./testfiles/simple")
+            .unwrap()
+            .write_to_path(&path)
+            .unwrap();
+
+        // Shared locks don't conflict with each other
+        let shared = OpenBuilder::new().lock(LockMode::Shared);
+        let reader = shared.open(&path).unwrap();
+        let other = shared.try_lock(LockMode::Shared).open(&path).unwrap();
+        assert!(matches!(
+            OpenBuilder::new().try_lock(LockMode::Exclusive).open(&path),
+            Err(Error::WouldBlock)
+        ));
+        drop(other);
+
+        // The writer waits until the reader drops its lock
+        let (tx, rx) = mpsc::channel();
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut raw = File::open_raw("./testfiles/LICENSE").unwrap();
+                raw.write_to_path(&path).unwrap();
+                tx.send(()).unwrap();
+            })
+        };
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(reader);
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        writer.join().unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let mut raw = File::open_raw("./testfiles/LICENSE").unwrap();
+        assert_eq!(file.read_line(3).unwrap(), raw.read_line(3).unwrap());
+
+        // Locks can be taken and released after opening
+        file.lock(LockMode::Exclusive).unwrap();
+        assert!(matches!(
+            Builder::new().try_lock(LockMode::Shared).index_from(&path),
+            Err(Error::WouldBlock)
+        ));
+        file.unlock().unwrap();
+        let other = Builder::new()
+            .try_lock(LockMode::Shared)
+            .index_from(&path)
+            .unwrap();
+        assert!(matches!(
+            file.try_lock(LockMode::Exclusive),
+            Err(Error::WouldBlock)
+        ));
+        drop(other);
+        file.try_lock(LockMode::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+        let expected: Vec<_> = (0..file.total_lines())
+            .map(|i| (i, file.read_line(i).unwrap().into_bytes()))
+            .collect();
+
+        let (lines, handle) = file.duplicate().unwrap().stream_lines(4);
+        assert_eq!(lines.collect::<Vec<_>>(), expected);
+        handle.join().unwrap().unwrap();
+
+        // Dropping the receiver stops the thread while it waits for space in the channel
+        let (mut lines, handle) = file.stream_lines(1);
+        assert_eq!(lines.next().unwrap().0, 0);
+        drop(lines);
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_path_in_error() {
+        let err = File::open("./testfiles/does_not_exist").unwrap_err();
+        match err {
+            Error::FileIo { path, .. } => {
+                assert_eq!(path, Path::new("./testfiles/does_not_exist"))
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        // The empty file has no index, so decoding its header fails
+        let err = File::open("./testfiles/empty").unwrap_err();
+        match err {
+            Error::FileIo { path, .. } => {
+                assert_eq!(path, fs::canonicalize("./testfiles/empty").unwrap())
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+}
@@ -1,161 +1,1021 @@
 use std::{
-    convert::TryInto,
-    io::{prelude::*, BufReader, Read, SeekFrom},
+    convert::{TryFrom, TryInto},
+    fmt::Display,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, prelude::*, BufReader, BufWriter, Read, SeekFrom},
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use serde::{Deserialize, Serialize};
 
-use crate::{error::Error, Result};
+use crate::{
+    bloom::BloomFilter,
+    builder::Builder,
+    collate::Collation,
+    error::Error,
+    lookup::LookupTable,
+    sniff::{ContentKind, Sniffer},
+    window::Window,
+    HashingWriter, Result,
+};
+
+/// Magic bytes starting every versioned header. Legacy headers only consist of the amount of
+/// lines encoded as little endian u64, so they can't start with these bytes since that would
+/// require more than 2^32 lines.
+pub const MAGIC: [u8; 8] = *b"IDXFILE\x01";
+
+/// Length of the legacy header in bytes
+pub const LEGACY_HEADER_SIZE: usize = 8;
+
+/// Length of the fixed part of a versioned header in bytes
+pub const HEADER_SIZE: usize = 28;
+
+/// Magic bytes ending the trailer of files storing their index behind the data, see
+/// `IndexedWriter`
+pub const TRAILER_MAGIC: [u8; 8] = *b"IDXTAIL\x01";
+
+/// Length of the trailer in bytes, consisting of the offset of the header encoded as little
+/// endian u64 followed by `TRAILER_MAGIC`
+pub const TRAILER_SIZE: usize = 16;
+
+/// Amount of offsets encoded at once by `Index::encode_into`
+const ENCODE_BATCH: usize = 4096;
+
+/// The format version written by this version of the library. Version 0 is the legacy format
+/// without magic bytes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Required flags known to this version of the library. Files having other required flags set
+/// can't be read.
+const KNOWN_REQUIRED_FLAGS: u16 = REQ_LINE_CHECKSUMS | REQ_BLOOM | REQ_LOOKUP_TABLE;
+
+/// Required flag indicating that a checksum of every line follows the offsets of the index
+const REQ_LINE_CHECKSUMS: u16 = 1;
+
+/// Required flag indicating that a `BloomFilter` follows the offsets and line checksums
+const REQ_BLOOM: u16 = 1 << 1;
+
+/// Required flag indicating that a `LookupTable` follows the bloom filter
+const REQ_LOOKUP_TABLE: u16 = 1 << 2;
+
+/// Optional flag indicating that the header contains a `SourceInfo`
+const OPT_SOURCE: u32 = 1;
+
+/// Optional flag indicating that lines are terminated by a custom delimiter
+const OPT_DELIMITER: u32 = 1 << 1;
+
+/// Optional flag indicating that the header contains a hash of the data section
+const OPT_CONTENT_HASH: u32 = 1 << 2;
+
+/// Optional flag indicating that the data starts with a UTF-8 BOM which is not part of the first
+/// line. This flag has no extension data.
+const OPT_BOM: u32 = 1 << 3;
 
-/// Length of header in bytes
-const HEADER_SIZE: usize = 8;
+/// Optional flag indicating that the header contains the id of the `Collation` the lines are
+/// sorted by
+const OPT_COLLATION: u32 = 1 << 4;
 
-/// An index header
-#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+/// Optional flag indicating that the header and the index are stored behind the data and are
+/// followed by a trailer, see `TRAILER_SIZE`. This flag has no extension data.
+const OPT_INDEX_AT_END: u32 = 1 << 5;
+
+/// Optional flags known to this version of the library. The extension data of optional features
+/// is stored in the order of their flags, so data of unknown flags always follows the known data.
+const KNOWN_OPTIONAL_FLAGS: u32 =
+    OPT_SOURCE | OPT_DELIMITER | OPT_CONTENT_HASH | OPT_BOM | OPT_COLLATION | OPT_INDEX_AT_END;
+
+/// The UTF-8 byte order mark
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+/// The byte terminating lines if no other delimiter is set
+pub const DEFAULT_DELIMITER: u8 = b'\n';
+
+/// Amount of bytes hashed at the end of the data to detect modifications
+const TAIL_HASH_LEN: u64 = 4096;
+
+/// Capacity of the buffer used when building an index in the background. Progress is reported
+/// after every chunk of this size.
+pub(crate) const BACKGROUND_CHUNK_SIZE: usize = 1 << 20;
+
+/// An index header.
+///
+/// The versioned header is laid out as follows, using little endian for all numbers:
+///
+/// | bytes | content                                      |
+/// |-------|----------------------------------------------|
+/// | 8     | magic bytes `IDXFILE\x01`                    |
+/// | 2     | format version                               |
+/// | 2     | required flags                               |
+/// | 4     | optional flags                               |
+/// | 8     | amount of lines                              |
+/// | 4     | length of the extension                      |
+/// | n     | extension holding data for optional features |
+///
+/// Readers must refuse files having unknown required flags set, while unknown optional flags
+/// and their extension data are ignored.
+///
+/// # Example
+///
+/// ```
+/// use indexed_file::{index::Header, IndexableFile, IndexedString};
+/// use std::io::Cursor;
+///
+/// let mut data = Vec::new();
+/// IndexedString::new_raw("a\nb\n")?.write_to(&mut data)?;
+///
+/// let header = Header::decode(&mut Cursor::new(&data))?;
+/// assert_eq!(header.items(), 2);
+/// assert_eq!(header.version(), indexed_file::index::FORMAT_VERSION);
+/// assert_eq!(header.encode(), data[..header.byte_len()]);
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+///
+/// The header is followed by the offset of every line. If the required flag `1` is set, the
+/// offsets are followed by a 4 byte checksum of every line. If the required flag `2` is set, a
+/// `BloomFilter` follows, and if the required flag `4` is set, a `LookupTable` follows last.
+///
+/// Files written by `IndexedWriter` store the data first, followed by the header, the index and a
+/// trailer of `TRAILER_SIZE` bytes holding the offset of the header. Their header has the
+/// optional flag `32` set.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header {
+    /// Version of the format. 0 represents the legacy header
+    version: u16,
+    /// Features which have to be understood in order to read the file
+    required_flags: u16,
+    /// Features which can be ignored by readers not knowing them
+    optional_flags: u32,
     /// Count of files lines.
-    /// This value is equivalent to the amount of entries in the index
-    items: usize,
+    /// This value is equivalent to the amount of entries in the index. It's stored as `u64`, so
+    /// headers are encoded the same way regardless of the width of `usize`.
+    items: u64,
+    /// Information about the indexed data, used to detect changes of the data
+    source: Option<SourceInfo>,
+    /// Custom delimiter terminating the lines
+    delimiter: Option<u8>,
+    /// Hash of the data section
+    content_hash: Option<u64>,
+    /// Whether the data starts with a skipped BOM
+    bom: bool,
+    /// Collation the lines are sorted by
+    collation: Option<Collation>,
+    /// Whether the header and the index are stored behind the data
+    index_at_end: bool,
+    /// Extension data of optional features unknown to this version
+    extension: Vec<u8>,
+}
+
+/// Describes the data an index was built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SourceInfo {
+    /// Length of the indexed data in bytes
+    pub(crate) data_len: u64,
+    /// Hash of the last bytes of the indexed data
+    pub(crate) tail_hash: u64,
+}
+
+impl SourceInfo {
+    const ENCODED_LEN: usize = 16;
+
+    /// Creates the `SourceInfo` for the first `data_len` bytes of `reader`
+    pub(crate) fn read<R: Read + Seek>(reader: &mut R, data_len: u64) -> Result<Self> {
+        let tail_len = data_len.min(TAIL_HASH_LEN);
+        reader.seek(SeekFrom::Start(data_len - tail_len))?;
+
+        let mut tail = vec![0u8; tail_len as usize];
+        reader.read_exact(&mut tail)?;
+
+        Ok(Self {
+            data_len,
+            tail_hash: crate::hash_bytes(&tail),
+        })
+    }
 }
 
 impl Header {
+    /// Creates a header of the current format version for an index of `items` lines, without
+    /// any flags set
     #[inline]
-    pub(crate) fn new(items: usize) -> Self {
-        Self { items }
+    pub fn new(items: usize) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            required_flags: 0,
+            optional_flags: 0,
+            items: items as u64,
+            source: None,
+            delimiter: None,
+            content_hash: None,
+            bom: false,
+            collation: None,
+            index_at_end: false,
+            extension: Vec::new(),
+        }
+    }
+
+    /// Returns the length of the encoded header in bytes, which is `LEGACY_HEADER_SIZE` for
+    /// legacy headers and `HEADER_SIZE` plus the length of the extension otherwise
+    #[inline]
+    pub fn byte_len(&self) -> usize {
+        if self.version == 0 {
+            return LEGACY_HEADER_SIZE;
+        }
+
+        HEADER_SIZE + self.known_extension_len() + self.extension.len()
+    }
+
+    /// Returns the format version of the header. 0 represents the legacy header
+    #[inline]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Returns the amount of lines of the index following the header
+    #[inline]
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// Returns the required flags, including flags unknown to this version
+    #[inline]
+    pub fn required_flags(&self) -> u16 {
+        self.required_flags
+    }
+
+    /// Returns the optional flags of all features set in the header, including flags unknown to
+    /// this version
+    #[inline]
+    pub fn optional_flags(&self) -> u32 {
+        self.all_optional_flags()
+    }
+
+    /// Returns the custom delimiter terminating the lines or `None` if lines are terminated by
+    /// `DEFAULT_DELIMITER`
+    #[inline]
+    pub fn delimiter(&self) -> Option<u8> {
+        self.delimiter
+    }
+
+    /// Returns true if the data starts with a UTF-8 BOM which is not part of the first line
+    #[inline]
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Returns the collation the lines are sorted by, if recorded
+    #[inline]
+    pub fn collation(&self) -> Option<Collation> {
+        self.collation
+    }
+
+    /// Returns true if the header and the index are stored behind the data, see `IndexedWriter`
+    #[inline]
+    pub fn index_at_end(&self) -> bool {
+        self.index_at_end
+    }
+
+    /// Sets whether the header and the index are stored behind the data
+    #[inline]
+    pub(crate) fn set_index_at_end(&mut self, index_at_end: bool) {
+        self.index_at_end = index_at_end;
+    }
+
+    /// Sets the format version used when encoding the header. Legacy headers only hold the
+    /// amount of lines.
+    #[inline]
+    pub(crate) fn set_version(&mut self, version: u16) {
+        self.version = version;
+    }
+
+    #[inline]
+    pub(crate) fn source(&self) -> Option<SourceInfo> {
+        self.source
+    }
+
+    #[inline]
+    pub(crate) fn set_source(&mut self, source: SourceInfo) {
+        self.source = Some(source);
+    }
+
+    /// Returns the hash of the data section or `None` if the file was written without one
+    #[inline]
+    pub fn content_hash(&self) -> Option<u64> {
+        self.content_hash
     }
 
-    /// Encode a header to bytes.
     #[inline]
-    pub(crate) fn encode(&self) -> [u8; HEADER_SIZE] {
-        let enc: [u8; HEADER_SIZE] = self.items.to_le_bytes().try_into().unwrap();
-        enc
+    pub(crate) fn set_content_hash(&mut self, hash: u64) {
+        self.content_hash = Some(hash);
     }
 
-    /// Decodes a header from a reader
+    /// Returns true if the index is followed by a checksum of every line
+    #[inline]
+    pub(crate) fn has_line_checksums(&self) -> bool {
+        self.required_flags & REQ_LINE_CHECKSUMS != 0
+    }
+
+    /// Returns true if the index is followed by a bloom filter
+    #[inline]
+    pub(crate) fn has_bloom(&self) -> bool {
+        self.required_flags & REQ_BLOOM != 0
+    }
+
+    /// Returns true if the index is followed by a lookup table
+    #[inline]
+    pub(crate) fn has_lookup_table(&self) -> bool {
+        self.required_flags & REQ_LOOKUP_TABLE != 0
+    }
+
+    /// Returns the optional flags of all features set in the header
+    #[inline]
+    fn all_optional_flags(&self) -> u32 {
+        let mut flags = self.optional_flags;
+        if self.source.is_some() {
+            flags |= OPT_SOURCE;
+        }
+        if self.delimiter.is_some() {
+            flags |= OPT_DELIMITER;
+        }
+        if self.content_hash.is_some() {
+            flags |= OPT_CONTENT_HASH;
+        }
+        if self.bom {
+            flags |= OPT_BOM;
+        }
+        if self.collation.is_some() {
+            flags |= OPT_COLLATION;
+        }
+        if self.index_at_end {
+            flags |= OPT_INDEX_AT_END;
+        }
+        flags
+    }
+
+    /// Returns the position of the content hash within the encoded header or `None` if the
+    /// header has no content hash
+    #[inline]
+    fn content_hash_pos(&self) -> Option<usize> {
+        self.content_hash?;
+        let source = self.source.map(|_| SourceInfo::ENCODED_LEN).unwrap_or(0);
+        Some(HEADER_SIZE + source + self.delimiter.map(|_| 1).unwrap_or(0))
+    }
+
+    /// Returns the length of the extension data of known features
+    #[inline]
+    fn known_extension_len(&self) -> usize {
+        self.source.map(|_| SourceInfo::ENCODED_LEN).unwrap_or(0)
+            + self.delimiter.map(|_| 1).unwrap_or(0)
+            + self.content_hash.map(|_| 8).unwrap_or(0)
+            + self.collation.map(|_| 1).unwrap_or(0)
+    }
+
+    /// Encode a header to bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        if self.version == 0 {
+            return self.items.to_le_bytes().to_vec();
+        }
+
+        let mut out = Vec::with_capacity(self.byte_len());
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.required_flags.to_le_bytes());
+        out.extend_from_slice(&self.all_optional_flags().to_le_bytes());
+        out.extend_from_slice(&self.items.to_le_bytes());
+        let ext_len = self.known_extension_len() + self.extension.len();
+        out.extend_from_slice(&(ext_len as u32).to_le_bytes());
+
+        if let Some(source) = &self.source {
+            out.extend_from_slice(&source.data_len.to_le_bytes());
+            out.extend_from_slice(&source.tail_hash.to_le_bytes());
+        }
+
+        if let Some(delimiter) = self.delimiter {
+            out.push(delimiter);
+        }
+
+        if let Some(hash) = self.content_hash {
+            out.extend_from_slice(&hash.to_le_bytes());
+        }
+
+        if let Some(collation) = self.collation {
+            out.push(collation.id());
+        }
+
+        out.extend_from_slice(&self.extension);
+        out
+    }
+
+    /// Decodes a header from a reader. Both, legacy and versioned headers are supported.
+    ///
+    /// Returns `Error::UnsupportedFormat` if the header was written by a newer, incompatible
+    /// version of the format.
     pub fn decode<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         reader.seek(SeekFrom::Start(0))?;
 
-        let mut header: [u8; 8] = [0; 8];
-        reader.read_exact(&mut header)?;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+
+        if magic != MAGIC {
+            return Ok(Header {
+                version: 0,
+                required_flags: 0,
+                optional_flags: 0,
+                items: u64::from_le_bytes(magic),
+                source: None,
+                delimiter: None,
+                content_hash: None,
+                bom: false,
+                collation: None,
+                index_at_end: false,
+                extension: Vec::new(),
+            });
+        }
+
+        let mut fixed = [0u8; HEADER_SIZE - MAGIC.len()];
+        reader.read_exact(&mut fixed)?;
+
+        let version = u16::from_le_bytes([fixed[0], fixed[1]]);
+        if version > FORMAT_VERSION {
+            return Err(Error::UnsupportedFormat { version });
+        }
+
+        let required_flags = u16::from_le_bytes([fixed[2], fixed[3]]);
+        let unknown = required_flags & !KNOWN_REQUIRED_FLAGS;
+        if unknown != 0 {
+            return Err(Error::UnsupportedFlags { flags: unknown });
+        }
 
-        let lines = usize::from_le_bytes(header);
+        let optional_flags = u32::from_le_bytes(fixed[4..8].try_into().unwrap());
+        let items = u64::from_le_bytes(fixed[8..16].try_into().unwrap());
+        let ext_len = u32::from_le_bytes(fixed[16..20].try_into().unwrap()) as usize;
 
-        Ok(Header { items: lines })
+        // The length isn't trusted for allocating, truncated data fails while reading
+        let mut extension = Vec::new();
+        reader
+            .by_ref()
+            .take(ext_len as u64)
+            .read_to_end(&mut extension)?;
+        if extension.len() < ext_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut rest = &extension[..];
+        let mut source = None;
+        if optional_flags & OPT_SOURCE != 0 {
+            if rest.len() < SourceInfo::ENCODED_LEN {
+                return Err(Error::MalformedIndex);
+            }
+            source = Some(SourceInfo {
+                data_len: u64::from_le_bytes(rest[0..8].try_into().unwrap()),
+                tail_hash: u64::from_le_bytes(rest[8..16].try_into().unwrap()),
+            });
+            rest = &rest[SourceInfo::ENCODED_LEN..];
+        }
+
+        let mut delimiter = None;
+        if optional_flags & OPT_DELIMITER != 0 {
+            let (first, remaining) = rest.split_first().ok_or(Error::MalformedIndex)?;
+            delimiter = Some(*first);
+            rest = remaining;
+        }
+
+        let mut content_hash = None;
+        if optional_flags & OPT_CONTENT_HASH != 0 {
+            if rest.len() < 8 {
+                return Err(Error::MalformedIndex);
+            }
+            content_hash = Some(u64::from_le_bytes(rest[0..8].try_into().unwrap()));
+            rest = &rest[8..];
+        }
+
+        // Unknown collations are ignored like unknown optional features, since reading the lines
+        // doesn't depend on them
+        let mut collation = None;
+        if optional_flags & OPT_COLLATION != 0 {
+            let (first, remaining) = rest.split_first().ok_or(Error::MalformedIndex)?;
+            collation = Collation::from_id(*first);
+            rest = remaining;
+        }
+
+        Ok(Header {
+            version,
+            required_flags,
+            optional_flags: optional_flags & !KNOWN_OPTIONAL_FLAGS,
+            items,
+            source,
+            delimiter,
+            content_hash,
+            bom: optional_flags & OPT_BOM != 0,
+            collation,
+            index_at_end: optional_flags & OPT_INDEX_AT_END != 0,
+            extension: rest.to_vec(),
+        })
     }
 }
 
-/// Contains an in-memory line-index
-#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+/// Contains an in-memory line-index. Two indexes are equal if they contain the same offsets,
+/// delimiter, skipped BOM and collation, regardless of whether they are prefixed to the data or
+/// not. Checksums, bloom filters and lookup tables are not compared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Index {
     /// Maps line to seek position in order to seek efficiently. The index within the Vec represents
     /// the line-index in the file
-    pub(crate) inner: Vec<u32>,
+    inner: Vec<u32>,
     /// The len in bytes of the index and the header
     len_bytes: usize,
+    /// The byte terminating the lines. Defaults to `\n` for indexes serialized without it.
+    #[serde(default = "default_delimiter")]
+    delimiter: u8,
+    /// Checksum of every line, see `line_checksum`. Only present if requested when writing the
+    /// file, since it doubles the size of the index.
+    #[serde(default)]
+    line_checksums: Option<Vec<u32>>,
+    /// Whether the data starts with a UTF-8 BOM which is skipped by the first line
+    #[serde(default)]
+    bom: bool,
+    /// The collation the lines are sorted by, if recorded
+    #[serde(default)]
+    collation: Option<Collation>,
+    /// Filter of the keys of all lines, stored behind the line checksums
+    #[serde(default)]
+    bloom: Option<BloomFilter>,
+    /// Table of the hashes of the keys of all lines, stored behind the bloom filter
+    #[serde(default)]
+    lookup_table: Option<LookupTable>,
+}
+
+#[inline]
+fn default_delimiter() -> u8 {
+    DEFAULT_DELIMITER
+}
+
+impl Default for Index {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            inner: Vec::new(),
+            len_bytes: 0,
+            delimiter: DEFAULT_DELIMITER,
+            line_checksums: None,
+            bom: false,
+            collation: None,
+            bloom: None,
+            lookup_table: None,
+        }
+    }
 }
 
 impl Index {
     /// Create a new Index
     #[inline]
     pub fn new<T: IntoIterator<Item = u32>>(line: T) -> Index {
-        let inner = line.into_iter().collect::<Vec<_>>();
-        let inner_byte_size = inner.len() * 4 + 1;
+        let mut index = Self {
+            len_bytes: 0,
+            inner: line.into_iter().collect(),
+            delimiter: DEFAULT_DELIMITER,
+            line_checksums: None,
+            bom: false,
+            collation: None,
+            bloom: None,
+            lookup_table: None,
+        };
+        index.len_bytes = index.calc_length();
+        index
+    }
+
+    /// Create a new empty Index with space for at least `capacity` lines
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Index {
         Self {
-            len_bytes: HEADER_SIZE + inner_byte_size,
-            inner,
+            inner: Vec::with_capacity(capacity),
+            len_bytes: 0,
+            delimiter: DEFAULT_DELIMITER,
+            line_checksums: None,
+            bom: false,
+            collation: None,
+            bloom: None,
+            lookup_table: None,
         }
     }
 
+    /// Samples up to `bytes_to_check` bytes from the start of `reader` and classifies them as
+    /// text or binary using the default thresholds of `Sniffer`. The position of `reader` is
+    /// restored afterwards.
+    #[inline]
+    pub fn sniff<R: Read + Seek>(reader: &mut R, bytes_to_check: usize) -> Result<ContentKind> {
+        Sniffer::new().sniff(reader, bytes_to_check)
+    }
+
     /// Build a new index for text within `reader`. Returns a `Vec<u8>` holding the bytes representing
     /// the index in encoded format. This is usually needed for building an indexed file.
+    ///
+    /// Lines are split like `str::lines` does: every line ends with a newline, except for the
+    /// last line which may be unterminated. A trailing newline doesn't start a new line, so `""`
+    /// has no lines, while `"\n"` and `"a\n"` have a single line. Unlike `str::lines`, a
+    /// carriage return before the newline belongs to the line.
+    #[inline]
     pub fn build<R: Read + Unpin + Seek>(reader: &mut BufReader<R>) -> Result<Self> {
+        Self::build_with_delimiter(reader, DEFAULT_DELIMITER)
+    }
+
+    /// Build a new index for data within `reader` whose lines are terminated by `delimiter`.
+    #[inline]
+    pub fn build_with_delimiter<R: Read + Unpin + Seek>(
+        reader: &mut BufReader<R>,
+        delimiter: u8,
+    ) -> Result<Self> {
+        Self::build_with(reader, delimiter, false, None)
+    }
+
+    /// Build a new index like `build` and collects statistics about the lines while scanning
+    /// them, which can be logged when indexing unknown files. See `BuildReport`.
+    #[inline]
+    pub fn build_with_report<R: Read + Unpin + Seek>(
+        reader: &mut BufReader<R>,
+    ) -> Result<(Self, BuildReport)> {
+        Self::build_reported(reader, DEFAULT_DELIMITER, false, None, None)
+    }
+
+    /// Build a new index like `build_with_delimiter`. If `skip_bom` is set and the data starts
+    /// with a UTF-8 BOM, the first line starts after it. Returns `Error::LineTooLong` if a line,
+    /// including its terminator, is longer than `max_line_len`.
+    #[inline]
+    pub(crate) fn build_with<R: Read + Unpin + Seek>(
+        reader: &mut BufReader<R>,
+        delimiter: u8,
+        skip_bom: bool,
+        max_line_len: Option<u64>,
+    ) -> Result<Self> {
+        Self::build_reported(reader, delimiter, skip_bom, max_line_len, None)
+            .map(|(index, _)| index)
+    }
+
+    /// Builds the index like `build_with`. If there is a `progress`, it gets reported to it after
+    /// every chunk and the build stops with `Error::Cancelled` once it got cancelled.
+    #[inline]
+    pub(crate) fn build_tracked<R: Read + Seek>(
+        reader: &mut BufReader<R>,
+        delimiter: u8,
+        skip_bom: bool,
+        max_line_len: Option<u64>,
+        progress: Option<&BuildProgress>,
+    ) -> Result<Self> {
+        Self::build_reported(reader, delimiter, skip_bom, max_line_len, progress)
+            .map(|(index, _)| index)
+    }
+
+    /// Builds the index like `build_tracked`, returning a `BuildReport` of the scanned data
+    fn build_reported<R: Read + Seek>(
+        reader: &mut BufReader<R>,
+        delimiter: u8,
+        skip_bom: bool,
+        max_line_len: Option<u64>,
+        progress: Option<&BuildProgress>,
+    ) -> Result<(Self, BuildReport)> {
+        let started = Instant::now();
+
+        if let Some(progress) = progress {
+            let total = reader.seek(SeekFrom::End(0))?;
+            progress.total.store(total, AtomicOrdering::Relaxed);
+        }
+
         // Seeking to 0 doesn't throw an error so we can unwrap it
         reader.seek(SeekFrom::Start(0)).unwrap();
 
-        //let mut line_index: Vec<u64> = Vec::new();
+        let bom = skip_bom && starts_with_bom(reader)?;
+        let start = if bom { UTF8_BOM.len() as u64 } else { 0 };
+        reader.seek(SeekFrom::Start(start))?;
+
         let mut line_index = Vec::new();
-        let mut curr_offset: u64 = 0;
+        let (end, terminated) = scan_lines(
+            reader,
+            &mut line_index,
+            start,
+            true,
+            delimiter,
+            max_line_len,
+            |scanned| match progress {
+                Some(progress) => {
+                    progress.scanned.store(scanned, AtomicOrdering::Relaxed);
+                    !progress.cancelled.load(AtomicOrdering::Relaxed)
+                }
+                None => true,
+            },
+        )?;
 
-        let mut buff = Vec::with_capacity(1000);
+        // Seeking to 0 doesn't throw an error so we can unwrap it
+        reader.seek(SeekFrom::Start(0)).unwrap();
 
-        loop {
-            let last_offset = curr_offset;
+        let report = BuildReport::new(&line_index, end, terminated, started.elapsed());
+        let index = Self {
+            inner: line_index,
+            len_bytes: 0,
+            delimiter,
+            line_checksums: None,
+            bom,
+            collation: None,
+            bloom: None,
+            lookup_table: None,
+        };
+        Ok((index, report))
+    }
 
-            buff.clear();
-            let n = reader.read_until(b'\n', &mut buff)?;
+    /// Builds the index for text within `reader` on a new thread. The returned handle can be used
+    /// to track the progress, cancel the build or wait for the index. Use
+    /// `Builder::build_background` to customize the index.
+    #[inline]
+    pub fn build_background<R: Read + Seek + Send + 'static>(reader: R) -> IndexBuildHandle<Index> {
+        Builder::new().build_background(reader)
+    }
 
-            if n == 0 || buff.is_empty() {
-                break;
-            }
+    /// Reads the index of the indexed file at `path` without keeping the file open. If the index
+    /// is stored in front of the data, the returned index expects the data to be prefixed by the
+    /// index, so it has to be converted using `zero_len` before using it on the raw data. Indexes
+    /// stored behind the data, see `IndexedWriter`, are returned as `zero_len` index already.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut reader = fs::File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| Error::from(e).with_path(path))?;
+        let parsed = Self::parse_any(&mut reader).map_err(|e| e.with_path(path))?;
+        Ok(parsed.0)
+    }
+
+    /// Writes the header and the encoded index into a new file at `path`. The data is not
+    /// written, which allows storing the index separately from the data it describes.
+    #[inline]
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to_path(path.as_ref(), &self.get_header())
+    }
 
-            // We don't want to push the last line-index twice which we would if this was at the
-            // top of the loop
-            line_index.push(last_offset as u32);
+    /// Writes the header and the index into `writer`, followed by the data which is written by
+    /// `copy`. The data is hashed while `copy` writes it and the hash gets patched into the
+    /// header afterwards, so the data only has to be read once. Returns the amount of bytes
+    /// written.
+    pub(crate) fn write_hashed<W, C>(&self, writer: &mut W, copy: C) -> Result<usize>
+    where
+        W: Write + Seek,
+        C: FnOnce(&mut HashingWriter<&mut W>) -> Result<u64>,
+    {
+        let start = writer.stream_position()?;
+        let mut header = self.get_header();
+        header.set_content_hash(0);
+        let hash_pos = header.content_hash_pos().unwrap_or(0) as u64;
+        let header = header.encode();
 
-            curr_offset += n as u64;
+        writer.write_all(&header)?;
+        let index_len = self.encode_into(writer)?;
+
+        let mut data = HashingWriter::new(&mut *writer);
+        let copied = copy(&mut data)?;
+        let hash = data.hash();
+
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(start + hash_pos))?;
+        writer.write_all(&hash.to_le_bytes())?;
+        writer.seek(SeekFrom::Start(end))?;
+        Ok(header.len() + index_len + copied as usize)
+    }
+
+    /// Writes `header` followed by the encoded index into a new file at `path`
+    pub(crate) fn write_to_path(&self, path: &Path, header: &Header) -> Result<()> {
+        let write = || -> Result<()> {
+            let mut writer = BufWriter::new(fs::File::create(path)?);
+            writer.write_all(&header.encode())?;
+            self.encode_into(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        };
+        write().map_err(|e| e.with_path(path))
+    }
+
+    /// Extends the index of the file at `path` which has grown since the index was built. Only the
+    /// data after the first `previously_indexed_bytes` bytes gets scanned for new lines. If the
+    /// previously indexed data didn't end with a newline, the new data continues its last line.
+    /// The offsets are relative to the raw data, without an index in front of it, and a skipped
+    /// BOM stays in front of the first line. Like `add`, this updates `len_bytes`, so the index
+    /// has to be converted using `zero_len` before using it on the raw data.
+    ///
+    /// Returns the amount of lines added to the index. Fails with `Error::InvalidIndex` if the last
+    /// line of the index doesn't start within the first `previously_indexed_bytes` bytes.
+    pub fn extend_from_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        previously_indexed_bytes: u64,
+    ) -> Result<usize> {
+        if let Some(&last) = self.inner.last() {
+            if previously_indexed_bytes <= u64::from(last) {
+                return Err(Error::InvalidIndex {
+                    line: self.len() - 1,
+                });
+            }
         }
 
-        // Seeking to 0 doesn't throw an error so we can unwrap it
-        reader.seek(SeekFrom::Start(0)).unwrap();
+        let path = path.as_ref();
+        let mut extend = || -> Result<usize> {
+            let mut reader = BufReader::new(fs::File::open(path)?);
 
-        Ok(Self {
-            // Storing it in a normal vec first is faster than pushing it repetitively
-            inner: line_index.into(),
-            len_bytes: 0,
-        })
+            // New lines only start directly if there are no lines yet or the last indexed line
+            // was terminated
+            let line_start = if self.inner.is_empty() {
+                true
+            } else {
+                reader.seek(SeekFrom::Start(previously_indexed_bytes - 1))?;
+                let mut last = [0u8; 1];
+                reader.read_exact(&mut last)?;
+                last[0] == self.delimiter
+            };
+
+            let start = previously_indexed_bytes.max(self.bom_len());
+            let len_before = self.len();
+            self.clear_line_tables();
+            reader.seek(SeekFrom::Start(start))?;
+            scan_lines(
+                &mut reader,
+                &mut self.inner,
+                start,
+                line_start,
+                self.delimiter,
+                None,
+                |_| true,
+            )?;
+            self.len_bytes = self.calc_length();
+            Ok(self.len() - len_before)
+        };
+        extend().map_err(|e| e.with_path(path))
+    }
+
+    /// Returns the start offsets of all lines
+    #[inline]
+    pub fn offsets(&self) -> &[u32] {
+        &self.inner
     }
 
-    /// Adds a new value to the index
+    /// Returns the start offsets of all lines
+    #[inline]
+    pub fn into_inner(self) -> Vec<u32> {
+        self.inner
+    }
+
+    /// Create a new Index from `offsets`, verifying that they are strictly increasing.
+    pub fn from_sorted_offsets(offsets: Vec<u32>) -> Result<Index> {
+        if let Some(line) = first_unordered(&offsets) {
+            return Err(Error::InvalidIndex { line });
+        }
+        Ok(Self::new(offsets))
+    }
+
+    /// Adds a new value to the index. `pos` has to be greater than the last value, which is not
+    /// checked: other values are added anyway and make reads of the affected lines fail or return
+    /// wrong data. Use `try_add` if `pos` isn't known to be valid.
     #[inline]
     pub fn add(&mut self, pos: u32) {
         self.inner.push(pos);
+        self.clear_line_tables();
         // Update length since we (might) have changed the index len
         self.len_bytes = self.calc_length();
     }
 
-    /// Encodes an index into bytes, which can be used to store it into a file.
+    /// Adds a new value to the index. Returns `Error::InvalidIndex` if `pos` is not greater than
+    /// the last value.
+    #[inline]
+    pub fn try_add(&mut self, pos: u32) -> Result<()> {
+        if matches!(self.inner.last(), Some(last) if *last >= pos) {
+            return Err(Error::InvalidIndex { line: self.len() });
+        }
+        self.add(pos);
+        Ok(())
+    }
+
+    /// Encodes an index into bytes, which can be used to store it into a file. Use
+    /// `encode_into` to write the encoded index without keeping it in memory.
     #[inline]
     pub fn encode(&self) -> Vec<u8> {
-        let mut out: Vec<_> = self
-            .inner
-            .iter()
-            .map(|i| i.to_le_bytes())
-            .flatten()
-            .collect();
-        out.push(b'\n');
+        let mut out = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut out)
+            .expect("writing into a Vec doesn't fail");
         out
     }
 
+    /// Encodes the index like `encode` directly into `w`. The offsets are written in batches, so
+    /// only a small buffer is needed regardless of the size of the index. Returns the amount of
+    /// bytes written.
+    pub fn encode_into<W: Write>(&self, w: &mut W) -> Result<usize> {
+        let checksums = self.line_checksums.iter().flatten();
+        let mut values = self.inner.iter().chain(checksums);
+
+        let mut batch = [0u8; ENCODE_BATCH * 4];
+        let mut written = 0;
+        loop {
+            let mut len = 0;
+            // The batch goes first, so no value is taken once it is full
+            for (out, value) in batch.chunks_exact_mut(4).zip(values.by_ref()) {
+                out.copy_from_slice(&value.to_le_bytes());
+                len += 4;
+            }
+            if len == 0 {
+                break;
+            }
+            w.write_all(&batch[..len])?;
+            written += len;
+        }
+
+        if let Some(bloom) = &self.bloom {
+            bloom.encode_into(w)?;
+            written += bloom.encoded_len();
+        }
+        if let Some(table) = &self.lookup_table {
+            table.encode_into(w)?;
+            written += table.encoded_len();
+        }
+
+        // The index is terminated by a newline
+        w.write_all(b"\n")?;
+        Ok(written + 1)
+    }
+
+    /// Returns the length of the encoded index, see `encode`
+    #[inline]
+    pub(crate) fn encoded_len(&self) -> usize {
+        let checksums = self.line_checksums.as_ref().map(|i| i.len()).unwrap_or(0);
+        let bloom = self.bloom.as_ref().map(|i| i.encoded_len()).unwrap_or(0);
+        let lookup_table = self.lookup_table.as_ref().map(|i| i.encoded_len());
+        (self.inner.len() + checksums) * 4 + bloom + lookup_table.unwrap_or(0) + 1
+    }
+
     /// Returns true if the index has a given value
     #[inline]
     pub fn has(&self, pos: usize) -> bool {
         self.inner.get(pos).is_some()
     }
 
-    /// Calculate the index size
+    /// Calculates the length of the header of the index, including extensions like the delimiter
+    /// or the collation, followed by the encoded index, including the newline terminating the
+    /// index. A content hash or source written along with the index is not included.
     #[inline]
     pub fn calc_length(&self) -> usize {
-        HEADER_SIZE + self.len() * 4
+        self.get_header().byte_len() + self.encoded_len()
     }
 
     /// Decodes an encoded index
     pub fn decode<R: Read + Unpin + Seek>(reader: &mut R, header: &Header) -> Result<Self> {
         // Skip header bytes
-        reader.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        reader.seek(SeekFrom::Start(header.byte_len() as u64))?;
+
+        // Indexes of more lines than addressable can't be held in memory
+        let items = usize::try_from(header.items).map_err(|_| Error::MalformedIndex)?;
 
         // List of the beginning offset of each line in the file
         let mut inner: Vec<u32> = Vec::new();
 
         // Decode line indices
         let mut buff = [0u8; 4];
-        for _ in 0..header.items {
+        for _ in 0..items {
             reader.read_exact(&mut buff)?;
-            inner.push(u32::from_le_bytes(
-                buff.try_into().map_err(|_| Error::MalformedIndex)?,
-            ));
+            inner.push(u32::from_le_bytes(buff));
+        }
+
+        let mut line_checksums = None;
+        if header.has_line_checksums() {
+            let mut checksums = Vec::with_capacity(inner.len());
+            for _ in 0..items {
+                reader.read_exact(&mut buff)?;
+                checksums.push(u32::from_le_bytes(buff));
+            }
+            line_checksums = Some(checksums);
         }
 
-        Ok(Index::new(inner))
+        let bloom = if header.has_bloom() {
+            Some(BloomFilter::decode(reader)?)
+        } else {
+            None
+        };
+        let lookup_table = if header.has_lookup_table() {
+            Some(LookupTable::decode(reader, inner.len())?)
+        } else {
+            None
+        };
+
+        let table_len = line_checksums.as_ref().map(|i| i.len() * 4).unwrap_or(0);
+        let bloom_len = bloom.as_ref().map(|i| i.encoded_len()).unwrap_or(0);
+        let lookup_len = lookup_table.as_ref().map(|i| i.encoded_len()).unwrap_or(0);
+        Ok(Self {
+            len_bytes: header.byte_len() + inner.len() * 4 + table_len + bloom_len + lookup_len + 1,
+            inner,
+            delimiter: header.delimiter.unwrap_or(DEFAULT_DELIMITER),
+            line_checksums,
+            bom: header.bom,
+            collation: header.collation,
+            bloom,
+            lookup_table,
+        })
     }
 
     /// Converts an `Index` to an index with zero length
@@ -164,22 +1024,156 @@ impl Index {
         Self {
             len_bytes: 0,
             inner: self.inner,
+            delimiter: self.delimiter,
+            line_checksums: self.line_checksums,
+            bom: self.bom,
+            collation: self.collation,
+            bloom: self.bloom,
+            lookup_table: self.lookup_table,
         }
     }
 
     /// Generate a header out of the index
     #[inline]
     pub(crate) fn get_header(&self) -> Header {
-        Header::new(self.inner.len())
+        let mut header = Header::new(self.inner.len());
+        if self.delimiter != DEFAULT_DELIMITER {
+            header.delimiter = Some(self.delimiter);
+        }
+        if self.line_checksums.is_some() {
+            header.required_flags |= REQ_LINE_CHECKSUMS;
+        }
+        if self.bloom.is_some() {
+            header.required_flags |= REQ_BLOOM;
+        }
+        if self.lookup_table.is_some() {
+            header.required_flags |= REQ_LOOKUP_TABLE;
+        }
+        header.bom = self.bom;
+        header.collation = self.collation;
+        header
+    }
+
+    /// Returns the byte terminating the lines
+    #[inline]
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    /// Sets the byte terminating the lines
+    #[inline]
+    pub(crate) fn set_delimiter(&mut self, delimiter: u8) {
+        self.delimiter = delimiter;
+    }
+
+    /// Returns true if the data starts with a UTF-8 BOM which is not part of the first line
+    #[inline]
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    /// Returns the collation the lines are sorted by, or `None` if the file doesn't record one
+    #[inline]
+    pub fn collation(&self) -> Option<Collation> {
+        self.collation
+    }
+
+    /// Records the collation the lines are sorted by, which gets stored in the header when the
+    /// file is written. This doesn't check the order of the lines.
+    #[inline]
+    pub fn set_collation(&mut self, collation: Option<Collation>) {
+        self.collation = collation;
+    }
+
+    /// Returns the bloom filter of the keys of the lines or `None` if the index has none, see
+    /// `IndexedReader::build_bloom`
+    #[inline]
+    pub fn bloom(&self) -> Option<&BloomFilter> {
+        self.bloom.as_ref()
+    }
+
+    /// Sets the bloom filter written behind the index and updates `len_bytes` like `add`.
+    /// Readers keep the position of their data using `set_len_bytes`.
+    #[inline]
+    pub(crate) fn set_bloom(&mut self, bloom: BloomFilter) {
+        self.bloom = Some(bloom);
+        self.len_bytes = self.calc_length();
+    }
+
+    /// Returns the lookup table of the keys of the lines or `None` if the index has none, see
+    /// `IndexedReader::build_lookup_table`
+    #[inline]
+    pub fn lookup_table(&self) -> Option<&LookupTable> {
+        self.lookup_table.as_ref()
+    }
+
+    /// Sets the lookup table written behind the index and updates `len_bytes` like `set_bloom`
+    #[inline]
+    pub(crate) fn set_lookup_table(&mut self, table: LookupTable) {
+        debug_assert_eq!(table.len(), self.len());
+        self.lookup_table = Some(table);
+        self.len_bytes = self.calc_length();
+    }
+
+    /// Returns true if the index has checksums, a bloom filter or a lookup table, which describe
+    /// the content of the lines
+    #[inline]
+    pub(crate) fn has_line_tables(&self) -> bool {
+        self.line_checksums.is_some() || self.bloom.is_some() || self.lookup_table.is_some()
+    }
+
+    /// Drops the checksums, bloom filter and lookup table, which don't describe changed lines
+    #[inline]
+    pub(crate) fn clear_line_tables(&mut self) {
+        self.line_checksums = None;
+        self.bloom = None;
+        self.lookup_table = None;
+    }
+
+    /// Returns the amount of bytes of the skipped BOM at the beginning of the data
+    #[inline]
+    pub(crate) fn bom_len(&self) -> u64 {
+        if self.bom {
+            UTF8_BOM.len() as u64
+        } else {
+            0
+        }
+    }
+
+    /// Returns the checksums of all lines or `None` if the index was written without them
+    #[inline]
+    pub fn line_checksums(&self) -> Option<&[u32]> {
+        self.line_checksums.as_deref()
+    }
+
+    /// Sets the checksums of all lines, which are written along with the offsets
+    #[inline]
+    pub(crate) fn set_line_checksums(&mut self, checksums: Vec<u32>) {
+        debug_assert_eq!(checksums.len(), self.len());
+        self.line_checksums = Some(checksums);
+        self.len_bytes = self.calc_length();
     }
 
     /// Get the Index value at `pos`
     #[inline]
     pub fn get(&self, pos: usize) -> Result<u32> {
-        Ok(*self.inner.get(pos).ok_or(Error::OutOfBounds)?)
+        self.inner.get(pos).copied().ok_or(Error::OutOfBounds {
+            line: pos,
+            lines: self.len(),
+        })
     }
 
-    /// Get the Index value at `pos` without bounds checking
+    /// Get the Index value at `pos` or `None` if `pos` is out of bounds
+    #[inline(always)]
+    pub fn get_opt(&self, pos: usize) -> Option<u32> {
+        self.inner.get(pos).copied()
+    }
+
+    /// Get the Index value at `pos` without returning an error. This is never undefined behavior.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pos` is out of bounds
     #[inline(always)]
     pub fn get_unchecked(&self, pos: usize) -> usize {
         self.inner[pos] as usize
@@ -187,8 +1181,40 @@ impl Index {
 
     /// Get the Index value at `pos`
     #[inline(always)]
+    #[deprecated(note = "use `get_opt` instead")]
     pub fn get2(&self, pos: usize) -> Option<usize> {
-        self.inner.get(pos).map(|i| *i as usize)
+        self.get_opt(pos).map(|i| i as usize)
+    }
+
+    /// Adds `delta` to the offsets of all lines starting at `pos`, eg. after the length of the line
+    /// before `pos` changed. Does nothing if `pos` is out of bounds.
+    ///
+    /// Returns `Error::OffsetOverflow` without changing the index if an offset leaves the range of
+    /// `u32`
+    pub(crate) fn shift_from(&mut self, pos: usize, delta: i64) -> Result<()> {
+        self.shift_range(pos..self.len(), delta)
+    }
+
+    /// Adds `delta` to the offsets of all lines within `lines`. Lines out of bounds are ignored.
+    ///
+    /// Returns `Error::OffsetOverflow` without changing the index if an offset leaves the range of
+    /// `u32`
+    pub(crate) fn shift_range(&mut self, lines: Range<usize>, delta: i64) -> Result<()> {
+        let end = lines.end.min(self.len());
+        let start = lines.start.min(end);
+        let shift = |offset: u32| u32::try_from(offset as i64 + delta).ok();
+        if let Some(pos) = self.inner[start..end]
+            .iter()
+            .position(|i| shift(*i).is_none())
+        {
+            return Err(Error::OffsetOverflow { line: start + pos });
+        }
+
+        self.clear_line_tables();
+        for offset in &mut self.inner[start..end] {
+            *offset = shift(*offset).unwrap();
+        }
+        Ok(())
     }
 
     /// Returns the amount of items of the index. On a properly built index, this represents the
@@ -204,6 +1230,13 @@ impl Index {
         self.len_bytes
     }
 
+    /// Sets the length of the header and the index in front of the data. Used by readers whose
+    /// index gets extended by tables which are not part of the data they read from.
+    #[inline]
+    pub(crate) fn set_len_bytes(&mut self, len_bytes: usize) {
+        self.len_bytes = len_bytes;
+    }
+
     /// Returns `true` if the index is empty
     #[inline]
     pub fn is_empty(&self) -> bool {
@@ -217,14 +1250,934 @@ impl Index {
         let index = Index::decode(reader, &header)?;
         Ok(index)
     }
+
+    /// Parses an index stored in front of the data or behind it, detecting the trailer of the
+    /// latter if the data doesn't start with a header. Indexes stored behind the data are
+    /// returned as `zero_len` index along with the data section, which ends at the header.
+    pub(crate) fn parse_any<R: Read + Unpin + Seek>(
+        reader: &mut R,
+    ) -> Result<(Index, Option<DataSection>)> {
+        // Without data, the header stored behind it starts the file
+        if has_magic(reader)? {
+            let header = Header::decode(reader)?;
+            if !header.index_at_end {
+                return Ok((Index::decode(reader, &header)?, None));
+            }
+        }
+
+        match Self::parse_trailing(reader)? {
+            Some((index, section)) => Ok((index, Some(section))),
+            None => Ok((Self::parse_index(reader)?, None)),
+        }
+    }
+
+    /// Parses an index stored behind the data as `zero_len` index and returns it along with the
+    /// data section in front of the header. Returns `None` if `reader` doesn't end with a trailer pointing
+    /// to a valid header and index, so legacy data which happens to end like a trailer is still
+    /// read using its legacy header.
+    pub(crate) fn parse_trailing<R: Read + Unpin + Seek>(
+        reader: &mut R,
+    ) -> Result<Option<(Index, DataSection)>> {
+        let (data_len, index_len) = match read_trailer(reader)? {
+            Some(trailer) => trailer,
+            None => return Ok(None),
+        };
+
+        let mut window = Window::new(&mut *reader, data_len, index_len)?;
+        let header = match Header::decode(&mut window) {
+            Ok(header) if header.index_at_end => header,
+            _ => return Ok(None),
+        };
+        match Index::decode(&mut window, &header) {
+            Ok(index) if index.len_bytes as u64 == index_len => {
+                let section = DataSection {
+                    len: data_len,
+                    header,
+                };
+                Ok(Some((index.zero_len(), section)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// The data section of data followed by its header and index, see `Index::parse_trailing`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DataSection {
+    /// Length of the data in front of the header
+    pub(crate) len: u64,
+    /// The header stored behind the data
+    pub(crate) header: Header,
+}
+
+/// Reads the trailer at the end of `reader`. Returns the length of the data section and the
+/// length of the header and index following it or `None` if `reader` doesn't end with a trailer.
+fn read_trailer<R: Read + Seek>(reader: &mut R) -> Result<Option<(u64, u64)>> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    if end < TRAILER_SIZE as u64 {
+        return Ok(None);
+    }
+
+    let mut trailer = [0u8; TRAILER_SIZE];
+    reader.seek(SeekFrom::Start(end - TRAILER_SIZE as u64))?;
+    reader.read_exact(&mut trailer)?;
+    if trailer[8..] != TRAILER_MAGIC {
+        return Ok(None);
+    }
+
+    let data_len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let index_len = (end - TRAILER_SIZE as u64).checked_sub(data_len);
+    Ok(index_len.map(|index_len| (data_len, index_len)))
+}
+
+/// Returns `true` if `reader` starts with the magic bytes of a versioned header. Data shorter
+/// than the magic bytes has no header.
+pub(crate) fn has_magic<R: Read + Seek>(reader: &mut R) -> Result<bool> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut magic = [0u8; MAGIC.len()];
+    match reader.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == MAGIC),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Scans `reader` chunk by chunk for line beginnings and pushes their offsets into `offsets`.
+/// `offset` is the position of `reader` within the data and `line_start` tells whether a line
+/// begins at this position. Lines are terminated by `delimiter`. After each chunk `progress` is
+/// called with the amount of bytes scanned so far and the scan is cancelled if it returns `false`.
+/// Returns `Error::LineTooLong` once a line gets longer than `max_line_len` bytes, including its
+/// terminator, and `Error::OffsetOverflow` once a line begins beyond `u32::MAX`. Returns the
+/// offset at which the scan ended and whether a line would begin there.
+fn scan_lines<R: BufRead, F: FnMut(u64) -> bool>(
+    reader: &mut R,
+    offsets: &mut Vec<u32>,
+    mut offset: u64,
+    mut line_start: bool,
+    delimiter: u8,
+    max_line_len: Option<u64>,
+    mut progress: F,
+) -> Result<(u64, bool)> {
+    // Returns an error if the last line is longer than `max_line_len` at the position `end`
+    let check_len = |offsets: &Vec<u32>, end: u64| match (max_line_len, offsets.last()) {
+        (Some(max), Some(start)) if end - *start as u64 > max => Err(Error::LineTooLong {
+            line: offsets.len() - 1,
+            len: (end - *start as u64) as usize,
+        }),
+        _ => Ok(()),
+    };
+
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let len = chunk.len();
+        let mut pos = 0;
+        while pos < len {
+            // Only push a line if there is data after the delimiter, so the last line doesn't get
+            // pushed twice
+            if line_start {
+                let start =
+                    u32::try_from(offset + pos as u64).map_err(|_| Error::OffsetOverflow {
+                        line: offsets.len(),
+                    })?;
+                offsets.push(start);
+                line_start = false;
+            }
+
+            match chunk[pos..].iter().position(|i| *i == delimiter) {
+                Some(newline) => {
+                    pos += newline + 1;
+                    line_start = true;
+                    check_len(offsets, offset + pos as u64)?;
+                }
+                None => break,
+            }
+        }
+
+        reader.consume(len);
+        offset += len as u64;
+
+        // The last line continues in the next chunk, so it can exceed the limit before its end
+        if !line_start {
+            check_len(offsets, offset)?;
+        }
+
+        if !progress(offset) {
+            return Err(Error::Cancelled);
+        }
+    }
+
+    Ok((offset, line_start))
+}
+
+/// Statistics about the data an index was built for, see `Index::build_with_report`. Lengths of
+/// lines don't include their terminator.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildReport {
+    /// The amount of lines
+    pub lines: usize,
+    /// The amount of bytes of the data, including a skipped BOM
+    pub data_bytes: u64,
+    /// The length of the shortest line, or 0 if there are no lines
+    pub min_line_len: u64,
+    /// The length of the longest line, or 0 if there are no lines
+    pub max_line_len: u64,
+    /// The average length of the lines, or 0 if there are no lines
+    pub mean_line_len: f64,
+    /// Whether the last line is terminated
+    pub trailing_newline: bool,
+    /// The amount of lines longer than `BuildReport::LONG_LINE_LEN`, which usually indicates
+    /// that the data isn't line based text
+    pub long_lines: usize,
+    /// The time it took to build the index
+    pub elapsed: Duration,
+}
+
+impl BuildReport {
+    /// Lines longer than this amount of bytes are counted as `long_lines`
+    pub const LONG_LINE_LEN: u64 = 1024 * 1024;
+
+    /// Creates the report of the lines starting at `offsets` within data ending at `end`.
+    /// `terminated` tells whether the data ends with a terminator.
+    fn new(offsets: &[u32], end: u64, terminated: bool, elapsed: Duration) -> Self {
+        let trailing_newline = terminated && !offsets.is_empty();
+        let ends = offsets.iter().skip(1).map(|i| *i as u64 - 1);
+        let last_end = end - trailing_newline as u64;
+        let lens = offsets
+            .iter()
+            .zip(ends.chain(std::iter::once(last_end)))
+            .map(|(start, end)| end - *start as u64);
+
+        let mut report = Self {
+            lines: offsets.len(),
+            data_bytes: end,
+            min_line_len: u64::MAX,
+            trailing_newline,
+            elapsed,
+            ..Self::default()
+        };
+        let mut total = 0;
+        for len in lens {
+            report.min_line_len = report.min_line_len.min(len);
+            report.max_line_len = report.max_line_len.max(len);
+            report.long_lines += (len > Self::LONG_LINE_LEN) as usize;
+            total += len;
+        }
+        if report.lines == 0 {
+            report.min_line_len = 0;
+        } else {
+            report.mean_line_len = total as f64 / report.lines as f64;
+        }
+        report
+    }
+}
+
+impl Display for BuildReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "lines: {}", self.lines)?;
+        writeln!(f, "data bytes: {}", self.data_bytes)?;
+        writeln!(
+            f,
+            "line length: min {}, max {}, mean {:.1}",
+            self.min_line_len, self.max_line_len, self.mean_line_len
+        )?;
+        writeln!(f, "trailing newline: {}", self.trailing_newline)?;
+        writeln!(f, "long lines: {}", self.long_lines)?;
+        write!(f, "elapsed: {:?}", self.elapsed)
+    }
+}
+
+/// Shared state between an `IndexBuildHandle` and its building thread
+#[derive(Debug, Default)]
+pub(crate) struct BuildProgress {
+    scanned: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+/// A handle to an index being built in the background
+#[derive(Debug)]
+pub struct IndexBuildHandle<T> {
+    progress: Arc<BuildProgress>,
+    handle: JoinHandle<Result<T>>,
+}
+
+impl<T: Send + 'static> IndexBuildHandle<T> {
+    /// Runs `f` on a new thread, passing it the progress which is shared with the handle
+    pub(crate) fn spawn<F>(f: F) -> Self
+    where
+        F: FnOnce(&BuildProgress) -> Result<T> + Send + 'static,
+    {
+        let progress = Arc::new(BuildProgress::default());
+        let thread_progress = Arc::clone(&progress);
+        let handle = thread::spawn(move || f(&thread_progress));
+        Self { progress, handle }
+    }
+
+    /// Returns the amount of bytes scanned and the total amount of bytes to scan
+    #[inline]
+    pub fn progress(&self) -> (u64, u64) {
+        (
+            self.progress.scanned.load(AtomicOrdering::Relaxed),
+            self.progress.total.load(AtomicOrdering::Relaxed),
+        )
+    }
+
+    /// Cancels the build. `join` will return `Error::Cancelled` unless the build has already
+    /// finished.
+    #[inline]
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+
+    /// Waits for the build to finish and returns its result
+    pub fn join(self) -> Result<T> {
+        self.handle
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+impl PartialEq for Index {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+            && self.delimiter == other.delimiter
+            && self.bom == other.bom
+            && self.collation == other.collation
+    }
+}
+
+impl Eq for Index {}
+
+impl Hash for Index {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state);
+        self.delimiter.hash(state);
+        self.bom.hash(state);
+        self.collation.hash(state);
+    }
+}
+
+/// Returns true if the data within `reader` starts with a UTF-8 BOM. The position of `reader`
+/// is moved.
+fn starts_with_bom<R: Read>(reader: &mut R) -> Result<bool> {
+    let mut start = [0u8; 3];
+    match reader.read_exact(&mut start) {
+        Ok(()) => Ok(start == UTF8_BOM),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Returns the checksum stored for `line`, which includes its terminator. These are the lower
+/// 32 bits of the FNV-1a hash of the line.
+#[inline]
+pub(crate) fn line_checksum(line: &[u8]) -> u32 {
+    crate::hash_bytes(line) as u32
+}
+
+/// Returns the position of the first offset which is not greater than its predecessor
+#[inline]
+pub(crate) fn first_unordered(offsets: &[u32]) -> Option<usize> {
+    offsets
+        .windows(2)
+        .position(|w| w[0] >= w[1])
+        .map(|pos| pos + 1)
 }
 
 impl Extend<u32> for Index {
-    /// Adds the values to the index. This should be preferred over `add` since it is faster
+    /// Adds the values to the index. This should be preferred over `add` since it is faster.
+    /// The values have to be strictly increasing, which is only checked in debug builds.
     fn extend<T: IntoIterator<Item = u32>>(&mut self, iter: T) {
+        let len_before = self.len();
         self.inner.extend(iter);
+        self.clear_line_tables();
+
+        debug_assert!(
+            first_unordered(&self.inner[len_before.saturating_sub(1)..]).is_none(),
+            "index offsets have to be strictly increasing"
+        );
 
         // Update length since we (might) have changed the index len
         self.len_bytes = self.calc_length();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{any::CloneableIndexedReader, temp::TempFile, File, Indexable, ReadByLine};
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = Header::new(1234);
+        let encoded = header.encode();
+        assert_eq!(encoded.len(), header.byte_len());
+        assert_eq!(&encoded[..8], &MAGIC);
+
+        let decoded = Header::decode(&mut std::io::Cursor::new(encoded)).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.items(), 1234);
+        assert_eq!(decoded.byte_len(), HEADER_SIZE);
+        assert_eq!((decoded.required_flags(), decoded.optional_flags()), (0, 0));
+
+        let mut index = Index::new(vec![0, 2]);
+        index.set_delimiter(b'\0');
+        index.set_collation(Some(Collation::CaselessAscii));
+        index.set_line_checksums(vec![0, 0]);
+        let header = index.get_header();
+        assert_eq!(header.required_flags(), REQ_LINE_CHECKSUMS);
+        assert_eq!(header.optional_flags(), OPT_DELIMITER | OPT_COLLATION);
+        assert_eq!(header.delimiter(), Some(b'\0'));
+        assert_eq!(header.collation(), Some(Collation::CaselessAscii));
+        assert!(!header.has_bom());
+    }
+
+    #[test]
+    fn test_header_items_u64() {
+        // Item counts are encoded as u64 regardless of the width of usize
+        let items = 1u64 << 33;
+        let header = Header {
+            items,
+            ..Header::new(0)
+        };
+        let encoded = header.encode();
+        assert_eq!(encoded[16..24], items.to_le_bytes());
+        let decoded = Header::decode(&mut std::io::Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoded.items(), items);
+
+        let legacy = Header::decode(&mut std::io::Cursor::new(u64::MAX.to_le_bytes())).unwrap();
+        assert_eq!(legacy.items(), u64::MAX);
+        assert_eq!(legacy.encode(), u64::MAX.to_le_bytes());
+
+        // The offsets of that many lines are missing
+        let mut data = encoded;
+        data.extend(0u32.to_le_bytes());
+        assert!(Index::decode(&mut std::io::Cursor::new(&data), &decoded).is_err());
+        if usize::BITS < 64 {
+            assert!(matches!(
+                Index::decode(&mut std::io::Cursor::new(&data), &legacy),
+                Err(Error::MalformedIndex)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_header_legacy() {
+        let decoded = Header::decode(&mut std::io::Cursor::new(3u64.to_le_bytes())).unwrap();
+        assert_eq!(decoded.version(), 0);
+        assert_eq!(decoded.items(), 3);
+        assert_eq!(decoded.byte_len(), LEGACY_HEADER_SIZE);
+        assert_eq!(decoded.encode(), 3u64.to_le_bytes());
+
+        // Data in the legacy format: header, offsets and a newline followed by the data
+        let mut data = 2u64.to_le_bytes().to_vec();
+        data.extend(0u32.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.push(b'\n');
+        data.extend(b"a\nb\n");
+
+        let mut reader = CloneableIndexedReader::new(data).unwrap();
+        assert_eq!(reader.read_line(0).unwrap(), "a\n");
+        assert_eq!(reader.read_line(1).unwrap(), "b\n");
+    }
+
+    #[test]
+    fn test_line_checksums_round_trip() {
+        let mut index = Index::new(vec![0, 2]);
+        index.set_line_checksums(vec![line_checksum(b"a\n"), line_checksum(b"b")]);
+        let header = index.get_header();
+        assert!(header.has_line_checksums());
+
+        let mut data = header.encode();
+        data.extend(index.encode());
+        data.extend(b"a\nb");
+
+        let decoded = Index::parse_index(&mut std::io::Cursor::new(&data)).unwrap();
+        assert_eq!(decoded.line_checksums(), index.line_checksums());
+        assert_eq!(decoded.len_bytes(), data.len() - 3);
+
+        let mut reader = CloneableIndexedReader::new(data).unwrap();
+        assert_eq!(reader.read_line(1).unwrap(), "b");
+
+        // Changing the offsets invalidates the checksums
+        index.add(4);
+        assert!(index.line_checksums().is_none());
+        assert!(!index.get_header().has_line_checksums());
+    }
+
+    #[test]
+    fn test_encode_into_round_trip() {
+        // More offsets than fit into a single batch
+        let offsets: Vec<u32> = (0..ENCODE_BATCH as u32 * 2 + 7).map(|i| i * 2).collect();
+        let mut index = Index::new(offsets[..10].to_vec());
+        assert_eq!(index.len_bytes(), index.calc_length());
+        index.extend(offsets[10..].iter().copied());
+
+        let mut checksummed = Index::new(vec![0, 2]);
+        checksummed.set_line_checksums(vec![line_checksum(b"a\n"), line_checksum(b"b")]);
+
+        for index in [Index::new(vec![]), index, checksummed] {
+            let mut encoded = Vec::new();
+            let written = index.encode_into(&mut encoded).unwrap();
+            assert_eq!(written, encoded.len());
+            assert_eq!(encoded, index.encode());
+            assert_eq!(encoded.last(), Some(&b'\n'));
+
+            let header = index.get_header();
+            assert_eq!(index.calc_length(), header.byte_len() + encoded.len());
+            assert_eq!(index.len_bytes(), index.calc_length());
+
+            let mut data = header.encode();
+            data.extend(encoded);
+            let decoded = Index::parse_index(&mut std::io::Cursor::new(&data)).unwrap();
+            assert_eq!(decoded.len_bytes(), data.len());
+            assert_eq!(decoded.len_bytes(), index.len_bytes());
+            assert_eq!(decoded.offsets(), index.offsets());
+            assert_eq!(decoded.line_checksums(), index.line_checksums());
+        }
+    }
+
+    #[test]
+    fn test_calc_length_extensions() {
+        let encoded_len = |index: &Index| {
+            let mut data = index.get_header().encode();
+            data.extend(index.encode());
+            data.len()
+        };
+
+        let mut index = Index::new(vec![0]);
+        index.set_delimiter(b'\0');
+        index.set_collation(Some(Collation::Bytewise));
+        index.add(2);
+        assert_eq!(index.len_bytes(), encoded_len(&index));
+        index.extend([4, 6]);
+        assert_eq!(index.len_bytes(), encoded_len(&index));
+        index.set_line_checksums(vec![0; 4]);
+        assert_eq!(index.len_bytes(), encoded_len(&index));
+
+        let mut bloom = BloomFilter::new(4, 0.01).unwrap();
+        bloom.insert(b"a");
+        index.set_bloom(bloom);
+        assert_eq!(index.len_bytes(), encoded_len(&index));
+        index.set_lookup_table(LookupTable::from_entries(
+            vec![(1, 0), (2, 1), (3, 2), (4, 3)],
+            false,
+        ));
+        assert_eq!(index.len_bytes(), encoded_len(&index));
+
+        let decoded = Index::parse_index(&mut std::io::Cursor::new(
+            [index.get_header().encode(), index.encode()].concat(),
+        ))
+        .unwrap();
+        assert_eq!(decoded.len_bytes(), index.len_bytes());
+    }
+
+    #[test]
+    fn test_header_unknown_flags() {
+        let mut header = Header::new(5);
+        header.optional_flags = 1 << 31;
+        header.extension = vec![1, 2, 3];
+
+        // Unknown optional flags and their extension are kept
+        let encoded = header.encode();
+        let decoded = Header::decode(&mut std::io::Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.encode(), encoded);
+
+        header.required_flags = 1 << 15;
+        let res = Header::decode(&mut std::io::Cursor::new(header.encode()));
+        assert!(matches!(res, Err(Error::UnsupportedFlags { flags }) if flags == 1 << 15));
+
+        // A huge extension length fails on the missing data instead of allocating it
+        let mut encoded = encoded;
+        encoded[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        let res = Header::decode(&mut std::io::Cursor::new(&encoded));
+        assert!(matches!(res, Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_header_newer_version() {
+        let mut header = Header::new(5);
+        header.version = FORMAT_VERSION + 1;
+
+        let temp = TempFile::new("version");
+        fs::write(temp.path(), header.encode()).unwrap();
+        let res = File::open(temp.path());
+
+        assert!(matches!(
+            res,
+            Err(Error::UnsupportedFormat { version }) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_build() {
+        for (data, expected) in [
+            ("", vec![]),
+            ("a", vec![0]),
+            ("a\n", vec![0]),
+            ("a\nb", vec![0, 2]),
+            ("\n\nab\n", vec![0, 1, 2]),
+        ] {
+            let mut reader = BufReader::with_capacity(2, std::io::Cursor::new(data));
+            assert_eq!(Index::build(&mut reader).unwrap().offsets(), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_build_with_report() {
+        for (data, lines, min, max, mean, trailing) in [
+            ("", 0, 0, 0, 0.0, false),
+            ("\n", 1, 0, 0, 0.0, true),
+            ("abc", 1, 3, 3, 3.0, false),
+            ("a\n\nabcd\n", 3, 0, 4, 5.0 / 3.0, true),
+            ("ab\nabcdef", 2, 2, 6, 4.0, false),
+        ] {
+            let mut reader = BufReader::with_capacity(2, std::io::Cursor::new(data));
+            let (index, report) = Index::build_with_report(&mut reader).unwrap();
+            assert_eq!(index.len(), lines);
+            assert_eq!(report.lines, lines);
+            assert_eq!(report.data_bytes, data.len() as u64);
+            assert_eq!(
+                (report.min_line_len, report.max_line_len),
+                (min, max),
+                "{:?}",
+                data
+            );
+            assert!((report.mean_line_len - mean).abs() < 1e-9);
+            assert_eq!(report.trailing_newline, trailing);
+            assert_eq!(report.long_lines, 0);
+        }
+
+        let long = format!(
+            "a\n{}\nb",
+            "x".repeat(BuildReport::LONG_LINE_LEN as usize + 1)
+        );
+        let (_, report) =
+            Index::build_with_report(&mut BufReader::new(std::io::Cursor::new(long))).unwrap();
+        assert_eq!(report.long_lines, 1);
+        assert_eq!(report.max_line_len, BuildReport::LONG_LINE_LEN + 1);
+        assert!(report.to_string().contains("long lines: 1"));
+    }
+
+    #[test]
+    fn test_build_background() {
+        let mut reader = BufReader::new(fs::File::open("./testfiles/LICENSE").unwrap());
+        let expected = Index::build(&mut reader).unwrap();
+
+        let handle = Index::build_background(fs::File::open("./testfiles/LICENSE").unwrap());
+        let index = handle.join().unwrap();
+        assert_eq!(index.offsets(), expected.offsets());
+
+        let handle = File::open_raw_background("./testfiles/LICENSE");
+        let mut file = handle.join().unwrap();
+        assert_eq!(file.get_index().offsets(), expected.offsets());
+        assert_eq!(
+            file.read_line(0).unwrap(),
+            File::open_raw("./testfiles/LICENSE")
+                .unwrap()
+                .read_line(0)
+                .unwrap()
+        );
+
+        assert!(File::open_raw_background("./testfiles/does_not_exist")
+            .join()
+            .is_err());
+    }
+
+    /// A reader which takes some time for each read
+    struct SlowReader(std::io::Cursor<Vec<u8>>);
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            let len = buf.len().min(1024);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for SlowReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_build_background_cancel() {
+        let reader = SlowReader(std::io::Cursor::new(b"line\n".repeat(100_000)));
+        let handle = Index::build_background(reader);
+        handle.cancel();
+        assert!(matches!(handle.join(), Err(Error::Cancelled)));
+
+        let reader = SlowReader(std::io::Cursor::new(b"line\n".repeat(1000)));
+        let handle = Index::build_background(reader);
+        while handle.progress().0 == 0 {
+            std::thread::yield_now();
+        }
+        let (scanned, total) = handle.progress();
+        assert!(scanned <= total);
+        assert_eq!(total, 5000);
+        assert_eq!(handle.join().unwrap().len(), 1000);
+    }
+
+    #[test]
+    fn test_header_source() {
+        let mut header = Header::new(5);
+        header.optional_flags = 1 << 31;
+        header.extension = vec![9, 9];
+        header.set_source(SourceInfo {
+            data_len: 1234,
+            tail_hash: 42,
+        });
+        header.delimiter = Some(b'\0');
+        header.set_content_hash(7);
+
+        let encoded = header.encode();
+        assert_eq!(encoded.len(), header.byte_len());
+        let decoded = Header::decode(&mut std::io::Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.source().unwrap().data_len, 1234);
+        assert_eq!(decoded.delimiter, Some(b'\0'));
+        assert_eq!(decoded.content_hash(), Some(7));
+    }
+
+    #[test]
+    fn test_extend_from_file() {
+        let temp = TempFile::new("extend");
+        let path = temp.path();
+        fs::write(path, "a\nb").unwrap();
+        let mut index = Index::build(&mut BufReader::new(fs::File::open(path).unwrap())).unwrap();
+        assert_eq!(index.offsets(), &[0, 2]);
+
+        // Data continuing the unterminated last line
+        fs::write(path, "a\nbc\nd\n").unwrap();
+        assert_eq!(index.extend_from_file(path, 3).unwrap(), 1);
+        assert_eq!(index.offsets(), &[0, 2, 5]);
+        assert_eq!(index.len_bytes(), index.calc_length());
+
+        fs::write(path, "a\nbc\nd\ne\nf").unwrap();
+        assert_eq!(index.extend_from_file(path, 7).unwrap(), 2);
+        assert_eq!(index.offsets(), &[0, 2, 5, 7, 9]);
+        assert_eq!(index.len_bytes(), index.calc_length());
+
+        assert_eq!(index.extend_from_file(path, 10).unwrap(), 0);
+
+        // The last indexed line has to start within the previously indexed data
+        assert!(matches!(
+            index.extend_from_file(path, 0),
+            Err(Error::InvalidIndex { line: 4 })
+        ));
+        assert!(matches!(
+            index.extend_from_file(path, 9),
+            Err(Error::InvalidIndex { line: 4 })
+        ));
+        assert_eq!(index.offsets(), &[0, 2, 5, 7, 9]);
+
+        // Lines appended to data consisting of a skipped BOM start behind it
+        fs::write(path, UTF8_BOM).unwrap();
+        let mut reader = BufReader::new(fs::File::open(path).unwrap());
+        let mut index = Index::build_with(&mut reader, b'\n', true, None).unwrap();
+        assert!(index.has_bom());
+        assert!(index.offsets().is_empty());
+        fs::write(path, [&UTF8_BOM[..], b"a\nb"].concat()).unwrap();
+        assert_eq!(index.extend_from_file(path, 3).unwrap(), 2);
+        assert_eq!(index.offsets(), &[3, 5]);
+
+        // The BOM is skipped if it wasn't indexed yet
+        index.inner.clear();
+        assert_eq!(index.extend_from_file(path, 0).unwrap(), 2);
+        assert_eq!(index.offsets(), &[3, 5]);
+    }
+
+    #[test]
+    fn test_eq_hash() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |index: &Index| {
+            let mut hasher = DefaultHasher::new();
+            index.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let mut reader = BufReader::new(std::io::Cursor::new("a\nb\nc\n"));
+        let built = Index::build(&mut reader).unwrap();
+        let new = Index::new(vec![0, 2, 4]);
+        let mut added = Index::with_capacity(3);
+        added.extend([0, 2, 4]);
+
+        assert_ne!(built.len_bytes(), new.len_bytes());
+        assert_eq!(built, new);
+        assert_eq!(built, added);
+        assert_eq!(hash(&built), hash(&new));
+        assert_eq!(hash(&built), hash(&added));
+        assert_ne!(built, Index::new(vec![0, 2]));
+
+        // Indexes with the same offsets reading the data differently
+        let mut delimiter = new.clone();
+        delimiter.set_delimiter(b'\0');
+        let mut bom = new.clone();
+        bom.bom = true;
+        let mut collation = new.clone();
+        collation.set_collation(Some(Collation::Bytewise));
+        for other in [delimiter, bom, collation] {
+            assert_ne!(new, other);
+            assert_ne!(hash(&new), hash(&other));
+        }
+
+        assert_eq!(new.into_inner(), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let index = Index::new(vec![0, 4, 9]);
+        assert_eq!(index.get_opt(0), Some(0));
+        assert_eq!(index.get_opt(2), Some(9));
+        assert_eq!(index.get_opt(3), None);
+        assert_eq!(index.get_unchecked(2), 9);
+        assert!(matches!(
+            index.get(3),
+            Err(Error::OutOfBounds { line: 3, lines: 3 })
+        ));
+        assert_eq!(Index::default().get_opt(0), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_unchecked_out_of_bounds() {
+        Index::new(vec![0, 4]).get_unchecked(2);
+    }
+
+    #[test]
+    fn test_shift_from() {
+        let mut index = Index::new(vec![0, 4, 9]);
+        index.shift_from(1, 3).unwrap();
+        assert_eq!(index.offsets(), [0, 7, 12]);
+        index.shift_from(2, -12).unwrap();
+        assert_eq!(index.offsets(), [0, 7, 0]);
+        index.shift_from(3, 100).unwrap();
+        assert_eq!(index.offsets(), [0, 7, 0]);
+    }
+
+    #[test]
+    fn test_shift_from_overflow() {
+        let mut index = Index::new(vec![0, 4]);
+        assert!(matches!(
+            index.shift_from(1, -5),
+            Err(Error::OffsetOverflow { line: 1 })
+        ));
+
+        // Nothing is shifted if a later offset overflows
+        let mut index = Index::new(vec![0, 4, u32::MAX - 1]);
+        assert!(matches!(
+            index.shift_from(1, 2),
+            Err(Error::OffsetOverflow { line: 2 })
+        ));
+        assert_eq!(index.offsets(), [0, 4, u32::MAX - 1]);
+    }
+
+    #[test]
+    fn test_monotonic() {
+        let mut index = Index::default();
+        index.try_add(0).unwrap();
+        index.try_add(5).unwrap();
+        assert!(matches!(
+            index.try_add(5),
+            Err(Error::InvalidIndex { line: 2 })
+        ));
+        assert!(matches!(
+            index.try_add(3),
+            Err(Error::InvalidIndex { line: 2 })
+        ));
+        index.try_add(6).unwrap();
+        assert_eq!(index.offsets(), &[0, 5, 6]);
+
+        // `add` doesn't check the value
+        index.add(6);
+        assert_eq!(index.offsets(), &[0, 5, 6, 6]);
+
+        assert_eq!(
+            Index::from_sorted_offsets(vec![0, 1, 10]).unwrap(),
+            Index::new(vec![0, 1, 10])
+        );
+        assert!(Index::from_sorted_offsets(vec![]).is_ok());
+        assert!(matches!(
+            Index::from_sorted_offsets(vec![0, 4, 4, 5]),
+            Err(Error::InvalidIndex { line: 2 })
+        ));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_extend_unordered() {
+        let mut index = Index::new(vec![0, 10]);
+        index.extend([5]);
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut index = Index::new(vec![0, 4, 9]);
+        index.set_delimiter(b';');
+        let json = serde_json::to_string(&index).unwrap();
+        let decoded: Index = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, index);
+        assert_eq!(decoded.delimiter(), b';');
+
+        // Indexes serialized before the delimiter was added use the default one
+        let legacy: Index = serde_json::from_str(r#"{"inner":[0,4],"len_bytes":36}"#).unwrap();
+        assert_eq!(legacy.delimiter(), DEFAULT_DELIMITER);
+        assert!(!legacy.has_bom());
+        assert!(legacy.line_checksums().is_none());
+        assert!(legacy.collation().is_none());
+        assert!(legacy.bloom().is_none());
+        assert!(legacy.lookup_table().is_none());
+
+        let data = b"\xEF\xBB\xBFa\nb";
+        let mut reader = BufReader::new(std::io::Cursor::new(data));
+        let mut index = Index::build_with(&mut reader, b'\n', true, None).unwrap();
+        index.set_line_checksums(vec![line_checksum(b"a\n"), line_checksum(b"b")]);
+        index.set_collation(Some(Collation::CaselessAscii));
+        let mut bloom = BloomFilter::new(2, 0.01).unwrap();
+        bloom.insert(b"a");
+        index.set_bloom(bloom);
+        index.set_lookup_table(LookupTable::from_entries(vec![(3, 1), (5, 0)], false));
+        let json = serde_json::to_string(&index).unwrap();
+        let decoded: Index = serde_json::from_str(&json).unwrap();
+        assert!(decoded.has_bom());
+        assert_eq!(decoded.line_checksums(), index.line_checksums());
+        assert_eq!(decoded.len_bytes(), index.len_bytes());
+        assert_eq!(decoded.collation(), Some(Collation::CaselessAscii));
+        assert_eq!(decoded.bloom(), index.bloom());
+        assert_eq!(decoded.lookup_table(), index.lookup_table());
+    }
+
+    #[test]
+    fn test_to_from_path() {
+        let mut reader = BufReader::new(fs::File::open("./testfiles/LICENSE").unwrap());
+        let index = Index::build(&mut reader).unwrap();
+
+        let temp = TempFile::new("index");
+        index.to_path(temp.path()).unwrap();
+        let read = Index::from_path(temp.path()).unwrap();
+
+        assert_eq!(read.offsets(), index.offsets());
+        assert_eq!(read.len_bytes(), HEADER_SIZE + read.len() * 4 + 1);
+
+        let mut file = File::open_custom("./testfiles/LICENSE", Arc::new(read.zero_len())).unwrap();
+        let mut orig = File::open_raw("./testfiles/LICENSE").unwrap();
+        for line in [0, 10, index.len() - 1] {
+            assert_eq!(file.read_line(line).unwrap(), orig.read_line(line).unwrap());
+        }
+
+        assert!(Index::from_path("./testfiles/does_not_exist").is_err());
+    }
+}
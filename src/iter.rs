@@ -0,0 +1,993 @@
+use std::{
+    io::{self, BufRead, Read},
+    ops::Range,
+    sync::mpsc::Receiver,
+};
+
+use crate::{
+    bufreader::DEFAULT_BUF_CAPACITY, error::Error, trim_terminator, IndexableFile, ReadByLine,
+    Result,
+};
+
+/// Defines what the offsets returned by `ReadByLine::offset_lines` are relative to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetBase {
+    /// Offsets are relative to the beginning of the data, as stored in the index. For data
+    /// indexed using `open_raw`, this is the position within the original file.
+    Data,
+    /// Offsets are relative to the beginning of the indexed file, so the header and the index
+    /// are included in the offsets.
+    File,
+}
+
+/// An iterator over the lines of a reader in reverse order. Created by
+/// [`ReadByLine::lines_rev`] and [`ReadByLine::rev_from`].
+///
+/// Lines are returned including their terminators, like `read_line` does. The iterator stops
+/// after the first error.
+#[derive(Debug)]
+pub struct RevLines<'a, R: ReadByLine> {
+    reader: &'a mut R,
+    /// The next line to read. `None` if the iteration has finished
+    next: Option<usize>,
+}
+
+impl<'a, R: ReadByLine> RevLines<'a, R> {
+    /// Creates a new `RevLines` starting at `line` and ending with the first line
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, line: Option<usize>) -> Self {
+        Self { reader, next: line }
+    }
+}
+
+impl<'a, R: ReadByLine> Iterator for RevLines<'a, R> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.next?;
+        let res = self.reader.read_line(line);
+
+        self.next = match res {
+            Ok(_) => line.checked_sub(1),
+            Err(_) => None,
+        };
+
+        Some(res)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let max = self.next.map(|line| line + 1).unwrap_or(0);
+        (0, Some(max))
+    }
+}
+
+impl<'a, R: ReadByLine> std::iter::FusedIterator for RevLines<'a, R> {}
+
+/// An iterator over all lines paired with their line-index and byte offset. Created by
+/// [`ReadByLine::offset_lines_raw`].
+///
+/// Lines are returned including their terminators. The iterator stops after the first error.
+#[derive(Debug)]
+pub struct RawOffsetLines<'a, R: ReadByLine> {
+    reader: &'a mut R,
+    /// Added to every offset of the index
+    base: u64,
+    /// The next line to read. `None` if the iteration has finished
+    next: Option<usize>,
+}
+
+impl<'a, R: ReadByLine> RawOffsetLines<'a, R> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, base: OffsetBase) -> Self {
+        let base = match base {
+            OffsetBase::Data => 0,
+            OffsetBase::File => reader.get_index_byte_len() as u64,
+        };
+        Self {
+            reader,
+            base,
+            next: Some(0),
+        }
+    }
+}
+
+impl<'a, R: ReadByLine> Iterator for RawOffsetLines<'a, R> {
+    type Item = Result<(usize, u64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.next.filter(|line| *line < self.reader.total_lines())?;
+
+        let res = self.reader.get_offset(line).and_then(|offset| {
+            let mut buf = Vec::new();
+            let read = self.reader.read_line_raw(line, &mut buf)?;
+            buf.truncate(read);
+            Ok((line, self.base + offset as u64, buf))
+        });
+
+        self.next = res.as_ref().ok().map(|_| line + 1);
+        Some(res)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let max = self
+            .next
+            .map(|line| self.reader.total_lines().saturating_sub(line))
+            .unwrap_or(0);
+        (0, Some(max))
+    }
+}
+
+impl<'a, R: ReadByLine> std::iter::FusedIterator for RawOffsetLines<'a, R> {}
+
+/// An iterator over all lines paired with their line-index and byte offset. Created by
+/// [`ReadByLine::offset_lines`].
+///
+/// Lines are returned including their terminators, like `read_line` does. The iterator stops
+/// after the first error.
+#[derive(Debug)]
+pub struct OffsetLines<'a, R: ReadByLine> {
+    inner: RawOffsetLines<'a, R>,
+}
+
+impl<'a, R: ReadByLine> OffsetLines<'a, R> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, base: OffsetBase) -> Self {
+        Self {
+            inner: RawOffsetLines::new(reader, base),
+        }
+    }
+}
+
+impl<'a, R: ReadByLine> Iterator for OffsetLines<'a, R> {
+    type Item = Result<(usize, u64, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self
+            .inner
+            .next()?
+            .and_then(|(line, offset, content)| Ok((line, offset, String::from_utf8(content)?)));
+
+        if res.is_err() {
+            self.inner.next = None;
+        }
+        Some(res)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, R: ReadByLine> std::iter::FusedIterator for OffsetLines<'a, R> {}
+
+/// An iterator over all lines in chunks of a fixed amount of lines. Created by
+/// [`ReadByLine::chunks_raw`].
+///
+/// Every chunk is returned as the bytes of its lines, including their terminators, together with
+/// the range of every line within these bytes. Only the last chunk may hold fewer lines. The
+/// iterator stops after the first error.
+#[derive(Debug)]
+pub struct RawChunkIter<'a, R: ReadByLine> {
+    reader: &'a mut R,
+    size: usize,
+    /// The first line of the next chunk. `None` if the iteration has finished
+    next: Option<usize>,
+}
+
+impl<'a, R: ReadByLine> RawChunkIter<'a, R> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(Error::ZeroChunkSize);
+        }
+        Ok(Self {
+            reader,
+            size,
+            next: Some(0),
+        })
+    }
+
+    /// Reads `lines` and returns the range of every line within the read bytes. Returns
+    /// `Error::IncompleteLine` for the first line which isn't contained in the read bytes.
+    fn read_chunk(&mut self, lines: Range<usize>) -> Result<(Vec<u8>, Vec<Range<usize>>)> {
+        let mut buf = Vec::new();
+        self.reader.read_span(lines.clone(), &mut buf)?;
+
+        let start = self.reader.get_offset(lines.start)? as u64;
+        let mut ranges = Vec::with_capacity(lines.len());
+        for line in lines {
+            let span = self.reader.line_span(line)?;
+            let range = (span.start - start) as usize..span.end.saturating_sub(start) as usize;
+            if range.end < range.start || range.end > buf.len() {
+                return Err(Error::IncompleteLine {
+                    line,
+                    have: buf.len().saturating_sub(range.start),
+                    want: range.end.saturating_sub(range.start),
+                });
+            }
+            ranges.push(range);
+        }
+        Ok((buf, ranges))
+    }
+}
+
+impl<'a, R: ReadByLine> Iterator for RawChunkIter<'a, R> {
+    type Item = Result<(Vec<u8>, Vec<Range<usize>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let total = self.reader.total_lines();
+        let start = self.next.filter(|line| *line < total)?;
+        let end = start.saturating_add(self.size).min(total);
+
+        let res = self.read_chunk(start..end);
+        self.next = res.as_ref().ok().map(|_| end);
+        Some(res)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let lines = self
+            .next
+            .map(|line| self.reader.total_lines().saturating_sub(line))
+            .unwrap_or(0);
+        (0, Some(lines.div_ceil(self.size)))
+    }
+}
+
+impl<'a, R: ReadByLine> std::iter::FusedIterator for RawChunkIter<'a, R> {}
+
+/// An iterator over all lines in chunks of a fixed amount of lines. Created by
+/// [`ReadByLine::chunks`].
+///
+/// Lines are returned including their terminators, like `read_line` does. Only the last chunk
+/// may hold fewer lines. The iterator stops after the first error.
+#[derive(Debug)]
+pub struct ChunkIter<'a, R: ReadByLine> {
+    inner: RawChunkIter<'a, R>,
+}
+
+impl<'a, R: ReadByLine> ChunkIter<'a, R> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, size: usize) -> Result<Self> {
+        Ok(Self {
+            inner: RawChunkIter::new(reader, size)?,
+        })
+    }
+}
+
+impl<'a, R: ReadByLine> Iterator for ChunkIter<'a, R> {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.inner.next.unwrap_or(0);
+        let res = self.inner.next()?.and_then(|(buf, ranges)| {
+            ranges
+                .into_iter()
+                .enumerate()
+                .map(|(i, range)| match buf.get(range.clone()) {
+                    Some(line) => Ok(String::from_utf8(line.to_vec())?),
+                    None => Err(Error::IncompleteLine {
+                        line: first + i,
+                        have: buf.len().saturating_sub(range.start),
+                        want: range.len(),
+                    }),
+                })
+                .collect()
+        });
+
+        if res.is_err() {
+            self.inner.next = None;
+        }
+        Some(res)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, R: ReadByLine> std::iter::FusedIterator for ChunkIter<'a, R> {}
+
+/// An iterator over the runs of adjacent duplicate lines. Created by
+/// [`ReadByLine::duplicate_runs`] and [`ReadByLine::duplicate_runs_by_key`].
+///
+/// Every run of at least two lines with equal keys is returned as its first line, its length and
+/// the content of its first line without terminator. Lines are compared without their terminators,
+/// so runs are found in sorted data in a single sequential pass. The iterator stops after the
+/// first error.
+pub struct DuplicateRuns<'a, R: ReadByLine, K> {
+    reader: &'a mut R,
+    key: K,
+    /// The next line to read. `None` if the iteration has finished
+    next: Option<usize>,
+    /// The first line of the current run, without terminator
+    first: Vec<u8>,
+    /// Buffer for the line compared with `first`
+    buf: Vec<u8>,
+    /// First line-index and length of the current run
+    run: (usize, usize),
+}
+
+impl<'a, R: ReadByLine, K> DuplicateRuns<'a, R, K>
+where
+    K: FnMut(&[u8]) -> &[u8],
+{
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, key: K) -> Self {
+        Self {
+            reader,
+            key,
+            next: Some(0),
+            first: Vec::new(),
+            buf: Vec::new(),
+            run: (0, 0),
+        }
+    }
+
+    /// Reads `line` into `buf` and strips its terminator
+    fn read_into(reader: &mut R, line: usize, buf: &mut Vec<u8>) -> Result<()> {
+        let read = reader.read_line_raw(line, buf)?;
+        let delimiter = reader.get_index().delimiter();
+        let len = trim_terminator(&buf[..read], delimiter).len();
+        buf.truncate(len);
+        Ok(())
+    }
+
+    /// Returns the current run if it has duplicates
+    fn finished_run(&self) -> Option<(usize, usize, Vec<u8>)> {
+        let (start, len) = self.run;
+        (len >= 2).then(|| (start, len, self.first.clone()))
+    }
+}
+
+impl<'a, R: ReadByLine, K> Iterator for DuplicateRuns<'a, R, K>
+where
+    K: FnMut(&[u8]) -> &[u8],
+{
+    type Item = Result<(usize, usize, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.next?;
+            if line >= self.reader.total_lines() {
+                self.next = None;
+                return self.finished_run().map(Ok);
+            }
+
+            let into = if self.run.1 == 0 {
+                &mut self.first
+            } else {
+                &mut self.buf
+            };
+            if let Err(err) = Self::read_into(self.reader, line, into) {
+                self.next = None;
+                return Some(Err(err));
+            }
+            self.next = Some(line + 1);
+
+            if self.run.1 == 0 {
+                self.run = (line, 1);
+                continue;
+            }
+
+            if (self.key)(&self.buf) == (self.key)(&self.first) {
+                self.run.1 += 1;
+                continue;
+            }
+
+            // The line starts a new run
+            let finished = self.finished_run();
+            std::mem::swap(&mut self.first, &mut self.buf);
+            self.run = (line, 1);
+            if finished.is_some() {
+                return finished.map(Ok);
+            }
+        }
+    }
+}
+
+impl<'a, R: ReadByLine, K> std::iter::FusedIterator for DuplicateRuns<'a, R, K> where
+    K: FnMut(&[u8]) -> &[u8]
+{
+}
+
+impl<'a, R: ReadByLine + std::fmt::Debug, K> std::fmt::Debug for DuplicateRuns<'a, R, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DuplicateRuns")
+            .field("reader", &self.reader)
+            .field("next", &self.next)
+            .field("run", &self.run)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator deserializing every line from JSON. Created by
+/// [`ReadByLine::deserialize_lines`].
+///
+/// Lines which can't be deserialized are returned as `Error::Deserialize` and the iteration
+/// continues with the next line. The iterator stops after the first error reading a line.
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub struct DeserializeLines<'a, R: ReadByLine, T> {
+    reader: &'a mut R,
+    /// The next line to read. `None` if the iteration has finished
+    next: Option<usize>,
+    /// Reused for all lines
+    buf: Vec<u8>,
+    _type: std::marker::PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "json")]
+impl<'a, R: ReadByLine, T> DeserializeLines<'a, R, T> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader,
+            next: Some(0),
+            buf: Vec::new(),
+            _type: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'a, R: ReadByLine, T: serde::de::DeserializeOwned> Iterator for DeserializeLines<'a, R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.next.filter(|line| *line < self.reader.total_lines())?;
+
+        let read = match self.reader.read_line_raw(line, &mut self.buf) {
+            Ok(read) => read,
+            Err(err) => {
+                self.next = None;
+                return Some(Err(err));
+            }
+        };
+
+        self.next = Some(line + 1);
+        Some(
+            serde_json::from_slice(&self.buf[..read])
+                .map_err(|source| crate::error::Error::Deserialize { line, source }),
+        )
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self
+            .next
+            .map(|line| self.reader.total_lines().saturating_sub(line))
+            .unwrap_or(0);
+        (0, Some(len))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'a, R: ReadByLine, T: serde::de::DeserializeOwned> std::iter::FusedIterator
+    for DeserializeLines<'a, R, T>
+{
+}
+
+/// A `BufRead` over the data of a reader, starting at a given line. Created by
+/// [`ReadByLine::as_bufread_from`].
+///
+/// The data is read line by line, including the terminators, so `BufRead::lines` and
+/// `BufRead::read_line` behave as they would on the plain data. When dropped, the underlying
+/// reader is positioned at the first line which wasn't consumed completely, so sequential reads
+/// can continue from there.
+#[derive(Debug)]
+pub struct LineBufRead<'a, R: ReadByLine> {
+    reader: &'a mut R,
+    /// The line to read into `buf` once it has been consumed
+    next_line: usize,
+    buf: Vec<u8>,
+    /// Amount of bytes of `buf` which have been consumed
+    pos: usize,
+}
+
+impl<'a, R: ReadByLine> LineBufRead<'a, R> {
+    #[inline]
+    pub(crate) fn new(reader: &'a mut R, start_line: usize) -> Self {
+        Self {
+            reader,
+            next_line: start_line,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, R: ReadByLine> BufRead for LineBufRead<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Once all lines have been read, the buffer stays empty
+        if self.pos >= self.buf.len() && self.next_line != self.reader.total_lines() {
+            self.buf.clear();
+            self.pos = 0;
+
+            let read = self.reader.read_line_raw(self.next_line, &mut self.buf)?;
+            self.buf.truncate(read);
+            self.next_line += 1;
+        }
+
+        Ok(&self.buf[self.pos..])
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+impl<'a, R: ReadByLine> Read for LineBufRead<'a, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<'a, R: ReadByLine> Drop for LineBufRead<'a, R> {
+    fn drop(&mut self) {
+        let mut line = self.next_line;
+        if self.pos < self.buf.len() {
+            line -= 1;
+        }
+
+        if line < self.reader.total_lines() {
+            // The adapter can't report errors here. A failed seek leaves the reader in a state
+            // which is fixed by its next seek.
+            let _ = self.reader.seek_line(line);
+        }
+    }
+}
+
+/// A `Read` and `BufRead` over the content of a single line without its terminator. Created by
+/// [`IndexableFile::line_reader`].
+///
+/// The line is read in chunks, so a huge line doesn't have to fit into a single buffer. When
+/// dropped within the line, the underlying reader stays usable and its next `seek_line` moves it
+/// to the requested line as usual.
+#[derive(Debug)]
+pub struct LineReader<'a, R: IndexableFile> {
+    reader: &'a mut R,
+    line: usize,
+    /// Length of the line without its terminator
+    len: u64,
+    /// Offset within the line of the data following `buf`
+    offset: u64,
+    buf: Vec<u8>,
+    /// Amount of bytes of `buf` which have been consumed
+    pos: usize,
+    /// Amount of bytes of `buf` which hold data
+    filled: usize,
+}
+
+impl<'a, R: IndexableFile> LineReader<'a, R> {
+    pub(crate) fn new(reader: &'a mut R, line: usize) -> Result<Self> {
+        // The last line ends at the end of the data, which might be before its start
+        let span = reader.line_span(line)?;
+        let mut len = span
+            .end
+            .checked_sub(span.start)
+            .ok_or(Error::IncompleteLine {
+                line,
+                have: 0,
+                want: 0,
+            })?;
+
+        // Only the last line can be unterminated
+        if len > 0 {
+            let mut last = [0u8];
+            let terminated = line + 1 < reader.total_lines()
+                || (reader.read_line_at(line, len - 1, &mut last)? == 1
+                    && last[0] == reader.get_index().delimiter());
+            if terminated {
+                len -= 1;
+            }
+        }
+
+        let buf = vec![0; (len as usize).min(DEFAULT_BUF_CAPACITY)];
+        Ok(Self {
+            reader,
+            line,
+            len,
+            offset: 0,
+            buf,
+            pos: 0,
+            filled: 0,
+        })
+    }
+
+    /// Returns the length of the line in bytes without its terminator
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the line is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'a, R: IndexableFile> BufRead for LineReader<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.filled && self.offset < self.len {
+            let want = ((self.len - self.offset) as usize).min(self.buf.len());
+            let read = self
+                .reader
+                .read_line_at(self.line, self.offset, &mut self.buf[..want])?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.offset += read as u64;
+            self.pos = 0;
+            self.filled = read;
+        }
+
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+impl<'a, R: IndexableFile> Read for LineReader<'a, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+/// An iterator over the lines read by another thread. Created by [`crate::File::stream_lines`].
+///
+/// Lines are returned with their index and including their terminators. The iterator ends once
+/// all lines were read or the reading thread stopped because of an error, which is returned by
+/// the thread's handle. Dropping the iterator stops the reading thread.
+#[derive(Debug)]
+pub struct LineReceiver {
+    receiver: Receiver<(usize, Vec<u8>)>,
+}
+
+impl LineReceiver {
+    #[inline]
+    pub(crate) fn new(receiver: Receiver<(usize, Vec<u8>)>) -> Self {
+        Self { receiver }
+    }
+}
+
+impl Iterator for LineReceiver {
+    type Item = (usize, Vec<u8>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl std::iter::FusedIterator for LineReceiver {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::CloneableIndexedReader, error::Error, index::Index, IndexableFile, IndexedString,
+    };
+    use std::{
+        io::{BufRead, Cursor, Read},
+        sync::Arc,
+    };
+
+    #[test]
+    fn test_offset_lines() {
+        let mut reader = IndexedString::new_raw("a\nbc\n\nd").unwrap();
+        let lines: Vec<_> = reader
+            .offset_lines(OffsetBase::Data)
+            .map(|i| i.unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (0, 0, "a\n".to_string()),
+                (1, 2, "bc\n".to_string()),
+                (2, 5, "\n".to_string()),
+                (3, 6, "d".to_string())
+            ]
+        );
+
+        // Offsets relative to the file point to the lines within the indexed data
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let mut reader = CloneableIndexedReader::new(indexed.clone()).unwrap();
+        for res in reader.offset_lines_raw(OffsetBase::File) {
+            let (_, offset, content) = res.unwrap();
+            let offset = offset as usize;
+            assert_eq!(&indexed[offset..offset + content.len()], &content[..]);
+        }
+
+        let mut invalid = CloneableIndexedReader::new_raw(vec![b'a', b'\n', 0xff]).unwrap();
+        let mut lines = invalid.offset_lines(OffsetBase::Data);
+        assert!(lines.next().unwrap().is_ok());
+        assert!(matches!(lines.next(), Some(Err(Error::UTF8Error))));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_chunks() {
+        let text = "a\nbc\n\nd\nef";
+        let mut reader = IndexedString::new_raw(text).unwrap();
+
+        let chunks: Vec<_> = reader.chunks(2).unwrap().map(|i| i.unwrap()).collect();
+        assert_eq!(chunks, [vec!["a\n", "bc\n"], vec!["\n", "d\n"], vec!["ef"]]);
+        let chunks: Vec<_> = reader.chunks(10).unwrap().map(|i| i.unwrap()).collect();
+        assert_eq!(chunks, [vec!["a\n", "bc\n", "\n", "d\n", "ef"]]);
+        assert_eq!(reader.chunks(2).unwrap().size_hint(), (0, Some(3)));
+        assert!(matches!(reader.chunks(0), Err(Error::ZeroChunkSize)));
+        assert!(matches!(reader.chunks_raw(0), Err(Error::ZeroChunkSize)));
+
+        // Ranges are relative to the buffer of their chunk
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let mut reader = CloneableIndexedReader::new(indexed).unwrap();
+        let chunks: Vec<_> = reader.chunks_raw(3).unwrap().map(|i| i.unwrap()).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], (b"a\nbc\n\n".to_vec(), vec![0..2, 2..5, 5..6]));
+        assert_eq!(chunks[1], (b"d\nef".to_vec(), vec![0..2, 2..4]));
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert_eq!(empty.chunks(3).unwrap().count(), 0);
+
+        let mut invalid = CloneableIndexedReader::new_raw(vec![b'a', b'\n', 0xff]).unwrap();
+        let mut chunks = invalid.chunks(1).unwrap();
+        assert_eq!(chunks.next().unwrap().unwrap(), ["a\n"]);
+        assert!(matches!(chunks.next(), Some(Err(Error::UTF8Error))));
+        assert!(chunks.next().is_none());
+
+        // Data ending before the last line fails instead of panicking
+        let index = Arc::new(Index::new(vec![0, 2, 5]).zero_len());
+        let mut truncated = IndexedString::new_custom("a\nbc", index);
+        let mut chunks = truncated.chunks(3).unwrap();
+        assert!(matches!(
+            chunks.next(),
+            Some(Err(Error::IncompleteLine {
+                line: 1,
+                have: 2,
+                want: 3
+            }))
+        ));
+        assert!(chunks.next().is_none());
+        assert!(matches!(
+            truncated.chunks_raw(2).unwrap().next(),
+            Some(Err(Error::InvalidIndex { line: 0 }))
+        ));
+    }
+
+    #[test]
+    fn test_as_bufread_from() {
+        let mut reader = IndexedString::new_raw("a\nbc\n\nd").unwrap();
+
+        let lines: Vec<_> = reader
+            .as_bufread_from(1)
+            .lines()
+            .map(|i| i.unwrap())
+            .collect();
+        assert_eq!(lines, ["bc", "", "d"]);
+
+        let mut all = String::new();
+        reader.as_bufread_from(0).read_to_string(&mut all).unwrap();
+        assert_eq!(all, "a\nbc\n\nd");
+
+        assert_eq!(reader.as_bufread_from(4).lines().count(), 0);
+        assert!(reader.as_bufread_from(5).fill_buf().is_err());
+
+        // Partially consumed lines are read again after dropping the adapter
+        let mut first = [0; 1];
+        let mut bufread = reader.as_bufread_from(1);
+        bufread.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"b");
+        drop(bufread);
+        let mut buf = Vec::new();
+        reader.read_current_line(&mut buf, 1).unwrap();
+        assert_eq!(buf, b"bc\n");
+    }
+
+    #[test]
+    fn test_rev_from() {
+        let mut reader = IndexedString::new_raw("a\nb\nc").unwrap();
+
+        let lines: Vec<_> = reader.rev_from(1).map(|i| i.unwrap()).collect();
+        assert_eq!(lines, ["b\n", "a\n"]);
+
+        let mut oob = reader.rev_from(3);
+        assert!(matches!(
+            oob.next(),
+            Some(Err(Error::OutOfBounds { line: 3, lines: 3 }))
+        ));
+        assert!(oob.next().is_none());
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert!(empty.lines_rev().next().is_none());
+    }
+
+    fn check_line_reader<R: ReadByLine>(reader: &mut R, lines: &[String]) {
+        for (line, expected) in lines.iter().enumerate() {
+            let mut line_reader = reader.line_reader(line).unwrap();
+            assert_eq!(line_reader.len(), expected.len() as u64);
+            let mut read = String::new();
+            line_reader.read_to_string(&mut read).unwrap();
+            assert_eq!(&read, expected);
+            assert_eq!(line_reader.fill_buf().unwrap(), b"");
+        }
+
+        // Splitting the line incrementally like a CSV parser would
+        let fields: Vec<_> = reader
+            .line_reader(1)
+            .unwrap()
+            .split(b',')
+            .map(|i| i.unwrap().len())
+            .collect();
+        assert_eq!(fields.len(), 5000);
+        assert!(fields.iter().all(|len| *len == 4));
+
+        // Dropping the reader within the line doesn't affect later reads
+        let mut prefix = [0; 10];
+        reader
+            .line_reader(1)
+            .unwrap()
+            .read_exact(&mut prefix)
+            .unwrap();
+        assert_eq!(&prefix, &lines[1].as_bytes()[..10]);
+        assert_eq!(reader.read_line(2).unwrap(), format!("{}\n", lines[2]));
+        reader
+            .line_reader(1)
+            .unwrap()
+            .read_exact(&mut prefix)
+            .unwrap();
+        assert_eq!(reader.read_line(0).unwrap(), format!("{}\n", lines[0]));
+        reader
+            .line_reader(3)
+            .unwrap()
+            .read_exact(&mut prefix[..1])
+            .unwrap();
+        assert_eq!(reader.read_line(3).unwrap(), lines[3]);
+
+        assert!(matches!(
+            reader.line_reader(lines.len()),
+            Err(Error::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_line_reader() {
+        let long = vec!["abcd"; 5000].join(",");
+        let lines = ["first".to_string(), long, String::new(), "last".to_string()];
+        let data = lines.join("\n");
+
+        let mut reader = IndexedString::new_raw(data.clone()).unwrap();
+        check_line_reader(&mut reader, &lines);
+
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let mut reader = crate::OpenBuilder::new()
+            .open_reader(Cursor::new(indexed.clone()))
+            .unwrap();
+        check_line_reader(&mut reader, &lines);
+        let mut reader = crate::IndexedSlice::new(&indexed).unwrap();
+        check_line_reader(&mut reader, &lines);
+
+        // The terminator of the last line is not part of the line either
+        let mut reader = IndexedString::new_raw(data + "\n").unwrap();
+        let mut last = String::new();
+        reader
+            .line_reader(3)
+            .unwrap()
+            .read_to_string(&mut last)
+            .unwrap();
+        assert_eq!(last, "last");
+
+        // Data ending before the last line fails instead of underflowing
+        let index = Arc::new(Index::new(vec![0, 2, 5]).zero_len());
+        let mut truncated = IndexedString::new_custom("a\nbc", index);
+        assert!(matches!(
+            truncated.line_reader(2),
+            Err(Error::IncompleteLine {
+                line: 2,
+                have: 0,
+                want: 0
+            })
+        ));
+    }
+
+    fn runs(data: &str) -> Vec<(usize, usize, String)> {
+        let mut reader = IndexedString::new_raw(data).unwrap();
+        reader
+            .duplicate_runs()
+            .map(|i| {
+                let (start, len, line) = i.unwrap();
+                (start, len, String::from_utf8(line).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_runs() {
+        let run = |start, len, line: &str| (start, len, line.to_string());
+
+        assert_eq!(
+            runs("a\na\nb\nc\nc\nc\nd\ne\ne"),
+            [run(0, 2, "a"), run(3, 3, "c"), run(7, 2, "e")]
+        );
+        assert_eq!(runs("a\nb\nb\nc"), [run(1, 2, "b")]);
+        assert_eq!(runs("x\nx\nx\nx\n"), [run(0, 4, "x")]);
+        assert_eq!(runs("\n\n\na"), [run(0, 3, "")]);
+        // The unterminated last line equals a terminated one
+        assert_eq!(runs("a\nb\nb"), [run(1, 2, "b")]);
+        assert!(runs("a\nb\nc\n").is_empty());
+        assert!(runs("a").is_empty());
+        assert!(runs("").is_empty());
+
+        // Comparing the first column only
+        let mut reader = IndexedString::new_raw("1,a\n1,b\n2,c\n3,d\n3,d\n3,e").unwrap();
+        let by_key: Vec<_> = reader
+            .duplicate_runs_by_key(|line| line.split(|b| *b == b',').next().unwrap())
+            .map(|i| i.unwrap())
+            .collect();
+        assert_eq!(by_key, [(0, 2, b"1,a".to_vec()), (3, 3, b"3,d".to_vec())]);
+        assert_eq!(reader.duplicate_runs().count(), 1);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_deserialize_lines() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Entry {
+            id: u32,
+            name: String,
+        }
+
+        let data = "{\"id\":1,\"name\":\"a\"}\n{\"id\":2}\n{\"id\":3,\"name\":\"c\"}";
+        let mut reader = IndexedString::new_raw(data).unwrap();
+
+        let entry: Entry = reader.read_line_as(2).unwrap();
+        assert_eq!(entry.name, "c");
+        assert!(matches!(
+            reader.read_line_as::<Entry>(1),
+            Err(Error::Deserialize { line: 1, .. })
+        ));
+        let value: serde_json::Value = reader.read_line_as(1).unwrap();
+        assert_eq!(value["id"], 2);
+        assert!(matches!(
+            reader.read_line_as::<Entry>(3),
+            Err(Error::OutOfBounds { .. })
+        ));
+
+        // Lines failing to deserialize don't stop the iteration
+        let entries: Vec<_> = reader.deserialize_lines::<Entry>().collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0].as_ref().unwrap(),
+            &Entry {
+                id: 1,
+                name: "a".to_string()
+            }
+        );
+        assert!(matches!(
+            entries[1],
+            Err(Error::Deserialize { line: 1, .. })
+        ));
+        assert_eq!(entries[2].as_ref().unwrap().id, 3);
+    }
+}
@@ -1,25 +1,89 @@
 //!A simple library to index and read large files by its lines using a pregenerated index
 
+/// Entries stored in memory
 pub mod mem_file;
 
 /// Generic implementation to use various types as reader
 pub mod any;
+/// Bloom filters for fast negative lookups
+pub mod bloom;
 /// Basic implementation for std::io::BufReader
 pub mod bufreader;
+/// Builders to create and open indexed files using custom options
+pub mod builder;
+/// Comparators for sorting and searching lines
+pub mod collate;
+/// Reading indexed lines as CSV records
+#[cfg(feature = "csv")]
+pub mod csv;
+/// Line-level diffs of indexed data
+pub mod diff;
+/// Writing modified copies of indexed data
+pub mod edit;
 pub mod error;
 /// A wrapper around std::fs::File which implements ReadByLine
 pub mod file;
 /// The index of files
 pub mod index;
+/// Iterators over lines
+pub mod iter;
+/// Hash tables for finding lines of unsorted data
+pub mod lookup;
+/// Merging of sorted indexed data
+pub mod merge;
+/// Conversion between layouts of indexed files
+pub mod migrate;
+/// Instrumentation of reads and seeks
+pub mod observer;
+/// Pooled buffers for reading lines
+pub mod pool;
+/// Seeking by lines
+pub mod seek;
+/// An indexed reader over borrowed data
+pub mod slice;
+/// Detection of binary data
+pub mod sniff;
+/// External sorting of files
+pub mod sort;
 /// An indexed string reader
 pub mod string;
-
+mod temp;
+/// Fixtures and assertions for tests of code using this crate
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+/// Verification of indexed files. The content hash in the header is a 64 bit FNV-1a hash, which
+/// is part of the file format and therefore not selectable by a feature. It detects accidental
+/// corruption, not deliberate modification.
+pub mod verify;
+/// Readers restricted to a region of the underlying data
+pub mod window;
+/// Streaming writers of indexed data
+pub mod writer;
+
+pub use builder::{Builder, OpenBuilder};
 pub use file::File;
+#[cfg(feature = "json")]
+pub use iter::DeserializeLines;
+pub use iter::{
+    ChunkIter, DuplicateRuns, LineBufRead, LineReader, LineReceiver, OffsetBase, OffsetLines,
+    RawChunkIter, RawOffsetLines, RevLines,
+};
+pub use mem_file::MemFile;
+pub use pool::PooledLine;
+pub use seek::{LineSeek, LineSeekFrom};
+pub use slice::{IndexedSlice, LineSlice};
 pub use string::IndexedString;
+pub use writer::IndexedWriter;
 
-use std::{cmp::Ordering, io::Write};
+use std::{
+    cmp::Ordering,
+    io::Write,
+    ops::{ControlFlow, Range},
+};
 
+use collate::Collation;
 use index::Index;
+use pool::BufPool;
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub trait Indexable {
@@ -32,10 +96,64 @@ pub trait Indexable {
         self.get_index().len()
     }
 
+    /// Returns the length of the header and the index in bytes. Use
+    /// `IndexableFile::data_start` to get the position of the data section.
     #[inline]
     fn get_index_byte_len(&self) -> usize {
         self.get_index().len_bytes()
     }
+
+    /// Returns `true` if both readers share the same index, for example because one is a clone of
+    /// the other or was created using `CloneableIndexedReader::index_arc`. Readers with equal but
+    /// separately loaded indexes are not considered equal.
+    #[inline]
+    fn index_ptr_eq(&self, other: &impl Indexable) -> bool
+    where
+        Self: Sized,
+    {
+        std::ptr::eq(self.get_index(), other.get_index())
+    }
+
+    /// Returns `false` if the bloom filter of the index tells that no line has the key `key`.
+    /// Returns `true` if a line probably has the key or if the index has no bloom filter.
+    #[inline]
+    fn maybe_contains(&self, key: &[u8]) -> bool {
+        self.get_index()
+            .bloom()
+            .is_none_or(|bloom| bloom.maybe_contains(key))
+    }
+
+    /// Returns the index of the `n`th line counted from the end, where `0` is the last line.
+    /// Returns `Error::OutOfBounds` if `n >= total_lines()`.
+    #[inline]
+    fn line_index_from_end(&self, n: usize) -> Result<usize> {
+        let lines = self.total_lines();
+        lines
+            .checked_sub(n + 1)
+            .ok_or(error::Error::OutOfBounds { line: n, lines })
+    }
+
+    /// Splits all lines into `parts` contiguous ranges, which can be processed in parallel. The
+    /// lengths of the ranges differ by at most one line. If there are less lines than `parts`,
+    /// the trailing ranges are empty.
+    fn partition_lines(&self, parts: usize) -> Vec<Range<usize>> {
+        if parts == 0 {
+            return Vec::new();
+        }
+
+        let lines = self.total_lines();
+        let (size, remainder) = (lines / parts, lines % parts);
+
+        let mut start = 0;
+        (0..parts)
+            .map(|part| {
+                let end = start + size + (part < remainder) as usize;
+                let range = start..end;
+                start = end;
+                range
+            })
+            .collect()
+    }
 }
 
 pub trait IndexableFile: Indexable {
@@ -45,15 +163,171 @@ pub trait IndexableFile: Indexable {
     /// Should seek the file to the given line `line`
     fn seek_line(&mut self, line: usize) -> Result<()>;
 
+    /// Should return the line the reader was last positioned at using `seek_line` or `None` if the
+    /// position is unknown. The default doesn't track the position and returns `None`.
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        None
+    }
+
+    /// Should return `true` if binary searches have to check that the lines are sorted before
+    /// searching, see `IndexedReader::set_check_sorted`. The default returns `false`.
+    #[inline]
+    fn checks_sorted(&self) -> bool {
+        false
+    }
+
+    /// Should return the length of the data section in bytes, not counting the index.
+    fn data_len(&self) -> u64;
+
+    /// Returns the absolute position of the data section within the underlying data, which is
+    /// the length of the header and the index, or 0 if the index is `zero_len`.
+    #[inline]
+    fn data_start(&self) -> u64 {
+        self.get_index_byte_len() as u64
+    }
+
+    /// Returns a hash of the offsets of all lines, the delimiter and the length of the data,
+    /// without reading any data. Readers with different fingerprints have different content, but
+    /// equal fingerprints only mean that the lines are laid out the same way. Use `File::verify`
+    /// to check the data against the hash stored in the header, which is required to be sure the
+    /// content is identical.
+    fn fingerprint(&self) -> u64 {
+        let index = self.get_index();
+        let mut hasher = Fnv1a::new();
+        hasher.write(&self.data_len().to_le_bytes());
+        hasher.write(&[index.delimiter(), u8::from(index.has_bom())]);
+        for offset in index.offsets() {
+            hasher.write(&offset.to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// Should return the absolute position of the reader within the underlying data, including
+    /// the header and the index. After reading a line, this is the start of the next line.
+    fn absolute_position(&mut self) -> Result<u64>;
+
+    /// Should read at most `max` bytes of `line` into `buf`, starting at the beginning of the line.
+    /// The rest of the line is not read. Returns the amount of bytes read. The default reads the
+    /// whole line using `read_current_line`.
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        self.seek_line(line)?;
+        let len = self.read_current_line(buf, line)?.min(max);
+        buf.truncate(len);
+        Ok(len)
+    }
+
+    /// Should read all lines within `lines` into `buf` using a single seek and a single read. The
+    /// read bytes include the terminators of every line. Returns the amount of bytes read. The
+    /// default reads every line on its own using `read_current_line`.
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        buf.clear();
+        let mut line_buf = Vec::new();
+        for line in lines {
+            self.seek_line(line)?;
+            let read = self.read_current_line(&mut line_buf, line)?;
+            buf.extend_from_slice(&line_buf[..read]);
+        }
+        Ok(buf.len())
+    }
+
+    /// Should read bytes of `line` into `buf`, starting `offset` bytes after the beginning of the
+    /// line. At most the rest of the line, including its terminator, is read. Returns the amount
+    /// of bytes read, which is 0 once `offset` reaches the end of the line. The default reads the
+    /// whole line using `read_current_line`.
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.seek_line(line)?;
+        let mut line_buf = Vec::new();
+        let read = self.read_current_line(&mut line_buf, line)?;
+        let start = offset.min(read as u64) as usize;
+        let len = (read - start).min(buf.len());
+        buf[..len].copy_from_slice(&line_buf[start..start + len]);
+        Ok(len)
+    }
+
+    /// Returns a reader over the content of `line` without its terminator, which is read
+    /// incrementally instead of into a single buffer. See `LineReader`.
+    #[inline]
+    fn line_reader(&mut self, line: usize) -> Result<LineReader<'_, Self>>
+    where
+        Self: Sized,
+    {
+        LineReader::new(self, line)
+    }
+
     /// Write the index, followed by the files contents into `writer`. A file generated using this
     /// function will always be parsable by `File::open`.
     fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize>;
 
+    /// Writes all lines for which `keep` returns `true` as new indexed data into `writer`. `keep`
+    /// gets called with the line-index and the content of every line without its terminator.
+    /// Kept lines are written unchanged. See `edit::filter_to`.
+    #[inline]
+    fn filter_to<W, F>(&mut self, writer: &mut W, keep: F) -> Result<edit::FilterStats>
+    where
+        Self: Sized,
+        W: Write,
+        F: FnMut(usize, &[u8]) -> bool,
+    {
+        edit::filter_to(self, writer, keep)
+    }
+
     /// Should return the offset to seek to given the line-index
     #[inline(always)]
     fn get_offset(&self, line: usize) -> Result<u32> {
         self.get_index().get(line)
     }
+
+    /// Returns the start and end byte offset of `line` within the data section. The end is the
+    /// start of the next line or the length of the data for the last line.
+    #[inline]
+    fn line_span(&self, line: usize) -> Result<Range<u64>> {
+        let start = self.get_offset(line)? as u64;
+        let end = self
+            .get_index()
+            .get_opt(line + 1)
+            .map(|i| i as u64)
+            .unwrap_or_else(|| self.data_len());
+        Ok(start..end)
+    }
+
+    /// Returns the share of the data in front of `line` within `[0, 1]`, which is the progress
+    /// after processing all lines before `line`. Lines past the last line and data without
+    /// lines return `1.0`.
+    fn byte_progress(&self, line: usize) -> f64 {
+        let len = self.data_len();
+        match self.get_index().get_opt(line) {
+            Some(offset) if len > 0 => (offset as f64 / len as f64).min(1.0),
+            _ => 1.0,
+        }
+    }
+
+    /// Returns the line containing the byte at `frac` of the data, so `0.5` returns the line in
+    /// the middle of the data by bytes. `frac` is clamped to `[0, 1]`, where `1.0` returns the
+    /// last line. Returns `0` if there are no lines.
+    fn line_at_fraction(&self, frac: f64) -> usize {
+        let frac = if frac.is_nan() {
+            0.0
+        } else {
+            frac.clamp(0.0, 1.0)
+        };
+        let target = (self.data_len() as f64 * frac) as u64;
+
+        let offsets = self.get_index().offsets();
+        let following = offsets.partition_point(|offset| *offset as u64 <= target);
+        following
+            .saturating_sub(1)
+            .min(offsets.len().saturating_sub(1))
+    }
+
+    /// Seeks to the line returned by `line_at_fraction` and returns it. Returns
+    /// `Error::OutOfBounds` if there are no lines.
+    #[inline]
+    fn seek_fraction(&mut self, frac: f64) -> Result<usize> {
+        let line = self.line_at_fraction(frac);
+        self.seek_line(line)?;
+        Ok(line)
+    }
 }
 
 /// A trait defining behavior for reading certain lines directly from indexed files.
@@ -64,8 +338,7 @@ pub trait ReadByLine: IndexableFile {
         let next = self.get_index().get(line + 1);
 
         let mut len = 0;
-        if next.is_ok() {
-            let next = next.unwrap();
+        if let Ok(next) = next {
             len = next - curr;
         }
 
@@ -74,14 +347,406 @@ pub trait ReadByLine: IndexableFile {
         Ok(String::from_utf8(buf)?)
     }
 
+    /// Reads the given line into a buffer taken from a thread-local pool. The buffer is returned
+    /// into the pool once the `PooledLine` gets dropped, so repeated reads don't allocate.
+    fn read_line_pooled(&mut self, line: usize) -> Result<PooledLine> {
+        let mut buf = BufPool::get();
+        let read = self.read_line_raw(line, &mut buf)?;
+        buf.truncate(read);
+        Ok(PooledLine::new(String::from_utf8(std::mem::take(
+            &mut *buf,
+        ))?))
+    }
+
+    /// Reads at most `max` bytes of the given line. If `max` ends within a multi-byte character,
+    /// the incomplete character is omitted, so the returned string may be shorter than `max`.
+    fn read_line_prefix_str(&mut self, line: usize, max: usize) -> Result<String> {
+        let mut buf = Vec::with_capacity(max);
+        self.read_line_prefix(line, max, &mut buf)?;
+
+        match std::str::from_utf8(&buf) {
+            Ok(_) => {}
+            // Only the last character got cut off
+            Err(err) if err.error_len().is_none() => buf.truncate(err.valid_up_to()),
+            Err(_) => return Err(error::Error::UTF8Error),
+        }
+
+        Ok(String::from_utf8(buf)?)
+    }
+
+    /// Reads at most `max_chars` characters of the given line without its terminator, for
+    /// previews of lines which may be huge. Returns the characters and whether the line got
+    /// truncated. Only `4 * max_chars + 1` bytes are read at most, so the line is cut on a
+    /// character boundary without reading all of it.
+    ///
+    /// Returns `Error::UTF8Error` if the read bytes aren't valid UTF-8
+    fn read_line_truncated(&mut self, line: usize, max_chars: usize) -> Result<(String, bool)> {
+        // One more byte than the characters can take tells whether the line continues
+        let max = max_chars.saturating_mul(4).saturating_add(1);
+        let mut buf = Vec::new();
+        let read = self.read_line_prefix(line, max, &mut buf)?;
+        buf.truncate(read);
+        let content_len = trim_terminator(&buf, self.get_index().delimiter()).len();
+        buf.truncate(content_len);
+
+        let valid = match std::str::from_utf8(&buf) {
+            Ok(valid) => valid.len(),
+            // The limit can end within a character of a longer line
+            Err(err) if err.error_len().is_none() && read == max => err.valid_up_to(),
+            Err(_) => return Err(error::Error::UTF8Error),
+        };
+
+        let end = std::str::from_utf8(&buf[..valid])
+            .expect("validated above")
+            .char_indices()
+            .nth(max_chars)
+            .map_or(valid, |(end, _)| end);
+        let truncated = end < content_len;
+        buf.truncate(end);
+        Ok((String::from_utf8(buf)?, truncated))
+    }
+
+    /// Reads the given line and deserializes it from JSON. The raw bytes of the line are
+    /// deserialized, so they don't have to be converted into a `String` first.
+    ///
+    /// Returns `Error::Deserialize` if the line isn't valid JSON for `T`
+    #[cfg(feature = "json")]
+    fn read_line_as<T: serde::de::DeserializeOwned>(&mut self, line: usize) -> Result<T> {
+        let mut buf = BufPool::get();
+        let read = self.read_line_raw(line, &mut buf)?;
+        serde_json::from_slice(&buf[..read])
+            .map_err(|source| error::Error::Deserialize { line, source })
+    }
+
+    /// Returns an iterator deserializing every line from JSON, starting at the first line. See
+    /// `DeserializeLines`.
+    #[cfg(feature = "json")]
+    #[inline]
+    fn deserialize_lines<T: serde::de::DeserializeOwned>(&mut self) -> DeserializeLines<'_, Self, T>
+    where
+        Self: Sized,
+    {
+        DeserializeLines::new(self)
+    }
+
+    /// Reads the `n`th line counted from the end, where `0` is the last line
+    #[inline]
+    fn read_line_from_end(&mut self, n: usize) -> Result<String> {
+        let line = self.line_index_from_end(n)?;
+        self.read_line(line)
+    }
+
+    /// Returns a view of `lines` in which they are numbered from 0, see `LineSlice`
+    #[inline]
+    fn slice(&mut self, lines: Range<usize>) -> Result<LineSlice<'_, Self>>
+    where
+        Self: Sized,
+    {
+        LineSlice::new(self, lines)
+    }
+
+    /// Reads `n` lines evenly spaced over the whole file, for example to show a preview. The
+    /// first and the last line are always included if `n >= 2`, and all lines are returned if
+    /// `n >= total_lines()`. The lines are read in ascending order, so the reader only seeks
+    /// forward. Returns pairs of the line index and its content like `read_line`.
+    fn sample_evenly(&mut self, n: usize) -> Result<Vec<(usize, String)>> {
+        let lines = self.total_lines();
+        let n = n.min(lines);
+        (0..n)
+            .map(|i| {
+                // Lines are at least one apart since `n <= lines`. The product overflows `usize`
+                // on 32 bit targets, but not `u64` since an index holds at most `u32::MAX` lines.
+                let line = match n {
+                    1 => 0,
+                    _ => (i as u64 * (lines as u64 - 1) / (n as u64 - 1)) as usize,
+                };
+                Ok((line, self.read_line(line)?))
+            })
+            .collect()
+    }
+
+    /// Reads all `lines`, which can be unsorted and contain duplicates, and returns them in the
+    /// same order like `read_line`. The lines are read once each in ascending order, and
+    /// adjacent lines are read at once using `read_span`, so the reader only seeks forward.
+    ///
+    /// Returns `Error::MultiGet` holding the positions within `lines` of all lines which are out
+    /// of bounds, before reading any line.
+    fn read_many(&mut self, lines: &[usize]) -> Result<Vec<String>> {
+        let total = self.total_lines();
+        let failed: Vec<usize> = (0..lines.len()).filter(|i| lines[*i] >= total).collect();
+        if !failed.is_empty() {
+            return Err(error::Error::MultiGet { failed });
+        }
+
+        let mut unique = lines.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+
+        let mut read = Vec::with_capacity(unique.len());
+        let mut buf = Vec::new();
+        for run in unique.chunk_by(|a, b| a + 1 == *b) {
+            let (first, last) = (run[0], run[run.len() - 1]);
+            self.read_span(first..last + 1, &mut buf)?;
+            let base = self.get_offset(first)? as u64;
+            for line in first..=last {
+                let span = self.line_span(line)?;
+                // Only lines of an unsorted index start in front of the first line of the run
+                let start = span
+                    .start
+                    .checked_sub(base)
+                    .ok_or(error::Error::InvalidIndex { line })?;
+                let range = start as usize..span.end.saturating_sub(base) as usize;
+                let content = buf.get(range.clone()).ok_or(error::Error::IncompleteLine {
+                    line,
+                    have: buf.len().saturating_sub(range.start),
+                    want: range.end.saturating_sub(range.start),
+                })?;
+                read.push(String::from_utf8(content.to_vec())?);
+            }
+        }
+
+        // Duplicates share the same read line
+        Ok(lines
+            .iter()
+            .map(|line| read[unique.binary_search(line).unwrap()].clone())
+            .collect())
+    }
+
+    /// Returns an iterator over the runs of at least two adjacent equal lines. See
+    /// `DuplicateRuns`.
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    fn duplicate_runs(&mut self) -> DuplicateRuns<'_, Self, fn(&[u8]) -> &[u8]>
+    where
+        Self: Sized,
+    {
+        DuplicateRuns::new(self, |line| line)
+    }
+
+    /// Returns an iterator over the runs of at least two adjacent lines with equal keys, like
+    /// `duplicate_runs`. `key` extracts the part of a line, without terminator, which is compared.
+    #[inline]
+    fn duplicate_runs_by_key<K>(&mut self, key: K) -> DuplicateRuns<'_, Self, K>
+    where
+        Self: Sized,
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        DuplicateRuns::new(self, key)
+    }
+
+    /// Returns an iterator over all lines in reverse order, starting at the last line
+    #[inline]
+    fn lines_rev(&mut self) -> RevLines<'_, Self>
+    where
+        Self: Sized,
+    {
+        let last = self.total_lines().checked_sub(1);
+        RevLines::new(self, last)
+    }
+
+    /// Returns an iterator over the lines in reverse order, starting at `line` and ending with the
+    /// first line. The iterator returns `Error::OutOfBounds` if `line` doesn't exist.
+    #[inline]
+    fn rev_from(&mut self, line: usize) -> RevLines<'_, Self>
+    where
+        Self: Sized,
+    {
+        RevLines::new(self, Some(line))
+    }
+
+    /// Returns an iterator over all lines together with their line-index and byte offset. The
+    /// offsets are taken from the index, so they don't require additional reads. `base` defines
+    /// whether the offsets are relative to the data or to the beginning of the indexed file.
+    #[inline]
+    fn offset_lines(&mut self, base: OffsetBase) -> OffsetLines<'_, Self>
+    where
+        Self: Sized,
+    {
+        OffsetLines::new(self, base)
+    }
+
+    /// Like `offset_lines` but returns the lines as bytes, so they don't have to be valid UTF-8
+    #[inline]
+    fn offset_lines_raw(&mut self, base: OffsetBase) -> RawOffsetLines<'_, Self>
+    where
+        Self: Sized,
+    {
+        RawOffsetLines::new(self, base)
+    }
+
+    /// Returns an iterator over all lines in chunks of `size` lines. Only the last chunk may hold
+    /// fewer lines. Every chunk is read using a single `read_span`. See `ChunkIter`.
+    ///
+    /// Returns `Error::ZeroChunkSize` if `size` is 0
+    #[inline]
+    fn chunks(&mut self, size: usize) -> Result<ChunkIter<'_, Self>>
+    where
+        Self: Sized,
+    {
+        ChunkIter::new(self, size)
+    }
+
+    /// Like `chunks` but returns every chunk as a single buffer together with the ranges of its
+    /// lines, so no allocation per line is needed. See `RawChunkIter`.
+    #[inline]
+    fn chunks_raw(&mut self, size: usize) -> Result<RawChunkIter<'_, Self>>
+    where
+        Self: Sized,
+    {
+        RawChunkIter::new(self, size)
+    }
+
+    /// Returns a `BufRead` over the data starting at `start_line`. See `LineBufRead`.
+    #[inline]
+    fn as_bufread_from(&mut self, start_line: usize) -> LineBufRead<'_, Self>
+    where
+        Self: Sized,
+    {
+        LineBufRead::new(self, start_line)
+    }
+
     /// Reads the given line and stores into `buf`
     fn read_line_raw(&mut self, line: usize, buf: &mut Vec<u8>) -> Result<usize> {
         self.seek_line(line)?;
-        Ok(self.read_current_line(buf, line)?)
+        self.read_current_line(buf, line)
+    }
+
+    /// Calls `f` with the line-index and the content of every line, starting at the first line.
+    /// The content is passed without its terminator and borrowed from a single buffer which is
+    /// reused for all lines, so there is no allocation per line. Returning `ControlFlow::Break`
+    /// stops the iteration early.
+    #[inline]
+    fn for_each_line<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnMut(usize, &[u8]) -> ControlFlow<()>,
+    {
+        self.for_each_line_in(0..self.total_lines(), f)
+    }
+
+    /// Calls `f` for every line within `lines` like `for_each_line` does. Returns
+    /// `Error::OutOfBounds` if `lines` exceeds the last line.
+    fn for_each_line_in<F>(&mut self, lines: Range<usize>, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &[u8]) -> ControlFlow<()>,
+    {
+        let mut buf = Vec::new();
+        let delimiter = self.get_index().delimiter();
+
+        for line in lines {
+            let read = self.read_line_raw(line, &mut buf)?;
+            if f(line, trim_terminator(&buf[..read], delimiter)).is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts the lines for which `f` returns `true`. `f` gets called with the content of every
+    /// line without its terminator, which is borrowed like in `for_each_line`.
+    fn count_matching<F>(&mut self, mut f: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        let mut count = 0;
+        self.for_each_line(|_, line| {
+            count += f(line) as usize;
+            ControlFlow::Continue(())
+        })?;
+        Ok(count)
+    }
+
+    /// Folds all lines into a single value, starting at `init`. `f` gets called with the value,
+    /// the line-index and the content of every line without its terminator.
+    fn fold_lines<B, F>(&mut self, init: B, mut f: F) -> Result<B>
+    where
+        F: FnMut(B, usize, &[u8]) -> B,
+    {
+        let mut acc = Some(init);
+        self.for_each_line(|line, content| {
+            acc = acc.take().map(|acc| f(acc, line, content));
+            ControlFlow::Continue(())
+        })?;
+        Ok(acc.expect("the value is always put back"))
+    }
+
+    /// Counts the lines for which `f` returns `true` like `count_matching`, using one clone of
+    /// the reader per thread. Each clone reads one partition of the lines, see
+    /// `Indexable::partition_lines`. Use `File::par_count_matching` for files.
+    #[cfg(feature = "rayon")]
+    fn par_count_matching<F>(&self, f: F) -> Result<usize>
+    where
+        Self: Sized + Clone + Send,
+        F: Fn(&[u8]) -> bool + Sync,
+    {
+        let readers = self
+            .partition_lines(rayon::current_num_threads())
+            .into_iter()
+            .map(|lines| (lines, self.clone()))
+            .collect();
+        par_count(readers, f)
+    }
+
+    /// Returns `true` if all lines are sorted in ascending order, which is the precondition of the
+    /// binary search functions. Lines are compared without their terminators.
+    ///
+    /// This scans the whole file.
+    #[inline]
+    fn is_sorted(&mut self) -> Result<bool> {
+        self.is_sorted_by(|a, b| a.cmp(b))
+    }
+
+    /// Returns `true` if all lines are sorted in ascending order according to `f`. Lines are
+    /// compared without their terminators.
+    ///
+    /// This scans the whole file.
+    #[inline]
+    fn is_sorted_by<F>(&mut self, f: F) -> Result<bool>
+    where
+        F: FnMut(&[u8], &[u8]) -> Ordering,
+    {
+        Ok(self.first_unsorted_by(f)?.is_none())
+    }
+
+    /// Returns the first line which is smaller than its predecessor or `None` if the file is
+    /// sorted.
+    ///
+    /// This scans the whole file.
+    #[inline]
+    fn first_unsorted_at(&mut self) -> Result<Option<usize>> {
+        self.first_unsorted_by(|a, b| a.cmp(b))
+    }
+
+    /// Returns the first line which is smaller than its predecessor according to `f` or `None` if
+    /// the file is sorted.
+    ///
+    /// This scans the whole file, stopping at the first unsorted line.
+    fn first_unsorted_by<F>(&mut self, mut f: F) -> Result<Option<usize>>
+    where
+        F: FnMut(&[u8], &[u8]) -> Ordering,
+    {
+        let mut prev = Vec::new();
+        let mut unsorted = None;
+
+        self.for_each_line(|line, content| {
+            if line > 0 && f(&prev, content) == Ordering::Greater {
+                unsorted = Some(line);
+                return ControlFlow::Break(());
+            }
+
+            prev.clear();
+            prev.extend_from_slice(content);
+            ControlFlow::Continue(())
+        })?;
+
+        Ok(unsorted)
     }
 
     /// Do a binary search on `ReadByLine` implementing Types, since it provides everything required
-    /// for binary search. Only works with sorted files
+    /// for binary search. Only works with sorted files.
+    ///
+    /// Returns `Error::NotFound` if no line matches. Probes never leave the bounds of the file, so
+    /// `Error::OutOfBounds` is never returned.
     #[inline]
     fn binary_search(&mut self, x: &str) -> Result<usize> {
         self.binary_search_by(|p| p.cmp(x))
@@ -93,14 +758,26 @@ pub trait ReadByLine: IndexableFile {
     where
         F: FnMut(&str) -> std::cmp::Ordering,
     {
+        if self.checks_sorted() {
+            check_sorted_by(self, |line| {
+                std::str::from_utf8(line)
+                    .map(&mut f)
+                    .map_err(|_| error::Error::UTF8Error)
+            })?;
+        }
+
         let mut size = self.total_lines();
         let mut left = 0;
         let mut right = size;
 
+        let mut buf = BufPool::get();
+
         while left < right {
             let mid = left + size / 2;
 
-            let cmp = f(&self.read_line(mid)?);
+            let read = self.read_line_raw(mid, &mut buf)?;
+            let line = std::str::from_utf8(&buf[..read]).map_err(|_| error::Error::UTF8Error)?;
+            let cmp = f(line);
 
             if cmp == Ordering::Less {
                 left = mid + 1;
@@ -123,40 +800,374 @@ pub trait ReadByLine: IndexableFile {
         self.binary_search_raw_by(|p| p.cmp(x))
     }
 
+    /// Do a binary search for the line equal to `key`. Unlike `binary_search_raw`, lines are
+    /// compared without their terminators. Only works with sorted files.
+    ///
+    /// Each probe only reads the first `key.len() + 1` bytes of a line, which is enough to
+    /// compare it with `key`, so long lines are never read entirely.
+    fn binary_search_prefix(&mut self, key: &[u8]) -> Result<usize> {
+        let delimiter = self.get_index().delimiter();
+        if self.checks_sorted() {
+            check_sorted_by(self, |line| Ok(trim_terminator(line, delimiter).cmp(key)))?;
+        }
+        let mut size = self.total_lines();
+        let mut left = 0;
+        let mut right = size;
+
+        let mut buf = Vec::with_capacity(key.len() + 1);
+
+        while left < right {
+            let mid = left + size / 2;
+
+            let read = self.read_line_prefix(mid, key.len() + 1, &mut buf)?;
+            // A prefix longer than `key` only ends with the terminator if the line equals `key`
+            let cmp = trim_terminator(&buf[..read], delimiter).cmp(key);
+
+            if cmp == Ordering::Less {
+                left = mid + 1;
+            } else if cmp == Ordering::Greater {
+                right = mid;
+            } else {
+                return Ok(mid);
+            }
+
+            size = right - left;
+        }
+
+        Err(error::Error::NotFound)
+    }
+
+    /// Do a binary search for the line equal to `key` like `binary_search_prefix`, returning
+    /// `Error::NotFound` without reading any line if the bloom filter tells that `key` is
+    /// missing. Only filters of whole lines are used, see `IndexedReader::build_bloom`, so
+    /// filters of keys are skipped.
+    #[inline]
+    fn binary_search_with_bloom(&mut self, key: &[u8]) -> Result<usize> {
+        let bloom = self.get_index().bloom();
+        if bloom.is_some_and(|bloom| !bloom.is_keyed() && !bloom.maybe_contains(key)) {
+            return Err(error::Error::NotFound);
+        }
+        self.binary_search_prefix(key)
+    }
+
+    /// Returns the first line equal to `key`, compared without its terminator, or `None` if no
+    /// line matches. Works with unsorted data, see `find_exact_by_key`.
+    #[inline]
+    fn find_exact(&mut self, key: &[u8]) -> Result<Option<usize>> {
+        find_exact_with(self, key, |line| line, false)
+    }
+
+    /// Returns the first line whose key returned by `key` is equal to `key_value`, or `None` if
+    /// no line matches. `key` gets called with the lines without their terminator.
+    ///
+    /// If the index has a `LookupTable` of keys, only the lines having the same hash are read,
+    /// so the table has to be built using the same key, see `IndexedReader::build_lookup_table`.
+    /// Otherwise all lines are read until a matching one is found.
+    #[inline]
+    fn find_exact_by_key<K>(&mut self, key_value: &[u8], key: K) -> Result<Option<usize>>
+    where
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        find_exact_with(self, key_value, key, true)
+    }
+
+    /// Do a binary search for the line equal to `key` according to `collation`. Lines are compared
+    /// without their terminators. Only works with files sorted using the same collation.
+    ///
+    /// Returns `Error::CollationMismatch` if the file records a different collation in its
+    /// header.
+    fn binary_search_collated(&mut self, key: &[u8], collation: Collation) -> Result<usize> {
+        collation.check(self.get_index())?;
+        let delimiter = self.get_index().delimiter();
+        self.binary_search_raw_by(|line| collation.compare(trim_terminator(line, delimiter), key))
+    }
+
+    /// Returns the index of the first line for which `pred` returns `false`, assuming the lines
+    /// are partitioned so `pred` returns `true` for all lines before it, like
+    /// `slice::partition_point`. Returns the amount of lines if `pred` is `true` for all lines.
+    /// `pred` gets called with the lines without their terminators.
+    ///
+    /// This expresses lower and upper bounds of sorted files: `|line| line < key` returns the
+    /// first line not smaller than `key` and `|line| line <= key` the first line greater than it.
+    ///
+    /// # Example
+    ///
+    /// Finding the first line of a chronologically sorted log at or after a given time. RFC 3339
+    /// timestamps using the same format and time zone sort like their bytes, so it's enough to
+    /// compare the prefix of each line:
+    ///
+    /// ```
+    /// use indexed_file::{IndexedString, ReadByLine};
+    ///
+    /// let log = "2021-06-01T08:00:00Z start\n\
+    ///            2021-06-01T09:30:00Z request\n\
+    ///            2021-06-02T10:00:00Z stop\n";
+    /// let mut reader = IndexedString::new_raw(log).unwrap();
+    ///
+    /// let since = b"2021-06-01T09:00:00Z";
+    /// let first = reader
+    ///     .partition_point(|line| &line[..since.len().min(line.len())] < since)
+    ///     .unwrap();
+    /// assert_eq!(first, 1);
+    /// ```
+    fn partition_point<F>(&mut self, mut pred: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        let delimiter = self.get_index().delimiter();
+        if self.checks_sorted() {
+            // Lines for which `pred` is `true` have to come first
+            check_sorted_by(self, |line| {
+                Ok(match pred(trim_terminator(line, delimiter)) {
+                    true => Ordering::Less,
+                    false => Ordering::Greater,
+                })
+            })?;
+        }
+
+        let mut left = 0;
+        let mut right = self.total_lines();
+
+        let mut buf = Vec::new();
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+
+            let read = self.read_line_raw(mid, &mut buf)?;
+            if pred(trim_terminator(&buf[..read], delimiter)) {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+
+        Ok(left)
+    }
+
     /// Do a binary search by on `ReadByLine` implementing Types, since it provides everything required
     /// for binary search. Only works with sorted files
-    fn binary_search_raw_by<F>(&mut self, mut f: F) -> Result<usize>
+    #[inline]
+    fn binary_search_raw_by<F>(&mut self, f: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]) -> std::cmp::Ordering,
+    {
+        self.binary_search_raw_fetch(f, &mut BufPool::get())
+    }
+
+    /// Like `binary_search_raw_by` but leaves the content of the matching line without its
+    /// terminator in `out`, so it doesn't have to be read again. `out` is empty if no line
+    /// matches.
+    fn binary_search_raw_fetch<F>(&mut self, mut f: F, out: &mut Vec<u8>) -> Result<usize>
     where
         F: FnMut(&[u8]) -> std::cmp::Ordering,
     {
+        if self.checks_sorted() {
+            check_sorted_by(self, |line| Ok(f(line)))?;
+        }
+
         let mut size = self.total_lines();
         let mut left = 0;
         let mut right = size;
 
-        let mut buf = Vec::new();
-
         while left < right {
             let mid = left + size / 2;
 
-            buf.clear();
-            self.read_line_raw(mid, &mut buf)?;
-            let cmp = f(&buf);
+            out.clear();
+            self.read_line_raw(mid, out)?;
+            let cmp = f(out);
 
             if cmp == Ordering::Less {
                 left = mid + 1;
             } else if cmp == Ordering::Greater {
                 right = mid;
             } else {
+                let len = trim_terminator(out, self.get_index().delimiter()).len();
+                out.truncate(len);
                 return Ok(mid);
             }
 
             size = right - left;
         }
 
+        out.clear();
         Err(error::Error::NotFound)
     }
 }
 
+/// Returns `Error::Unsorted` unless the lines are ordered as a binary search using the comparator
+/// `f` expects, which is that `f` returns `Less`, `Equal` and `Greater` in this order. `f` gets
+/// called with the lines including their terminators. See `IndexedReader::set_check_sorted`.
+fn check_sorted_by<R, F>(reader: &mut R, mut f: F) -> Result<()>
+where
+    R: ReadByLine + ?Sized,
+    F: FnMut(&[u8]) -> Result<Ordering>,
+{
+    let mut buf = BufPool::get();
+    let mut prev = Ordering::Less;
+    for line in 0..reader.total_lines() {
+        let read = reader.read_line_raw(line, &mut buf)?;
+        let cmp = f(&buf[..read])?;
+        if cmp < prev {
+            return Err(error::Error::Unsorted { line });
+        }
+        prev = cmp;
+    }
+    Ok(())
+}
+
+/// Returns the first line whose key is equal to `key_value`, see `ReadByLine::find_exact_by_key`.
+/// The `LookupTable` of the index is only used if `keyed` matches the kind of the table.
+fn find_exact_with<R, K>(
+    reader: &mut R,
+    key_value: &[u8],
+    mut key: K,
+    keyed: bool,
+) -> Result<Option<usize>>
+where
+    R: ReadByLine + ?Sized,
+    K: FnMut(&[u8]) -> &[u8],
+{
+    let table = reader.get_index().lookup_table();
+    let candidates: Vec<usize> = match table.filter(|table| table.is_keyed() == keyed) {
+        Some(table) => table.candidates(key_value).collect(),
+        None => {
+            let mut found = None;
+            reader.for_each_line(|line, content| {
+                if key(content) != key_value {
+                    return ControlFlow::Continue(());
+                }
+                found = Some(line);
+                ControlFlow::Break(())
+            })?;
+            return Ok(found);
+        }
+    };
+
+    // Candidates only share the hash, so each one gets verified
+    let mut buf = Vec::new();
+    let delimiter = reader.get_index().delimiter();
+    for line in candidates {
+        let read = reader.read_line_raw(line, &mut buf)?;
+        if key(trim_terminator(&buf[..read], delimiter)) == key_value {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns `line` without its trailing `delimiter`
+#[inline]
+pub(crate) fn trim_terminator(line: &[u8], delimiter: u8) -> &[u8] {
+    line.strip_suffix(&[delimiter]).unwrap_or(line)
+}
+
+/// Counts the lines for which `f` returns `true`, reading each partition of the lines using its
+/// own reader on the rayon thread pool
+#[cfg(feature = "rayon")]
+pub(crate) fn par_count<R, F>(readers: Vec<(Range<usize>, R)>, f: F) -> Result<usize>
+where
+    R: ReadByLine + Send,
+    F: Fn(&[u8]) -> bool + Sync,
+{
+    use rayon::prelude::*;
+
+    readers
+        .into_par_iter()
+        .map(|(lines, mut reader)| {
+            let mut count = 0;
+            reader.for_each_line_in(lines, |_, line| {
+                count += f(line) as usize;
+                ControlFlow::Continue(())
+            })?;
+            Ok(count)
+        })
+        .try_reduce(|| 0, |a, b| Ok(a + b))
+}
+
+/// Hashes `data` using 64 bit FNV-1a. This is not a cryptographic hash but stable across
+/// platforms and versions, so it can be stored in files.
+#[inline]
+pub(crate) fn hash_bytes(data: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Hashes everything `reader` yields until its end, like `hash_bytes`
+pub(crate) fn hash_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut hasher = Fnv1a::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finish()),
+            Ok(read) => hasher.write(&buf[..read]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A writer hashing everything written into the inner writer like `hash_bytes`, so data can be
+/// hashed while copying it
+#[derive(Debug)]
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Fnv1a,
+}
+
+impl<W: Write> HashingWriter<W> {
+    #[inline]
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Fnv1a::new(),
+        }
+    }
+
+    /// Returns the hash of all bytes written so far
+    #[inline]
+    pub(crate) fn hash(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        Ok(written)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Incremental 64 bit FNV-1a hasher, see `hash_bytes`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    #[inline]
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        self.0 = data.iter().fold(self.0, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+    }
+
+    #[inline]
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::{distributions::Uniform, Rng};
@@ -167,7 +1178,7 @@ mod tests {
     use std::{
         convert::TryInto,
         fs::read_to_string,
-        io::{prelude::*, BufReader},
+        io::{prelude::*, BufReader, Cursor},
         sync::Arc,
     };
 
@@ -212,12 +1223,235 @@ mod tests {
     }
 
     fn test_reader<L: ReadByLine>(reader: &mut L, original_file: &str) {
-        test_sequencially(reader, &original_file);
-        test_random(reader, &original_file);
+        test_sequencially(reader, original_file);
+        test_random(reader, original_file);
+        test_span(reader, original_file);
+        test_for_each_line(reader, original_file);
+        test_prefix(reader, original_file);
+        test_rev(reader, original_file);
+        test_position(reader);
+    }
+
+    fn test_position<L: ReadByLine>(reader: &mut L) {
+        let start = reader.data_start();
+        assert_eq!(start, reader.get_index_byte_len() as u64);
+
+        for line in (0..reader.total_lines()).rev() {
+            let span = reader.line_span(line).unwrap();
+            reader.seek_line(line).unwrap();
+            assert_eq!(reader.absolute_position().unwrap(), start + span.start);
+            reader.read_line(line).unwrap();
+            assert_eq!(reader.absolute_position().unwrap(), start + span.end);
+        }
+    }
+
+    fn test_rev<L: ReadByLine>(reader: &mut L, original_file: &str) {
+        let original = read_to_string(original_file).unwrap();
+        let mut expected: Vec<_> = original.split_inclusive('\n').collect();
+        expected.reverse();
+
+        let lines: Vec<_> = reader.lines_rev().map(|i| i.unwrap()).collect();
+        assert_eq!(lines, expected);
+    }
+
+    fn test_prefix<L: ReadByLine>(reader: &mut L, original_file: &str) {
+        let original = std::fs::read(original_file).unwrap();
+        let mut buf = Vec::new();
+
+        for line in 0..reader.total_lines() {
+            let span = reader.line_span(line).unwrap();
+            let content = &original[span.start as usize..span.end as usize];
+
+            for max in [0, 3, usize::MAX] {
+                let read = reader.read_line_prefix(line, max, &mut buf).unwrap();
+                assert_eq!(read, content.len().min(max));
+                assert_eq!(&buf[..read], &content[..read]);
+            }
+
+            // Reading the next line after a prefix must not continue within the current line
+            if line + 1 < reader.total_lines() {
+                reader.read_line_prefix(line, 1, &mut buf).unwrap();
+                let next = reader.line_span(line + 1).unwrap();
+                let read = reader.read_line_raw(line + 1, &mut buf).unwrap();
+                assert_eq!(
+                    &buf[..read],
+                    &original[next.start as usize..next.end as usize]
+                );
+            }
+        }
+    }
+
+    fn test_for_each_line<L: ReadByLine>(reader: &mut L, original_file: &str) {
+        let original = read_to_string(original_file).unwrap();
+        let expected: Vec<_> = original.lines().collect();
+
+        let mut lines = Vec::new();
+        reader
+            .for_each_line(|line, content| {
+                assert_eq!(line, lines.len());
+                lines.push(String::from_utf8(content.to_vec()).unwrap());
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(lines, expected);
+
+        let mut visited = 0;
+        reader
+            .for_each_line(|line, _| {
+                visited += 1;
+                if line == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(visited, 3.min(expected.len()));
+
+        let mut in_range = Vec::new();
+        let end = expected.len().min(5);
+        reader
+            .for_each_line_in(1.min(end)..end, |line, content| {
+                in_range.push((line, content.to_vec()));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(in_range.len(), end.saturating_sub(1));
+        for (line, content) in in_range {
+            assert_eq!(content, expected[line].as_bytes());
+        }
+        assert!(reader
+            .for_each_line_in(0..expected.len() + 1, |_, _| ControlFlow::Continue(()))
+            .is_err());
+
+        let matching = expected.iter().filter(|i| i.starts_with(' ')).count();
+        let counted = reader
+            .count_matching(|line| line.starts_with(b" "))
+            .unwrap();
+        assert_eq!(counted, matching);
+
+        let len = expected.iter().map(|i| i.len()).sum::<usize>();
+        let folded = reader
+            .fold_lines(0, |acc, _, line| acc + line.len())
+            .unwrap();
+        assert_eq!(folded, len);
+        let last = reader.fold_lines(None, |_, line, _| Some(line)).unwrap();
+        assert_eq!(last, expected.len().checked_sub(1));
+    }
+
+    #[test]
+    fn test_byte_progress() {
+        // Lines start at 0, 10, 12, 20 and the data is 40 bytes long
+        let data = format!(
+            "{}\nb\n{}\n{}",
+            "a".repeat(9),
+            "c".repeat(7),
+            "d".repeat(20)
+        );
+        let mut reader = IndexedString::new_raw(data).unwrap();
+
+        assert_eq!(reader.byte_progress(0), 0.0);
+        assert_eq!(reader.byte_progress(1), 0.25);
+        assert_eq!(reader.byte_progress(3), 0.5);
+        assert_eq!(reader.byte_progress(4), 1.0);
+        assert_eq!(reader.byte_progress(100), 1.0);
+
+        let fractions = [
+            (-1.0, 0),
+            (0.0, 0),
+            (0.2, 0),
+            (0.25, 1),
+            (0.29, 1),
+            (0.3, 2),
+            (0.5, 3),
+            (0.99, 3),
+            (1.0, 3),
+            (7.0, 3),
+            (f64::NAN, 0),
+        ];
+        for (frac, line) in fractions {
+            assert_eq!(reader.line_at_fraction(frac), line, "{}", frac);
+        }
+
+        assert_eq!(reader.seek_fraction(0.6).unwrap(), 3);
+        let mut buf = Vec::new();
+        reader.read_current_line(&mut buf, 3).unwrap();
+        assert_eq!(buf, "d".repeat(20).as_bytes());
+
+        // Readers with an index in front of the data
+        let mut indexed = Vec::new();
+        reader.write_to(&mut indexed).unwrap();
+        let reader = IndexedSlice::new(&indexed).unwrap();
+        assert_eq!(reader.byte_progress(3), 0.5);
+        assert_eq!(reader.line_at_fraction(0.5), 3);
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert_eq!(empty.byte_progress(0), 1.0);
+        assert_eq!(empty.line_at_fraction(0.5), 0);
+        assert!(matches!(
+            empty.seek_fraction(0.5),
+            Err(error::Error::OutOfBounds { line: 0, lines: 0 })
+        ));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_count_matching() {
+        let file = "./testfiles/LICENSE";
+        let expected = read_to_string(file)
+            .unwrap()
+            .lines()
+            .filter(|i| i.contains("the"))
+            .count();
+        let contains = |line: &[u8]| line.windows(3).any(|i| i == b"the");
+
+        let reader = IndexedString::new_raw(read_to_string(file).unwrap()).unwrap();
+        assert_eq!(reader.par_count_matching(contains).unwrap(), expected);
+
+        let indexed_file = File::open_raw(file).unwrap();
+        assert_eq!(indexed_file.par_count_matching(contains).unwrap(), expected);
+    }
+
+    fn test_span<L: ReadByLine>(reader: &mut L, original_file: &str) {
+        let original = std::fs::read(original_file).unwrap();
+        let total = reader.total_lines();
+
+        assert_eq!(reader.data_len(), original.len() as u64);
+        assert_eq!(
+            reader.line_span(total - 1).unwrap().end,
+            original.len() as u64
+        );
+        assert!(reader.line_span(total).is_err());
+
+        let mut buf = Vec::new();
+        let read = reader.read_span(0..total, &mut buf).unwrap();
+        assert_eq!(read, original.len());
+        assert_eq!(buf, original);
+
+        for (start, end) in [(0, 1), (1, total / 2), (total / 2, total), (total, total)] {
+            let read = reader.read_span(start..end, &mut buf).unwrap();
+            let from = if start < total {
+                reader.line_span(start).unwrap().start as usize
+            } else {
+                original.len()
+            };
+            assert_eq!(&buf[..read], &original[from..from + read]);
+        }
+
+        // Reading sequencially after a span read must continue after the span
+        reader.read_span(0..1, &mut buf).unwrap();
+        let span = reader.line_span(1).unwrap();
+        let read = reader.read_line_raw(1, &mut buf).unwrap();
+        assert_eq!(
+            &buf[..read],
+            &original[span.start as usize..span.end as usize]
+        );
+
+        assert!(reader.read_span(0..total + 1, &mut buf).is_err());
     }
 
     fn test_sequencially<L: ReadByLine>(reader: &mut L, original_file: &str) {
-        let original = BufReader::new(std::fs::File::open(&original_file).unwrap());
+        let original = BufReader::new(std::fs::File::open(original_file).unwrap());
 
         for (line, original) in original.lines().enumerate() {
             let original = original.unwrap();
@@ -231,7 +1465,7 @@ mod tests {
 
             assert_eq!(original, read);
 
-            for mut buf in vec![Vec::new(), vec![65u8; 5]] {
+            for mut buf in [Vec::new(), vec![65u8; 5]] {
                 let res = reader.read_line_raw(line, &mut buf);
                 buf.pop();
                 assert!(res.is_ok());
@@ -244,7 +1478,7 @@ mod tests {
     }
 
     fn test_random<L: ReadByLine>(reader: &mut L, original_file: &str) {
-        let original = BufReader::new(std::fs::File::open(&original_file).unwrap());
+        let original = BufReader::new(std::fs::File::open(original_file).unwrap());
         let orig_content: Vec<_> = original.lines().map(|i| i.unwrap()).collect();
 
         let lines: Vec<_> = rand::thread_rng()
@@ -263,7 +1497,7 @@ mod tests {
 
             assert_eq!(*original, read);
 
-            for mut buf in vec![Vec::new(), vec![65u8; 5]] {
+            for mut buf in [Vec::new(), vec![65u8; 5]] {
                 let res = reader.read_line_raw(line, &mut buf);
                 buf.pop();
                 assert!(res.is_ok());
@@ -275,6 +1509,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_partition_lines() {
+        let reader = IndexedString::new_raw("a\nb\nc\nd\ne\n").unwrap();
+        assert_eq!(reader.partition_lines(2), vec![0..3, 3..5]);
+        assert_eq!(
+            reader.partition_lines(5),
+            vec![0..1, 1..2, 2..3, 3..4, 4..5]
+        );
+        assert_eq!(reader.partition_lines(7).last(), Some(&(5..5)));
+        assert_eq!(reader.partition_lines(7).len(), 7);
+        assert!(reader.partition_lines(0).is_empty());
+    }
+
+    #[test]
+    fn test_rev_no_new_line() {
+        let mut reader = IndexedString::new_raw("a\nb\nc").unwrap();
+        let lines: Vec<_> = reader.lines_rev().map(|i| i.unwrap()).collect();
+        assert_eq!(lines, ["c", "b\n", "a\n"]);
+    }
+
     #[test]
     fn test_no_new_line() {
         let index = Index::new(vec![0, 5, 10, 15, 20, 25, 30, 35, 40, 45]);
@@ -287,23 +1541,657 @@ mod tests {
             assert_eq!(read.len(), 5);
         }
 
-        for mut buf in vec![Vec::new(), vec![65u8; 5]] {
+        for mut buf in [Vec::new(), vec![65u8; 5]] {
             let read = indexed_text.reader.read_all(&mut buf).unwrap();
             assert_eq!(&buf[..read], text.as_bytes());
         }
     }
 
+    #[test]
+    fn test_error_variants() {
+        let content = read_to_string("./testfiles/simple").unwrap();
+        let file = File::open_raw("./testfiles/simple").unwrap();
+        let vec_reader: CloneableIndexedReader<Vec<u8>> = File::open_raw("./testfiles/simple")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        test_error_variants_reader(file, &content);
+        test_error_variants_reader(IndexedString::new_raw(&content).unwrap(), &content);
+        test_error_variants_reader(vec_reader, &content);
+    }
+
+    fn test_error_variants_reader<L: ReadByLine>(mut reader: L, content: &str) {
+        let lines = reader.total_lines();
+        let first = format!("{}\n", content.lines().next().unwrap());
+
+        let is_oob = |res: Result<_>, line| matches!(res, Err(error::Error::OutOfBounds { line: l, lines: n }) if l == line && n == lines);
+
+        assert!(is_oob(reader.read_line(lines).map(|_| ()), lines));
+        assert!(is_oob(reader.read_line(lines + 10).map(|_| ()), lines + 10));
+        assert!(is_oob(
+            reader.read_line_raw(lines + 1, &mut Vec::new()).map(|_| ()),
+            lines + 1
+        ));
+        assert!(is_oob(reader.seek_line(lines), lines));
+
+        // Reading the line after the last one, which could use the sequencial shortcut
+        reader.read_line(lines - 1).unwrap();
+        assert!(is_oob(reader.read_line(lines).map(|_| ()), lines));
+
+        // Failures must not break subsequent reads
+        assert_eq!(reader.read_line(0).unwrap(), first);
+
+        // Neither must repositioning the reader by writing it
+        reader.write_to(&mut Vec::new()).unwrap();
+        assert_eq!(
+            reader.read_line(1).unwrap(),
+            format!("{}\n", content.lines().nth(1).unwrap())
+        );
+
+        // Searches never leak bounds errors
+        assert!(matches!(
+            reader.binary_search("\u{10FFFF}"),
+            Err(error::Error::NotFound)
+        ));
+        assert!(matches!(
+            reader.binary_search(""),
+            Err(error::Error::NotFound)
+        ));
+        assert!(matches!(
+            reader.binary_search_raw(&[0xff; 4]),
+            Err(error::Error::NotFound)
+        ));
+        assert_eq!(reader.read_line(0).unwrap(), first);
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert!(matches!(
+            empty.binary_search("a"),
+            Err(error::Error::NotFound)
+        ));
+        assert!(matches!(
+            empty.read_line(0),
+            Err(error::Error::OutOfBounds { line: 0, lines: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_from_end() {
+        for content in ["a\nb\nc\n", "a\nb\nc"] {
+            let mut indexed = IndexedString::new_raw(content).unwrap();
+            let mem_file = mem_file::MemFile::from(content.lines());
+
+            let temp = temp::TempFile::new("from_end");
+            std::fs::write(temp.path(), content).unwrap();
+            let mut file = File::open_raw(temp.path()).unwrap();
+
+            for (n, expected) in ["c", "b", "a"].iter().enumerate() {
+                assert_eq!(indexed.line_index_from_end(n).unwrap(), 2 - n);
+                assert_eq!(file.line_index_from_end(n).unwrap(), 2 - n);
+                assert_eq!(mem_file.line_index_from_end(n).unwrap(), 2 - n);
+
+                assert_eq!(indexed.read_line_from_end(n).unwrap().trim_end(), *expected);
+                assert_eq!(file.read_line_from_end(n).unwrap().trim_end(), *expected);
+                let line = mem_file.line_index_from_end(n).unwrap();
+                assert_eq!(mem_file.get(line).unwrap(), expected.as_bytes());
+            }
+
+            assert!(matches!(
+                indexed.read_line_from_end(3),
+                Err(error::Error::OutOfBounds { line: 3, lines: 3 })
+            ));
+            assert!(file.read_line_from_end(3).is_err());
+            assert!(mem_file.line_index_from_end(3).is_err());
+        }
+    }
+
+    #[test]
+    fn test_sample_evenly() {
+        let content: String = (0..10).map(|i| format!("{}\n", i)).collect();
+        let mut reader = IndexedString::new_raw(content).unwrap();
+        let sampled = |reader: &mut IndexedString, n| {
+            let sample = reader.sample_evenly(n).unwrap();
+            assert!(sample.iter().all(|(line, s)| *s == format!("{}\n", line)));
+            sample.into_iter().map(|i| i.0).collect::<Vec<_>>()
+        };
+
+        assert!(sampled(&mut reader, 0).is_empty());
+        assert_eq!(sampled(&mut reader, 1), [0]);
+        assert_eq!(sampled(&mut reader, 2), [0, 9]);
+        assert_eq!(sampled(&mut reader, 4), [0, 3, 6, 9]);
+        assert_eq!(sampled(&mut reader, 9), [0, 1, 2, 3, 4, 5, 6, 7, 9]);
+        assert_eq!(sampled(&mut reader, 10), (0..10).collect::<Vec<_>>());
+        assert_eq!(sampled(&mut reader, 50), (0..10).collect::<Vec<_>>());
+
+        let mut single = IndexedString::new_raw("only").unwrap();
+        assert_eq!(single.sample_evenly(3).unwrap(), [(0, "only".to_string())]);
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert!(empty.sample_evenly(3).unwrap().is_empty());
+
+        // The products of the line numbers exceed `u32::MAX`
+        let mut many = IndexedString::new_raw("\n".repeat(70_000)).unwrap();
+        let sample = many.sample_evenly(70_000).unwrap();
+        assert!(sample.iter().enumerate().all(|(i, (line, _))| i == *line));
+    }
+
+    /// Implements only the required methods of `IndexableFile`
+    struct MinimalReader(IndexedString);
+
+    impl Indexable for MinimalReader {
+        fn get_index(&self) -> &Index {
+            self.0.get_index()
+        }
+    }
+
+    impl IndexableFile for MinimalReader {
+        fn read_current_line(&mut self, buf: &mut Vec<u8>, line: usize) -> Result<usize> {
+            self.0.read_current_line(buf, line)
+        }
+
+        fn seek_line(&mut self, line: usize) -> Result<()> {
+            self.0.seek_line(line)
+        }
+
+        fn data_len(&self) -> u64 {
+            self.0.data_len()
+        }
+
+        fn absolute_position(&mut self) -> Result<u64> {
+            self.0.absolute_position()
+        }
+
+        fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
+            self.0.write_to(writer)
+        }
+    }
+
+    impl ReadByLine for MinimalReader {}
+
+    #[test]
+    fn test_default_methods() {
+        let text = "first\nsecond\n\nlast";
+        let mut full = IndexedString::new_raw(text).unwrap();
+        let mut minimal = MinimalReader(IndexedString::new_raw(text).unwrap());
+        assert_eq!(minimal.current_line(), None);
+
+        let (mut expected, mut buf) = (Vec::new(), Vec::new());
+        for lines in [0..4, 1..3, 2..2, 3..4] {
+            let len = full.read_span(lines.clone(), &mut expected).unwrap();
+            assert_eq!(minimal.read_span(lines, &mut buf).unwrap(), len);
+            assert_eq!(buf, expected);
+        }
+        assert!(minimal.read_span(0..5, &mut buf).is_err());
+
+        for (line, max) in [(0, 3), (1, 100), (2, 0), (3, 4)] {
+            let len = full.read_line_prefix(line, max, &mut expected).unwrap();
+            assert_eq!(minimal.read_line_prefix(line, max, &mut buf).unwrap(), len);
+            assert_eq!(buf, expected);
+        }
+
+        for (line, offset) in [(0, 0), (1, 3), (1, 7), (1, 100), (3, 2)] {
+            let mut expected = [0u8; 4];
+            let mut buf = [0u8; 4];
+            let len = full.read_line_at(line, offset, &mut expected).unwrap();
+            assert_eq!(minimal.read_line_at(line, offset, &mut buf).unwrap(), len);
+            assert_eq!(buf[..len], expected[..len]);
+        }
+        assert_eq!(minimal.read_line(1).unwrap(), "second\n");
+    }
+
+    #[test]
+    fn test_read_many() {
+        let content: String = (0..10).map(|i| format!("{}\n", i)).collect();
+        let mut reader = builder::Builder::new()
+            .index_reader(std::io::Cursor::new(content))
+            .unwrap();
+        let counter = observer::CountingObserver::new();
+        reader.set_observer(Box::new(counter.clone()));
+
+        let read = reader.read_many(&[7, 2, 3, 7, 9, 0, 8]).unwrap();
+        assert_eq!(read, ["7\n", "2\n", "3\n", "7\n", "9\n", "0\n", "8\n"]);
+        // The runs 0, 2..4 and 7..10 are read once each
+        assert_eq!(counter.reads(), 3);
+        assert!(reader.read_many(&[]).unwrap().is_empty());
+
+        let err = reader.read_many(&[1, 10, 2, 42]).unwrap_err();
+        assert!(matches!(err, error::Error::MultiGet { failed } if failed == [1, 3]));
+
+        let mut unterminated = IndexedString::new_raw("a\nb").unwrap();
+        assert_eq!(unterminated.read_many(&[1, 0]).unwrap(), ["b", "a\n"]);
+
+        // Data ending before the last line fails instead of panicking
+        let index = Arc::new(index::Index::new(vec![0, 2, 5]).zero_len());
+        let mut truncated = IndexedString::new_custom("a\nbc", index);
+        assert!(matches!(
+            truncated.read_many(&[2, 0, 1]),
+            Err(error::Error::IncompleteLine {
+                line: 1,
+                have: 2,
+                want: 3
+            })
+        ));
+
+        // Lines of an unsorted index starting in front of their run fail too
+        let index = Arc::new(index::Index::new(vec![2, 0, 4]).zero_len());
+        let mut unsorted = IndexedString::new_custom("a\nb\nc\n", index);
+        assert!(matches!(
+            unsorted.read_many(&[0, 1]),
+            Err(error::Error::InvalidIndex { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_is_sorted() {
+        let mut sorted = IndexedString::new_raw("a\nab\nb\nb\nc").unwrap();
+        assert!(sorted.is_sorted().unwrap());
+        assert_eq!(sorted.first_unsorted_at().unwrap(), None);
+        assert!(!sorted.is_sorted_by(|a, b| b.cmp(a)).unwrap());
+        assert!([2, 3].contains(&sorted.binary_search_raw(b"b\n").unwrap()));
+
+        let mut unsorted = IndexedString::new_raw("a\nc\nb\nd\n").unwrap();
+        assert!(!unsorted.is_sorted().unwrap());
+        assert_eq!(unsorted.first_unsorted_at().unwrap(), Some(2));
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert!(empty.is_sorted().unwrap());
+    }
+
+    #[test]
+    fn test_read_line_prefix_str() {
+        let mut reader = IndexedString::new_raw("äöü\nabc\n").unwrap();
+        assert_eq!(reader.read_line_prefix_str(0, 3).unwrap(), "ä");
+        assert_eq!(reader.read_line_prefix_str(0, 4).unwrap(), "äö");
+        assert_eq!(reader.read_line_prefix_str(0, 100).unwrap(), "äöü\n");
+        assert_eq!(reader.read_line_prefix_str(1, 2).unwrap(), "ab");
+
+        let mut invalid = CloneableIndexedReader::new_raw(vec![b'a', 0xff, b'b']).unwrap();
+        assert!(matches!(
+            invalid.read_line_prefix_str(0, 3),
+            Err(error::Error::UTF8Error)
+        ));
+    }
+
+    #[test]
+    fn test_read_line_truncated() {
+        let data = "日本語のテキスト\n😀😃😄\nabc\n\n";
+        let mut reader = IndexedString::new_raw(data).unwrap();
+        assert_eq!(
+            reader.read_line_truncated(0, 3).unwrap(),
+            ("日本語".to_string(), true)
+        );
+        assert_eq!(
+            reader.read_line_truncated(0, 8).unwrap(),
+            ("日本語のテキスト".to_string(), false)
+        );
+        assert_eq!(
+            reader.read_line_truncated(1, 1).unwrap(),
+            ("😀".to_string(), true)
+        );
+        assert_eq!(
+            reader.read_line_truncated(1, 2).unwrap(),
+            ("😀😃".to_string(), true)
+        );
+        assert_eq!(
+            reader.read_line_truncated(1, 100).unwrap(),
+            ("😀😃😄".to_string(), false)
+        );
+        assert_eq!(
+            reader.read_line_truncated(2, 0).unwrap(),
+            (String::new(), true)
+        );
+        assert_eq!(
+            reader.read_line_truncated(2, 3).unwrap(),
+            ("abc".to_string(), false)
+        );
+        assert_eq!(
+            reader.read_line_truncated(3, 0).unwrap(),
+            (String::new(), false)
+        );
+
+        // Only a bounded prefix of huge lines is read
+        let huge = format!("{}\n", "ä".repeat(1 << 20));
+        let mut reader = builder::Builder::new()
+            .index_reader(std::io::Cursor::new(huge))
+            .unwrap();
+        let counter = observer::CountingObserver::new();
+        reader.set_observer(Box::new(counter.clone()));
+        let (preview, truncated) = reader.read_line_truncated(0, 5).unwrap();
+        assert_eq!((preview.as_str(), truncated), ("äääää", true));
+        assert_eq!(counter.bytes_read(), 21);
+
+        let mut invalid = CloneableIndexedReader::new_raw(vec![b'a', 0xff, b'b']).unwrap();
+        assert!(matches!(
+            invalid.read_line_truncated(0, 3),
+            Err(error::Error::UTF8Error)
+        ));
+    }
+
+    #[test]
+    fn test_binary_search_prefix() {
+        let lines = ["", "a", "ab", "abc", "abd", "b", "bcdefghijklmnop", "c"];
+        let data = lines.join("\n");
+
+        check_binary_search_prefix(&mut IndexedString::new_raw(&data).unwrap(), &lines);
+        check_binary_search_prefix(
+            &mut builder::Builder::new()
+                .index_reader(std::io::Cursor::new(data))
+                .unwrap(),
+            &lines,
+        );
+    }
+
+    fn check_binary_search_prefix<L: ReadByLine>(reader: &mut L, lines: &[&str]) {
+        for (pos, line) in lines.iter().enumerate() {
+            assert_eq!(reader.binary_search_prefix(line.as_bytes()).unwrap(), pos);
+        }
+
+        for missing in ["aa", "abcd", "bc", "bcdefghijklmnopq", "d"] {
+            assert!(matches!(
+                reader.binary_search_prefix(missing.as_bytes()),
+                Err(error::Error::NotFound)
+            ));
+        }
+
+        // Reading the prefix must not break reading whole lines afterwards
+        assert_eq!(reader.read_line(6).unwrap(), "bcdefghijklmnop\n");
+    }
+
+    #[test]
+    fn test_binary_search_raw_fetch() {
+        let mut reader = IndexedString::new_raw("a\nb\nc").unwrap();
+        let mut out = Vec::new();
+
+        for (i, key) in [&b"a"[..], b"b", b"c"].iter().enumerate() {
+            let found = reader
+                .binary_search_raw_fetch(|line| trim_terminator(line, b'\n').cmp(key), &mut out)
+                .unwrap();
+            assert_eq!(found, i);
+            assert_eq!(&out, key);
+        }
+
+        let res = reader.binary_search_raw_fetch(|line| line.cmp(b"bb"), &mut out);
+        assert!(matches!(res, Err(error::Error::NotFound)));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_partition_point() {
+        let lines = ["a", "b", "b", "b", "d"];
+        let mut reader = IndexedString::new_raw(lines.join("\n")).unwrap();
+
+        for key in ["", "a", "b", "c", "d", "e"] {
+            let key = key.as_bytes();
+            let lower = reader.partition_point(|line| line < key).unwrap();
+            let upper = reader.partition_point(|line| line <= key).unwrap();
+            assert_eq!(lower, lines.partition_point(|line| line.as_bytes() < key));
+            assert_eq!(upper, lines.partition_point(|line| line.as_bytes() <= key));
+        }
+
+        let mut empty = IndexedString::new_raw("").unwrap();
+        assert_eq!(empty.partition_point(|_| true).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_check_sorted() {
+        let mut sorted = IndexedString::new_raw("a\nab\nb\nc").unwrap();
+        sorted.set_check_sorted(true);
+        assert_eq!(sorted.binary_search_raw(b"b\n").unwrap(), 2);
+        assert_eq!(sorted.binary_search("c").unwrap(), 3);
+        assert_eq!(sorted.binary_search_prefix(b"ab").unwrap(), 1);
+        assert_eq!(sorted.partition_point(|line| line < b"b").unwrap(), 2);
+        assert!(matches!(
+            sorted.binary_search_collated(b"x", Collation::Bytewise),
+            Err(error::Error::NotFound)
+        ));
+
+        // Comparators ordering the lines differently are detected as well
+        assert!(matches!(
+            sorted.binary_search_by(|line| "b".cmp(line)),
+            Err(error::Error::Unsorted { line: 2 })
+        ));
+
+        let mut unsorted = IndexedString::new_raw("a\nc\nb\nd\n").unwrap();
+        assert_eq!(unsorted.binary_search_raw(b"a\n").unwrap(), 0);
+        unsorted.set_check_sorted(true);
+        let mut clone = unsorted.clone();
+        for reader in [&mut unsorted, &mut clone] {
+            assert!(matches!(
+                reader.binary_search_raw(b"b\n"),
+                Err(error::Error::Unsorted { line: 2 })
+            ));
+            assert!(matches!(
+                reader.binary_search_prefix(b"b"),
+                Err(error::Error::Unsorted { line: 2 })
+            ));
+            assert!(matches!(
+                reader.partition_point(|line| line < b"c"),
+                Err(error::Error::Unsorted { line: 2 })
+            ));
+        }
+    }
+
+    /// Inputs covering the corner cases of splitting data into lines
+    const LINE_SEMANTICS: &[&str] = &["", "\n", "a", "a\n", "a\n\n", "\na", "\n\n", "a\nb"];
+
+    /// Compares the lines of `reader` with `str::lines`. Lines are read including their
+    /// terminators, so the terminator is removed before comparing.
+    fn check_line_semantics<L: ReadByLine>(reader: &mut L, input: &str) {
+        let expected: Vec<_> = input.lines().collect();
+        assert_eq!(reader.total_lines(), expected.len(), "input {:?}", input);
+
+        for (line, expected) in expected.iter().enumerate() {
+            let read = reader.read_line(line).unwrap();
+            assert_eq!(read.strip_suffix('\n').unwrap_or(&read), *expected);
+        }
+        assert!(reader.read_line(expected.len()).is_err());
+        assert_eq!(reader.lines_rev().count(), expected.len());
+    }
+
+    #[test]
+    fn test_line_semantics() {
+        let temp = temp::TempFile::new("line_semantics");
+
+        for input in LINE_SEMANTICS {
+            check_line_semantics(&mut IndexedString::new_raw(*input).unwrap(), input);
+            check_line_semantics(&mut IndexedSlice::new_raw(input.as_bytes()).unwrap(), input);
+
+            std::fs::write(temp.path(), input).unwrap();
+            let mut raw = File::open_raw(temp.path()).unwrap();
+            check_line_semantics(&mut raw, input);
+
+            // Writing the index must not change the lines
+            let mut indexed = Vec::new();
+            raw.write_to(&mut indexed).unwrap();
+            check_line_semantics(
+                &mut CloneableIndexedReader::new(indexed.clone()).unwrap(),
+                input,
+            );
+            check_line_semantics(&mut IndexedSlice::new(&indexed).unwrap(), input);
+
+            std::fs::write(temp.path(), &indexed).unwrap();
+            check_line_semantics(&mut File::open(temp.path()).unwrap(), input);
+        }
+    }
+
+    /// Reads all lines of `reader` without their terminators and compares them to `lines`
+    fn check_round_trip<L: ReadByLine>(reader: &mut L, lines: &[Vec<u8>], case: &str) {
+        assert_eq!(reader.total_lines(), lines.len(), "{}", case);
+        let delimiter = reader.get_index().delimiter();
+
+        let mut buf = Vec::new();
+        for (line, expected) in lines.iter().enumerate() {
+            let read = reader.read_line_raw(line, &mut buf).unwrap();
+            let read = trim_terminator(&buf[..read], delimiter);
+            assert_eq!(read, &expected[..], "{}, line {}", case, line);
+        }
+        // Reading backwards moves the reader in the other direction
+        for (line, expected) in lines.iter().enumerate().rev() {
+            let read = reader.read_line_raw(line, &mut buf).unwrap();
+            assert_eq!(trim_terminator(&buf[..read], delimiter), &expected[..]);
+        }
+    }
+
+    /// Generates lines of arbitrary bytes without `delimiter`, including empty lines and
+    /// non UTF-8 data, and encodes them. The last line is only left unterminated if it's not
+    /// empty, since a trailing delimiter doesn't start a new line.
+    fn round_trip_case<R: Rng>(rng: &mut R, delimiter: u8) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let count = rng.gen_range(0..40);
+        let lines: Vec<Vec<u8>> = (0..count)
+            .map(|_| {
+                let len = match rng.gen_range(0..10) {
+                    0..=2 => 0,
+                    3 => rng.gen_range(100..3000),
+                    _ => rng.gen_range(1..20),
+                };
+                (0..len)
+                    .map(|_| loop {
+                        let byte: u8 = if rng.gen_bool(0.5) {
+                            rng.gen()
+                        } else {
+                            rng.gen_range(b'a'..b'e')
+                        };
+                        if byte != delimiter {
+                            break byte;
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut data = Vec::new();
+        for line in &lines {
+            data.extend_from_slice(line);
+            data.push(delimiter);
+        }
+        if lines.last().is_some_and(|i| !i.is_empty()) && rng.gen_bool(0.5) {
+            data.pop();
+        }
+        (lines, data)
+    }
+
+    /// Every sequence of lines has to survive building the index, writing it and opening the
+    /// written data again, using every reader and both the default and a custom delimiter
+    #[test]
+    fn test_round_trip_property() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let temp = temp::TempFile::new("round_trip");
+
+        for seed in 0..300u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let delimiter = if seed % 2 == 0 { b'\n' } else { b'\0' };
+            let (lines, data) = round_trip_case(&mut rng, delimiter);
+            let case = format!("seed {}", seed);
+            let builder = Builder::new().delimiter(delimiter);
+
+            let mut reader = builder.index_reader(Cursor::new(data.clone())).unwrap();
+            check_round_trip(&mut reader, &lines, &case);
+            std::fs::write(temp.path(), &data).unwrap();
+            let mut raw = builder.index_from(temp.path()).unwrap();
+            check_round_trip(&mut raw, &lines, &case);
+            if delimiter == index::DEFAULT_DELIMITER {
+                let mut slice = IndexedSlice::new_raw(&data).unwrap();
+                check_round_trip(&mut slice, &lines, &case);
+                let mut cloneable = CloneableIndexedReader::new_raw(data.clone()).unwrap();
+                check_round_trip(&mut cloneable, &lines, &case);
+            }
+
+            let mut indexed = Vec::new();
+            let written = reader.write_to(&mut indexed).unwrap();
+            assert_eq!(written, indexed.len(), "{}", case);
+            let mut indexed_raw = Vec::new();
+            raw.write_to(&mut indexed_raw).unwrap();
+            assert_eq!(indexed, indexed_raw, "{}", case);
+
+            check_round_trip(
+                &mut CloneableIndexedReader::new(indexed.clone()).unwrap(),
+                &lines,
+                &case,
+            );
+            check_round_trip(&mut IndexedSlice::new(&indexed).unwrap(), &lines, &case);
+            let mut reopened = OpenBuilder::new()
+                .open_reader(Cursor::new(indexed.clone()))
+                .unwrap();
+            check_round_trip(&mut reopened, &lines, &case);
+
+            std::fs::write(temp.path(), &indexed).unwrap();
+            let mut file = File::open(temp.path()).unwrap();
+            check_round_trip(&mut file, &lines, &case);
+            let mut all = Vec::new();
+            file.read_all(&mut all).unwrap();
+            assert_eq!(all, data, "{}", case);
+
+            // Writing the reopened file again produces the same file
+            let mut rewritten = Vec::new();
+            file.write_to(&mut rewritten).unwrap();
+            assert_eq!(rewritten, indexed, "{}", case);
+        }
+    }
+
+    #[test]
+    fn test_data_start() {
+        let mut indexed = Vec::new();
+        File::open_raw("./testfiles/simple")
+            .unwrap()
+            .write_to(&mut indexed)
+            .unwrap();
+        let temp = temp::TempFile::new("data_start");
+        std::fs::write(temp.path(), &indexed).unwrap();
+
+        let mut file = File::open(temp.path()).unwrap();
+        assert!(file.data_start() > 0);
+        test_position(&mut file);
+
+        let mut string = CloneableIndexedReader::new(indexed.clone()).unwrap();
+        assert_eq!(string.data_start(), file.data_start());
+        test_position(&mut string);
+
+        let mut slice = IndexedSlice::new(&indexed).unwrap();
+        assert_eq!(slice.data_start(), file.data_start());
+        test_position(&mut slice);
+    }
+
+    #[test]
+    fn test_index_identity() {
+        let mut indexed = Vec::new();
+        File::open_raw("./testfiles/simple")
+            .unwrap()
+            .write_to(&mut indexed)
+            .unwrap();
+
+        let string = CloneableIndexedReader::new(indexed.clone()).unwrap();
+        let slice = IndexedSlice::new(&indexed).unwrap();
+        assert!(string.index_ptr_eq(&string.clone()));
+        assert!(!string.index_ptr_eq(&slice));
+        assert_eq!(string.fingerprint(), slice.fingerprint());
+        assert_eq!(
+            File::open_raw("./testfiles/simple").unwrap().fingerprint(),
+            slice.fingerprint()
+        );
+
+        // Lines of the same length have the same layout
+        let data = read_to_string("./testfiles/simple").unwrap();
+        let mut changed = IndexedString::new_raw(data.as_str()).unwrap();
+        let same_layout = changed.with_data(data.to_ascii_uppercase()).unwrap();
+        assert!(same_layout.index_ptr_eq(&changed));
+        assert_eq!(same_layout.fingerprint(), changed.fingerprint());
+
+        let before = changed.fingerprint();
+        changed.push_line("").unwrap();
+        assert_ne!(changed.fingerprint(), before);
+    }
+
     #[test]
     fn test_write_to() {
         let file = "./testfiles/pre_indexed";
 
         let mut indexed_file = File::open_raw("./testfiles/LICENSE").expect("failed opening file");
         indexed_file
-            .write_to(&mut std::fs::File::create(&file).unwrap())
+            .write_to(&mut std::fs::File::create(file).unwrap())
             .unwrap();
 
         let mut indexed_data = Vec::new();
-        File::open(&file)
+        File::open(file)
             .expect("failed opening pre_indexed file")
             .write_to(&mut indexed_data)
             .unwrap();
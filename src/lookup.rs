@@ -0,0 +1,330 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{Read, Write},
+    ops::ControlFlow,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{error::Error, hash_bytes, ReadByLine, Result};
+
+/// Size of an encoded entry of the table
+const ENTRY_LEN: usize = 12;
+
+/// A table mapping the hash of the key of every line to its line number, which allows finding
+/// lines of unsorted data without reading all lines, see `ReadByLine::find_exact`. Lines whose
+/// keys have the same hash are kept next to each other, so candidates are verified by reading
+/// them.
+///
+/// The table is stored behind the bloom filter of the index and is encoded as follows, using
+/// little endian for all numbers:
+///
+/// | bytes  | content                                     |
+/// |--------|---------------------------------------------|
+/// | 1      | 1 for a table of keys, else 0               |
+/// | 8      | amount of entries                           |
+/// | 12 * n | 8 byte hash and 4 byte line of every entry  |
+///
+/// The entries are sorted by their hash and line. Keys are hashed using 64 bit FNV-1a. Tables
+/// whose keys differ from the whole lines are marked as tables of keys, so
+/// `ReadByLine::find_exact` only uses tables of whole lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupTable {
+    entries: Vec<(u64, u32)>,
+    /// Whether the table holds keys other than the whole lines
+    keyed: bool,
+}
+
+impl LookupTable {
+    /// Builds a table of the keys returned by `key` for every line of `reader`. `key` gets
+    /// called with the lines without their terminator.
+    pub fn build<R, K>(reader: &mut R, mut key: K) -> Result<Self>
+    where
+        R: ReadByLine,
+        K: FnMut(&[u8]) -> &[u8],
+    {
+        let mut entries = Vec::with_capacity(reader.total_lines());
+        let mut keyed = false;
+        reader.for_each_line(|line, content| {
+            let line_key = key(content);
+            keyed |= line_key != content;
+            entries.push((hash_bytes(line_key), line as u32));
+            ControlFlow::Continue(())
+        })?;
+        entries.sort_unstable();
+        Ok(Self { entries, keyed })
+    }
+
+    /// Returns `true` if the table holds keys other than the whole lines. Such tables are used
+    /// by `ReadByLine::find_exact_by_key` but not by `ReadByLine::find_exact`.
+    #[inline]
+    pub fn is_keyed(&self) -> bool {
+        self.keyed
+    }
+
+    /// Returns the lines whose key has the same hash as `key` in ascending order. These lines
+    /// have to be read to check whether their key is equal to `key`.
+    pub fn candidates(&self, key: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let hash = hash_bytes(key);
+        let start = self.entries.partition_point(|(i, _)| *i < hash);
+        self.entries[start..]
+            .iter()
+            .take_while(move |(i, _)| *i == hash)
+            .map(|(_, line)| *line as usize)
+    }
+
+    /// Returns the amount of lines in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the table contains no lines
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the length of the encoded table in bytes
+    #[inline]
+    pub(crate) fn encoded_len(&self) -> usize {
+        9 + self.entries.len() * ENTRY_LEN
+    }
+
+    /// Writes the encoded table into `w`
+    pub(crate) fn encode_into<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&[u8::from(self.keyed)])?;
+        w.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for (hash, line) in &self.entries {
+            w.write_all(&hash.to_le_bytes())?;
+            w.write_all(&line.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a table written by `encode_into` for an index of `lines` lines. Returns
+    /// `Error::MalformedIndex` if the table doesn't contain every line exactly once in order.
+    pub(crate) fn decode<R: Read>(reader: &mut R, lines: usize) -> Result<Self> {
+        let mut keyed = [0u8; 1];
+        reader.read_exact(&mut keyed)?;
+        let keyed = match keyed[0] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedIndex),
+        };
+
+        let mut len = [0u8; 8];
+        reader.read_exact(&mut len)?;
+        let len = usize::try_from(u64::from_le_bytes(len)).map_err(|_| Error::MalformedIndex)?;
+        if len != lines {
+            return Err(Error::MalformedIndex);
+        }
+
+        let mut entries = Vec::with_capacity(len);
+        let mut seen = vec![false; len];
+        let mut entry = [0u8; ENTRY_LEN];
+        for _ in 0..len {
+            reader.read_exact(&mut entry)?;
+            let (hash, line) = entry.split_at(8);
+            let hash = u64::from_le_bytes(hash.try_into().unwrap());
+            let line = u32::from_le_bytes(line.try_into().unwrap());
+
+            let unordered = matches!(entries.last(), Some(last) if *last >= (hash, line));
+            match seen.get_mut(line as usize) {
+                Some(seen) if !*seen && !unordered => *seen = true,
+                _ => return Err(Error::MalformedIndex),
+            }
+            entries.push((hash, line));
+        }
+
+        Ok(Self { entries, keyed })
+    }
+
+    /// Creates a table of raw entries, which don't have to match the lines
+    #[cfg(test)]
+    pub(crate) fn from_entries(mut entries: Vec<(u64, u32)>, keyed: bool) -> Self {
+        entries.sort_unstable();
+        Self { entries, keyed }
+    }
+}
+
+impl Serialize for LookupTable {
+    /// Serializes the table as its encoded bytes, see `LookupTable`
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut encoded = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut encoded)
+            .map_err(serde::ser::Error::custom)?;
+        encoded.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LookupTable {
+    /// Deserializes the encoded bytes of a table, rejecting malformed tables like `decode`. The
+    /// table has to contain the lines `0..n`, with `n` being its amount of entries.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let encoded = Vec::<u8>::deserialize(deserializer)?;
+        let lines = encoded
+            .get(1..9)
+            .map(|len| u64::from_le_bytes(len.try_into().unwrap()))
+            .filter(|&len| encoded.len() as u64 == 9 + len.saturating_mul(ENTRY_LEN as u64))
+            .ok_or_else(|| de::Error::custom(Error::MalformedIndex))?;
+        Self::decode(&mut encoded.as_slice(), lines as usize).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::CloneableIndexedReader, index::Index, temp::TempFile, File, Indexable, IndexableFile,
+        IndexedString,
+    };
+    use std::{io::Cursor, sync::Arc};
+
+    #[test]
+    fn test_find_exact() {
+        let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+        let wanted = file.read_line(20).unwrap();
+        let wanted = wanted.trim_end_matches('\n').as_bytes().to_vec();
+        let first = file.find_exact(&wanted).unwrap();
+
+        file.build_lookup_table(|line| line).unwrap();
+        assert_eq!(
+            file.get_index().lookup_table().unwrap().len(),
+            file.total_lines()
+        );
+        assert_eq!(file.find_exact(&wanted).unwrap(), first);
+        assert!(first.unwrap() <= 20);
+        assert_eq!(file.find_exact(b"not a line of the license").unwrap(), None);
+
+        let temp = TempFile::new("lookup");
+        file.write_to(&mut std::fs::File::create(temp.path()).unwrap())
+            .unwrap();
+        let mut reopened = File::open(temp.path()).unwrap();
+        assert_eq!(reopened.get_index(), file.get_index());
+        assert_eq!(
+            reopened.get_index().lookup_table(),
+            file.get_index().lookup_table()
+        );
+        assert_eq!(reopened.find_exact(&wanted).unwrap(), first);
+        // Empty lines exist in the license and the first one is returned
+        let empty = reopened.find_exact(b"").unwrap().unwrap();
+        assert_eq!(reopened.read_line(empty).unwrap(), "\n");
+        assert!((0..empty).all(|i| reopened.read_line(i).unwrap() != "\n"));
+    }
+
+    fn key(line: &[u8]) -> &[u8] {
+        line.split(|i| *i == b'=').next().unwrap()
+    }
+
+    #[test]
+    fn test_buckets() {
+        let data = "c=3\na=1\nb=2\na=4\n";
+        let mut file = IndexedString::new_raw(data).unwrap();
+        assert_eq!(file.find_exact_by_key(b"a", key).unwrap(), Some(1));
+        file.build_lookup_table(key).unwrap();
+        assert_eq!(
+            file.get_index()
+                .lookup_table()
+                .unwrap()
+                .candidates(b"a")
+                .collect::<Vec<_>>(),
+            [1, 3]
+        );
+        assert_eq!(file.find_exact_by_key(b"a", key).unwrap(), Some(1));
+        assert_eq!(file.find_exact_by_key(b"b", key).unwrap(), Some(2));
+        assert_eq!(file.find_exact_by_key(b"d", key).unwrap(), None);
+        assert_eq!(file.find_exact(b"a").unwrap(), None);
+        assert!(file.get_index().lookup_table().unwrap().is_keyed());
+
+        // A table of keys isn't used to find whole lines and vice versa
+        assert_eq!(file.find_exact(b"b=2").unwrap(), Some(2));
+        let mut lines = IndexedString::new_raw(data).unwrap();
+        lines.build_lookup_table(|line| line).unwrap();
+        assert!(!lines.get_index().lookup_table().unwrap().is_keyed());
+        assert_eq!(lines.find_exact(b"a=4").unwrap(), Some(3));
+        assert_eq!(lines.find_exact_by_key(b"a", key).unwrap(), Some(1));
+
+        // Colliding lines are verified instead of being returned blindly
+        let collision = hash_bytes(b"b=2");
+        let table = LookupTable::from_entries(
+            vec![
+                (hash_bytes(b"c=3"), 0),
+                (collision, 1),
+                (collision, 2),
+                (hash_bytes(b"a=4"), 3),
+            ],
+            false,
+        );
+        let mut index = file.get_index().clone();
+        index.set_lookup_table(table);
+        let mut colliding = IndexedString::new_custom(data, Arc::new(index.zero_len()));
+        assert_eq!(colliding.find_exact(b"b=2").unwrap(), Some(2));
+        assert_eq!(colliding.find_exact(b"a=1").unwrap(), None);
+    }
+
+    #[test]
+    fn test_serde() {
+        let table = LookupTable::from_entries(vec![(7, 1), (3, 0), (7, 2)], true);
+        let json = serde_json::to_string(&table).unwrap();
+        let decoded: LookupTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, table);
+
+        let trailing = json.replace(']', ",0]");
+        assert!(serde_json::from_str::<LookupTable>(&trailing).is_err());
+        assert!(serde_json::from_str::<LookupTable>("[0,1]").is_err());
+
+        let mut encoded = Vec::new();
+        LookupTable::from_entries(vec![(3, 0), (7, 2)], false)
+            .encode_into(&mut encoded)
+            .unwrap();
+        let json = serde_json::to_string(&encoded).unwrap();
+        assert!(serde_json::from_str::<LookupTable>(&json).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let table = LookupTable::from_entries(vec![(7, 1), (3, 0), (7, 2)], true);
+        let mut encoded = Vec::new();
+        table.encode_into(&mut encoded).unwrap();
+        assert_eq!(encoded.len(), table.encoded_len());
+        assert_eq!(
+            LookupTable::decode(&mut Cursor::new(&encoded), 3).unwrap(),
+            table
+        );
+        assert!(matches!(
+            LookupTable::decode(&mut Cursor::new(&encoded), 4),
+            Err(Error::MalformedIndex)
+        ));
+        assert!(LookupTable::decode(&mut Cursor::new(&encoded[..20]), 3).is_err());
+        encoded[0] = 2;
+        assert!(matches!(
+            LookupTable::decode(&mut Cursor::new(&encoded), 3),
+            Err(Error::MalformedIndex)
+        ));
+
+        for entries in [vec![(3, 0), (7, 0), (7, 2)], vec![(3, 0), (7, 2), (7, 3)]] {
+            let mut encoded = Vec::new();
+            LookupTable::from_entries(entries, false)
+                .encode_into(&mut encoded)
+                .unwrap();
+            assert!(matches!(
+                LookupTable::decode(&mut Cursor::new(&encoded), 3),
+                Err(Error::MalformedIndex)
+            ));
+        }
+
+        // Readers embedding the index keep the table
+        let mut file = IndexedString::new_raw("x\ny\n").unwrap();
+        file.build_lookup_table(|line| line).unwrap();
+        let mut written = Vec::new();
+        file.write_to(&mut written).unwrap();
+        let mut parsed = CloneableIndexedReader::new(written).unwrap();
+        assert_eq!(parsed.find_exact(b"y").unwrap(), Some(1));
+
+        let mut index = Index::new(vec![0, 2]);
+        index.set_lookup_table(LookupTable::from_entries(vec![(0, 0), (1, 1)], false));
+        index.add(4);
+        assert_eq!(index.lookup_table(), None);
+    }
+}
@@ -1,10 +1,42 @@
-use crate::index::Index;
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::{
+    any::{Anyable, CloneableIndexedReader},
+    error::Error,
+    index::Index,
+    string::IndexedString,
+    trim_terminator, Indexable, IndexableFile,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, convert::TryFrom, sync::Arc};
+
+/// Version of the serialized layout of `MemFile`
+const MEM_FILE_VERSION: u16 = 1;
+
+/// Flag in the upper byte of the serialized version, set if the layout contains tags
+const MEM_FILE_TAGS: u16 = 1 << 8;
+
+/// Entries of arbitrary bytes stored in memory, together with an index of their offsets. Unlike
+/// lines, entries have no terminators, so they may contain newlines.
+///
+/// # Example
+///
+/// ```
+/// use indexed_file::{IndexedString, MemFile, ReadByLine};
+/// use std::convert::TryFrom;
+///
+/// let mut file = MemFile::new();
+/// file.extend(["pear", "apple", "fig"]);
+/// file.sort_unstable();
+/// assert_eq!(file.get(0), Some(&b"apple"[..]));
+///
+/// // Entries without newlines can be searched as lines
+/// let mut lines = IndexedString::try_from(file).unwrap();
+/// assert_eq!(lines.binary_search("fig\n").unwrap(), 1);
+/// ```
+#[derive(Clone, Debug)]
 pub struct MemFile {
     data: Vec<u8>,
     index: Index,
+    tags: Option<Vec<u32>>,
 }
 
 impl MemFile {
@@ -18,12 +50,17 @@ impl MemFile {
         Self {
             data: Vec::with_capacity(capacity),
             index: Index::default(),
+            tags: None,
         }
     }
 
     #[inline]
     pub fn new_raw(data: Vec<u8>, index: Index) -> Self {
-        Self { data, index }
+        Self {
+            data,
+            index,
+            tags: None,
+        }
     }
 
     /// Inserts Data into the file
@@ -32,19 +69,62 @@ impl MemFile {
         let pos = self.index.len();
         self.index.add(self.data.len() as u32);
         self.data.extend_from_slice(data);
+        if let Some(tags) = &mut self.tags {
+            tags.push(0);
+        }
         pos
     }
 
+    /// Inserts data into the file together with a tag. Enables tags if they aren't enabled yet.
+    #[inline]
+    pub fn insert_tagged(&mut self, data: &[u8], tag: u32) -> usize {
+        self.enable_tags();
+        let pos = self.insert(data);
+        self.set_tag(pos, tag);
+        pos
+    }
+
+    /// Enables storing a `u32` tag for every entry. Existing entries get tagged with 0, as do
+    /// entries inserted without a tag later on. Does nothing if tags are already enabled.
+    pub fn enable_tags(&mut self) {
+        if self.tags.is_none() {
+            self.tags = Some(vec![0; self.len()]);
+        }
+    }
+
+    /// Returns `true` if the entries have tags
+    #[inline]
+    pub fn has_tags(&self) -> bool {
+        self.tags.is_some()
+    }
+
+    /// Returns the tag of the entry at `pos`, or `None` if `pos` is out of bounds or tags aren't
+    /// enabled
+    #[inline]
+    pub fn tag(&self, pos: usize) -> Option<u32> {
+        self.tags.as_ref()?.get(pos).copied()
+    }
+
+    /// Sets the tag of the entry at `pos`, enabling tags if they aren't enabled yet. Returns
+    /// `None` if `pos` is out of bounds.
+    pub fn set_tag(&mut self, pos: usize, tag: u32) -> Option<()> {
+        if pos >= self.len() {
+            return None;
+        }
+        self.enable_tags();
+        *self.tags.as_mut()?.get_mut(pos)? = tag;
+        Some(())
+    }
+
     /// Replaces an entry with new data. This automatically adjusts the index which means the new input can be any size.
-    /// Depending on amount of data stored in MemFile this can take some time
+    /// Depending on amount of data stored in MemFile this can take some time. Returns `None`
+    /// without changing anything if `pos` is out of bounds or the entries after `pos` would start
+    /// beyond the range of the index.
     pub fn replace(&mut self, pos: usize, data: &[u8]) -> Option<()> {
         let (start, end) = self.index_range(pos)?;
+        let diff = data.len() as i64 - (start..end).len() as i64;
+        self.index.shift_from(pos + 1, diff).ok()?;
         self.data.splice(start..end, data.iter().copied());
-        let diff = data.len() as isize - (start..end).len() as isize;
-
-        for i in self.index.inner.iter_mut().skip(pos + 1) {
-            *i = (*i as isize + diff) as u32;
-        }
 
         Some(())
     }
@@ -63,8 +143,12 @@ impl MemFile {
 
     #[inline]
     fn index_range(&self, pos: usize) -> Option<(usize, usize)> {
-        let start = self.index.get2(pos)?;
-        let next = self.index.get2(pos + 1).unwrap_or(self.raw_len());
+        let start = self.index.get_opt(pos)? as usize;
+        let next = self
+            .index
+            .get_opt(pos + 1)
+            .map(|i| i as usize)
+            .unwrap_or(self.raw_len());
         Some((start, next))
     }
 
@@ -72,7 +156,7 @@ impl MemFile {
     fn index_range_unchecked(&self, pos: usize) -> (usize, usize) {
         let start = self.index.get_unchecked(pos);
         let next_pos = pos + 1;
-        if next_pos < self.index.inner.len() {
+        if next_pos < self.index.len() {
             (start, self.index.get_unchecked(next_pos))
         } else {
             (start, self.raw_len())
@@ -84,17 +168,288 @@ impl MemFile {
         MemFileIter::new(self)
     }
 
+    /// Returns the index holding the offsets of the entries
+    #[inline]
+    pub fn as_index(&self) -> &Index {
+        &self.index
+    }
+
     /// Returns the amount of entries in the file
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.index.len()
     }
 
+    /// Returns `true` if the file has no entries
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.index.len() == 0
+    }
+
     /// Returns the amount of bytes stored in the file
     #[inline(always)]
     pub fn raw_len(&self) -> usize {
         self.data.len()
     }
+
+    /// Sorts all entries in ascending order. The order of equal entries is not preserved.
+    #[inline]
+    pub fn sort_unstable(&mut self) {
+        self.sort_unstable_by(|a, b| a.cmp(b))
+    }
+
+    /// Sorts all entries using `compare`. The order of equal entries is not preserved. The data
+    /// gets rewritten in the new order, which requires memory for a copy of it.
+    pub fn sort_unstable_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&[u8], &[u8]) -> Ordering,
+    {
+        let mut order: Vec<_> = (0..self.len()).collect();
+        order.sort_unstable_by(|a, b| compare(self.get_unchecked(*a), self.get_unchecked(*b)));
+        self.rewrite(&order);
+    }
+
+    /// Swaps the entries `a` and `b`. Only the bytes from the start of the first to the end of the
+    /// second entry are moved, and entries of equal length are swapped without moving the entries
+    /// between them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` is out of bounds
+    pub fn swap(&mut self, a: usize, b: usize) {
+        let (a, b) = (a.min(b), a.max(b));
+        let (a_start, a_end) = self.index_range(a).expect("entry out of bounds");
+        let (b_start, b_end) = self.index_range(b).expect("entry out of bounds");
+        if a == b {
+            return;
+        }
+        if let Some(tags) = &mut self.tags {
+            tags.swap(a, b);
+        }
+
+        let (a_len, b_len) = (a_end - a_start, b_end - b_start);
+        if a_len == b_len {
+            let (first, second) = self.data.split_at_mut(b_start);
+            first[a_start..a_end].swap_with_slice(&mut second[..b_len]);
+            return;
+        }
+
+        // [a, between, b] -> [between, b, a] -> [b, between, a]
+        let region = &mut self.data[a_start..b_end];
+        region.rotate_left(a_len);
+        region[..b_end - a_start - a_len].rotate_right(b_len);
+        self.index
+            .shift_range(a + 1..b + 1, b_len as i64 - a_len as i64)
+            .expect("shifted offsets stay within the data");
+    }
+
+    /// Rearranges the entries so that entry `i` is the entry previously at `permutation[i]`. The
+    /// data gets rewritten in a single pass, which requires memory for a copy of it.
+    ///
+    /// Returns `Error::InvalidPermutation` if `permutation` doesn't contain every position
+    /// within `0..len()` exactly once
+    pub fn reorder(&mut self, permutation: &[usize]) -> Result<(), Error> {
+        let len = self.len();
+        let mut seen = vec![false; len];
+        let valid = permutation.len() == len
+            && permutation
+                .iter()
+                .all(|pos| *pos < len && !std::mem::replace(&mut seen[*pos], true));
+        if !valid {
+            return Err(Error::InvalidPermutation { len });
+        }
+
+        self.rewrite(permutation);
+        Ok(())
+    }
+
+    /// Rewrites the data with the entries at the positions of `order`
+    fn rewrite(&mut self, order: &[usize]) {
+        let mut new = MemFile::with_capacity(self.raw_len());
+        for pos in order {
+            new.insert(self.get_unchecked(*pos));
+        }
+        new.tags = self
+            .tags
+            .as_ref()
+            .map(|tags| order.iter().map(|pos| tags[*pos]).collect());
+        *self = new;
+    }
+
+    /// Encodes the file into bytes which can be decoded using `from_bytes`. The layout is stable
+    /// across releases and uses little endian for all numbers:
+    ///
+    /// | bytes | content                                         |
+    /// |-------|-------------------------------------------------|
+    /// | 2     | version of the layout, currently 1, and flags   |
+    /// | 8     | length of the data in bytes                     |
+    /// | n     | data                                            |
+    /// | 8     | amount of entries                               |
+    /// | 4 * m | offset of every entry as u32                    |
+    /// | 4 * m | tag of every entry as u32, only if tags are set |
+    ///
+    /// The lower byte of the version holds the version, the upper byte holds flags of optional
+    /// parts. Bit 8 is set if the layout contains tags, so files without tags are encoded exactly
+    /// like before tags were supported.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let offsets = self.index.offsets();
+        let tags = self.tags.as_deref().unwrap_or(&[]);
+        let mut out = Vec::with_capacity(18 + self.data.len() + offsets.len() * 4 + tags.len() * 4);
+        out.extend_from_slice(&self.version().to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out.extend_from_slice(&(offsets.len() as u64).to_le_bytes());
+        out.extend(offsets.iter().flat_map(|i| i.to_le_bytes()));
+        out.extend(tags.iter().flat_map(|i| i.to_le_bytes()));
+        out
+    }
+
+    /// Returns the serialized version including the flags of the parts present in the file
+    #[inline]
+    fn version(&self) -> u16 {
+        if self.has_tags() {
+            MEM_FILE_VERSION | MEM_FILE_TAGS
+        } else {
+            MEM_FILE_VERSION
+        }
+    }
+
+    /// Decodes a file encoded using `to_bytes`.
+    ///
+    /// Returns `Error::UnsupportedFormat` for unknown versions of the layout, `Error::InvalidIndex`
+    /// if the offsets are decreasing or beyond the data and `Error::MalformedIndex` if `bytes`
+    /// are truncated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+            if bytes.len() < len {
+                return Err(Error::MalformedIndex);
+            }
+            let (taken, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Ok(taken)
+        }
+        let take_u64 = |bytes: &mut &[u8]| -> Result<usize, Error> {
+            let mut num = [0u8; 8];
+            num.copy_from_slice(take(bytes, 8)?);
+            usize::try_from(u64::from_le_bytes(num)).map_err(|_| Error::MalformedIndex)
+        };
+
+        let mut rest = bytes;
+        let version = take(&mut rest, 2)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        let has_tags = check_version(version)?;
+
+        let data_len = take_u64(&mut rest)?;
+        let data = take(&mut rest, data_len)?.to_vec();
+        let items = take_u64(&mut rest)?;
+        let take_u32s = |bytes: &mut &[u8]| -> Result<Vec<u32>, Error> {
+            Ok(
+                take(bytes, items.checked_mul(4).ok_or(Error::MalformedIndex)?)?
+                    .chunks_exact(4)
+                    .map(|i| u32::from_le_bytes([i[0], i[1], i[2], i[3]]))
+                    .collect(),
+            )
+        };
+        let offsets = take_u32s(&mut rest)?;
+        let tags = if has_tags {
+            Some(take_u32s(&mut rest)?)
+        } else {
+            None
+        };
+
+        if !rest.is_empty() {
+            return Err(Error::MalformedIndex);
+        }
+        Self::from_parts(data, offsets, tags)
+    }
+
+    /// Creates a file from decoded data, offsets and tags. The offsets have to be increasing and
+    /// within the data, equal offsets represent empty entries. Tags, if present, need one per
+    /// entry.
+    fn from_parts(data: Vec<u8>, offsets: Vec<u32>, tags: Option<Vec<u32>>) -> Result<Self, Error> {
+        if tags
+            .as_ref()
+            .is_some_and(|tags| tags.len() != offsets.len())
+        {
+            return Err(Error::MalformedIndex);
+        }
+
+        let invalid = offsets
+            .iter()
+            .enumerate()
+            .position(|(i, o)| *o as usize > data.len() || (i > 0 && *o < offsets[i - 1]));
+        if let Some(line) = invalid {
+            return Err(Error::InvalidIndex { line });
+        }
+
+        let mut file = Self::new_raw(data, Index::new(offsets));
+        file.tags = tags;
+        Ok(file)
+    }
+}
+
+/// Checks a serialized version of `MemFile` and returns whether the layout contains tags
+fn check_version(version: u16) -> Result<bool, Error> {
+    if version & !MEM_FILE_TAGS != MEM_FILE_VERSION {
+        return Err(Error::UnsupportedFormat { version });
+    }
+    Ok(version & MEM_FILE_TAGS != 0)
+}
+
+/// The serialized layout of `MemFile`, a struct with the fields `version` (`u16`), `data` (a
+/// sequence of bytes), `offsets` (a sequence of `u32`) and, only if the entries have tags, `tags`
+/// (a sequence of `u32`). It doesn't depend on the internals of `Index`, so it stays stable across
+/// releases. The version contains the same flags as in `MemFile::to_bytes`.
+#[derive(Serialize)]
+#[serde(rename = "MemFile")]
+struct SerializedMemFile<'a> {
+    version: u16,
+    data: &'a [u8],
+    offsets: &'a [u32],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a [u32]>,
+}
+
+/// Owned counterpart of `SerializedMemFile`
+#[derive(Deserialize)]
+#[serde(rename = "MemFile")]
+struct DeserializedMemFile {
+    version: u16,
+    data: Vec<u8>,
+    offsets: Vec<u32>,
+    #[serde(default)]
+    tags: Option<Vec<u32>>,
+}
+
+impl Serialize for MemFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedMemFile {
+            version: self.version(),
+            data: &self.data,
+            offsets: self.index.offsets(),
+            tags: self.tags.as_deref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MemFile {
+    /// Deserializes a `MemFile`, rejecting unknown versions of the layout
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let wire = DeserializedMemFile::deserialize(deserializer)?;
+        let has_tags = check_version(wire.version).map_err(de::Error::custom)?;
+        if has_tags != wire.tags.is_some() {
+            return Err(de::Error::custom(Error::MalformedIndex));
+        }
+        Self::from_parts(wire.data, wire.offsets, wire.tags).map_err(de::Error::custom)
+    }
+}
+
+impl Indexable for MemFile {
+    #[inline]
+    fn get_index(&self) -> &Index {
+        &self.index
+    }
 }
 
 impl<I: AsRef<[u8]>> Extend<I> for MemFile {
@@ -116,6 +471,74 @@ impl<U: Iterator<Item = impl AsRef<[u8]>>> From<U> for MemFile {
     }
 }
 
+impl<T: Anyable> TryFrom<&CloneableIndexedReader<T>> for MemFile {
+    type Error = Error;
+
+    /// Creates a new `MemFile` holding every line of `reader` as entry. The line terminators are
+    /// not part of the entries.
+    ///
+    /// Returns `Error::InvalidIndex` if the index of `reader` points outside of its data
+    fn try_from(reader: &CloneableIndexedReader<T>) -> Result<Self, Self::Error> {
+        let data = reader
+            .data
+            .as_ref()
+            .get(reader.get_index_byte_len()..)
+            .unwrap_or_default();
+
+        let delimiter = reader.get_index().delimiter();
+
+        let mut new = MemFile::with_capacity(data.len());
+        for line in 0..reader.total_lines() {
+            let span = reader.line_span(line)?;
+            let content = data
+                .get(span.start as usize..span.end as usize)
+                .ok_or(Error::InvalidIndex { line })?;
+            new.insert(trim_terminator(content, delimiter));
+        }
+        Ok(new)
+    }
+}
+
+impl<T: Anyable> TryFrom<CloneableIndexedReader<T>> for MemFile {
+    type Error = Error;
+
+    /// Creates a new `MemFile` holding every line of `reader` as entry, see
+    /// `TryFrom<&CloneableIndexedReader>`
+    #[inline]
+    fn try_from(reader: CloneableIndexedReader<T>) -> Result<Self, Self::Error> {
+        Self::try_from(&reader)
+    }
+}
+
+impl TryFrom<MemFile> for IndexedString {
+    type Error = Error;
+
+    /// Creates an `IndexedString` using every entry as line. Each line gets terminated by a `\n`.
+    /// Returns an error if an entry contains a `\n` or isn't valid UTF-8.
+    fn try_from(file: MemFile) -> Result<Self, Self::Error> {
+        let mut data = Vec::with_capacity(file.raw_len() + file.len());
+        let mut offsets = Vec::with_capacity(file.len());
+
+        for (pos, entry) in file.iter().enumerate() {
+            if entry.contains(&b'\n') {
+                return Err(Error::ContainsNewline { entry: pos });
+            }
+
+            let start =
+                u32::try_from(data.len()).map_err(|_| Error::OffsetOverflow { line: pos })?;
+            offsets.push(start);
+            data.extend_from_slice(entry);
+            data.push(b'\n');
+        }
+
+        let index = Index::new(offsets).zero_len();
+        Ok(IndexedString::new_custom(
+            String::from_utf8(data)?,
+            Arc::new(index),
+        ))
+    }
+}
+
 pub struct MemFileIter<'a> {
     file: &'a MemFile,
     pos: usize,
@@ -139,12 +562,28 @@ impl<'a> Iterator for MemFileIter<'a> {
     }
 }
 
+impl PartialEq<[u8]> for MemFile {
+    /// Compares the entries with `other`, in which every entry is terminated by a `\n`. This is
+    /// the data of the `IndexedString` created by `IndexedString::try_from`.
+    fn eq(&self, other: &[u8]) -> bool {
+        let mut rest = other;
+        for entry in self.iter() {
+            match rest.strip_prefix(entry).and_then(|i| i.strip_prefix(b"\n")) {
+                Some(next) => rest = next,
+                None => return false,
+            }
+        }
+        rest.is_empty()
+    }
+}
+
 impl Default for MemFile {
     #[inline]
     fn default() -> Self {
         Self {
             data: Default::default(),
             index: Default::default(),
+            tags: None,
         }
     }
 }
@@ -164,6 +603,199 @@ mod tests {
         ]
     }
 
+    #[test]
+    fn test_sort_unstable() {
+        let mut m_file: MemFile = ["c", "", "b", "a", "b"].iter().into();
+        m_file.sort_unstable();
+        let entries: Vec<_> = m_file.iter().collect();
+        assert_eq!(entries, [&b""[..], b"a", b"b", b"b", b"c"]);
+
+        m_file.sort_unstable_by(|a, b| b.cmp(a));
+        let entries: Vec<_> = m_file.iter().collect();
+        assert_eq!(entries, [&b"c"[..], b"b", b"b", b"a", b""]);
+    }
+
+    #[test]
+    fn test_stable_encoding() {
+        let m_file: MemFile = ["first", "", "音楽好き", "last"].iter().into();
+
+        // The fixture must keep decoding in future releases
+        let fixture = include_bytes!("../testfiles/mem_file_v1.bin");
+        assert_eq!(m_file.to_bytes(), fixture);
+        let decoded = MemFile::from_bytes(fixture).unwrap();
+        assert_eq!(
+            decoded.iter().collect::<Vec<_>>(),
+            m_file.iter().collect::<Vec<_>>()
+        );
+
+        let json = serde_json::to_string(&m_file).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"data":[102,105,114,115,116,233,159,179,230,165,189,229,165,189,227,129,141,108,97,115,116],"offsets":[0,5,5,17]}"#
+        );
+        let decoded: MemFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.to_bytes(), fixture);
+
+        let mut unknown = fixture.to_vec();
+        unknown[0] = 2;
+        assert!(matches!(
+            MemFile::from_bytes(&unknown),
+            Err(Error::UnsupportedFormat { version: 2 })
+        ));
+        let err =
+            serde_json::from_str::<MemFile>(&json.replace(r#""version":1"#, r#""version":2"#))
+                .unwrap_err();
+        assert!(err.to_string().contains("unsupported format version 2"));
+
+        assert!(matches!(
+            MemFile::from_bytes(&fixture[..fixture.len() - 1]),
+            Err(Error::MalformedIndex)
+        ));
+        let invalid = json.replace("[0,5,5,17]", "[0,5,4,17]");
+        assert!(serde_json::from_str::<MemFile>(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_from_borrowed_reader() {
+        let reader = IndexedString::new_raw("a\nb\n\nc").unwrap();
+        let mut m_file = MemFile::try_from(&reader).unwrap();
+        m_file.extend(["d", "e"].iter().copied());
+
+        let entries: Vec<_> = m_file.iter().collect();
+        assert_eq!(entries, [&b"a"[..], b"b", b"", b"c", b"d", b"e"]);
+        assert_eq!(m_file.as_index().offsets(), [0, 1, 2, 2, 3, 4]);
+
+        // Offsets pointing past the data are rejected
+        let index = Arc::new(Index::new(vec![0, 10]).zero_len());
+        let reader = IndexedString::new_custom("a\nb\n", index);
+        assert!(matches!(
+            MemFile::try_from(&reader),
+            Err(Error::InvalidIndex { line: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_eq_bytes() {
+        let m_file: MemFile = ["a", "", "b\nc"].iter().into();
+        assert_eq!(m_file, *b"a\n\nb\nc\n".as_slice());
+        assert_ne!(m_file, *b"a\n\nb\nc".as_slice());
+        assert_ne!(m_file, *b"a\n\nb\nc\nd\n".as_slice());
+        assert_ne!(m_file, *b"a\nb\nc\n".as_slice());
+        assert_eq!(MemFile::new(), *b"".as_slice());
+        assert_ne!(MemFile::new(), *b"\n".as_slice());
+
+        // Matches the data of the IndexedString created from the entries
+        let m_file: MemFile = test_data().iter().into();
+        let string = IndexedString::try_from(m_file.clone()).unwrap();
+        assert_eq!(m_file, *string.content());
+    }
+
+    #[test]
+    fn test_swap() {
+        let entries = ["a", "bcd", "", "ef", "ghi", "j"];
+        let mut m_file: MemFile = entries.iter().into();
+
+        let mut expected = entries.to_vec();
+        for (a, b) in [
+            (0, 5),
+            (1, 4),
+            (4, 1),
+            (1, 2),
+            (2, 3),
+            (0, 4),
+            (3, 3),
+            (5, 0),
+        ] {
+            m_file.swap(a, b);
+            expected.swap(a, b);
+            let swapped: Vec<_> = m_file.iter().collect();
+            let expected: Vec<_> = expected.iter().map(|i| i.as_bytes()).collect();
+            assert_eq!(swapped, expected, "swap({}, {})", a, b);
+        }
+        assert_eq!(m_file.raw_len(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_swap_out_of_bounds() {
+        let mut m_file: MemFile = ["a", "b"].iter().into();
+        m_file.swap(0, 2);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut m_file: MemFile = ["a", "bc", "", "def"].iter().into();
+        m_file.reorder(&[3, 0, 2, 1]).unwrap();
+        let entries: Vec<_> = m_file.iter().collect();
+        assert_eq!(entries, [&b"def"[..], b"a", b"", b"bc"]);
+        assert_eq!(m_file.as_index().offsets(), [0, 3, 4, 4]);
+
+        for invalid in [
+            &[0, 1, 2][..],
+            &[0, 1, 2, 3, 0],
+            &[0, 1, 1, 2],
+            &[0, 1, 2, 4],
+        ] {
+            assert!(matches!(
+                m_file.reorder(invalid),
+                Err(Error::InvalidPermutation { len: 4 })
+            ));
+        }
+        let entries: Vec<_> = m_file.iter().collect();
+        assert_eq!(entries, [&b"def"[..], b"a", b"", b"bc"]);
+
+        let mut empty = MemFile::new();
+        empty.reorder(&[]).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut m_file: MemFile = ["c", "a"].iter().into();
+        assert!(!m_file.has_tags());
+        assert_eq!(m_file.tag(0), None);
+
+        m_file.insert_tagged(b"b", 7);
+        assert!(m_file.has_tags());
+        assert_eq!(m_file.tag(0), Some(0));
+        assert_eq!(m_file.tag(2), Some(7));
+        assert_eq!(m_file.set_tag(0, 3), Some(()));
+        assert_eq!(m_file.set_tag(3, 3), None);
+        m_file.insert(b"dd");
+        assert_eq!(m_file.tag(3), Some(0));
+
+        // Tags move with their entries
+        m_file.replace(1, b"aaaa").unwrap();
+        m_file.sort_unstable();
+        let tags: Vec<_> = (0..4).map(|i| m_file.tag(i).unwrap()).collect();
+        assert_eq!(
+            m_file.iter().collect::<Vec<_>>(),
+            [&b"aaaa"[..], b"b", b"c", b"dd"]
+        );
+        assert_eq!(tags, [0, 7, 3, 0]);
+        m_file.swap(0, 2);
+        assert_eq!((m_file.tag(0), m_file.tag(2)), (Some(3), Some(0)));
+        m_file.reorder(&[1, 0, 2, 3]).unwrap();
+        assert_eq!((m_file.tag(0), m_file.tag(1)), (Some(7), Some(3)));
+
+        let bytes = m_file.to_bytes();
+        assert_eq!(bytes[..2], (MEM_FILE_VERSION | MEM_FILE_TAGS).to_le_bytes());
+        let decoded = MemFile::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.tag(0), Some(7));
+        assert!(matches!(
+            MemFile::from_bytes(&bytes[..bytes.len() - 4]),
+            Err(Error::MalformedIndex)
+        ));
+
+        let json = serde_json::to_string(&m_file).unwrap();
+        assert!(json.ends_with(r#""tags":[7,3,0,0]}"#));
+        let decoded: MemFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        let missing = json.replace(r#","tags":[7,3,0,0]"#, "");
+        assert!(serde_json::from_str::<MemFile>(&missing).is_err());
+    }
+
     #[test]
     fn test_mem_file_unicode() {
         test_entries(test_data());
@@ -249,6 +881,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_indexed_string_conversion() {
+        for input_file in &["simple", "LICENSE", "input1"] {
+            let content = read_to_string(format!("./testfiles/{}", input_file)).unwrap();
+            let lines: Vec<_> = content.lines().collect();
+
+            let file = MemFile::try_from(IndexedString::new_raw(&content).unwrap()).unwrap();
+            assert_eq!(file.len(), lines.len());
+            for (entry, line) in file.iter().zip(lines.iter()) {
+                assert_eq!(entry, line.as_bytes());
+            }
+
+            let mut indexed = IndexedString::try_from(file).unwrap();
+            let mut buf = Vec::new();
+            indexed.reader.read_all(&mut buf).unwrap();
+            assert_eq!(buf, content.as_bytes());
+        }
+
+        // Files with embedded index
+        let mut data = Vec::new();
+        IndexedString::new_raw("a\nb\nc")
+            .unwrap()
+            .write_to(&mut data)
+            .unwrap();
+        let file = MemFile::try_from(CloneableIndexedReader::new(data).unwrap()).unwrap();
+        assert_eq!(file.iter().collect::<Vec<_>>(), vec![b"a", b"b", b"c"]);
+
+        let file = MemFile::from(["a", "b\nc"].iter());
+        assert!(matches!(
+            IndexedString::try_from(file),
+            Err(Error::ContainsNewline { entry: 1 })
+        ));
+
+        let mut file = MemFile::new();
+        file.insert(&[0xff, 0xfe]);
+        assert!(IndexedString::try_from(file).is_err());
+    }
+
     fn test_from_iter(entries: &[&str]) {
         let new_file = MemFile::from(entries.iter());
 
@@ -0,0 +1,415 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    convert::TryFrom,
+    fs,
+    io::{self, BufWriter, Write},
+    ops::ControlFlow,
+};
+
+use crate::{
+    collate::Collation,
+    error::Error,
+    index::{Index, DEFAULT_DELIMITER},
+    temp::TempFile,
+    trim_terminator, Fnv1a, IndexedWriter, ReadByLine, Result,
+};
+
+/// Defines how lines which occur multiple times are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicates {
+    /// Write every line
+    Keep,
+    /// Write lines occurring multiple times only once
+    Remove,
+}
+
+/// Statistics about a merge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Amount of lines read from all inputs
+    pub lines_read: usize,
+    /// Amount of lines written to the output
+    pub lines_written: usize,
+    /// Amount of lines which were skipped since they were duplicates
+    pub duplicates_removed: usize,
+    /// Amount of bytes written to the output, including header and index
+    pub bytes_written: usize,
+}
+
+/// Statistics about a deduplication
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Amount of lines written to the output
+    pub kept: usize,
+    /// Amount of lines which were skipped since they were duplicates
+    pub dropped: usize,
+    /// Amount of bytes written to the output, including header and index
+    pub bytes_written: usize,
+}
+
+/// Merges the lines of all sorted `inputs` into `writer`, producing sorted indexed data which can
+/// be opened using `File::open`. Lines are compared without their terminators and every written
+/// line gets terminated, including the last line of each input. Equal lines of different inputs
+/// are written in the order of the inputs. The output uses the delimiter of the first input.
+///
+/// Only one line per input is kept in memory. Since the index has to be written before the data,
+/// the merged data is buffered in a temporary file.
+#[inline]
+pub fn merge_sorted<R: ReadByLine, W: Write>(
+    inputs: &mut [R],
+    writer: &mut W,
+    duplicates: Duplicates,
+) -> Result<MergeStats> {
+    merge_sorted_by(inputs, writer, duplicates, |a, b| a.cmp(b))
+}
+
+/// Merges the lines of all `inputs` sorted according to `compare` into `writer`. Lines for which
+/// `compare` returns `Ordering::Equal` are considered duplicates. See `merge_sorted`.
+#[inline]
+pub fn merge_sorted_by<R, W, F>(
+    inputs: &mut [R],
+    writer: &mut W,
+    duplicates: Duplicates,
+    compare: F,
+) -> Result<MergeStats>
+where
+    R: ReadByLine,
+    W: Write,
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    merge_sorted_with(inputs, writer, duplicates, compare, None)
+}
+
+/// Merges like `merge_sorted_by` and records `collation` in the header of the output
+pub(crate) fn merge_sorted_with<R, W, F>(
+    inputs: &mut [R],
+    writer: &mut W,
+    duplicates: Duplicates,
+    compare: F,
+    collation: Option<Collation>,
+) -> Result<MergeStats>
+where
+    R: ReadByLine,
+    W: Write,
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    let delimiter = inputs
+        .first()
+        .map(|i| i.get_index().delimiter())
+        .unwrap_or(DEFAULT_DELIMITER);
+
+    let (temp, data) = TempFile::create("merge")?;
+    let mut data = BufWriter::new(data);
+
+    let mut stats = MergeStats::default();
+    let mut offsets = Vec::new();
+    let mut offset = 0u64;
+    let mut hasher = Fnv1a::new();
+
+    let mut heap = BinaryHeap::with_capacity(inputs.len());
+    let mut next_lines = vec![0; inputs.len()];
+    for input in 0..inputs.len() {
+        push_next(inputs, &mut next_lines, input, &compare, &mut heap)?;
+    }
+
+    let mut last: Option<Vec<u8>> = None;
+    while let Some(Reverse(HeapEntry { line, input, .. })) = heap.pop() {
+        stats.lines_read += 1;
+        push_next(inputs, &mut next_lines, input, &compare, &mut heap)?;
+
+        let duplicate = matches!(&last, Some(last) if compare(last, &line) == Ordering::Equal);
+        if duplicates == Duplicates::Remove && duplicate {
+            stats.duplicates_removed += 1;
+            continue;
+        }
+
+        let pos = u32::try_from(offset).map_err(|_| Error::OffsetOverflow {
+            line: offsets.len(),
+        })?;
+        offsets.push(pos);
+
+        data.write_all(&line)?;
+        data.write_all(&[delimiter])?;
+        hasher.write(&line);
+        hasher.write(&[delimiter]);
+        offset += line.len() as u64 + 1;
+        stats.lines_written += 1;
+
+        last = Some(line);
+    }
+    data.flush()?;
+    drop(data);
+
+    let mut index = Index::new(offsets);
+    index.set_delimiter(delimiter);
+    index.set_collation(collation);
+    let mut header = index.get_header();
+    header.set_content_hash(hasher.finish());
+    let header = header.encode();
+
+    writer.write_all(&header)?;
+    let index_len = index.encode_into(writer)?;
+    let copied = io::copy(&mut fs::File::open(temp.path())?, writer)?;
+
+    stats.bytes_written = header.len() + index_len + copied as usize;
+    Ok(stats)
+}
+
+/// Writes the lines of the sorted `reader` into `writer`, skipping lines which are equal to their
+/// previous line. The output is indexed data which can be opened using `File::open`. The order of
+/// the lines is kept and every written line gets terminated using the delimiter of `reader`.
+///
+/// The lines are read once and written using `IndexedWriter`, so the header and the index are
+/// stored behind the data. Apart from the offsets of the written lines, only the previous line is
+/// kept in memory.
+#[inline]
+pub fn dedup_sorted_to<R: ReadByLine, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<DedupStats> {
+    dedup_sorted_by_key_to(reader, writer, |line| line)
+}
+
+/// Like `dedup_sorted_to` but skips lines whose key is equal to the key of their previous line.
+/// `key` extracts the part of a line, without terminator, which is compared. Of each run of lines
+/// with equal keys, only the first line is written.
+pub fn dedup_sorted_by_key_to<R, W, K>(
+    reader: &mut R,
+    writer: &mut W,
+    mut key: K,
+) -> Result<DedupStats>
+where
+    R: ReadByLine,
+    W: Write,
+    K: FnMut(&[u8]) -> &[u8],
+{
+    let delimiter = reader.get_index().delimiter();
+    let mut out = IndexedWriter::with_delimiter(BufWriter::new(writer), delimiter);
+    let (kept, dropped) = for_each_unique(reader, &mut key, |line| out.write_line(line))?;
+    let (_, bytes_written) = out.finish_with_len()?;
+
+    Ok(DedupStats {
+        kept,
+        dropped,
+        bytes_written,
+    })
+}
+
+/// Calls `f` with every line of `reader` whose key differs from the key of its previous line.
+/// Returns the amount of lines passed to `f` and the amount of skipped lines.
+fn for_each_unique<R, K, F>(reader: &mut R, key: &mut K, mut f: F) -> Result<(usize, usize)>
+where
+    R: ReadByLine,
+    K: FnMut(&[u8]) -> &[u8],
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut last: Option<Vec<u8>> = None;
+    let (mut kept, mut dropped) = (0, 0);
+    let mut res = Ok(());
+
+    reader.for_each_line(|_, line| {
+        let current = key(line);
+        if last.as_deref() == Some(current) {
+            dropped += 1;
+            return ControlFlow::Continue(());
+        }
+
+        let last = last.get_or_insert_with(Vec::new);
+        last.clear();
+        last.extend_from_slice(current);
+
+        res = f(line);
+        if res.is_err() {
+            return ControlFlow::Break(());
+        }
+        kept += 1;
+        ControlFlow::Continue(())
+    })?;
+
+    res.map(|_| (kept, dropped))
+}
+
+/// The current line of an input. Entries are ordered by their line using `compare` and by the
+/// position of their input, so equal lines are merged in the order of the inputs.
+struct HeapEntry<'a, F> {
+    line: Vec<u8>,
+    input: usize,
+    compare: &'a F,
+}
+
+impl<'a, F: Fn(&[u8], &[u8]) -> Ordering> Ord for HeapEntry<'a, F> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&self.line, &other.line).then(self.input.cmp(&other.input))
+    }
+}
+
+impl<'a, F: Fn(&[u8], &[u8]) -> Ordering> PartialOrd for HeapEntry<'a, F> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, F: Fn(&[u8], &[u8]) -> Ordering> PartialEq for HeapEntry<'a, F> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, F: Fn(&[u8], &[u8]) -> Ordering> Eq for HeapEntry<'a, F> {}
+
+/// Reads the next line of `inputs[input]` without its terminator and pushes it onto `heap`
+fn push_next<'a, R, F>(
+    inputs: &mut [R],
+    next_lines: &mut [usize],
+    input: usize,
+    compare: &'a F,
+    heap: &mut BinaryHeap<Reverse<HeapEntry<'a, F>>>,
+) -> Result<()>
+where
+    R: ReadByLine,
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    let reader = &mut inputs[input];
+    let line = next_lines[input];
+    if line >= reader.total_lines() {
+        return Ok(());
+    }
+
+    let mut buf = Vec::new();
+    let read = reader.read_line_raw(line, &mut buf)?;
+    buf.truncate(trim_terminator(&buf[..read], reader.get_index().delimiter()).len());
+
+    heap.push(Reverse(HeapEntry {
+        line: buf,
+        input,
+        compare,
+    }));
+    next_lines[input] += 1;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::CloneableIndexedReader, File, Indexable, IndexedString};
+
+    fn merge(duplicates: Duplicates) -> (MergeStats, Vec<String>) {
+        let mut inputs = vec![
+            IndexedString::new_raw("a\nc\ne\ne\n").unwrap(),
+            IndexedString::new_raw("b\nc\nf").unwrap(),
+            IndexedString::new_raw("").unwrap(),
+            IndexedString::new_raw("a\nd\n").unwrap(),
+        ];
+
+        let temp = TempFile::new("merge_test");
+        let stats = merge_sorted(
+            &mut inputs,
+            &mut fs::File::create(temp.path()).unwrap(),
+            duplicates,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::metadata(temp.path()).unwrap().len(),
+            stats.bytes_written as u64
+        );
+
+        let mut file = File::open(temp.path()).unwrap();
+        assert!(file.is_sorted().unwrap());
+        let lines = (0..file.total_lines())
+            .map(|i| file.read_line(i).unwrap())
+            .collect();
+        (stats, lines)
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let (stats, lines) = merge(Duplicates::Keep);
+        assert_eq!(
+            lines,
+            ["a\n", "a\n", "b\n", "c\n", "c\n", "d\n", "e\n", "e\n", "f\n"]
+        );
+        assert_eq!(stats.lines_read, 9);
+        assert_eq!(stats.lines_written, 9);
+        assert_eq!(stats.duplicates_removed, 0);
+
+        let (stats, lines) = merge(Duplicates::Remove);
+        assert_eq!(lines, ["a\n", "b\n", "c\n", "d\n", "e\n", "f\n"]);
+        assert_eq!(stats.lines_read, 9);
+        assert_eq!(stats.lines_written, 6);
+        assert_eq!(stats.duplicates_removed, 3);
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        let mut reader = IndexedString::new_raw("a\na\nb\nc\nc\nc\nd").unwrap();
+
+        let temp = TempFile::new("dedup_test");
+        let stats =
+            dedup_sorted_to(&mut reader, &mut fs::File::create(temp.path()).unwrap()).unwrap();
+        assert_eq!(stats.kept, 4);
+        assert_eq!(stats.dropped, 3);
+        assert_eq!(
+            fs::metadata(temp.path()).unwrap().len(),
+            stats.bytes_written as u64
+        );
+
+        let mut file = File::open(temp.path()).unwrap();
+        let lines: Vec<_> = (0..file.total_lines())
+            .map(|i| file.read_line(i).unwrap())
+            .collect();
+        assert_eq!(lines, ["a\n", "b\n", "c\n", "d\n"]);
+
+        // Keeps the first line of every run with equal keys
+        let mut reader = IndexedString::new_raw("1,x\n1,y\n2,z\n1,w\n").unwrap();
+        let mut out = Vec::new();
+        let stats = dedup_sorted_by_key_to(&mut reader, &mut out, |line| &line[..1]).unwrap();
+        assert_eq!((stats.kept, stats.dropped), (3, 1));
+        assert_eq!(stats.bytes_written, out.len());
+        assert!(out.ends_with(&crate::index::TRAILER_MAGIC));
+        let mut deduped = CloneableIndexedReader::new(out).unwrap();
+        assert_eq!(deduped, *b"1,x\n2,z\n1,w\n".as_slice());
+        assert_eq!(deduped.read_line(2).unwrap(), "1,w\n");
+
+        // Custom delimiters are kept
+        let data = "b\0b\0c\0".to_string();
+        let mut cursor = std::io::BufReader::new(std::io::Cursor::new(data.clone()));
+        let index = crate::Builder::new()
+            .delimiter(b'\0')
+            .build_index(&mut cursor)
+            .unwrap();
+        let mut reader = IndexedString::new_custom(data, std::sync::Arc::new(index.zero_len()));
+        let mut out = Vec::new();
+        dedup_sorted_to(&mut reader, &mut out).unwrap();
+        let deduped = CloneableIndexedReader::new(out).unwrap();
+        assert_eq!(deduped.get_index().delimiter(), b'\0');
+        assert_eq!(deduped, *b"b\0c\0".as_slice());
+
+        let mut out = Vec::new();
+        let stats = dedup_sorted_to(&mut IndexedString::new_raw("").unwrap(), &mut out).unwrap();
+        assert_eq!((stats.kept, stats.dropped), (0, 0));
+        assert_eq!(CloneableIndexedReader::new(out).unwrap().total_lines(), 0);
+    }
+
+    #[test]
+    fn test_merge_sorted_by() {
+        let mut inputs = vec![
+            IndexedString::new_raw("C\nb\na").unwrap(),
+            IndexedString::new_raw("c\nB").unwrap(),
+        ];
+
+        let mut out = Vec::new();
+        let stats = merge_sorted_by(&mut inputs, &mut out, Duplicates::Keep, |a, b| {
+            b.to_ascii_lowercase().cmp(&a.to_ascii_lowercase())
+        })
+        .unwrap();
+        assert_eq!(stats.lines_written, 5);
+
+        let mut merged = CloneableIndexedReader::new(out).unwrap();
+        let lines: Vec<_> = (0..5).map(|i| merged.read_line(i).unwrap()).collect();
+        assert_eq!(lines, ["C\n", "c\n", "b\n", "B\n", "a\n"]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    error::Error,
+    file, hash_reader,
+    index::{Header, Index},
+    Result,
+};
+
+/// Layouts of indexed files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FormatVersion {
+    /// The amount of lines followed by the index and the data, without magic bytes
+    Legacy,
+    /// The versioned header, supporting optional features like custom delimiters and hashes
+    V1,
+}
+
+impl FormatVersion {
+    /// The version written by this version of the library
+    pub const LATEST: Self = Self::V1;
+
+    /// Returns the version number stored in the header
+    #[inline]
+    pub fn number(self) -> u16 {
+        match self {
+            Self::Legacy => 0,
+            Self::V1 => 1,
+        }
+    }
+
+    #[inline]
+    fn from_number(version: u16) -> Result<Self> {
+        match version {
+            0 => Ok(Self::Legacy),
+            1 => Ok(Self::V1),
+            version => Err(Error::UnsupportedFormat { version }),
+        }
+    }
+}
+
+/// Returns the format version of the indexed file at `path`
+pub fn detect_version<P: AsRef<Path>>(path: P) -> Result<FormatVersion> {
+    let path = path.as_ref();
+    let (mut reader, _) = file::open_buffered(path)?;
+    let header = match Index::parse_trailing(&mut reader).map_err(|e| e.with_path(path))? {
+        Some((_, section)) => section.header,
+        None => Header::decode(&mut reader).map_err(|e| e.with_path(path))?,
+    };
+    FormatVersion::from_number(header.version())
+}
+
+/// Rewrites the indexed file at `input` in the layout of `target` into `output`. The index is
+/// taken over from `input`, so the data isn't scanned for newlines. Upgrading to `V1` adds a hash
+/// of the data, which can be checked using `File::verify`. `input` and `output` must not be the
+/// same file. Files storing their index behind the data, see `IndexedWriter`, are rewritten with
+/// the index in front of the data.
+///
+/// Returns `Error::UnsupportedFormat` if `target` is older than the layout of `input`, since
+/// older layouts can't hold all information of newer ones.
+pub fn upgrade<P: AsRef<Path>>(input: P, output: P, target: FormatVersion) -> Result<()> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let (mut reader, _) = file::open_buffered(input)?;
+
+    let read_input = |reader: &mut io::BufReader<fs::File>| -> Result<(Header, Index, u64, u64)> {
+        let (header, index, data_len) = match Index::parse_trailing(reader)? {
+            Some((index, section)) => {
+                let mut header = section.header;
+                header.set_index_at_end(false);
+                (header, index, section.len)
+            }
+            None => {
+                reader.seek(SeekFrom::Start(0))?;
+                let header = Header::decode(reader)?;
+                let index = Index::decode(reader, &header)?;
+                let end = reader.seek(SeekFrom::End(0))?;
+                let data_len = end.saturating_sub(index.len_bytes() as u64);
+                (header, index, data_len)
+            }
+        };
+
+        let data_start = index.len_bytes() as u64;
+        reader.seek(SeekFrom::Start(data_start))?;
+        let hash = hash_reader(&mut reader.by_ref().take(data_len))?;
+        reader.seek(SeekFrom::Start(data_start))?;
+        Ok((header, index, hash, data_len))
+    };
+    let (mut header, index, hash, data_len) =
+        read_input(&mut reader).map_err(|e| e.with_path(input))?;
+
+    if target < FormatVersion::from_number(header.version())? {
+        return Err(Error::UnsupportedFormat {
+            version: target.number(),
+        });
+    }
+
+    header.set_version(target.number());
+    if target >= FormatVersion::V1 {
+        header.set_content_hash(hash);
+    }
+
+    let write_output = |reader: &mut io::BufReader<fs::File>| -> Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(output)?);
+        writer.write_all(&header.encode())?;
+        index.encode_into(&mut writer)?;
+        io::copy(&mut reader.take(data_len), &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    };
+    write_output(&mut reader).map_err(|e| e.with_path(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{temp::TempFile, File, Indexable, IndexedWriter, ReadByLine};
+
+    #[test]
+    fn test_upgrade() {
+        let legacy = TempFile::new("migrate_legacy");
+        let upgraded = TempFile::new("migrate_upgraded");
+
+        let index = File::open_raw("./testfiles/LICENSE").unwrap().index_arc();
+        let mut data = (index.len() as u64).to_le_bytes().to_vec();
+        data.extend(index.encode());
+        data.extend(fs::read("./testfiles/LICENSE").unwrap());
+        fs::write(legacy.path(), &data).unwrap();
+
+        assert_eq!(
+            detect_version(legacy.path()).unwrap(),
+            FormatVersion::Legacy
+        );
+        upgrade(legacy.path(), upgraded.path(), FormatVersion::V1).unwrap();
+        assert_eq!(detect_version(upgraded.path()).unwrap(), FormatVersion::V1);
+
+        let mut old = File::open(legacy.path()).unwrap();
+        let mut new = File::open_verified(upgraded.path()).unwrap();
+        assert_eq!(old.total_lines(), new.total_lines());
+        for line in 0..old.total_lines() {
+            assert_eq!(old.read_line(line).unwrap(), new.read_line(line).unwrap());
+        }
+
+        // Downgrading isn't supported
+        assert!(matches!(
+            upgrade(upgraded.path(), legacy.path(), FormatVersion::Legacy),
+            Err(Error::UnsupportedFormat { version: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_index_at_end() {
+        let input = TempFile::new("migrate_at_end");
+        let upgraded = TempFile::new("migrate_at_end_upgraded");
+
+        let mut writer = IndexedWriter::new(Vec::new());
+        for line in ["first", "", "last"] {
+            writer.write_line(line.as_bytes()).unwrap();
+        }
+        fs::write(input.path(), writer.finish().unwrap()).unwrap();
+
+        assert_eq!(detect_version(input.path()).unwrap(), FormatVersion::V1);
+        let index = Index::from_path(input.path()).unwrap();
+        assert_eq!(index.len_bytes(), 0);
+        assert_eq!(index.len(), 3);
+
+        upgrade(input.path(), upgraded.path(), FormatVersion::V1).unwrap();
+        assert_eq!(detect_version(upgraded.path()).unwrap(), FormatVersion::V1);
+        let data = fs::read(upgraded.path()).unwrap();
+        assert!(data.starts_with(&crate::index::MAGIC));
+        assert!(data.ends_with(b"first\n\nlast\n"));
+
+        let mut file = File::open_verified(upgraded.path()).unwrap();
+        assert_ne!(file.get_index().len_bytes(), 0);
+        assert_eq!(file.read_line(2).unwrap(), "last\n");
+        assert_eq!(Index::from_path(upgraded.path()).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Receives events about the reads and seeks of an `IndexedReader`, see
+/// `IndexedReader::set_observer`. All methods do nothing by default.
+pub trait ReadObserver: Send {
+    /// Called after the underlying reader was seeked to the beginning of `line`, or into it for
+    /// partial reads. `bytes_skipped` is the distance of the seek, which is negative for seeks
+    /// backwards and `None` if the previous position of the reader was unknown.
+    #[inline]
+    fn on_seek(&mut self, _line: usize, _bytes_skipped: Option<i64>) {}
+
+    /// Called after `bytes` bytes of `line` were read. Reads spanning multiple lines report their
+    /// first line.
+    #[inline]
+    fn on_read(&mut self, _line: usize, _bytes: usize) {}
+
+    /// Called if the reader was already positioned at the beginning of `line`, so reading it
+    /// doesn't require a seek
+    #[inline]
+    fn on_sequential_hit(&mut self, _line: usize) {}
+}
+
+impl std::fmt::Debug for dyn ReadObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ReadObserver")
+    }
+}
+
+/// A `ReadObserver` counting all events. Clones share their counters, so a clone can be passed
+/// to a reader while the counters are read from the original.
+///
+/// ```
+/// use indexed_file::{observer::CountingObserver, OpenBuilder, ReadByLine};
+/// # use indexed_file::{IndexedString, IndexableFile};
+/// # use std::io::Cursor;
+/// # let mut data = Vec::new();
+/// # IndexedString::new_raw("a\nb\nc\n")?.write_to(&mut data)?;
+///
+/// let mut reader = OpenBuilder::new().open_reader(Cursor::new(data))?;
+/// let counter = CountingObserver::new();
+/// reader.set_observer(Box::new(counter.clone()));
+///
+/// for line in 0..3 {
+///     reader.read_line(line)?;
+/// }
+/// assert_eq!(counter.reads(), 3);
+/// assert_eq!(counter.seeks(), 1);
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CountingObserver {
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    seeks: AtomicU64,
+    bytes_skipped: AtomicU64,
+    reads: AtomicU64,
+    bytes_read: AtomicU64,
+    sequential_hits: AtomicU64,
+}
+
+impl CountingObserver {
+    /// Creates a new `CountingObserver` with all counters set to 0
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the amount of seeks
+    #[inline]
+    pub fn seeks(&self) -> u64 {
+        self.counts.seeks.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sum of the distances of all seeks whose distance is known, regardless of
+    /// their direction
+    #[inline]
+    pub fn bytes_skipped(&self) -> u64 {
+        self.counts.bytes_skipped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of reads
+    #[inline]
+    pub fn reads(&self) -> u64 {
+        self.counts.reads.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of bytes read
+    #[inline]
+    pub fn bytes_read(&self) -> u64 {
+        self.counts.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of lines which could be read without a seek
+    #[inline]
+    pub fn sequential_hits(&self) -> u64 {
+        self.counts.sequential_hits.load(Ordering::Relaxed)
+    }
+
+    /// Sets all counters to 0
+    pub fn reset(&self) {
+        let counts = &self.counts;
+        for counter in [
+            &counts.seeks,
+            &counts.bytes_skipped,
+            &counts.reads,
+            &counts.bytes_read,
+            &counts.sequential_hits,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ReadObserver for CountingObserver {
+    #[inline]
+    fn on_seek(&mut self, _line: usize, bytes_skipped: Option<i64>) {
+        self.counts.seeks.fetch_add(1, Ordering::Relaxed);
+        let skipped = bytes_skipped.map(|i| i.unsigned_abs()).unwrap_or(0);
+        self.counts
+            .bytes_skipped
+            .fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn on_read(&mut self, _line: usize, bytes: usize) {
+        self.counts.reads.fetch_add(1, Ordering::Relaxed);
+        self.counts
+            .bytes_read
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn on_sequential_hit(&mut self, _line: usize) {
+        self.counts.sequential_hits.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, Indexable, IndexableFile, ReadByLine};
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_counting_observer() {
+        let mut file = File::open_raw("./testfiles/LICENSE").unwrap();
+        let counter = CountingObserver::new();
+        file.set_observer(Box::new(counter.clone()));
+
+        // The reader starts at the first line and sequential reads don't need to seek, also while
+        // reading ahead
+        let lines = file.total_lines() as u64;
+        file.for_each_line(|_, _| ControlFlow::Continue(()))
+            .unwrap();
+        assert_eq!(counter.seeks(), 0);
+        assert_eq!(counter.reads(), lines);
+        assert_eq!(counter.sequential_hits(), lines);
+        assert_eq!(counter.bytes_read(), file.data_len());
+
+        counter.reset();
+        assert_eq!(counter.reads(), 0);
+        let (line3, line4, line10) = (
+            file.line_span(3).unwrap(),
+            file.line_span(4).unwrap(),
+            file.line_span(10).unwrap(),
+        );
+        file.read_line(10).unwrap();
+        file.read_line(3).unwrap();
+        file.read_line(4).unwrap();
+        assert_eq!(counter.seeks(), 2);
+        assert_eq!(counter.sequential_hits(), 1);
+        assert_eq!(counter.reads(), 3);
+        let skipped = (file.data_len() - line10.start) + (line10.end - line3.start);
+        assert_eq!(counter.bytes_skipped(), skipped);
+        let read = [&line3, &line4, &line10]
+            .iter()
+            .map(|i| i.end - i.start)
+            .sum::<u64>();
+        assert_eq!(counter.bytes_read(), read);
+
+        // Partial reads seek into the line
+        let mut buf = [0; 4];
+        file.read_line_at(20, 2, &mut buf).unwrap();
+        assert_eq!(counter.seeks(), 3);
+        assert_eq!(counter.bytes_read(), read + 4);
+        let line20 = file.line_span(20).unwrap();
+        assert_eq!(
+            counter.bytes_skipped(),
+            skipped + line20.start + 2 - line4.end
+        );
+    }
+}
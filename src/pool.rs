@@ -0,0 +1,190 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display},
+    mem,
+    ops::{Deref, DerefMut},
+};
+
+/// Maximum amount of buffers kept per thread
+const MAX_POOLED_BUFS: usize = 16;
+
+/// Buffers with a larger capacity are dropped instead of being returned into the pool, so a
+/// single long line doesn't keep its memory alive
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static POOL: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A thread-local freelist of buffers, used to read lines without allocating a new buffer for
+/// every read
+pub(crate) struct BufPool;
+
+impl BufPool {
+    /// Takes an empty buffer from the pool of the current thread or allocates a new one if the
+    /// pool is empty. The buffer is returned into the pool when the guard gets dropped.
+    #[inline]
+    pub(crate) fn get() -> PooledBuf {
+        let buf = POOL
+            .with(|pool| pool.borrow_mut().pop())
+            .unwrap_or_default();
+        PooledBuf(buf)
+    }
+
+    /// Returns `buf` into the pool of the current thread
+    #[inline]
+    fn put(mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+
+        buf.clear();
+        // The pool is unavailable while the thread gets destroyed. The buffer gets dropped then.
+        let _ = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED_BUFS {
+                pool.push(buf);
+            }
+        });
+    }
+
+    /// Returns the amount of buffers in the pool of the current thread
+    #[cfg(test)]
+    fn len() -> usize {
+        POOL.with(|pool| pool.borrow().len())
+    }
+}
+
+/// A buffer taken from the `BufPool`, which is returned into the pool when dropped
+#[derive(Debug, Default)]
+pub(crate) struct PooledBuf(Vec<u8>);
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuf {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for PooledBuf {
+    #[inline]
+    fn drop(&mut self) {
+        BufPool::put(mem::take(&mut self.0));
+    }
+}
+
+/// A line read using `ReadByLine::read_line_pooled`. Its buffer is returned into a thread-local
+/// pool when dropped and reused by the next pooled read on the same thread.
+#[derive(Default)]
+pub struct PooledLine(String);
+
+impl PooledLine {
+    #[inline]
+    pub(crate) fn new(line: String) -> Self {
+        Self(line)
+    }
+
+    /// Takes the line out of the pool, so it can be kept without holding the guard
+    #[inline]
+    pub fn into_string(mut self) -> String {
+        mem::take(&mut self.0)
+    }
+}
+
+impl Deref for PooledLine {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<str> for PooledLine {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for PooledLine {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for PooledLine {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl Drop for PooledLine {
+    #[inline]
+    fn drop(&mut self) {
+        BufPool::put(mem::take(&mut self.0).into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IndexedString, ReadByLine};
+
+    /// Empties the pool of the current thread, which is shared by all tests running on it
+    fn clear_pool() {
+        POOL.with(|pool| pool.borrow_mut().clear());
+    }
+
+    #[test]
+    fn test_pool_reuse() {
+        clear_pool();
+        let mut reader = IndexedString::new_raw("first\nsecond\n").unwrap();
+
+        let line = reader.read_line_pooled(1).unwrap();
+        assert_eq!(&*line, "second\n");
+        let ptr = line.as_ptr();
+        drop(line);
+        assert_eq!(BufPool::len(), 1);
+
+        // The buffer of the previous line gets reused
+        let line = reader.read_line_pooled(0).unwrap();
+        assert_eq!(&*line, "first\n");
+        assert_eq!(line.as_ptr(), ptr);
+        assert_eq!(BufPool::len(), 0);
+
+        // Taken lines don't return into the pool
+        assert_eq!(line.into_string(), "first\n");
+        assert_eq!(BufPool::len(), 0);
+    }
+
+    #[test]
+    fn test_pool_limits() {
+        clear_pool();
+        let bufs: Vec<_> = (0..MAX_POOLED_BUFS + 4)
+            .map(|_| {
+                let mut buf = BufPool::get();
+                buf.extend_from_slice(b"data");
+                buf
+            })
+            .collect();
+        drop(bufs);
+        assert_eq!(BufPool::len(), MAX_POOLED_BUFS);
+
+        let mut large = BufPool::get();
+        large.reserve(MAX_POOLED_CAPACITY * 2);
+        drop(large);
+        assert_eq!(BufPool::len(), MAX_POOLED_BUFS - 1);
+    }
+}
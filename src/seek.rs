@@ -0,0 +1,119 @@
+use crate::{error::Error, IndexableFile, Result};
+
+/// Possible positions to seek to in line units, similar to `std::io::SeekFrom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSeekFrom {
+    /// Seeks to the given line
+    Start(usize),
+    /// Seeks relative to the end of the file. `End(-1)` seeks to the last line
+    End(isize),
+    /// Seeks relative to the current line. If the reader wasn't positioned yet, this is relative
+    /// to the first line
+    Current(isize),
+}
+
+/// Seeking relative to lines of an indexed file
+pub trait LineSeek {
+    /// Seeks to the line described by `from` and returns the resulting line. Seeking to the next
+    /// line using `LineSeekFrom::Current(1)` doesn't require an actual seek.
+    ///
+    /// Returns `Error::InvalidSeek` when seeking before the first line and `Error::OutOfBounds`
+    /// when seeking past the last line. The position of the reader is unchanged on errors.
+    fn seek_lines(&mut self, from: LineSeekFrom) -> Result<usize>;
+}
+
+impl<T: IndexableFile> LineSeek for T {
+    fn seek_lines(&mut self, from: LineSeekFrom) -> Result<usize> {
+        let (base, offset) = match from {
+            LineSeekFrom::Start(line) => (line, 0),
+            LineSeekFrom::End(offset) => (self.total_lines(), offset),
+            LineSeekFrom::Current(offset) => (self.current_line().unwrap_or(0), offset),
+        };
+
+        let line = if offset < 0 {
+            base.checked_sub(offset.unsigned_abs())
+        } else {
+            base.checked_add(offset as usize)
+        }
+        .ok_or(Error::InvalidSeek)?;
+
+        self.seek_line(line)?;
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Indexable, IndexedString, ReadByLine};
+
+    fn content(reader: &mut IndexedString, line: usize) -> String {
+        let mut buf = Vec::new();
+        let read = reader.read_current_line(&mut buf, line).unwrap();
+        String::from_utf8(buf[..read].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_seek_lines() {
+        let mut reader = IndexedString::new_raw("0\n1\n2\n3\n4\n5").unwrap();
+
+        assert_eq!(reader.seek_lines(LineSeekFrom::Current(2)).unwrap(), 2);
+        assert_eq!(content(&mut reader, 2), "2\n");
+
+        // Sequencial reads
+        assert_eq!(reader.seek_lines(LineSeekFrom::Current(1)).unwrap(), 3);
+        assert_eq!(content(&mut reader, 3), "3\n");
+
+        assert_eq!(reader.seek_lines(LineSeekFrom::Current(-3)).unwrap(), 0);
+        assert_eq!(content(&mut reader, 0), "0\n");
+
+        assert_eq!(reader.seek_lines(LineSeekFrom::End(-1)).unwrap(), 5);
+        assert_eq!(content(&mut reader, 5), "5");
+
+        assert_eq!(reader.seek_lines(LineSeekFrom::End(-3)).unwrap(), 3);
+        assert_eq!(content(&mut reader, 3), "3\n");
+
+        assert_eq!(reader.seek_lines(LineSeekFrom::Start(4)).unwrap(), 4);
+        assert_eq!(content(&mut reader, 4), "4\n");
+        assert_eq!(reader.read_line(1).unwrap(), "1\n");
+
+        // Seeking multiple times without reading
+        reader.seek_lines(LineSeekFrom::Start(0)).unwrap();
+        reader.seek_lines(LineSeekFrom::Current(1)).unwrap();
+        reader.seek_lines(LineSeekFrom::Current(1)).unwrap();
+        assert_eq!(content(&mut reader, 2), "2\n");
+    }
+
+    #[test]
+    fn test_seek_lines_bounds() {
+        let mut reader = IndexedString::new_raw("0\n1\n2\n").unwrap();
+        let lines = reader.total_lines();
+
+        reader.seek_lines(LineSeekFrom::Start(1)).unwrap();
+
+        assert!(matches!(
+            reader.seek_lines(LineSeekFrom::Current(-2)),
+            Err(Error::InvalidSeek)
+        ));
+        assert!(matches!(
+            reader.seek_lines(LineSeekFrom::End(-4)),
+            Err(Error::InvalidSeek)
+        ));
+        assert!(matches!(
+            reader.seek_lines(LineSeekFrom::End(0)),
+            Err(Error::OutOfBounds { line: 3, lines: 3 })
+        ));
+        assert!(matches!(
+            reader.seek_lines(LineSeekFrom::Current(2)),
+            Err(Error::OutOfBounds { line: 3, .. })
+        ));
+        assert!(matches!(
+            reader.seek_lines(LineSeekFrom::Start(lines)),
+            Err(Error::OutOfBounds { .. })
+        ));
+
+        // The position is unchanged
+        assert_eq!(reader.current_line(), Some(1));
+        assert_eq!(content(&mut reader, 1), "1\n");
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    borrow::Cow,
+    io::{BufReader, Cursor, Write},
+    ops::Range,
+};
+
+use crate::{error::Error, hash_bytes, index::Index, Indexable, IndexableFile, ReadByLine, Result};
+
+/// An indexed reader borrowing its data instead of owning it. Lines can be accessed without
+/// copying them using `get_line`.
+#[derive(Debug, Clone)]
+pub struct IndexedSlice<'a> {
+    data: &'a [u8],
+    index: Cow<'a, Index>,
+    /// The line the reader was last positioned at
+    last_line: Option<usize>,
+    /// Absolute position after the last read, see `IndexableFile::absolute_position`
+    pos: u64,
+}
+
+impl<'a> IndexedSlice<'a> {
+    /// Creates a new `IndexedSlice` using data containing an index, which may be stored in front
+    /// of the data or behind it.
+    ///
+    /// Returns an error if the index is malformed or missing
+    #[inline]
+    pub fn new(data: &'a [u8]) -> Result<IndexedSlice<'a>> {
+        let (index, section) = Index::parse_any(&mut Cursor::new(data))?;
+        let data = match section {
+            Some(section) => &data[..section.len as usize],
+            None => data,
+        };
+        Ok(Self::new_custom(data, Cow::Owned(index)))
+    }
+
+    /// Creates a new `IndexedSlice` from unindexed data and builds an index.
+    #[inline]
+    pub fn new_raw(data: &'a [u8]) -> Result<IndexedSlice<'a>> {
+        let index = Index::build(&mut BufReader::new(Cursor::new(data)))?;
+        Ok(Self::new_custom(data, Cow::Owned(index)))
+    }
+
+    /// Creates a new `IndexedSlice` using `index` as index, which can be owned or borrowed.
+    /// Expects the index to be properly built. If the provided data does not contain an index, you
+    /// have to pass a `zero_len` index.
+    #[inline]
+    pub fn new_custom(data: &'a [u8], index: Cow<'a, Index>) -> IndexedSlice<'a> {
+        Self {
+            data,
+            index,
+            last_line: None,
+            pos: 0,
+        }
+    }
+
+    /// Returns the content of `line` including its terminator without copying it. The returned
+    /// slice borrows the underlying data, not the reader.
+    #[inline]
+    pub fn get_line(&self, line: usize) -> Result<&'a [u8]> {
+        let span = self.line_span(line)?;
+        self.data_span(span, line)
+    }
+
+    /// Returns the bytes of `span` within the data section
+    #[inline]
+    fn data_span(&self, span: Range<u64>, line: usize) -> Result<&'a [u8]> {
+        let offset = self.get_index_byte_len();
+        self.data
+            .get(offset + span.start as usize..offset + span.end as usize)
+            .ok_or(Error::InvalidIndex { line })
+    }
+}
+
+impl<'a> Indexable for IndexedSlice<'a> {
+    #[inline]
+    fn get_index(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl<'a> IndexableFile for IndexedSlice<'a> {
+    #[inline]
+    fn read_current_line(&mut self, buf: &mut Vec<u8>, line: usize) -> Result<usize> {
+        let span = self.line_span(line)?;
+        let data = self.data_span(span.clone(), line)?;
+
+        buf.clear();
+        buf.extend_from_slice(data);
+        self.pos = self.data_start() + span.end;
+        Ok(data.len())
+    }
+
+    #[inline]
+    fn seek_line(&mut self, line: usize) -> Result<()> {
+        let offset = self.get_offset(line)?;
+        self.last_line = Some(line);
+        self.pos = self.data_start() + offset as u64;
+        Ok(())
+    }
+
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        self.last_line
+    }
+
+    #[inline]
+    fn data_len(&self) -> u64 {
+        self.data.len().saturating_sub(self.get_index_byte_len()) as u64
+    }
+
+    #[inline]
+    fn absolute_position(&mut self) -> Result<u64> {
+        Ok(self.pos)
+    }
+
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        let span = self.line_span(line)?;
+        let end = span.end.min(span.start.saturating_add(max as u64));
+
+        buf.clear();
+        buf.extend_from_slice(self.data_span(span.start..end, line)?);
+
+        self.last_line = Some(line);
+        self.pos = self.data_start() + end;
+        Ok(buf.len())
+    }
+
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        buf.clear();
+
+        if lines.start >= lines.end {
+            return Ok(0);
+        }
+
+        let start = self.line_span(lines.start)?.start;
+        let end = self.line_span(lines.end - 1)?.end;
+        buf.extend_from_slice(self.data_span(start..end, lines.start)?);
+
+        self.last_line = Some(lines.end - 1);
+        self.pos = self.data_start() + end;
+        Ok(buf.len())
+    }
+
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let span = self.line_span(line)?;
+        let start = span.end.min(span.start.saturating_add(offset));
+        let end = span.end.min(start + buf.len() as u64);
+        let data = self.data_span(start..end, line)?;
+        buf[..data.len()].copy_from_slice(data);
+
+        self.last_line = Some(line);
+        self.pos = self.data_start() + end;
+        Ok(data.len())
+    }
+
+    fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
+        let data = self
+            .data
+            .get(self.get_index_byte_len()..)
+            .unwrap_or_default();
+        let mut header = self.get_index().get_header();
+        header.set_content_hash(hash_bytes(data));
+
+        let header = header.encode();
+
+        writer.write_all(&header)?;
+        let index_len = self.get_index().encode_into(writer)?;
+        writer.write_all(data)?;
+
+        self.last_line = None;
+        Ok(header.len() + index_len + data.len())
+    }
+}
+
+impl<'a> ReadByLine for IndexedSlice<'a> {}
+
+/// A view restricting a reader to a range of its lines, created by `ReadByLine::slice`. Lines
+/// are numbered from 0 within the view and reading lines outside of it returns
+/// `Error::OutOfBounds`. Since `LineSlice` implements `ReadByLine`, slices can be sliced again.
+///
+/// # Example
+///
+/// ```
+/// use indexed_file::{Indexable, IndexedString, ReadByLine};
+///
+/// let mut reader = IndexedString::new_raw("a\nb1\nb2\nb3\nc\n").unwrap();
+/// let mut b = reader.slice(1..4).unwrap();
+/// assert_eq!(b.total_lines(), 3);
+/// assert_eq!(b.read_line(0).unwrap(), "b1\n");
+/// assert_eq!(b.binary_search("b3\n").unwrap(), 2);
+/// assert!(b.read_line(3).is_err());
+/// ```
+#[derive(Debug)]
+pub struct LineSlice<'a, R: ReadByLine> {
+    reader: &'a mut R,
+    /// The first line of the view within `reader`
+    start: usize,
+    /// Offset of the first line within the data section of `reader`
+    base: u64,
+    /// The offsets of the lines within the view, relative to `base`
+    index: Index,
+    /// Length of the lines within the view in bytes
+    len: u64,
+}
+
+impl<'a, R: ReadByLine> LineSlice<'a, R> {
+    /// Creates a view of `lines` of `reader`. An empty or reversed range creates an empty view.
+    ///
+    /// Returns `Error::OutOfBounds` if the range ends after the last line
+    pub fn new(reader: &'a mut R, lines: Range<usize>) -> Result<Self> {
+        let total = reader.total_lines();
+        let lines = lines.start..lines.end.max(lines.start);
+        if lines.end > total {
+            return Err(Error::OutOfBounds {
+                line: lines.end - 1,
+                lines: total,
+            });
+        }
+
+        let (base, end) = if lines.is_empty() {
+            let base = reader
+                .get_index()
+                .get_opt(lines.start)
+                .map_or_else(|| reader.data_len(), u64::from);
+            (base, base)
+        } else {
+            (
+                reader.line_span(lines.start)?.start,
+                reader.line_span(lines.end - 1)?.end,
+            )
+        };
+
+        let offsets = reader.get_index().offsets()[lines.clone()]
+            .iter()
+            .map(|i| (u64::from(*i) - base) as u32);
+        let mut index = Index::new(offsets).zero_len();
+        index.set_delimiter(reader.get_index().delimiter());
+
+        Ok(Self {
+            reader,
+            start: lines.start,
+            base,
+            index,
+            len: end.saturating_sub(base),
+        })
+    }
+
+    /// Returns the range of lines of the underlying reader covered by the view
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.index.len()
+    }
+
+    /// Returns the line of the underlying reader for `line` of the view or `Error::OutOfBounds`
+    /// if it's outside the view
+    #[inline]
+    fn outer_line(&self, line: usize) -> Result<usize> {
+        self.get_offset(line)?;
+        Ok(self.start + line)
+    }
+}
+
+impl<'a, R: ReadByLine> Indexable for LineSlice<'a, R> {
+    #[inline]
+    fn get_index(&self) -> &Index {
+        &self.index
+    }
+}
+
+impl<'a, R: ReadByLine> IndexableFile for LineSlice<'a, R> {
+    #[inline]
+    fn read_current_line(&mut self, buf: &mut Vec<u8>, line: usize) -> Result<usize> {
+        let outer = self.outer_line(line)?;
+        self.reader.read_current_line(buf, outer)
+    }
+
+    #[inline]
+    fn seek_line(&mut self, line: usize) -> Result<()> {
+        let outer = self.outer_line(line)?;
+        self.reader.seek_line(outer)
+    }
+
+    #[inline]
+    fn current_line(&self) -> Option<usize> {
+        let line = self.reader.current_line()?.checked_sub(self.start)?;
+        (line < self.total_lines()).then_some(line)
+    }
+
+    #[inline]
+    fn data_len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the position relative to the beginning of the first line of the view, or 0 if
+    /// the underlying reader is positioned in front of it
+    #[inline]
+    fn absolute_position(&mut self) -> Result<u64> {
+        let start = self.reader.data_start() + self.base;
+        Ok(self.reader.absolute_position()?.saturating_sub(start))
+    }
+
+    #[inline]
+    fn read_line_prefix(&mut self, line: usize, max: usize, buf: &mut Vec<u8>) -> Result<usize> {
+        let outer = self.outer_line(line)?;
+        self.reader.read_line_prefix(outer, max, buf)
+    }
+
+    fn read_span(&mut self, lines: Range<usize>, buf: &mut Vec<u8>) -> Result<usize> {
+        if lines.start >= lines.end {
+            buf.clear();
+            return Ok(0);
+        }
+
+        let start = self.outer_line(lines.start)?;
+        let end = self.outer_line(lines.end - 1)? + 1;
+        self.reader.read_span(start..end, buf)
+    }
+
+    #[inline]
+    fn read_line_at(&mut self, line: usize, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let outer = self.outer_line(line)?;
+        self.reader.read_line_at(outer, offset, buf)
+    }
+
+    /// Writes the lines of the view as new indexed data. The lines are read into memory at once
+    /// to hash them for the header.
+    fn write_to<W: Write + Unpin + Send>(&mut self, writer: &mut W) -> Result<usize> {
+        let mut data = Vec::new();
+        self.read_span(0..self.total_lines(), &mut data)?;
+
+        let mut header = self.index.get_header();
+        header.set_content_hash(hash_bytes(&data));
+        let header = header.encode();
+
+        writer.write_all(&header)?;
+        let index_len = self.index.encode_into(writer)?;
+        writer.write_all(&data)?;
+        Ok(header.len() + index_len + data.len())
+    }
+}
+
+impl<'a, R: ReadByLine> ReadByLine for LineSlice<'a, R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_slice() {
+        let content = "zero\none\ntwo\nthree\nfour\nfive";
+        let mut reader = IndexedSlice::new_raw(content.as_bytes()).unwrap();
+
+        let mut slice = reader.slice(2..6).unwrap();
+        assert_eq!(slice.range(), 2..6);
+        assert_eq!(slice.total_lines(), 4);
+        assert_eq!(slice.data_len(), "two\nthree\nfour\nfive".len() as u64);
+        assert_eq!(slice.read_line(0).unwrap(), "two\n");
+        assert_eq!(slice.current_line(), Some(0));
+        assert_eq!(slice.absolute_position().unwrap(), 4);
+        assert_eq!(slice.read_line(3).unwrap(), "five");
+        assert!(matches!(
+            slice.read_line(4),
+            Err(Error::OutOfBounds { line: 4, lines: 4 })
+        ));
+
+        let mut buf = Vec::new();
+        slice.read_span(1..3, &mut buf).unwrap();
+        assert_eq!(buf, b"three\nfour\n");
+        assert!(slice.read_span(1..5, &mut buf).is_err());
+        let mut at = [0; 3];
+        assert_eq!(slice.read_line_at(1, 2, &mut at).unwrap(), 3);
+        assert_eq!(&at, b"ree");
+
+        // Nested slices compose
+        let mut nested = slice.slice(1..3).unwrap();
+        assert_eq!(nested.read_line(1).unwrap(), "four\n");
+        assert_eq!(nested.read_line_prefix_str(0, 2).unwrap(), "th");
+        assert!(nested.read_line(2).is_err());
+        assert!(nested.slice(0..3).is_err());
+        let empty = nested.slice(2..2).unwrap();
+        assert_eq!(empty.total_lines(), 0);
+        assert_eq!(empty.data_len(), 0);
+
+        // A written slice is a file of its own
+        let mut written = Vec::new();
+        slice.write_to(&mut written).unwrap();
+        let mut parsed = IndexedSlice::new(&written).unwrap();
+        assert_eq!(parsed.total_lines(), 4);
+        assert_eq!(parsed.read_line(3).unwrap(), "five");
+
+        assert!(matches!(
+            reader.slice(5..7),
+            Err(Error::OutOfBounds { line: 6, lines: 6 })
+        ));
+        assert_eq!(reader.slice(6..6).unwrap().total_lines(), 0);
+    }
+
+    #[test]
+    fn test_slice() {
+        let data = b"first\nsecond\nthird".to_vec();
+
+        let line = {
+            let mut slice = IndexedSlice::new_raw(&data).unwrap();
+            assert_eq!(slice.total_lines(), 3);
+            assert_eq!(slice.read_line(1).unwrap(), "second\n");
+            assert_eq!(slice.current_line(), Some(1));
+            slice.get_line(2).unwrap()
+        };
+        // Lines borrow the data and not the reader
+        assert_eq!(line, b"third");
+
+        // Write the indexed data and read it using a borrowed index
+        let mut indexed = Vec::new();
+        IndexedSlice::new_raw(&data)
+            .unwrap()
+            .write_to(&mut indexed)
+            .unwrap();
+        let mut parsed = IndexedSlice::new(&indexed).unwrap();
+        let index = parsed.get_index().clone();
+        let mut borrowed = IndexedSlice::new_custom(&indexed, Cow::Borrowed(&index));
+
+        for line in 0..3 {
+            assert_eq!(
+                parsed.get_line(line).unwrap(),
+                borrowed.get_line(line).unwrap()
+            );
+            assert_eq!(
+                parsed.read_line(line).unwrap(),
+                borrowed.read_line(line).unwrap()
+            );
+        }
+
+        let mut buf = Vec::new();
+        borrowed.read_span(0..2, &mut buf).unwrap();
+        assert_eq!(buf, b"first\nsecond\n");
+        assert!(matches!(
+            borrowed.get_line(3),
+            Err(Error::OutOfBounds { line: 3, lines: 3 })
+        ));
+    }
+}
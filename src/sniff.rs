@@ -0,0 +1,186 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::Result;
+
+/// Default amount of bytes sampled by `File::open_raw_text_only`
+pub const DEFAULT_SNIFF_LEN: usize = 8 * 1024;
+
+/// Kind of data detected by `Sniffer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentKind {
+    Text,
+    Binary,
+}
+
+/// Classifies data as text or binary by sampling its first bytes. Data is considered binary once
+/// the share of NUL bytes or of bytes which are not valid UTF-8 exceeds its threshold.
+///
+/// ```
+/// use indexed_file::sniff::{ContentKind, Sniffer};
+/// use std::io::Cursor;
+///
+/// let sniffer = Sniffer::new().max_invalid_utf8_percent(0);
+/// let kind = sniffer.sniff(&mut Cursor::new(b"caf\xE9\n"), 1024)?;
+/// assert_eq!(kind, ContentKind::Binary);
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sniffer {
+    /// Maximum share of NUL bytes in percent
+    max_nul_percent: u8,
+    /// Maximum share of bytes which are not valid UTF-8 in percent
+    max_invalid_utf8_percent: u8,
+}
+
+impl Default for Sniffer {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_nul_percent: 0,
+            max_invalid_utf8_percent: 5,
+        }
+    }
+}
+
+impl Sniffer {
+    /// Creates a new `Sniffer` with the default thresholds
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the share of NUL bytes in percent which text may contain. Defaults to 0, so a single
+    /// NUL byte classifies the data as binary. Data using `\0` as delimiter needs a higher value.
+    #[inline]
+    pub fn max_nul_percent(mut self, percent: u8) -> Self {
+        self.max_nul_percent = percent;
+        self
+    }
+
+    /// Sets the share of bytes in percent which text may contain without being valid UTF-8.
+    /// Defaults to 5, which tolerates a few Latin-1 characters in otherwise ASCII text.
+    #[inline]
+    pub fn max_invalid_utf8_percent(mut self, percent: u8) -> Self {
+        self.max_invalid_utf8_percent = percent;
+        self
+    }
+
+    /// Samples up to `bytes_to_check` bytes from the start of `reader` and classifies them. The
+    /// position of `reader` is restored afterwards. Empty data is classified as text.
+    pub fn sniff<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        bytes_to_check: usize,
+    ) -> Result<ContentKind> {
+        let pos = reader.stream_position()?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut sample = Vec::with_capacity(bytes_to_check);
+        let read = reader
+            .by_ref()
+            .take(bytes_to_check as u64)
+            .read_to_end(&mut sample);
+        reader.seek(SeekFrom::Start(pos))?;
+        read?;
+
+        Ok(self.classify(&sample))
+    }
+
+    /// Classifies `sample`
+    pub fn classify(&self, sample: &[u8]) -> ContentKind {
+        let nul = sample.iter().filter(|b| **b == 0).count();
+        if exceeds(nul, sample.len(), self.max_nul_percent) {
+            return ContentKind::Binary;
+        }
+
+        let invalid = invalid_utf8_bytes(sample);
+        if exceeds(invalid, sample.len(), self.max_invalid_utf8_percent) {
+            return ContentKind::Binary;
+        }
+
+        ContentKind::Text
+    }
+}
+
+/// Returns `true` if `count` is more than `percent` percent of `len`
+#[inline]
+fn exceeds(count: usize, len: usize, percent: u8) -> bool {
+    count as u64 * 100 > len as u64 * percent as u64
+}
+
+/// Counts the bytes in `data` which are not part of a valid UTF-8 sequence. A sequence cut off at
+/// the end of `data` is not counted, since the sample may end within a character.
+fn invalid_utf8_bytes(mut data: &[u8]) -> usize {
+    let mut invalid = 0;
+    while let Err(err) = std::str::from_utf8(data) {
+        let len = match err.error_len() {
+            Some(len) => len,
+            None => break,
+        };
+        invalid += len;
+        data = &data[err.valid_up_to() + len..];
+    }
+    invalid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_classify() {
+        let sniffer = Sniffer::new();
+        let samples: &[(&[u8], ContentKind)] = &[
+            (b"", ContentKind::Text),
+            (b"first line\nsecond line\n", ContentKind::Text),
+            ("grüße, ½ € \u{1F600}\n".as_bytes(), ContentKind::Text),
+            // A character cut off at the end of the sample
+            (b"abc\xE2\x82", ContentKind::Text),
+            (b"text with a \0 byte\n", ContentKind::Binary),
+            (b"\x1F\x8B\x08\x00\x00\x00\x00\x00", ContentKind::Binary),
+            (b"\xFF\xFE\xFDnot utf-8\n", ContentKind::Binary),
+            // One Latin-1 byte in 29 is within the default threshold
+            (b"a caf\xE9 with twenty-nine bytes", ContentKind::Text),
+        ];
+
+        for (sample, kind) in samples {
+            assert_eq!(sniffer.classify(sample), *kind, "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let nul_delimited = b"first\0second\0third\0";
+        assert_eq!(Sniffer::new().classify(nul_delimited), ContentKind::Binary);
+        let sniffer = Sniffer::new().max_nul_percent(20);
+        assert_eq!(sniffer.classify(nul_delimited), ContentKind::Text);
+        let sniffer = Sniffer::new().max_nul_percent(10);
+        assert_eq!(sniffer.classify(nul_delimited), ContentKind::Binary);
+
+        // 2 invalid bytes in 10
+        let latin1 = b"\xE9t\xE9 texts\n";
+        assert_eq!(Sniffer::new().classify(latin1), ContentKind::Binary);
+        let sniffer = Sniffer::new().max_invalid_utf8_percent(20);
+        assert_eq!(sniffer.classify(latin1), ContentKind::Text);
+        let sniffer = Sniffer::new().max_invalid_utf8_percent(19);
+        assert_eq!(sniffer.classify(latin1), ContentKind::Binary);
+    }
+
+    #[test]
+    fn test_sniff() {
+        let mut data = b"some text\n".repeat(10);
+        data.extend_from_slice(b"\0\0\0\0");
+        let mut reader = Cursor::new(data);
+        reader.set_position(7);
+
+        let sniffer = Sniffer::new();
+        assert_eq!(sniffer.sniff(&mut reader, 100).unwrap(), ContentKind::Text);
+        assert_eq!(reader.position(), 7);
+        assert_eq!(
+            sniffer.sniff(&mut reader, 101).unwrap(),
+            ContentKind::Binary
+        );
+        assert_eq!(reader.position(), 7);
+    }
+}
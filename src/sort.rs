@@ -0,0 +1,285 @@
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fs,
+    io::{BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    collate::Collation,
+    error::Error,
+    file::{self, File},
+    index::{Index, DEFAULT_DELIMITER},
+    mem_file::MemFile,
+    merge::{merge_sorted_with, Duplicates},
+    temp::TempFile,
+    trim_terminator, Result,
+};
+
+/// The default memory budget of `SortOptions`
+pub const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Maximum amount of temporary files merged at once, which limits the amount of open files
+const MERGE_FAN_IN: usize = 64;
+
+/// Options for `sort_file`
+#[derive(Debug, Clone, Copy)]
+pub struct SortOptions<F = fn(&[u8], &[u8]) -> Ordering> {
+    memory_budget: usize,
+    compare: F,
+    /// Collation recorded in the header of the output
+    collation: Option<Collation>,
+}
+
+impl Default for SortOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            compare: |a, b| a.cmp(b),
+            collation: None,
+        }
+    }
+}
+
+impl SortOptions {
+    /// Creates new `SortOptions` sorting lines in ascending byte order using a memory budget of
+    /// `DEFAULT_MEMORY_BUDGET`
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<F: Fn(&[u8], &[u8]) -> Ordering> SortOptions<F> {
+    /// Sets the amount of bytes of lines held in memory before they get sorted and written into a
+    /// temporary file. A higher budget results in fewer temporary files.
+    #[inline]
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Sorts the lines using `compare`. Lines are passed without their terminators.
+    #[inline]
+    pub fn compare_by<G>(self, compare: G) -> SortOptions<G>
+    where
+        G: Fn(&[u8], &[u8]) -> Ordering,
+    {
+        SortOptions {
+            memory_budget: self.memory_budget,
+            compare,
+            collation: None,
+        }
+    }
+
+    /// Sorts the lines using `collation` and records it in the header of the output, so readers
+    /// can check that they search it using the same collation
+    #[inline]
+    pub fn collation(self, collation: Collation) -> SortOptions {
+        SortOptions {
+            memory_budget: self.memory_budget,
+            compare: collation.comparator(),
+            collation: Some(collation),
+        }
+    }
+}
+
+/// Sorts the lines of the non indexed file `input` and writes them as indexed file to `output`,
+/// which can be binary searched afterwards. Every line of the output is terminated by a `\n`.
+///
+/// The input is read in chunks of `SortOptions::memory_budget` bytes, which are sorted in memory
+/// and written into temporary files. Those get merged into the output afterwards, so the input
+/// can be larger than the available memory. At most 64 temporary files are opened at once, more
+/// of them are merged in multiple passes.
+pub fn sort_file<F>(input: &Path, output: &Path, opts: SortOptions<F>) -> Result<()>
+where
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    let (mut reader, input) = file::open_buffered(input)?;
+    let mut runs = Vec::new();
+    let mut chunk = MemFile::new();
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(DEFAULT_DELIMITER, &mut buf)
+            .map_err(|e| Error::from(e).with_path(&input))?;
+        if read > 0 {
+            chunk.insert(trim_terminator(&buf, DEFAULT_DELIMITER));
+        }
+
+        // Every entry also occupies 4 bytes in the index
+        let used = chunk.raw_len() + chunk.len() * 4;
+        if (read == 0 && !chunk.is_empty()) || used >= opts.memory_budget {
+            chunk.sort_unstable_by(&opts.compare);
+            runs.push(write_run(&chunk)?);
+            chunk = MemFile::new();
+        }
+
+        if read == 0 {
+            break;
+        }
+    }
+
+    let runs = merge_runs(runs, MERGE_FAN_IN, &opts.compare)?;
+    let mut inputs = runs
+        .iter()
+        .map(|run| File::open(run.path()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer =
+        BufWriter::new(fs::File::create(output).map_err(|e| Error::from(e).with_path(output))?);
+    merge_sorted_with(
+        &mut inputs,
+        &mut writer,
+        Duplicates::Keep,
+        &opts.compare,
+        opts.collation,
+    )
+    .map_err(|e| e.with_path(output))?;
+    writer
+        .flush()
+        .map_err(|e| Error::from(e).with_path(output))?;
+    Ok(())
+}
+
+/// Merges groups of `fan_in` consecutive runs into new runs until at most `fan_in` runs are
+/// left. Runs are merged in order, so equal lines keep the order of the input.
+fn merge_runs<F>(mut runs: Vec<TempFile>, fan_in: usize, compare: F) -> Result<Vec<TempFile>>
+where
+    F: Fn(&[u8], &[u8]) -> Ordering,
+{
+    while runs.len() > fan_in {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(fan_in));
+        for group in runs.chunks(fan_in) {
+            let mut inputs = group
+                .iter()
+                .map(|run| File::open(run.path()))
+                .collect::<Result<Vec<_>>>()?;
+
+            let (temp, file) = TempFile::create("sort_run")?;
+            let mut writer = BufWriter::new(file);
+            merge_sorted_with(&mut inputs, &mut writer, Duplicates::Keep, &compare, None)?;
+            writer.flush()?;
+            merged.push(temp);
+        }
+        runs = merged;
+    }
+    Ok(runs)
+}
+
+/// Writes all entries of `chunk` as indexed file into a new temporary file
+fn write_run(chunk: &MemFile) -> Result<TempFile> {
+    let (temp, file) = TempFile::create("sort_run")?;
+
+    let mut offsets = Vec::with_capacity(chunk.len());
+    let mut pos = 0u64;
+    for entry in chunk.iter() {
+        let start = u32::try_from(pos).map_err(|_| Error::OffsetOverflow {
+            line: offsets.len(),
+        })?;
+        offsets.push(start);
+        pos += entry.len() as u64 + 1;
+    }
+    let index = Index::new(offsets);
+
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&index.get_header().encode())?;
+    index.encode_into(&mut writer)?;
+    for entry in chunk.iter() {
+        writer.write_all(entry)?;
+        writer.write_all(&[DEFAULT_DELIMITER])?;
+    }
+    writer.flush()?;
+
+    Ok(temp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{any::CloneableIndexedReader, Indexable, ReadByLine};
+
+    fn sorted_lines<F>(input: &str, opts: SortOptions<F>) -> Vec<String>
+    where
+        F: Fn(&[u8], &[u8]) -> Ordering,
+    {
+        let input_file = TempFile::new("sort_input");
+        let output_file = TempFile::new("sort_output");
+        fs::write(input_file.path(), input).unwrap();
+
+        sort_file(input_file.path(), output_file.path(), opts).unwrap();
+
+        let mut file = File::open(output_file.path()).unwrap();
+        (0..file.total_lines())
+            .map(|i| file.read_line(i).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_file() {
+        let license = fs::read_to_string("./testfiles/LICENSE").unwrap();
+        let mut expected: Vec<_> = license.lines().map(|i| format!("{}\n", i)).collect();
+        expected.sort_unstable();
+
+        // A small budget results in many runs
+        for budget in [DEFAULT_MEMORY_BUDGET, 4096, 256] {
+            let opts = SortOptions::new().memory_budget(budget);
+            assert_eq!(sorted_lines(&license, opts), expected);
+        }
+
+        expected.reverse();
+        let opts = SortOptions::new()
+            .memory_budget(4096)
+            .compare_by(|a: &[u8], b: &[u8]| b.cmp(a));
+        assert_eq!(sorted_lines(&license, opts), expected);
+    }
+
+    #[test]
+    fn test_merge_runs() {
+        let mut expected = Vec::new();
+        let runs: Vec<_> = (0..7)
+            .map(|run| {
+                let mut chunk = MemFile::new();
+                for i in 0..5 {
+                    let line = format!("{:02}", i * 7 + run);
+                    chunk.insert(line.as_bytes());
+                    expected.push(format!("{}\n", line));
+                }
+                write_run(&chunk).unwrap()
+            })
+            .collect();
+        expected.sort_unstable();
+
+        let runs = merge_runs(runs, 2, |a: &[u8], b: &[u8]| a.cmp(b)).unwrap();
+        assert_eq!(runs.len(), 2);
+
+        let mut inputs: Vec<_> = runs.iter().map(|i| File::open(i.path()).unwrap()).collect();
+        let mut out = Vec::new();
+        merge_sorted_with(
+            &mut inputs,
+            &mut out,
+            Duplicates::Keep,
+            |a, b| a.cmp(b),
+            None,
+        )
+        .unwrap();
+        let mut merged = CloneableIndexedReader::new(out).unwrap();
+        let lines: Vec<_> = (0..merged.total_lines())
+            .map(|i| merged.read_line(i).unwrap())
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn test_sort_file_small() {
+        assert_eq!(
+            sorted_lines("c\nb\na", SortOptions::new()),
+            ["a\n", "b\n", "c\n"]
+        );
+        assert!(sorted_lines("", SortOptions::new()).is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::BuildHasher,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+/// How often `TempFile::create` picks a new path if the file already exists
+const CREATE_ATTEMPTS: usize = 16;
+
+/// A path within the temporary directory which gets removed on drop. Used to store data which
+/// has to be written after the index, since the index is only known once all data is processed.
+#[derive(Debug)]
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Returns a new unique path for a temporary file. The file itself is not created.
+    #[cfg(test)]
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            path: unique_path(name),
+        }
+    }
+
+    /// Creates a new temporary file and returns it opened for reading and writing. The path
+    /// contains a random part and the file is created exclusively, so a file or link placed at
+    /// the path by someone else is never opened. On unix, only the current user can access it.
+    pub(crate) fn create(name: &str) -> io::Result<(Self, fs::File)> {
+        let mut options = fs::OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        for _ in 0..CREATE_ATTEMPTS {
+            let path = unique_path(name);
+            match options.open(&path) {
+                Ok(file) => return Ok((Self { path }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "failed to create a unique temporary file",
+        ))
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    #[inline]
+    fn drop(&mut self) {
+        // The file might not have been created
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A new directory within the temporary directory which gets removed together with its content
+/// on drop
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl TempDir {
+    /// Creates a new empty directory at a unique path
+    pub(crate) fn create(name: &str) -> io::Result<Self> {
+        let path = unique_path(name);
+        fs::create_dir(&path)?;
+        Ok(Self { path })
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Returns a path within the temporary directory which is hard to guess, since it contains a
+/// hash using the randomly seeded keys of `RandomState`
+fn unique_path(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let random = RandomState::new().hash_one((counter, SystemTime::now()));
+
+    std::env::temp_dir().join(format!(
+        "indexed_file_{}_{}_{}_{:016x}",
+        name,
+        std::process::id(),
+        counter,
+        random
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_create() {
+        let (temp, mut file) = TempFile::create("temp_test").unwrap();
+        file.write_all(b"data").unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut read = String::new();
+        file.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "data");
+
+        let (other, _) = TempFile::create("temp_test").unwrap();
+        assert_ne!(temp.path(), other.path());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(temp.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let path = temp.path().to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_dir() {
+        let dir = TempDir::create("temp_dir_test").unwrap();
+        assert!(dir.path().is_dir());
+        fs::write(dir.path().join("file"), b"data").unwrap();
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{trim_terminator, ReadByLine};
+
+/// Generation of deterministic test data
+pub mod fixture {
+    use std::{
+        fs,
+        io::{BufWriter, Write},
+        ops::Range,
+        path::Path,
+    };
+
+    use crate::{error::Error, IndexableFile, IndexedString, Result};
+
+    /// A small deterministic random number generator (SplitMix64). The generated data only
+    /// depends on the seed, so fixtures stay the same across versions and platforms.
+    #[derive(Debug, Clone)]
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        }
+
+        /// Returns a number within `range` or `range.start` if the range is empty
+        fn range(&mut self, range: &Range<usize>) -> usize {
+            if range.start >= range.end {
+                return range.start;
+            }
+            range.start + (self.next() % (range.end - range.start) as u64) as usize
+        }
+
+        fn string(&mut self, len: usize, charset: &[u8]) -> String {
+            (0..len)
+                .map(|_| charset[self.range(&(0..charset.len()))] as char)
+                .collect()
+        }
+    }
+
+    const ALPHANUMERIC: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+    const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+    /// Generates `n` lines of random alphanumeric characters and spaces whose lengths are within
+    /// `len_range`. The same seed always generates the same lines.
+    pub fn random_lines(n: usize, len_range: Range<usize>, seed: u64) -> Vec<String> {
+        let mut rng = Rng(seed);
+        (0..n)
+            .map(|_| {
+                let len = rng.range(&len_range);
+                rng.string(len, ALPHANUMERIC)
+            })
+            .collect()
+    }
+
+    /// Generates `n` random lowercase words with 1 to 10 characters in ascending order. Words
+    /// can occur multiple times. The same seed always generates the same words.
+    pub fn sorted_words(n: usize, seed: u64) -> Vec<String> {
+        let mut rng = Rng(seed);
+        let mut words: Vec<_> = (0..n)
+            .map(|_| {
+                let len = rng.range(&(1..11));
+                rng.string(len, LOWERCASE)
+            })
+            .collect();
+        words.sort_unstable();
+        words
+    }
+
+    /// Writes `lines` as indexed file to `path`, which can be opened using `File::open`. Every
+    /// line gets terminated by a newline.
+    ///
+    /// Returns `Error::ContainsNewline` if a line contains a newline
+    pub fn write_indexed<S: AsRef<str>, P: AsRef<Path>>(lines: &[S], path: P) -> Result<()> {
+        let mut data = String::new();
+        for (entry, line) in lines.iter().enumerate() {
+            let line = line.as_ref();
+            if line.contains('\n') {
+                return Err(Error::ContainsNewline { entry });
+            }
+            data.push_str(line);
+            data.push('\n');
+        }
+
+        let path = path.as_ref();
+        let mut reader = IndexedString::new_raw(data)?;
+        let mut write = || -> Result<()> {
+            let mut writer = BufWriter::new(fs::File::create(path)?);
+            reader.write_to(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        };
+        write().map_err(|e| e.with_path(path))
+    }
+}
+
+/// Asserts that `reader` holds exactly the lines in `expected`, which are given without their
+/// terminators. The lines are read sequentially, in reverse, in a shuffled order and as raw
+/// bytes, so position handling between the reads is covered as well.
+///
+/// # Panics
+///
+/// Panics with the failing line if a read fails or returns different content
+pub fn assert_reader_matches<R: ReadByLine, S: AsRef<str>>(reader: &mut R, expected: &[S]) {
+    assert_eq!(reader.total_lines(), expected.len(), "amount of lines");
+    let delimiter = reader.get_index().delimiter();
+
+    let check = |reader: &mut R, line: usize| {
+        let read = reader
+            .read_line(line)
+            .unwrap_or_else(|e| panic!("reading line {} failed: {}", line, e));
+        let read = trim_terminator(read.as_bytes(), delimiter);
+        assert_eq!(read, expected[line].as_ref().as_bytes(), "line {}", line);
+    };
+
+    for line in 0..expected.len() {
+        check(reader, line);
+    }
+    for line in (0..expected.len()).rev() {
+        check(reader, line);
+    }
+
+    // Visit every line once in a shuffled order. Any step coprime to the amount of lines does.
+    let len = expected.len();
+    let step = (1..len.max(2))
+        .rev()
+        .find(|i| gcd(*i, len) == 1)
+        .unwrap_or(1);
+    for i in 0..len {
+        check(reader, (i * step + len / 3) % len);
+    }
+
+    let mut buf = Vec::new();
+    for (line, content) in expected.iter().enumerate() {
+        let read = reader
+            .read_line_raw(line, &mut buf)
+            .unwrap_or_else(|e| panic!("reading raw line {} failed: {}", line, e));
+        let read = trim_terminator(&buf[..read], delimiter);
+        assert_eq!(read, content.as_ref().as_bytes(), "raw line {}", line);
+    }
+
+    assert!(reader.read_line(len).is_err(), "line {} exists", len);
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fixture::*, *};
+    use crate::{error::Error, File, IndexedSlice, IndexedString};
+
+    #[test]
+    fn test_fixtures() {
+        let lines = random_lines(200, 0..40, 7);
+        assert_eq!(lines, random_lines(200, 0..40, 7));
+        assert_ne!(lines, random_lines(200, 0..40, 8));
+        assert!(lines.iter().all(|i| i.len() < 40 && !i.contains('\n')));
+        assert!(lines.iter().any(|i| i.is_empty()));
+        assert!(random_lines(3, 5..5, 1).iter().all(|i| i.len() == 5));
+
+        let words = sorted_words(500, 3);
+        assert!(words.windows(2).all(|i| i[0] <= i[1]));
+        assert!(words.iter().all(|i| (1..=10).contains(&i.len())));
+
+        let path =
+            std::env::temp_dir().join(format!("indexed_file_fixture_{}", std::process::id()));
+        write_indexed(&lines, &path).unwrap();
+        assert_reader_matches(&mut File::open(&path).unwrap(), &lines);
+
+        let data = std::fs::read(&path).unwrap();
+        assert_reader_matches(&mut IndexedSlice::new(&data).unwrap(), &lines);
+        assert_reader_matches(
+            &mut IndexedString::new_raw(words.join("\n")).unwrap(),
+            &words,
+        );
+
+        assert!(matches!(
+            write_indexed(&["a", "b\nc"], &path),
+            Err(Error::ContainsNewline { entry: 1 })
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        let empty: [&str; 0] = [];
+        assert_reader_matches(&mut IndexedString::new_raw("").unwrap(), &empty);
+    }
+
+    #[test]
+    #[should_panic(expected = "line 1")]
+    fn test_assert_reader_matches_fails() {
+        let mut reader = IndexedString::new_raw("a\nb\nc").unwrap();
+        assert_reader_matches(&mut reader, &["a", "x", "c"]);
+    }
+}
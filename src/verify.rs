@@ -0,0 +1,93 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    error::Error,
+    hash_reader,
+    index::{first_unordered, Header, Index},
+    Result,
+};
+
+/// State of the data compared to the hash stored in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashStatus {
+    /// The data matches the stored hash
+    Valid,
+    /// The data doesn't match the stored hash
+    Mismatch,
+    /// There is no hash to compare with, eg. because the file was written by an older version
+    Unknown,
+}
+
+/// The result of verifying an indexed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Amount of lines in the index
+    pub lines: usize,
+    /// Whether the data matches the hash stored in the header
+    pub content_hash: HashStatus,
+    /// The first line whose offset is not greater than the offset of its previous line or which
+    /// starts outside of the data. `None` if the index is valid
+    pub invalid_line: Option<usize>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problems were found. Files without a hash are considered valid if
+    /// their index is valid.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.content_hash != HashStatus::Mismatch && self.invalid_line.is_none()
+    }
+
+    /// Returns `Error::ContentMismatch` or `Error::InvalidIndex` if a problem was found
+    pub fn check(&self) -> Result<()> {
+        if self.content_hash == HashStatus::Mismatch {
+            return Err(Error::ContentMismatch);
+        }
+
+        match self.invalid_line {
+            Some(line) => Err(Error::InvalidIndex { line }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Verifies the `data_len` bytes of data within `reader` using its `index`. The hash is read
+/// from the header in front of the data. Data whose header is stored elsewhere, like behind the
+/// data, passes the hash of that header as `content_hash`. Without a header only the index gets
+/// verified.
+pub(crate) fn verify_data<R: Read + Seek>(
+    reader: &mut R,
+    index: &Index,
+    data_len: u64,
+    content_hash: Option<u64>,
+) -> Result<VerifyReport> {
+    let data_start = index.len_bytes() as u64;
+    let expected = if data_start > 0 {
+        Header::decode(reader)?.content_hash()
+    } else {
+        content_hash
+    };
+
+    let mut content_hash = HashStatus::Unknown;
+    if let Some(expected) = expected {
+        reader.seek(SeekFrom::Start(data_start))?;
+        content_hash = if hash_reader(&mut reader.take(data_len))? == expected {
+            HashStatus::Valid
+        } else {
+            HashStatus::Mismatch
+        };
+    }
+
+    let offsets = index.offsets();
+    let out_of_bounds = offsets.iter().position(|i| *i as u64 >= data_len);
+    let invalid_line = match (first_unordered(offsets), out_of_bounds) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    Ok(VerifyReport {
+        lines: index.len(),
+        content_hash,
+        invalid_line,
+    })
+}
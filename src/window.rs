@@ -0,0 +1,240 @@
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    sync::Arc,
+};
+
+use crate::any::Anyable;
+
+/// A reader restricted to a region of an underlying reader, whose start is position zero. Reads
+/// end at the end of the region, so indexed data can be embedded within a larger container
+/// file, see `File::open_at`.
+#[derive(Debug)]
+pub struct Window<R> {
+    inner: R,
+    start: u64,
+    /// Absolute end of the region or `None` if it extends to the end of `inner`
+    end: Option<u64>,
+    /// Position within the region
+    pos: u64,
+}
+
+impl<R: Seek> Window<R> {
+    /// Creates a window over the `len` bytes of `inner` starting at `start`. Returns an error if
+    /// `inner` can't be seeked to `start`.
+    #[inline]
+    pub fn new(inner: R, start: u64, len: u64) -> io::Result<Self> {
+        let end = start.checked_add(len).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the window exceeds u64::MAX")
+        })?;
+        Self::with_bounds(inner, start, Some(end))
+    }
+
+    /// Creates a window starting at `start` and ending at `end` or the end of `inner`
+    pub(crate) fn with_bounds(mut inner: R, start: u64, end: Option<u64>) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            inner,
+            start,
+            end,
+            pos: 0,
+        })
+    }
+
+    /// Returns the absolute start and end of the region, see `with_bounds`
+    #[inline]
+    pub(crate) fn bounds(&self) -> (u64, Option<u64>) {
+        (self.start, self.end)
+    }
+}
+
+impl<R> Window<R> {
+    /// Creates a window over the whole of `inner`, which is positioned at its start
+    #[inline]
+    pub(crate) fn whole(inner: R) -> Self {
+        Self {
+            inner,
+            start: 0,
+            end: None,
+            pos: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying reader
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the underlying reader. Its position is absolute and not restricted to the window.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Window<R> {
+    /// Returns the absolute position of `pos` within the window, or an `InvalidInput` error if it
+    /// exceeds `u64::MAX`
+    #[inline]
+    fn absolute(&self, pos: u64) -> io::Result<u64> {
+        self.start.checked_add(pos).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the position exceeds u64::MAX")
+        })
+    }
+}
+
+impl<R: Read> Read for Window<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.end {
+            Some(end) => {
+                let left = end.saturating_sub(self.absolute(self.pos)?);
+                buf.len().min(usize::try_from(left).unwrap_or(usize::MAX))
+            }
+            None => buf.len(),
+        };
+
+        let read = self.inner.read(&mut buf[..len])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Window<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match (pos, self.end) {
+            (SeekFrom::Start(pos), _) => Some(pos),
+            (SeekFrom::Current(delta), _) => self.pos.checked_add_signed(delta),
+            (SeekFrom::End(delta), Some(end)) => (end - self.start).checked_add_signed(delta),
+            (SeekFrom::End(delta), None) => {
+                let end = self.inner.seek(SeekFrom::End(0))?;
+                (end.saturating_sub(self.start)).checked_add_signed(delta)
+            }
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seeking before the window")
+        })?;
+
+        self.inner.seek(SeekFrom::Start(self.absolute(target)?))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+/// A range of shared data, which allows reading indexed data embedded within a larger buffer
+/// without copying it, see `CloneableIndexedReader::new_at`.
+#[derive(Debug, Clone)]
+pub struct Region<T: Anyable> {
+    data: Arc<T>,
+    range: Range<usize>,
+}
+
+impl<T: Anyable> Region<T> {
+    /// Creates a region of the bytes of `data` within `range`. Returns `None` if `range` is out
+    /// of bounds.
+    #[inline]
+    pub fn new(data: Arc<T>, range: Range<usize>) -> Option<Self> {
+        data.as_ref().as_ref().get(range.clone())?;
+        Some(Self { data, range })
+    }
+
+    /// Returns the range of the region within the shared data
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+impl<T: Anyable> AsRef<[u8]> for Region<T> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.data.as_ref().as_ref()[self.range.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::CloneableIndexedReader, temp::TempFile, File, Indexable, IndexableFile, IndexedString,
+        ReadByLine,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_window() {
+        let mut window = Window::new(Cursor::new(b"0123456789".to_vec()), 2, 5).unwrap();
+        let mut read = String::new();
+        window.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "23456");
+
+        assert_eq!(window.seek(SeekFrom::End(-2)).unwrap(), 3);
+        assert_eq!(window.seek(SeekFrom::Current(-1)).unwrap(), 2);
+        let mut buf = [0u8; 2];
+        window.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"45");
+        assert!(window.seek(SeekFrom::Current(-5)).is_err());
+        assert_eq!(window.seek(SeekFrom::Start(10)).unwrap(), 10);
+        assert_eq!(window.read(&mut buf).unwrap(), 0);
+
+        let mut whole = Window::whole(Cursor::new(b"0123".to_vec()));
+        assert_eq!(whole.seek(SeekFrom::End(-1)).unwrap(), 3);
+        assert!(Window::new(Cursor::new(Vec::new()), 1, u64::MAX).is_err());
+
+        // Positions beyond `u64::MAX` are rejected instead of overflowing
+        let err = window.seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(window.stream_position().unwrap(), 10);
+        let mut open = Window::with_bounds(Cursor::new(b"0123".to_vec()), 2, None).unwrap();
+        assert!(open.seek(SeekFrom::Start(u64::MAX)).is_err());
+        assert_eq!(open.read(&mut buf).unwrap(), 2);
+    }
+
+    fn blob(data: &str) -> Vec<u8> {
+        let mut blob = Vec::new();
+        IndexedString::new_raw(data)
+            .unwrap()
+            .write_to(&mut blob)
+            .unwrap();
+        blob
+    }
+
+    #[test]
+    fn test_open_at() {
+        let first = blob("a\nb\n");
+        let second = blob("x\ny\nz");
+        let mut container = b"prefix".to_vec();
+        container.extend_from_slice(&first);
+        container.extend_from_slice(&second);
+        container.extend_from_slice(b"\ntrailing section");
+
+        let temp = TempFile::new("window");
+        std::fs::write(temp.path(), &container).unwrap();
+        let first_start = 6;
+        let second_start = first_start + first.len() as u64;
+
+        let mut file = File::open_at(temp.path(), first_start, first.len() as u64).unwrap();
+        assert_eq!(file.total_lines(), 2);
+        assert_eq!(file.read_line(1).unwrap(), "b\n");
+        let mut file = File::open_at(temp.path(), second_start, second.len() as u64).unwrap();
+        assert_eq!(file.read_line(2).unwrap(), "z");
+        assert_eq!(file.read_line(0).unwrap(), "x\n");
+        file.verify().unwrap().check().unwrap();
+        assert_eq!(file.duplicate().unwrap().read_line(2).unwrap(), "z");
+
+        // Written regions are standalone files
+        let mut written = Vec::new();
+        file.write_to(&mut written).unwrap();
+        assert_eq!(written, second);
+
+        assert!(File::open_at(temp.path(), 0, 100).is_err());
+
+        let shared = Arc::new(container);
+        let range = first_start as usize..second_start as usize;
+        let mut reader = CloneableIndexedReader::new_at(Arc::clone(&shared), range).unwrap();
+        assert_eq!(reader.read_line(0).unwrap(), "a\n");
+        assert_eq!(reader.content(), b"a\nb\n");
+        assert!(CloneableIndexedReader::new_at(shared, 0..usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use crate::{
+    error::Error,
+    index::{Index, DEFAULT_DELIMITER, TRAILER_MAGIC, TRAILER_SIZE},
+    Fnv1a, Result,
+};
+
+/// Writes indexed data as it arrives, without knowing the lines in advance. The data is written
+/// first and the header and index are appended by `finish`, followed by a trailer pointing to the
+/// header, so the writer never seeks backwards and `W` can be a pipe or socket. See `Header` for
+/// the layout. `File::open` and the other readers detect this layout by its trailer.
+///
+/// # Example
+///
+/// ```
+/// use indexed_file::{any::CloneableIndexedReader, IndexedWriter, ReadByLine};
+/// use std::io::Write;
+///
+/// let mut writer = IndexedWriter::new(Vec::new());
+/// writer.write_line(b"first")?;
+/// writer.write_all(b"second\nthird")?;
+/// let data = writer.finish()?;
+///
+/// let mut reader = CloneableIndexedReader::new(data)?;
+/// assert_eq!(reader.read_line(2)?, "third");
+/// # Ok::<(), indexed_file::error::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct IndexedWriter<W: Write> {
+    writer: W,
+    delimiter: u8,
+    offsets: Vec<u32>,
+    /// Amount of data bytes written
+    offset: u64,
+    /// Whether the next byte begins a new line
+    line_start: bool,
+    hasher: Fnv1a,
+}
+
+impl<W: Write> IndexedWriter<W> {
+    /// Creates a new `IndexedWriter` writing lines terminated by `DEFAULT_DELIMITER` into
+    /// `writer`
+    #[inline]
+    pub fn new(writer: W) -> Self {
+        Self::with_delimiter(writer, DEFAULT_DELIMITER)
+    }
+
+    /// Creates a new `IndexedWriter` writing lines terminated by `delimiter` into `writer`
+    #[inline]
+    pub fn with_delimiter(writer: W, delimiter: u8) -> Self {
+        Self {
+            writer,
+            delimiter,
+            offsets: Vec::new(),
+            offset: 0,
+            line_start: true,
+            hasher: Fnv1a::new(),
+        }
+    }
+
+    /// Writes `line` followed by the delimiter. If the data written using `Write` doesn't end
+    /// with the delimiter, the last line gets terminated first.
+    ///
+    /// Returns `Error::ContainsNewline` if `line` contains the delimiter
+    pub fn write_line(&mut self, line: &[u8]) -> Result<()> {
+        if line.contains(&self.delimiter) {
+            return Err(Error::ContainsNewline {
+                entry: self.offsets.len(),
+            });
+        }
+
+        if !self.line_start {
+            self.write_data(&[self.delimiter])?;
+        }
+        self.write_data(line)?;
+        self.write_data(&[self.delimiter])
+    }
+
+    /// Returns the amount of lines written so far
+    #[inline]
+    pub fn lines(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns a reference to the underlying writer
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Appends the header, the index and the trailer behind the written data and returns the
+    /// underlying writer.
+    #[inline]
+    pub fn finish(self) -> Result<W> {
+        self.finish_with_len().map(|(writer, _)| writer)
+    }
+
+    /// Finishes the data like `finish` and also returns the total amount of bytes written,
+    /// including the header, the index and the trailer
+    pub(crate) fn finish_with_len(mut self) -> Result<(W, usize)> {
+        let mut index = Index::new(self.offsets);
+        index.set_delimiter(self.delimiter);
+        let mut header = index.get_header();
+        header.set_content_hash(self.hasher.finish());
+        header.set_index_at_end(true);
+        let header = header.encode();
+
+        self.writer.write_all(&header)?;
+        let index_len = index.encode_into(&mut self.writer)?;
+        self.writer.write_all(&self.offset.to_le_bytes())?;
+        self.writer.write_all(&TRAILER_MAGIC)?;
+        self.writer.flush()?;
+
+        let len = self.offset as usize + header.len() + index_len + TRAILER_SIZE;
+        Ok((self.writer, len))
+    }
+
+    /// Writes `data` and records the offsets of the lines beginning within it. The state is only
+    /// updated once `data` was written, so a failed write doesn't leave offsets of unwritten
+    /// lines behind.
+    ///
+    /// Returns `Error::OffsetOverflow` if a line would start beyond `u32::MAX`
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        let mut offsets = Vec::new();
+        let mut line_start = self.line_start;
+        let mut pos = 0;
+        while pos < data.len() {
+            if line_start {
+                let offset =
+                    u32::try_from(self.offset + pos as u64).map_err(|_| Error::OffsetOverflow {
+                        line: self.offsets.len() + offsets.len(),
+                    })?;
+                offsets.push(offset);
+                line_start = false;
+            }
+
+            match data[pos..].iter().position(|i| *i == self.delimiter) {
+                Some(end) => {
+                    pos += end + 1;
+                    line_start = true;
+                }
+                None => pos = data.len(),
+            }
+        }
+
+        self.writer.write_all(data)?;
+        self.offsets.extend(offsets);
+        self.line_start = line_start;
+        self.hasher.write(data);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IndexedWriter<W> {
+    /// Writes raw data, which may contain multiple or partial lines
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_data(buf)?;
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        any::CloneableIndexedReader, temp::TempFile, verify::HashStatus, File, Indexable,
+        IndexableFile, IndexedSlice, OpenBuilder, ReadByLine,
+    };
+    use std::{fs, io::Cursor};
+
+    fn write_lines(lines: &[&str]) -> Vec<u8> {
+        let mut writer = IndexedWriter::new(Vec::new());
+        for line in lines {
+            writer.write_line(line.as_bytes()).unwrap();
+        }
+        assert_eq!(writer.lines(), lines.len());
+        writer.finish().unwrap()
+    }
+
+    fn all_lines<R: ReadByLine>(reader: &mut R) -> Vec<String> {
+        (0..reader.total_lines())
+            .map(|i| reader.read_line(i).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = IndexedWriter::with_delimiter(Vec::new(), b'\0');
+        writer.write_all(b"a\0bc").unwrap();
+        writer.write_all(b"d\0").unwrap();
+        writer.write_all(b"e").unwrap();
+        writer.write_line(b"f").unwrap();
+        assert!(matches!(
+            writer.write_line(b"g\0"),
+            Err(Error::ContainsNewline { entry: 4 })
+        ));
+        assert_eq!(writer.get_ref(), b"a\0bcd\0e\0f\0");
+        let data = writer.finish().unwrap();
+
+        // The data is written first, the index is appended
+        assert!(data.starts_with(b"a\0bcd\0e\0f\0"));
+        assert!(data.ends_with(&TRAILER_MAGIC));
+        let mut reader = CloneableIndexedReader::new(data).unwrap();
+        assert_eq!(reader.get_index().offsets(), [0, 2, 6, 8]);
+        assert_eq!(reader.get_index().delimiter(), b'\0');
+        assert_eq!(all_lines(&mut reader), ["a\0", "bcd\0", "e\0", "f\0"]);
+        assert_eq!(reader.content(), b"a\0bcd\0e\0f\0");
+
+        let empty = IndexedWriter::new(Vec::new()).finish().unwrap();
+        let reader = CloneableIndexedReader::new(empty).unwrap();
+        assert_eq!(reader.total_lines(), 0);
+        assert_eq!(reader.content(), b"");
+    }
+
+    /// Fails every write while `fail` is set
+    struct FailingWriter {
+        data: Vec<u8>,
+        fail: bool,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.fail {
+                return Err(io::Error::other("failed"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let mut writer = IndexedWriter::new(FailingWriter {
+            data: Vec::new(),
+            fail: false,
+        });
+        writer.write_line(b"first").unwrap();
+        writer.writer.fail = true;
+        assert!(writer.write_all(b"lost\nlines\n").is_err());
+        assert!(writer.write_line(b"lost").is_err());
+        assert_eq!(writer.lines(), 1);
+
+        writer.writer.fail = false;
+        writer.write_line(b"second").unwrap();
+        let data = writer.finish().unwrap().data;
+        let mut reader = CloneableIndexedReader::new(data).unwrap();
+        assert_eq!(all_lines(&mut reader), ["first\n", "second\n"]);
+        reader.reader.verify().unwrap().check().unwrap();
+    }
+
+    #[test]
+    fn test_index_at_end_round_trip() {
+        let lines = ["first", "", "third line", "last"];
+        let data = write_lines(&lines);
+        let expected = vec!["first\n", "\n", "third line\n", "last\n"];
+
+        let temp = TempFile::new("writer");
+        fs::write(temp.path(), &data).unwrap();
+        let mut file = File::open(temp.path()).unwrap();
+        assert_eq!(file.get_index().len_bytes(), 0);
+        assert_eq!(all_lines(&mut file), expected);
+        assert_eq!(file.duplicate().unwrap().read_line(3).unwrap(), "last\n");
+        file.verify().unwrap().check().unwrap();
+
+        let mut reader = OpenBuilder::new()
+            .open_reader(Cursor::new(data.clone()))
+            .unwrap();
+        assert_eq!(all_lines(&mut reader), expected);
+        let mut all = Vec::new();
+        reader.read_all(&mut all).unwrap();
+        assert_eq!(all, b"first\n\nthird line\nlast\n");
+
+        let mut cloneable = CloneableIndexedReader::new(data.clone()).unwrap();
+        assert_eq!(all_lines(&mut cloneable.clone()), expected);
+        cloneable.push_line(b"appended").unwrap();
+        assert_eq!(
+            cloneable.content(),
+            b"first\n\nthird line\nlast\nappended\n"
+        );
+
+        let mut slice = IndexedSlice::new(&data).unwrap();
+        assert_eq!(all_lines(&mut slice), expected);
+
+        // Converting to the prefixed layout keeps the lines
+        let mut written = Vec::new();
+        file.write_to(&mut written).unwrap();
+        assert!(written.starts_with(&crate::index::MAGIC));
+        let mut prefixed = CloneableIndexedReader::new(written).unwrap();
+        assert_eq!(all_lines(&mut prefixed), expected);
+        assert_eq!(prefixed.get_index(), file.get_index());
+
+        // Embedded regions may use either layout
+        let mut container = b"prefix".to_vec();
+        container.extend_from_slice(&data);
+        fs::write(temp.path(), &container).unwrap();
+        let mut region = File::open_at(temp.path(), 6, data.len() as u64).unwrap();
+        assert_eq!(all_lines(&mut region), expected);
+    }
+
+    #[test]
+    fn test_verify_index_at_end() {
+        let data = write_lines(&["first", "second"]);
+        let temp = TempFile::new("writer_verify");
+        fs::write(temp.path(), &data).unwrap();
+        let report = File::open(temp.path()).unwrap().verify().unwrap();
+        assert_eq!(report.content_hash, HashStatus::Valid);
+        report.check().unwrap();
+
+        // Corrupt the data without changing its length
+        let mut corrupted = data.clone();
+        corrupted[1] = b'X';
+        fs::write(temp.path(), &corrupted).unwrap();
+        let mut file = File::open(temp.path()).unwrap();
+        assert_eq!(file.read_line(0).unwrap(), "fXrst\n");
+        let report = file.verify().unwrap();
+        assert_eq!(report.content_hash, HashStatus::Mismatch);
+        assert!(matches!(report.check(), Err(Error::ContentMismatch)));
+        assert!(File::open_verified(temp.path()).is_err());
+
+        // Clones keep the hash of the header behind the data
+        let reader = CloneableIndexedReader::new(corrupted.clone()).unwrap();
+        assert!(matches!(
+            reader.clone().reader.verify().unwrap().check(),
+            Err(Error::ContentMismatch)
+        ));
+        let mut reader = OpenBuilder::new()
+            .open_reader(Cursor::new(corrupted))
+            .unwrap();
+        assert_eq!(reader.verify().unwrap().content_hash, HashStatus::Mismatch);
+        let mut reader = CloneableIndexedReader::new(data).unwrap();
+        assert_eq!(
+            reader.reader.verify().unwrap().content_hash,
+            HashStatus::Valid
+        );
+    }
+
+    #[test]
+    fn test_malformed_trailer() {
+        let mut data = write_lines(&["a", "b"]);
+        let trailer_start = data.len() - TRAILER_SIZE;
+
+        let mut moved = data.clone();
+        moved[trailer_start..trailer_start + 8].copy_from_slice(&3u64.to_le_bytes());
+        assert!(CloneableIndexedReader::new(moved).is_err());
+
+        let mut beyond = data.clone();
+        beyond[trailer_start..trailer_start + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(CloneableIndexedReader::new(beyond).is_err());
+
+        // Without the trailer the data is read as legacy header, which fails
+        data.truncate(trailer_start);
+        assert!(CloneableIndexedReader::new(data).is_err());
+
+        // Legacy data ending like a trailer is still read using its legacy header
+        let mut legacy = 1u64.to_le_bytes().to_vec();
+        legacy.extend(0u32.to_le_bytes());
+        legacy.push(b'\n');
+        legacy.extend(b"12345678");
+        legacy.extend(TRAILER_MAGIC);
+        let mut reader = CloneableIndexedReader::new(legacy.clone()).unwrap();
+        assert_eq!(reader.read_line(0).unwrap(), "12345678IDXTAIL\x01");
+
+        let temp = TempFile::new("legacy_trailer");
+        fs::write(temp.path(), &legacy).unwrap();
+        let mut file = File::open(temp.path()).unwrap();
+        assert_eq!(file.get_index().len_bytes(), 13);
+        assert_eq!(file.read_line(0).unwrap(), "12345678IDXTAIL\x01");
+    }
+}